@@ -0,0 +1,174 @@
+//! A language server that knows no language, for the editor's tests. It keeps
+//! the documents it is sent, applying edits the way the protocol counts
+//! positions, and tells what it holds in answer to `stub/state`.
+//!
+//! `--sync N` sets the `textDocumentSync` kind it asks for (2 by default). The
+//! `stub/crash` notification makes it exit with status 3.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+#[derive(Default)]
+struct State {
+    // By URI: version and text
+    documents: BTreeMap<String, (i64, String)>,
+    initialized: bool,
+    // What the editor answered to workspace/configuration
+    configuration: Value,
+    changes: usize,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let sync = args
+        .iter()
+        .position(|arg| arg == "--sync")
+        .and_then(|i| args.get(i + 1)?.parse::<u64>().ok())
+        .unwrap_or(2);
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    let mut state = State::default();
+
+    while let Some(message) = read(&mut input) {
+        let id = message.get("id").cloned();
+        let params = &message["params"];
+        match message["method"].as_str() {
+            Some("initialize") => {
+                send(
+                    &mut output,
+                    json!({ "id": "configuration", "method": "workspace/configuration",
+                            "params": { "items": [{ "section": "stub" }] } }),
+                );
+                let capabilities = json!({ "textDocumentSync": sync });
+                send(
+                    &mut output,
+                    json!({ "id": id, "result": { "capabilities": capabilities } }),
+                );
+            }
+            Some("initialized") => state.initialized = true,
+            Some("textDocument/didOpen") => {
+                let document = &params["textDocument"];
+                state.documents.insert(
+                    string(&document["uri"]),
+                    (
+                        document["version"].as_i64().unwrap_or_default(),
+                        string(&document["text"]),
+                    ),
+                );
+            }
+            Some("textDocument/didChange") => {
+                state.changes += 1;
+                let uri = string(&params["textDocument"]["uri"]);
+                let Some((version, text)) = state.documents.get_mut(&uri) else {
+                    continue;
+                };
+                *version = params["textDocument"]["version"]
+                    .as_i64()
+                    .unwrap_or_default();
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let replacement = string(&change["text"]);
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = offset(text, &range["start"]);
+                            let end = offset(text, &range["end"]).max(start);
+                            text.replace_range(start..end, &replacement);
+                        }
+                        None => *text = replacement,
+                    }
+                }
+            }
+            Some("textDocument/didClose") => {
+                state
+                    .documents
+                    .remove(&string(&params["textDocument"]["uri"]));
+            }
+            Some("stub/state") => {
+                let documents: BTreeMap<&String, Value> = state
+                    .documents
+                    .iter()
+                    .map(|(uri, (version, text))| {
+                        (uri, json!({ "version": version, "text": text }))
+                    })
+                    .collect();
+                let result = json!({
+                    "initialized": state.initialized,
+                    "configuration": state.configuration,
+                    "documents": documents,
+                    "changes": state.changes,
+                });
+                send(&mut output, json!({ "id": id, "result": result }));
+            }
+            Some("stub/crash") => {
+                eprintln!("crashing on purpose");
+                std::process::exit(3);
+            }
+            Some("shutdown") => send(&mut output, json!({ "id": id, "result": null })),
+            Some("exit") => return,
+            // A response to the configuration request
+            None => state.configuration = message["result"].clone(),
+            Some(method) => {
+                if id.is_some() {
+                    send(
+                        &mut output,
+                        json!({ "id": id, "error": { "code": -32601, "message": method } }),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+// The byte offset of a protocol position, which counts UTF-16 units into the line
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(end) => line_start += end + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn read(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok()?;
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(output: &mut impl Write, mut message: Value) {
+    message["jsonrpc"] = json!("2.0");
+    let body = message.to_string();
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
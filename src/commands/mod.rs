@@ -0,0 +1,938 @@
+use crate::editor::guides::WhitespaceMode;
+use crate::editor::macros::MACROS;
+use crate::editor::references;
+use crate::editor::wrap::WrapMode;
+use crate::editor::{navigation, typography, workspace_edit};
+use crate::keymap::KEYMAP;
+use crate::settings::{update_settings, SETTINGS};
+use crate::utils::log;
+use dioxus::prelude::*;
+
+// Central list of every named action. Key bindings, menus and the command palette
+// all refer to commands by id. Each command has its handler: a function here
+// when it needs only global state, otherwise the component that owns the state.
+
+/// Component that handles a command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    /// The layout: panels, splits, themes and file dialogs
+    Workbench,
+    /// The most recently focused editor
+    Editor,
+    Terminal,
+}
+
+/// How a command runs.
+#[derive(Clone, Copy, Debug)]
+pub enum Handler {
+    /// Called with the command's argument
+    Run(fn(Option<&str>)),
+    /// Sent to the component that handles `Target`'s commands
+    Send(Target),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Menu {
+    File,
+    Edit,
+    Selection,
+    View,
+    Help,
+}
+
+impl Menu {
+    pub const ALL: [Menu; 5] = [
+        Menu::File,
+        Menu::Edit,
+        Menu::Selection,
+        Menu::View,
+        Menu::Help,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Menu::File => "File",
+            Menu::Edit => "Edit",
+            Menu::Selection => "Selection",
+            Menu::View => "View",
+            Menu::Help => "Help",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Command {
+    pub id: &'static str,
+    pub category: &'static str,
+    pub title: &'static str,
+    pub handler: Handler,
+    pub menu: Option<Menu>,
+    /// Menu items are separated where the group changes
+    pub group: u8,
+    /// Listed in the command palette; false for commands that need an argument
+    pub in_palette: bool,
+}
+
+impl Command {
+    const fn new(
+        id: &'static str,
+        category: &'static str,
+        title: &'static str,
+        target: Target,
+    ) -> Self {
+        Self {
+            id,
+            category,
+            title,
+            handler: Handler::Send(target),
+            menu: None,
+            group: 0,
+            in_palette: true,
+        }
+    }
+
+    const fn run(
+        id: &'static str,
+        category: &'static str,
+        title: &'static str,
+        run: fn(Option<&str>),
+    ) -> Self {
+        Self {
+            handler: Handler::Run(run),
+            ..Self::new(id, category, title, Target::Workbench)
+        }
+    }
+
+    const fn in_menu(mut self, menu: Menu, group: u8) -> Self {
+        self.menu = Some(menu);
+        self.group = group;
+        self
+    }
+
+    const fn hidden(mut self) -> Self {
+        self.in_palette = false;
+        self
+    }
+
+    /// Title as shown in the command palette, e.g. "View: Toggle Terminal".
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.category, self.title)
+    }
+}
+
+use Target::{Editor, Terminal, Workbench};
+
+pub const COMMANDS: &[Command] = &[
+    // File
+    Command::new("file.newFile", "File", "New File...", Workbench).in_menu(Menu::File, 0),
+    Command::new("file.openFile", "File", "Open File...", Workbench).in_menu(Menu::File, 1),
+    Command::new("file.openFolder", "File", "Open Folder...", Workbench).in_menu(Menu::File, 1),
+    Command::new("editor.save", "File", "Save", Editor).in_menu(Menu::File, 2),
+    Command::new("file.saveAs", "File", "Save As...", Editor).in_menu(Menu::File, 2),
+    Command::new("app.exit", "File", "Exit", Workbench).in_menu(Menu::File, 3),
+    // Edit
+    Command::new("editor.undo", "Edit", "Undo", Editor).in_menu(Menu::Edit, 0),
+    Command::new("editor.redo", "Edit", "Redo", Editor).in_menu(Menu::Edit, 0),
+    Command::new("editor.cut", "Edit", "Cut", Editor).in_menu(Menu::Edit, 1),
+    Command::new("editor.copy", "Edit", "Copy", Editor).in_menu(Menu::Edit, 1),
+    Command::new("editor.paste", "Edit", "Paste", Editor).in_menu(Menu::Edit, 1),
+    Command::new(
+        "editor.toggleLineComment",
+        "Edit",
+        "Toggle Line Comment",
+        Editor,
+    )
+    .in_menu(Menu::Edit, 2),
+    Command::new(
+        "editor.changeLanguage",
+        "Edit",
+        "Change Language Mode...",
+        Editor,
+    ),
+    Command::new(
+        "lsp.restartServer",
+        "Edit",
+        "Restart Language Server",
+        Editor,
+    ),
+    // Completion
+    Command::new("editor.triggerSuggest", "Edit", "Trigger Suggest", Editor),
+    Command::new("suggest.next", "Edit", "Select Next Suggestion", Editor).hidden(),
+    Command::new(
+        "suggest.previous",
+        "Edit",
+        "Select Previous Suggestion",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "suggest.nextPage",
+        "Edit",
+        "Select Next Page of Suggestions",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "suggest.previousPage",
+        "Edit",
+        "Select Previous Page of Suggestions",
+        Editor,
+    )
+    .hidden(),
+    Command::new("suggest.accept", "Edit", "Accept Suggestion", Editor).hidden(),
+    Command::new("suggest.hide", "Edit", "Hide Suggestions", Editor).hidden(),
+    Command::new(
+        "editor.triggerParameterHints",
+        "Edit",
+        "Trigger Parameter Hints",
+        Editor,
+    ),
+    Command::new("parameterHints.next", "Edit", "Show Next Signature", Editor).hidden(),
+    Command::new(
+        "parameterHints.previous",
+        "Edit",
+        "Show Previous Signature",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "parameterHints.hide",
+        "Edit",
+        "Hide Parameter Hints",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "snippet.next",
+        "Edit",
+        "Go to Next Snippet Placeholder",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "snippet.previous",
+        "Edit",
+        "Go to Previous Snippet Placeholder",
+        Editor,
+    )
+    .hidden(),
+    Command::new("snippet.exit", "Edit", "Exit Snippet", Editor).hidden(),
+    Command::new("editor.toggleVim", "Edit", "Toggle Vim Mode", Editor).in_menu(Menu::Edit, 2),
+    Command::new(
+        "editor.toggleEmacs",
+        "Edit",
+        "Toggle Emacs Keybindings",
+        Editor,
+    )
+    .in_menu(Menu::Edit, 2),
+    // Macros
+    Command::new(
+        "macro.toggleRecording",
+        "Macro",
+        "Start/Stop Recording",
+        Editor,
+    )
+    .in_menu(Menu::Edit, 3),
+    Command::new("macro.play", "Macro", "Play Last Macro", Editor).in_menu(Menu::Edit, 3),
+    Command::run(
+        "macro.playTimes",
+        "Macro",
+        "Play Last Macro N Times...",
+        play_macro_times,
+    ),
+    Command::new(
+        "macro.playToEnd",
+        "Macro",
+        "Play Last Macro to End of File",
+        Editor,
+    ),
+    Command::run("macro.save", "Macro", "Save Last Macro As...", save_macro),
+    Command::run(
+        "macro.runSaved",
+        "Macro",
+        "Run Saved Macro...",
+        run_saved_macro,
+    ),
+    Command::run(
+        "macro.deleteSaved",
+        "Macro",
+        "Delete Saved Macro...",
+        delete_saved_macro,
+    ),
+    Command::new("macro.run", "Macro", "Run Macro", Editor).hidden(),
+    // Selection
+    Command::new("selection.selectAll", "Selection", "Select All", Editor)
+        .in_menu(Menu::Selection, 0),
+    Command::new("selection.clear", "Selection", "Clear Selection", Editor)
+        .in_menu(Menu::Selection, 0),
+    Command::new("selection.expand", "Selection", "Expand Selection", Editor)
+        .in_menu(Menu::Selection, 0),
+    Command::new("selection.shrink", "Selection", "Shrink Selection", Editor)
+        .in_menu(Menu::Selection, 0),
+    Command::new(
+        "selection.columnUp",
+        "Selection",
+        "Column Select Up",
+        Editor,
+    )
+    .in_menu(Menu::Selection, 1),
+    Command::new(
+        "selection.columnDown",
+        "Selection",
+        "Column Select Down",
+        Editor,
+    )
+    .in_menu(Menu::Selection, 1),
+    Command::new(
+        "selection.columnLeft",
+        "Selection",
+        "Column Select Left",
+        Editor,
+    )
+    .in_menu(Menu::Selection, 1),
+    Command::new(
+        "selection.columnRight",
+        "Selection",
+        "Column Select Right",
+        Editor,
+    )
+    .in_menu(Menu::Selection, 1),
+    // Go
+    Command::new("editor.goToLine", "Go", "Go to Line/Column...", Editor),
+    Command::new(
+        "editor.goToSymbol",
+        "Go",
+        "Go to Symbol in Editor...",
+        Editor,
+    ),
+    Command::new("editor.goToDefinition", "Go", "Go to Definition", Editor),
+    Command::new("editor.peekDefinition", "Go", "Peek Definition", Editor),
+    Command::new(
+        "editor.goToTypeDefinition",
+        "Go",
+        "Go to Type Definition",
+        Editor,
+    ),
+    Command::new(
+        "editor.goToImplementation",
+        "Go",
+        "Go to Implementations",
+        Editor,
+    ),
+    Command::new(
+        "editor.findAllReferences",
+        "Go",
+        "Find All References",
+        Editor,
+    ),
+    Command::new("editor.closePeek", "Go", "Close Peek", Editor).hidden(),
+    Command::run("references.close", "Go", "Close References", |_| {
+        references::close()
+    }),
+    Command::new("editor.showHover", "Edit", "Show Hover", Editor),
+    // Refactoring
+    Command::new("editor.rename", "Refactor", "Rename Symbol", Editor),
+    Command::new("editor.quickFix", "Refactor", "Quick Fix...", Editor),
+    Command::new(
+        "editor.applyCodeAction",
+        "Refactor",
+        "Apply Code Action",
+        Editor,
+    )
+    .hidden(),
+    Command::new(
+        "editor.organizeImports",
+        "Refactor",
+        "Organize Imports",
+        Editor,
+    ),
+    Command::run(
+        "refactor.applyPreview",
+        "Refactor",
+        "Apply Previewed Edit",
+        |_| workspace_edit::apply_preview(),
+    ),
+    Command::run(
+        "refactor.discardPreview",
+        "Refactor",
+        "Discard Previewed Edit",
+        |_| workspace_edit::discard_preview(),
+    ),
+    Command::new("navigation.back", "Go", "Go Back", Editor),
+    Command::new("navigation.forward", "Go", "Go Forward", Editor),
+    Command::new(
+        "editor.toggleBookmark",
+        "Bookmarks",
+        "Toggle Bookmark",
+        Editor,
+    ),
+    Command::new("editor.nextBookmark", "Bookmarks", "Next Bookmark", Editor),
+    Command::new(
+        "editor.previousBookmark",
+        "Bookmarks",
+        "Previous Bookmark",
+        Editor,
+    ),
+    Command::new(
+        "navigation.listBookmarks",
+        "Bookmarks",
+        "List Bookmarks...",
+        Workbench,
+    ),
+    Command::new("editor.openBookmark", "Bookmarks", "Open Bookmark", Editor).hidden(),
+    Command::run(
+        "navigation.openBookmark",
+        "Bookmarks",
+        "Open Bookmark",
+        open_bookmark,
+    )
+    .hidden(),
+    // View
+    Command::run(
+        "workbench.showCommands",
+        "View",
+        "Command Palette...",
+        toggle_palette,
+    )
+    .in_menu(Menu::View, 0),
+    Command::new(
+        "workbench.toggleActivityBar",
+        "View",
+        "Toggle Activity Bar",
+        Workbench,
+    )
+    .in_menu(Menu::View, 1),
+    Command::new(
+        "workbench.toggleSidebar",
+        "View",
+        "Toggle Primary Side Bar",
+        Workbench,
+    )
+    .in_menu(Menu::View, 1),
+    Command::new(
+        "workbench.toggleRightSidebar",
+        "View",
+        "Toggle Secondary Side Bar",
+        Workbench,
+    )
+    .in_menu(Menu::View, 1),
+    Command::new(
+        "workbench.toggleTerminal",
+        "View",
+        "Toggle Terminal",
+        Workbench,
+    )
+    .in_menu(Menu::View, 1),
+    Command::new(
+        "workbench.splitRight",
+        "View",
+        "Split Editor Right",
+        Workbench,
+    )
+    .in_menu(Menu::View, 2),
+    Command::new("workbench.closeSplit", "View", "Close Split", Workbench).in_menu(Menu::View, 2),
+    Command::new(
+        "workbench.nextEditor",
+        "View",
+        "Open Next Editor",
+        Workbench,
+    )
+    .in_menu(Menu::View, 2),
+    Command::new(
+        "workbench.previousEditor",
+        "View",
+        "Open Previous Editor",
+        Workbench,
+    )
+    .in_menu(Menu::View, 2),
+    Command::new("editor.foldAll", "View", "Fold All", Editor).in_menu(Menu::View, 3),
+    Command::new("editor.unfoldAll", "View", "Unfold All", Editor).in_menu(Menu::View, 3),
+    Command::new("editor.fold", "View", "Fold", Editor),
+    Command::new("editor.unfold", "View", "Unfold", Editor),
+    Command::new("editor.toggleFold", "View", "Toggle Fold", Editor),
+    Command::run(
+        "editor.toggleWordWrap",
+        "View",
+        "Toggle Word Wrap",
+        toggle_word_wrap,
+    )
+    .in_menu(Menu::View, 4),
+    Command::run("editor.toggleMinimap", "View", "Toggle Minimap", |_| {
+        update_settings(|settings| settings.minimap = !settings.minimap)
+    })
+    .in_menu(Menu::View, 4),
+    Command::run(
+        "editor.toggleStickyScroll",
+        "View",
+        "Toggle Sticky Scroll",
+        |_| update_settings(|settings| settings.sticky_scroll = !settings.sticky_scroll),
+    )
+    .in_menu(Menu::View, 4),
+    Command::run(
+        "editor.toggleInlayHints",
+        "View",
+        "Toggle Inlay Hints",
+        |_| update_settings(|settings| settings.inlay_hints = !settings.inlay_hints),
+    )
+    .in_menu(Menu::View, 4),
+    Command::run(
+        "editor.stickyScrollMaxLines",
+        "Preferences",
+        "Sticky Scroll Maximum Lines...",
+        sticky_scroll_max_lines,
+    ),
+    Command::run(
+        "editor.toggleRenderWhitespace",
+        "View",
+        "Toggle Render Whitespace",
+        toggle_render_whitespace,
+    )
+    .in_menu(Menu::View, 4),
+    Command::run(
+        "editor.toggleIndentGuides",
+        "View",
+        "Toggle Indent Guides",
+        |_| update_settings(|settings| settings.indent_guides = !settings.indent_guides),
+    )
+    .in_menu(Menu::View, 4),
+    Command::run(
+        "editor.renderWhitespace",
+        "Preferences",
+        "Render Whitespace...",
+        render_whitespace,
+    ),
+    Command::run("editor.rulers", "Preferences", "Rulers...", rulers),
+    Command::run("editor.fontZoomIn", "View", "Editor Font Zoom In", |_| {
+        typography::zoom_in()
+    })
+    .in_menu(Menu::View, 5),
+    Command::run("editor.fontZoomOut", "View", "Editor Font Zoom Out", |_| {
+        typography::zoom_out()
+    })
+    .in_menu(Menu::View, 5),
+    Command::run(
+        "editor.fontZoomReset",
+        "View",
+        "Editor Font Zoom Reset",
+        |_| typography::reset_zoom(),
+    )
+    .in_menu(Menu::View, 5),
+    Command::run("editor.wordWrap", "Preferences", "Word Wrap...", word_wrap),
+    Command::run(
+        "editor.wrapColumn",
+        "Preferences",
+        "Word Wrap Column...",
+        wrap_column,
+    ),
+    Command::run(
+        "editor.foldToLevel",
+        "View",
+        "Fold to Level...",
+        fold_to_level,
+    ),
+    Command::new("editor.foldLevel", "View", "Fold Level", Editor).hidden(),
+    Command::new(
+        "theme.vscode",
+        "Preferences",
+        "Color Theme: VS Code Dark",
+        Workbench,
+    ),
+    Command::new(
+        "theme.gruvbox",
+        "Preferences",
+        "Color Theme: Gruvbox",
+        Workbench,
+    ),
+    Command::new(
+        "theme.atom",
+        "Preferences",
+        "Color Theme: Atom One Dark",
+        Workbench,
+    ),
+    Command::new(
+        "theme.monokai",
+        "Preferences",
+        "Color Theme: Monokai",
+        Workbench,
+    ),
+    Command::new(
+        "iconTheme.vscode",
+        "Preferences",
+        "File Icon Theme: VS Code Icons",
+        Workbench,
+    ),
+    Command::new(
+        "iconTheme.material",
+        "Preferences",
+        "File Icon Theme: Material Icons",
+        Workbench,
+    ),
+    Command::new(
+        "iconTheme.gruvbox",
+        "Preferences",
+        "File Icon Theme: Gruvbox Icons",
+        Workbench,
+    ),
+    Command::new(
+        "iconTheme.atom",
+        "Preferences",
+        "File Icon Theme: Atom Icons",
+        Workbench,
+    ),
+    // Help
+    Command::new(
+        "workbench.openKeybindings",
+        "Preferences",
+        "Open Keyboard Shortcuts File",
+        Workbench,
+    )
+    .in_menu(Menu::Help, 0),
+    Command::run(
+        "keybindings.reload",
+        "Preferences",
+        "Reload Keyboard Shortcuts",
+        |_| KEYMAP.write().reload(),
+    )
+    .in_menu(Menu::Help, 0),
+    // Cursor and text editing
+    Command::new("cursor.up", "Cursor", "Move Up", Editor),
+    Command::new("cursor.down", "Cursor", "Move Down", Editor),
+    Command::new("cursor.left", "Cursor", "Move Left", Editor),
+    Command::new("cursor.right", "Cursor", "Move Right", Editor),
+    Command::new("cursor.lineStart", "Cursor", "Go to Line Start", Editor),
+    Command::new("cursor.lineEnd", "Cursor", "Go to Line End", Editor),
+    Command::new("editor.pageUp", "Editor", "Scroll Page Up", Editor),
+    Command::new("editor.pageDown", "Editor", "Scroll Page Down", Editor),
+    Command::new("editor.deleteLeft", "Editor", "Delete Left", Editor),
+    Command::new("editor.deleteRight", "Editor", "Delete Right", Editor),
+    Command::new("editor.newline", "Editor", "Insert Line Break", Editor),
+    Command::new("editor.tab", "Editor", "Indent", Editor),
+    // Terminal
+    Command::new("terminal.clear", "Terminal", "Clear", Terminal),
+];
+
+// Handlers of the commands that need only global state. Those that take an
+// argument ask for it with the palette when run without one.
+
+fn toggle_palette(_: Option<&str>) {
+    let open = PALETTE.read().is_some();
+    *PALETTE.write() = if open {
+        None
+    } else {
+        Some(PaletteMode::Commands)
+    };
+}
+
+fn toggle_word_wrap(_: Option<&str>) {
+    update_settings(|settings| {
+        settings.word_wrap = match settings.word_wrap {
+            WrapMode::Off => WrapMode::Viewport,
+            _ => WrapMode::Off,
+        };
+    });
+}
+
+fn word_wrap(argument: Option<&str>) {
+    match argument.and_then(WrapMode::from_name) {
+        Some(mode) => update_settings(|settings| settings.word_wrap = mode),
+        None => {
+            let modes = WrapMode::ALL
+                .iter()
+                .map(|mode| mode.name().to_string())
+                .collect();
+            prompt("Soft wrap lines at", "editor.wordWrap", modes);
+        }
+    }
+}
+
+fn wrap_column(argument: Option<&str>) {
+    match argument.and_then(|column| column.trim().parse::<usize>().ok()) {
+        Some(column) => update_settings(|settings| settings.wrap_column = column.max(1)),
+        None => prompt("Column to wrap lines at", "editor.wrapColumn", Vec::new()),
+    }
+}
+
+fn toggle_render_whitespace(_: Option<&str>) {
+    update_settings(|settings| {
+        settings.render_whitespace = match settings.render_whitespace {
+            WhitespaceMode::None => WhitespaceMode::All,
+            _ => WhitespaceMode::None,
+        };
+    });
+}
+
+fn sticky_scroll_max_lines(argument: Option<&str>) {
+    match argument.and_then(|lines| lines.trim().parse::<usize>().ok()) {
+        Some(lines) => update_settings(|settings| settings.sticky_scroll_max_lines = lines),
+        None => {
+            let counts = (1..=10).map(|lines| lines.to_string()).collect();
+            prompt(
+                "Most lines to pin at the top",
+                "editor.stickyScrollMaxLines",
+                counts,
+            );
+        }
+    }
+}
+
+fn render_whitespace(argument: Option<&str>) {
+    match argument.and_then(WhitespaceMode::from_name) {
+        Some(mode) => update_settings(|settings| settings.render_whitespace = mode),
+        None => {
+            let modes = WhitespaceMode::ALL
+                .iter()
+                .map(|mode| mode.name().to_string())
+                .collect();
+            prompt("Whitespace to render", "editor.renderWhitespace", modes);
+        }
+    }
+}
+
+// A list such as "80, 100"; an empty one removes the rulers
+fn rulers(argument: Option<&str>) {
+    match argument {
+        Some(columns) => {
+            let mut rulers: Vec<usize> = columns
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|column| column.parse().ok())
+                .collect();
+            rulers.sort_unstable();
+            rulers.dedup();
+            update_settings(|settings| settings.rulers = rulers);
+        }
+        None => prompt(
+            "Columns of the rulers, such as 80, 100",
+            "editor.rulers",
+            Vec::new(),
+        ),
+    }
+}
+
+fn fold_to_level(_: Option<&str>) {
+    let levels = (1..=7).map(|level| level.to_string()).collect();
+    prompt(
+        "Fold regions at this nesting level and deeper",
+        "editor.foldLevel",
+        levels,
+    );
+}
+
+fn play_macro_times(_: Option<&str>) {
+    prompt(
+        "Number of times to play the last macro",
+        "macro.play",
+        Vec::new(),
+    );
+}
+
+fn save_macro(argument: Option<&str>) {
+    let last = MACROS.read().last().map(|steps| steps.to_vec());
+    match (argument, last) {
+        (Some(name), Some(steps)) => update_settings(|settings| {
+            settings.macros.insert(name.to_string(), steps);
+        }),
+        (None, Some(_)) => prompt("Name for the last recorded macro", "macro.save", Vec::new()),
+        (_, None) => log::write("Macros", "No macro has been recorded"),
+    }
+}
+
+fn saved_macro_names() -> Vec<String> {
+    SETTINGS.read().macros.keys().cloned().collect()
+}
+
+fn run_saved_macro(_: Option<&str>) {
+    prompt("Saved macro to run", "macro.run", saved_macro_names());
+}
+
+fn delete_saved_macro(argument: Option<&str>) {
+    match argument {
+        Some(name) => update_settings(|settings| {
+            settings.macros.remove(name);
+        }),
+        None => prompt(
+            "Saved macro to delete",
+            "macro.deleteSaved",
+            saved_macro_names(),
+        ),
+    }
+}
+
+fn open_bookmark(argument: Option<&str>) {
+    if let Some(bookmark) = argument.and_then(navigation::bookmark_at) {
+        navigation::jump(None, bookmark);
+    }
+}
+
+/// Looks up a command; an argument after a colon ("macro.play:3") is ignored.
+pub fn find(id: &str) -> Option<&'static Command> {
+    let id = id.split_once(':').map_or(id, |(id, _)| id);
+    COMMANDS.iter().find(|command| command.id == id)
+}
+
+pub fn menu_commands(menu: Menu) -> impl Iterator<Item = &'static Command> {
+    COMMANDS
+        .iter()
+        .filter(move |command| command.menu == Some(menu))
+}
+
+static WORKBENCH_QUEUE: GlobalSignal<Vec<String>> = Signal::global(Vec::new);
+static EDITOR_QUEUE: GlobalSignal<Vec<String>> = Signal::global(Vec::new);
+static TERMINAL_QUEUE: GlobalSignal<Vec<String>> = Signal::global(Vec::new);
+
+/// Component id of the editor that last had focus; it receives editor commands.
+pub static ACTIVE_EDITOR: GlobalSignal<Option<u64>> = Signal::global(|| None);
+
+// Most recent first, shown at the top of the command palette
+pub static RECENT_COMMANDS: GlobalSignal<Vec<String>> = Signal::global(Vec::new);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaletteMode {
+    Commands,
+    /// Asks for a value and runs `command:value`, offering `suggestions`
+    Input {
+        prompt: String,
+        command: &'static str,
+        suggestions: Vec<String>,
+    },
+}
+
+pub static PALETTE: GlobalSignal<Option<PaletteMode>> = Signal::global(|| None);
+
+/// Opens the palette to ask for the argument of `command`.
+pub fn prompt(prompt: &str, command: &'static str, suggestions: Vec<String>) {
+    *PALETTE.write() = Some(PaletteMode::Input {
+        prompt: prompt.to_string(),
+        command,
+        suggestions,
+    });
+}
+
+const MAX_RECENT_COMMANDS: usize = 8;
+
+fn queue(target: Target) -> &'static GlobalSignal<Vec<String>> {
+    match target {
+        Target::Workbench => &WORKBENCH_QUEUE,
+        Target::Editor => &EDITOR_QUEUE,
+        Target::Terminal => &TERMINAL_QUEUE,
+    }
+}
+
+/// Runs a command, or queues it for the component that handles it.
+pub fn execute(full_command: &str) {
+    match find(full_command).map(|command| command.handler) {
+        Some(Handler::Run(run)) => run(full_command.split_once(':').map(|(_, argument)| argument)),
+        Some(Handler::Send(target)) => queue(target).write().push(full_command.to_string()),
+        None => log::write("Commands", format!("Unknown command: {full_command}")),
+    }
+}
+
+/// Drains the commands queued for `target`. Call from an effect to run them;
+/// reading the queue subscribes the effect to new commands.
+pub fn take_queued(target: Target) -> Vec<String> {
+    let queue = queue(target);
+    if queue.read().is_empty() {
+        return Vec::new();
+    }
+    std::mem::take(&mut *queue.write())
+}
+
+pub fn record_recent(id: &str) {
+    let mut recent = RECENT_COMMANDS.write();
+    recent.retain(|existing| existing != id);
+    recent.insert(0, id.to_string());
+    recent.truncate(MAX_RECENT_COMMANDS);
+}
+
+/// Scores `text` against a fuzzy `query`: every query character must appear in order.
+/// Consecutive matches and matches at word starts score higher.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let query: Vec<char> = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut query_idx = 0;
+    let mut previous_match: Option<usize> = None;
+    for (i, c) in text.iter().enumerate() {
+        if query_idx == query.len() {
+            break;
+        }
+        if c.to_lowercase().next() != Some(query[query_idx]) {
+            continue;
+        }
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == i) {
+            score += 5;
+        }
+        let word_start = i == 0
+            || !text[i - 1].is_alphanumeric()
+            || (c.is_uppercase() && text[i - 1].is_lowercase());
+        if word_start {
+            score += 8;
+        }
+        previous_match = Some(i);
+        query_idx += 1;
+    }
+
+    if query_idx < query.len() {
+        return None;
+    }
+    // Prefer shorter titles when the matches are otherwise equal
+    Some(score * 10 - text.len() as i32 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_scores_prefer_word_starts_and_short_titles() {
+        assert!(fuzzy_score("tt", "View: Toggle Terminal").is_some());
+        assert!(fuzzy_score("xyz", "View: Toggle Terminal").is_none());
+        assert_eq!(fuzzy_score(" ", "anything"), Some(0));
+        let terminal = fuzzy_score("togterm", "View: Toggle Terminal").unwrap();
+        let keybindings = fuzzy_score("togterm", "Preferences: Open Keyboard Shortcuts File");
+        assert!(keybindings.is_none_or(|score| terminal > score));
+        assert!(fuzzy_score("save", "File: Save") > fuzzy_score("save", "File: Save As..."));
+    }
+
+    #[test]
+    fn finds_commands_with_arguments() {
+        assert_eq!(
+            find("macro.play:3").map(|command| command.id),
+            Some("macro.play")
+        );
+        assert!(find("no.such.command").is_none());
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        for (index, command) in COMMANDS.iter().enumerate() {
+            assert!(
+                COMMANDS[index + 1..]
+                    .iter()
+                    .all(|other| other.id != command.id),
+                "{} is registered twice",
+                command.id
+            );
+        }
+    }
+
+    #[test]
+    fn menus_list_their_commands_in_order() {
+        let edit: Vec<_> = menu_commands(Menu::Edit)
+            .map(|command| command.id)
+            .collect();
+        assert_eq!(&edit[..2], ["editor.undo", "editor.redo"]);
+        for menu in Menu::ALL {
+            let groups: Vec<u8> = menu_commands(menu).map(|command| command.group).collect();
+            assert!(groups.windows(2).all(|pair| pair[0] <= pair[1]), "{menu:?}");
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::editor::navigation;
+use crate::editor::workspace_edit::{self, PREVIEW};
+use crate::theme::use_theme;
+use dioxus::prelude::*;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+// An edit across files waiting to be applied, such as a rename, listed by
+// file with each change's line: the text it replaces struck out and the new
+// text after it. Picking a change shows it in its file.
+
+#[component]
+pub fn EditPreviewPanel(workspace_path: Signal<String>) -> Element {
+    let mut collapsed = use_signal(HashSet::<PathBuf>::new);
+    let mut selected = use_signal(|| None::<usize>);
+    let colors = use_theme().colors();
+
+    // A new preview starts with every file open and nothing picked
+    use_effect(move || {
+        let _ = PREVIEW.read();
+        collapsed.write().clear();
+        selected.set(None);
+    });
+
+    let Some(preview) = PREVIEW.read().clone() else {
+        return rsx! {};
+    };
+    let root = PathBuf::from(workspace_path());
+    let summary = match (preview.edit.edit_count(), preview.edit.files.len()) {
+        (1, _) => "1 change".to_string(),
+        (changes, 1) => format!("{changes} changes in 1 file"),
+        (changes, files) => format!("{changes} changes in {files} files"),
+    };
+
+    // Changes by file, keeping their order
+    let mut groups: Vec<(PathBuf, Vec<usize>)> = Vec::new();
+    for (index, row) in preview.rows.iter().enumerate() {
+        match groups.last_mut() {
+            Some((path, indices)) if *path == row.location.path => indices.push(index),
+            _ => groups.push((row.location.path.clone(), vec![index])),
+        }
+    }
+
+    rsx! {
+        div {
+            style: "height: 220px; flex-shrink: 0; display: flex; flex-direction: column; border-top: 1px solid {colors.border_primary}; background-color: {colors.bg_tertiary}; color: {colors.text_primary}; font-size: 0.85rem; user-select: none;",
+            div {
+                style: "display: flex; align-items: center; gap: 10px; padding: 6px 15px; border-bottom: 1px solid {colors.border_primary};",
+                span { style: "font-size: 0.75rem; font-weight: 600; text-transform: uppercase; letter-spacing: 0.5px; color: {colors.text_muted};", "{preview.title}" }
+                span { style: "flex: 1; color: {colors.text_muted}; font-size: 0.8rem;", "{summary}" }
+                button {
+                    style: "padding: 2px 12px; border: none; border-radius: 2px; background-color: {colors.accent}; color: {colors.bg_primary}; cursor: pointer; font-size: 0.8rem;",
+                    onclick: move |_| workspace_edit::apply_preview(),
+                    "Apply"
+                }
+                button {
+                    style: "padding: 2px 12px; border: 1px solid {colors.border_primary}; border-radius: 2px; background: none; color: {colors.text_primary}; cursor: pointer; font-size: 0.8rem;",
+                    onclick: move |_| workspace_edit::discard_preview(),
+                    "Discard"
+                }
+            }
+            div {
+                style: "flex: 1; overflow-y: auto; padding: 4px 5px;",
+                for (path, indices) in groups {
+                    {
+                        let is_collapsed = collapsed.read().contains(&path);
+                        let arrow = if is_collapsed { "▸" } else { "▾" };
+                        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        let folder = path
+                            .parent()
+                            .map(|parent| parent.strip_prefix(&root).unwrap_or(parent).to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let count = indices.len();
+                        let toggled = path.clone();
+                        rsx! {
+                            div {
+                                key: "{path.display()}",
+                                div {
+                                    style: "display: flex; align-items: center; gap: 6px; padding: 3px 8px; cursor: pointer; white-space: nowrap; overflow: hidden;",
+                                    onclick: move |_| {
+                                        let mut collapsed = collapsed.write();
+                                        if !collapsed.remove(&toggled) {
+                                            collapsed.insert(toggled.clone());
+                                        }
+                                    },
+                                    span { style: "width: 14px; flex-shrink: 0;", "{arrow}" }
+                                    span { "{name}" }
+                                    span { style: "color: {colors.text_muted}; font-size: 0.8rem; overflow: hidden; text-overflow: ellipsis;", "{folder}" }
+                                    span { style: "margin-left: auto; color: {colors.text_muted}; font-size: 0.75rem;", "{count}" }
+                                }
+                                if !is_collapsed {
+                                    for index in indices {
+                                        {
+                                            let row = preview.rows[index].clone();
+                                            let origin = preview.origin.clone();
+                                            let background = if selected() == Some(index) { colors.bg_accent } else { "transparent" };
+                                            rsx! {
+                                                div {
+                                                    key: "{index}",
+                                                    style: "display: flex; gap: 8px; padding: 2px 8px 2px 28px; cursor: pointer; white-space: pre; overflow: hidden; background-color: {background}; border-radius: 3px;",
+                                                    onclick: move |_| {
+                                                        selected.set(Some(index));
+                                                        navigation::jump(Some(origin.clone()), row.location.clone());
+                                                    },
+                                                    span { style: "color: {colors.text_muted}; min-width: 32px; text-align: right; flex-shrink: 0;", "{row.location.line + 1}" }
+                                                    span {
+                                                        style: "overflow: hidden; text-overflow: ellipsis;",
+                                                        "{row.before}"
+                                                        span { style: "color: {colors.error}; text-decoration: line-through;", "{row.removed}" }
+                                                        span { style: "color: {colors.accent}; font-weight: 600;", "{row.inserted}" }
+                                                        "{row.after}"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::editor::language;
 use crate::layout::OpenFile;
 use crate::theme::use_theme;
 use dioxus::prelude::*;
@@ -133,18 +134,9 @@ fn FileTreeNode(
                 .extension()
                 .and_then(|e| e.to_str())
                 .unwrap_or("");
-            match (icon_theme, ext) {
-                (_, "rs") => "🦀",
-                (_, "js") => "🟨",
-                (_, "ts") => "🟦",
-                (_, "json") => "🧾",
-                (_, "md") => "📝",
-                (_, "toml") => "⚙️",
-                (_, "yaml") | (_, "yml") => "📜",
-                (_, "html") => "🌐",
-                (_, "css") => "🎨",
-                (_, "png") | (_, "jpg") | (_, "jpeg") | (_, "gif") | (_, "webp") => "🖼",
-                _ => "📄",
+            match ext {
+                "png" | "jpg" | "jpeg" | "gif" | "webp" => "🖼",
+                _ => language::for_path(&node.path).map_or("📄", |language| language.icon),
             }
         }
     };
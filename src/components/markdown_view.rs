@@ -0,0 +1,86 @@
+use crate::editor::markdown::{self, Block, Inline};
+use crate::theme::{use_theme, ThemeColors};
+use dioxus::prelude::*;
+
+// Markdown from a language server, as in hover tooltips. Code is set in
+// `code_font`, the editor's font, and the rest in the interface's.
+
+#[component]
+pub fn MarkdownView(text: String, code_font: String) -> Element {
+    let colors = use_theme().colors();
+    let blocks = markdown::parse(&text);
+    rsx! {
+        div {
+            style: "display: flex; flex-direction: column; gap: 6px; color: {colors.text_secondary}; font-family: system-ui, sans-serif; font-size: 0.85rem; line-height: 1.4;",
+            for (index, block) in blocks.into_iter().enumerate() {
+                match block {
+                    Block::Heading(level, inlines) => {
+                        let size = match level {
+                            1 => 1.2,
+                            2 => 1.1,
+                            _ => 1.0,
+                        };
+                        rsx! {
+                            div {
+                                key: "{index}",
+                                style: "font-weight: 600; font-size: {size}em; color: {colors.text_primary};",
+                                {render_inlines(inlines, &code_font, &colors)}
+                            }
+                        }
+                    }
+                    Block::Paragraph(inlines) => rsx! {
+                        div { key: "{index}", {render_inlines(inlines, &code_font, &colors)} }
+                    },
+                    Block::List(items) => rsx! {
+                        div {
+                            key: "{index}",
+                            style: "display: flex; flex-direction: column; gap: 2px;",
+                            for (item, (marker, inlines)) in items.into_iter().enumerate() {
+                                div {
+                                    key: "{item}",
+                                    style: "display: flex; gap: 6px;",
+                                    span { style: "color: {colors.text_muted}; flex-shrink: 0;", "{marker}" }
+                                    span { {render_inlines(inlines, &code_font, &colors)} }
+                                }
+                            }
+                        }
+                    },
+                    Block::Code { text, .. } => rsx! {
+                        div {
+                            key: "{index}",
+                            style: "white-space: pre; overflow-x: auto; color: {colors.text_primary}; {code_font}",
+                            "{text}"
+                        }
+                    },
+                    Block::Rule => rsx! {
+                        div { key: "{index}", style: "height: 1px; background-color: {colors.border_primary};" }
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn render_inlines(inlines: Vec<Inline>, code_font: &str, colors: &ThemeColors) -> Element {
+    rsx! {
+        for (index, inline) in inlines.into_iter().enumerate() {
+            match inline {
+                Inline::Text(text) => rsx! { span { key: "{index}", "{text}" } },
+                Inline::Code(code) => rsx! {
+                    code {
+                        key: "{index}",
+                        style: "padding: 0 3px; border-radius: 3px; background-color: {colors.bg_tertiary}; color: {colors.text_primary}; {code_font}",
+                        "{code}"
+                    }
+                },
+                Inline::Strong(text) => rsx! {
+                    strong { key: "{index}", style: "color: {colors.text_primary};", "{text}" }
+                },
+                Inline::Emphasis(text) => rsx! { em { key: "{index}", "{text}" } },
+                Inline::Link { text, url } => rsx! {
+                    span { key: "{index}", title: "{url}", style: "color: {colors.accent};", "{text}" }
+                },
+            }
+        }
+    }
+}
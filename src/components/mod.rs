@@ -1,3 +1,7 @@
+pub mod edit_preview_panel;
 pub mod file_explorer;
 pub mod file_tree;
 pub mod git_panel;
+pub mod markdown_view;
+pub mod outline_panel;
+pub mod references_panel;
@@ -0,0 +1,152 @@
+use crate::editor::navigation::{self, Location};
+use crate::editor::outline::{SymbolKind, OUTLINE};
+use crate::theme::{use_theme, ThemeColors};
+use dioxus::prelude::*;
+use std::collections::HashSet;
+
+// The symbols of the focused file as a tree, following its cursor. Clicking an
+// entry jumps there through the navigation history.
+
+#[component]
+pub fn OutlinePanel() -> Element {
+    // Collapsed entries by depth and name, so they stay collapsed across edits
+    let mut collapsed = use_signal(HashSet::<(usize, String)>::new);
+    let colors = use_theme().colors();
+
+    // Keeps the entry around the cursor in view
+    use_effect(move || {
+        if OUTLINE
+            .read()
+            .as_ref()
+            .is_some_and(|outline| outline.current.is_some())
+        {
+            document::eval(
+                "document.getElementById('outline_current')?.scrollIntoView({ block: 'nearest' })",
+            );
+        }
+    });
+
+    let outline = OUTLINE.read().clone();
+    let title = outline
+        .as_ref()
+        .and_then(|outline| outline.cursor.path.file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // Entries left once collapsed ones hide their children
+    let rows: Vec<(usize, bool)> = match &outline {
+        Some(outline) => {
+            let symbols = &outline.symbols;
+            let collapsed = collapsed.read();
+            let mut hidden_below = None;
+            let mut rows = Vec::new();
+            for (index, symbol) in symbols.iter().enumerate() {
+                if hidden_below.is_some_and(|depth| symbol.depth > depth) {
+                    continue;
+                }
+                hidden_below = None;
+                let has_children = symbols
+                    .get(index + 1)
+                    .is_some_and(|next| next.depth > symbol.depth);
+                if has_children && collapsed.contains(&(symbol.depth, symbol.name.clone())) {
+                    hidden_below = Some(symbol.depth);
+                }
+                rows.push((index, has_children));
+            }
+            rows
+        }
+        None => Vec::new(),
+    };
+
+    rsx! {
+        div {
+            style: "display: flex; flex-direction: column; height: 100%; min-height: 0; color: {colors.text_primary}; font-size: 0.85rem; user-select: none;",
+            div {
+                style: "padding: 10px 15px; font-size: 0.75rem; font-weight: 600; color: {colors.text_muted}; text-transform: uppercase; letter-spacing: 0.5px; white-space: nowrap; overflow: hidden; text-overflow: ellipsis;",
+                if title.is_empty() { "Outline" } else { "Outline: {title}" }
+            }
+            div {
+                style: "flex: 1; overflow-y: auto; overflow-x: hidden; padding: 0 5px 10px 5px;",
+                match &outline {
+                    None => rsx! {
+                        div { style: "padding: 4px 10px; color: {colors.text_muted};", "No editor is active." }
+                    },
+                    Some(outline) if outline.symbols.is_empty() => rsx! {
+                        div { style: "padding: 4px 10px; color: {colors.text_muted};", "No symbols found in this file." }
+                    },
+                    Some(outline) => rsx! {
+                        for (index, has_children) in rows {
+                            {
+                                let symbol = outline.symbols[index].clone();
+                                let is_current = outline.current == Some(index);
+                                let key = (symbol.depth, symbol.name.clone());
+                                let is_collapsed = collapsed.read().contains(&key);
+                                let from = outline.cursor.clone();
+                                let target = Location {
+                                    path: from.path.clone(),
+                                    line: symbol.line,
+                                    column: symbol.column,
+                                };
+                                let indent = 4 + symbol.depth * 12;
+                                let background = if is_current { colors.bg_accent } else { "transparent" };
+                                let arrow = match (has_children, is_collapsed) {
+                                    (false, _) => "",
+                                    (true, true) => "▸",
+                                    (true, false) => "▾",
+                                };
+                                let icon_color = kind_color(symbol.kind, &colors);
+                                rsx! {
+                                    div {
+                                        key: "{index}",
+                                        id: if is_current { "outline_current" },
+                                        title: "{symbol.kind.label()}, line {symbol.line + 1}",
+                                        style: "padding: 3px 8px 3px {indent}px; cursor: pointer; display: flex; align-items: center; gap: 4px; background-color: {background}; border-radius: 3px; white-space: nowrap; overflow: hidden;",
+                                        onclick: move |_| navigation::jump(Some(from.clone()), target.clone()),
+                                        span {
+                                            style: "width: 14px; font-size: 0.8rem; display: inline-flex; justify-content: center; flex-shrink: 0;",
+                                            onclick: move |evt| {
+                                                if has_children {
+                                                    evt.stop_propagation();
+                                                    let mut collapsed = collapsed.write();
+                                                    if !collapsed.remove(&key) {
+                                                        collapsed.insert(key.clone());
+                                                    }
+                                                }
+                                            },
+                                            "{arrow}"
+                                        }
+                                        span {
+                                            style: "width: 14px; text-align: center; flex-shrink: 0; color: {icon_color};",
+                                            "{symbol.kind.icon()}"
+                                        }
+                                        span {
+                                            style: "flex: 1; overflow: hidden; text-overflow: ellipsis;",
+                                            "{symbol.name}"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn kind_color(kind: SymbolKind, colors: &ThemeColors) -> &'static str {
+    match kind {
+        SymbolKind::Function | SymbolKind::Method => colors.syntax_function,
+        SymbolKind::Struct | SymbolKind::Enum | SymbolKind::Class | SymbolKind::Type => {
+            colors.syntax_type
+        }
+        SymbolKind::Trait | SymbolKind::Interface | SymbolKind::Impl | SymbolKind::Module => {
+            colors.syntax_keyword
+        }
+        SymbolKind::Constant => colors.syntax_constant,
+        SymbolKind::Variable => colors.syntax_parameter,
+        SymbolKind::Macro => colors.syntax_macro,
+        SymbolKind::Heading => colors.accent,
+        SymbolKind::Field | SymbolKind::Key => colors.syntax_property,
+    }
+}
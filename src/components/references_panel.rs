@@ -0,0 +1,133 @@
+use crate::editor::navigation;
+use crate::editor::references::{self, REFERENCES};
+use crate::theme::use_theme;
+use dioxus::prelude::*;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+// The results of find all references, or of a definition search with more
+// than one answer, grouped by file. Picking one jumps there; going back from
+// any of them returns to where the search started.
+
+#[component]
+pub fn ReferencesPanel(workspace_path: Signal<String>) -> Element {
+    let mut collapsed = use_signal(HashSet::<PathBuf>::new);
+    let mut selected = use_signal(|| None::<usize>);
+    let colors = use_theme().colors();
+
+    // A new search starts with every file open and nothing picked
+    use_effect(move || {
+        let _ = REFERENCES.read();
+        collapsed.write().clear();
+        selected.set(None);
+    });
+
+    let Some(found) = REFERENCES.read().clone() else {
+        return rsx! {};
+    };
+    let root = PathBuf::from(workspace_path());
+    let files = found
+        .results
+        .iter()
+        .map(|result| &result.location.path)
+        .collect::<HashSet<_>>()
+        .len();
+    let summary = match (found.results.len(), files) {
+        (0, _) => "No results".to_string(),
+        (1, _) => "1 result".to_string(),
+        (results, 1) => format!("{results} results in 1 file"),
+        (results, files) => format!("{results} results in {files} files"),
+    };
+
+    // Results by file, keeping their order
+    let mut groups: Vec<(PathBuf, Vec<usize>)> = Vec::new();
+    for (index, result) in found.results.iter().enumerate() {
+        match groups.last_mut() {
+            Some((path, indices)) if *path == result.location.path => indices.push(index),
+            _ => groups.push((result.location.path.clone(), vec![index])),
+        }
+    }
+
+    rsx! {
+        div {
+            style: "height: 220px; flex-shrink: 0; display: flex; flex-direction: column; border-top: 1px solid {colors.border_primary}; background-color: {colors.bg_tertiary}; color: {colors.text_primary}; font-size: 0.85rem; user-select: none;",
+            div {
+                style: "display: flex; align-items: center; gap: 10px; padding: 6px 15px; border-bottom: 1px solid {colors.border_primary};",
+                span { style: "font-size: 0.75rem; font-weight: 600; text-transform: uppercase; letter-spacing: 0.5px; color: {colors.text_muted};", "{found.title}" }
+                span { style: "flex: 1; color: {colors.text_muted}; font-size: 0.8rem;", "{summary}" }
+                button {
+                    style: "background: none; border: none; color: {colors.text_muted}; cursor: pointer; font-size: 0.9rem;",
+                    title: "Close",
+                    onclick: move |_| references::close(),
+                    "✕"
+                }
+            }
+            div {
+                style: "flex: 1; overflow-y: auto; padding: 4px 5px;",
+                for (path, indices) in groups {
+                    {
+                        let is_collapsed = collapsed.read().contains(&path);
+                        let arrow = if is_collapsed { "▸" } else { "▾" };
+                        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                        let folder = path
+                            .parent()
+                            .map(|parent| parent.strip_prefix(&root).unwrap_or(parent).to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let count = indices.len();
+                        let toggled = path.clone();
+                        rsx! {
+                            div {
+                                key: "{path.display()}",
+                                div {
+                                    style: "display: flex; align-items: center; gap: 6px; padding: 3px 8px; cursor: pointer; white-space: nowrap; overflow: hidden;",
+                                    onclick: move |_| {
+                                        let mut collapsed = collapsed.write();
+                                        if !collapsed.remove(&toggled) {
+                                            collapsed.insert(toggled.clone());
+                                        }
+                                    },
+                                    span { style: "width: 14px; flex-shrink: 0;", "{arrow}" }
+                                    span { "{name}" }
+                                    span { style: "color: {colors.text_muted}; font-size: 0.8rem; overflow: hidden; text-overflow: ellipsis;", "{folder}" }
+                                    span { style: "margin-left: auto; color: {colors.text_muted}; font-size: 0.75rem;", "{count}" }
+                                }
+                                if !is_collapsed {
+                                    for index in indices {
+                                        {
+                                            let result = found.results[index].clone();
+                                            let origin = found.origin.clone();
+                                            let chars: Vec<char> = result.preview.chars().collect();
+                                            let start = result.preview_column.min(chars.len());
+                                            let end = (start + result.length).min(chars.len());
+                                            let before: String = chars[..start].iter().collect();
+                                            let matched: String = chars[start..end].iter().collect();
+                                            let after: String = chars[end..].iter().collect();
+                                            let background = if selected() == Some(index) { colors.bg_accent } else { "transparent" };
+                                            rsx! {
+                                                div {
+                                                    key: "{index}",
+                                                    style: "display: flex; gap: 8px; padding: 2px 8px 2px 28px; cursor: pointer; white-space: pre; overflow: hidden; background-color: {background}; border-radius: 3px;",
+                                                    onclick: move |_| {
+                                                        selected.set(Some(index));
+                                                        navigation::jump(Some(origin.clone()), result.location.clone());
+                                                    },
+                                                    span { style: "color: {colors.text_muted}; min-width: 32px; text-align: right; flex-shrink: 0;", "{result.location.line + 1}" }
+                                                    span {
+                                                        style: "overflow: hidden; text-overflow: ellipsis;",
+                                                        "{before}"
+                                                        span { style: "color: {colors.accent}; font-weight: 600;", "{matched}" }
+                                                        "{after}"
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
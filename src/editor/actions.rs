@@ -0,0 +1,273 @@
+use super::block;
+use super::expand;
+use super::rope_engine::RopeEditor;
+
+// Buffer-level editing commands shared by the view and macro playback. Commands
+// that need the view (scrolling, saving, mode toggles) are not handled here.
+
+pub fn is_edit_command(command: &str) -> bool {
+    matches!(
+        command,
+        "cursor.up"
+            | "cursor.down"
+            | "cursor.left"
+            | "cursor.right"
+            | "cursor.lineStart"
+            | "cursor.lineEnd"
+            | "editor.deleteLeft"
+            | "editor.deleteRight"
+            | "editor.newline"
+            | "editor.tab"
+            | "editor.toggleLineComment"
+            | "editor.cut"
+            | "editor.copy"
+            | "editor.paste"
+            | "selection.selectAll"
+            | "selection.clear"
+            | "selection.expand"
+            | "selection.shrink"
+            | "selection.columnUp"
+            | "selection.columnDown"
+            | "selection.columnLeft"
+            | "selection.columnRight"
+    )
+}
+
+/// Types `text` at the cursor, or on every line of a column selection.
+pub fn type_text(editor: &mut RopeEditor, text: &str) {
+    if editor.block_selection().is_some() {
+        block::insert(editor, text);
+    } else {
+        editor.insert_text(text);
+    }
+}
+
+/// Runs `command` on the buffer. Returns false if it isn't an edit command.
+pub fn run_edit_command(editor: &mut RopeEditor, command: &str) -> bool {
+    if block::run_block_command(editor, command) {
+        return true;
+    }
+    // Anything else ends a column selection
+    if is_edit_command(command) {
+        editor.set_block_selection(None);
+    }
+    match command {
+        "cursor.up" => editor.move_cursor_up(),
+        "cursor.down" => editor.move_cursor_down(),
+        "cursor.left" => editor.move_cursor_left(),
+        "cursor.right" => editor.move_cursor_right(),
+        "cursor.lineStart" => editor.move_cursor_to_line_start(),
+        "cursor.lineEnd" => editor.move_cursor_to_line_end(),
+
+        "editor.deleteLeft" => editor.backspace(),
+        "editor.deleteRight" => editor.delete(),
+        "editor.newline" => newline(editor),
+        "editor.tab" => {
+            let unit = editor.language().indent_unit;
+            editor.insert_text(unit);
+        }
+        "editor.toggleLineComment" => toggle_line_comment(editor),
+
+        // Cut/Copy/Paste act on the selection, or the whole line without one
+        "editor.cut" => {
+            if let Some((start, end)) = editor.selection_range() {
+                let text = editor.slice_text(start, end);
+                editor.set_clipboard(text);
+                editor.delete_range(start, end);
+                editor.set_selection_anchor(None);
+            } else {
+                editor.copy_line();
+                let line = editor.get_cursor().line;
+                let start = editor.line_to_char(line);
+                let end = (start + editor.line_len(line) + 1).min(editor.total_chars());
+                editor.delete_range(start, end);
+            }
+        }
+        "editor.copy" => match editor.selected_text() {
+            Some(text) => editor.set_clipboard(text),
+            None => editor.copy_line(),
+        },
+        "editor.paste" => {
+            if let Some((start, end)) = editor.selection_range() {
+                editor.delete_range(start, end);
+                editor.set_selection_anchor(None);
+            }
+            editor.paste();
+        }
+
+        "selection.selectAll" => {
+            let end = editor.total_chars();
+            editor.set_selection_anchor(Some(0));
+            editor.set_cursor_offset(end);
+        }
+        "selection.clear" => editor.set_selection_anchor(None),
+        "selection.expand" => expand::expand(editor),
+        "selection.shrink" => expand::shrink(editor),
+
+        _ => return false,
+    }
+    true
+}
+
+/// Breaks the line at the cursor, keeping its indentation, one level deeper after
+/// an opening bracket or another of the language's openers. Between a bracket
+/// pair the closing bracket moves to a line of its own.
+pub fn newline(editor: &mut RopeEditor) {
+    let language = editor.language();
+    let (line, offset) = {
+        let cursor = editor.get_cursor();
+        (cursor.line, cursor.byte_offset)
+    };
+    let before = editor.slice_text(editor.line_to_char(line), offset);
+    let indent: String = before
+        .chars()
+        .take_while(|&c| c == ' ' || c == '\t')
+        .collect();
+    let before = before.trim_end();
+    let closing = before
+        .chars()
+        .next_back()
+        .and_then(|c| language.closing_bracket(c));
+    let deeper = closing.is_some()
+        || language
+            .indent_after
+            .iter()
+            .any(|end| before.ends_with(end));
+    if !deeper {
+        editor.insert_text(&format!("\n{indent}"));
+        return;
+    }
+
+    let inner = format!("\n{indent}{}", language.indent_unit);
+    if closing.is_some() && editor.char_at(offset) == closing {
+        editor.insert_text(&format!("{inner}\n{indent}"));
+        editor.set_cursor_offset(offset + inner.chars().count());
+    } else {
+        editor.insert_text(&inner);
+    }
+}
+
+/// Comments out the selected lines, or the cursor's line, with the language's
+/// line comment, or uncomments them when they all are. Languages with only block
+/// comments get the lines wrapped in one instead.
+pub fn toggle_line_comment(editor: &mut RopeEditor) {
+    let language = editor.language();
+    let lines = selected_lines(editor);
+    // Blank lines are left alone
+    let lines: Vec<(usize, String)> = lines
+        .filter_map(|line| editor.get_line(line).map(|text| (line, text)))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .collect();
+    let (Some(first), Some(last)) = (lines.first(), lines.last()) else {
+        return;
+    };
+    let indent_of = |text: &str| text.chars().take_while(|c| c.is_whitespace()).count();
+    let (cursor_line, cursor_column) = {
+        let cursor = editor.get_cursor();
+        (cursor.line, cursor.column)
+    };
+    let had_selection = editor.selection_range().is_some();
+    editor.set_selection_anchor(None);
+
+    // Columns added (or taken, when negative) at a column of the cursor's line
+    let mut shift: Option<(usize, isize)> = None;
+    editor.begin_undo_group();
+    match (language.line_comment, language.block_comment) {
+        (Some(token), _) => {
+            let commented = lines
+                .iter()
+                .all(|(_, text)| text.trim_start().starts_with(token));
+            let column = lines
+                .iter()
+                .map(|(_, text)| indent_of(text))
+                .min()
+                .unwrap_or(0);
+            for (line, text) in &lines {
+                let start = editor.line_to_char(*line);
+                if commented {
+                    let indent = indent_of(text);
+                    let after = text.chars().nth(indent + token.chars().count());
+                    let len = token.chars().count() + usize::from(after == Some(' '));
+                    editor.delete_range(start + indent, start + indent + len);
+                    if *line == cursor_line {
+                        shift = Some((indent, -(len as isize)));
+                    }
+                } else {
+                    editor.set_cursor_offset(start + column);
+                    editor.insert_text(&format!("{token} "));
+                    if *line == cursor_line {
+                        shift = Some((column, token.chars().count() as isize + 1));
+                    }
+                }
+            }
+        }
+        (None, Some((open, close))) => {
+            let (first_line, first_text) = first;
+            let (last_line, last_text) = last;
+            let commented =
+                first_text.trim_start().starts_with(open) && last_text.trim_end().ends_with(close);
+            let indent = indent_of(first_text);
+            // The end first, so the start of the first line stays put
+            let end = editor.line_to_char(*last_line) + last_text.trim_end().chars().count();
+            if commented {
+                let before_close = last_text.trim_end().strip_suffix(close).unwrap_or_default();
+                let len = close.chars().count() + usize::from(before_close.ends_with(' '));
+                editor.delete_range(end - len, end);
+                let start = editor.line_to_char(*first_line) + indent;
+                let after = first_text
+                    .trim_start()
+                    .strip_prefix(open)
+                    .unwrap_or_default();
+                let len = open.chars().count() + usize::from(after.starts_with(' '));
+                editor.delete_range(start, start + len);
+                if *first_line == cursor_line {
+                    shift = Some((indent, -(len as isize)));
+                }
+            } else {
+                editor.set_cursor_offset(end);
+                editor.insert_text(&format!(" {close}"));
+                editor.set_cursor_offset(editor.line_to_char(*first_line) + indent);
+                editor.insert_text(&format!("{open} "));
+                if *first_line == cursor_line {
+                    shift = Some((indent, open.chars().count() as isize + 1));
+                }
+            }
+        }
+        (None, None) => {}
+    }
+    editor.end_undo_group();
+
+    if had_selection {
+        // The whole lines stay selected
+        let start = editor.line_to_char(first.0);
+        editor.set_selection_anchor(Some(start));
+        editor.set_cursor(last.0, editor.line_len(last.0));
+    } else {
+        let column = match shift {
+            Some((at, by)) if cursor_column >= at => {
+                cursor_column.saturating_add_signed(by).max(at)
+            }
+            _ => cursor_column,
+        };
+        editor.set_cursor(cursor_line, column);
+    }
+}
+
+// Lines the selection touches, or the cursor's line. A selection ending at the
+// start of a line leaves that line out.
+fn selected_lines(editor: &RopeEditor) -> std::ops::RangeInclusive<usize> {
+    match editor.selection_range() {
+        Some((start, end)) => {
+            let first = editor.char_to_line(start);
+            let mut last = editor.char_to_line(end);
+            if last > first && editor.line_to_char(last) == end {
+                last -= 1;
+            }
+            first..=last
+        }
+        None => {
+            let line = editor.get_cursor().line;
+            line..=line
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use super::rope_engine::RopeEditor;
+use super::types::BlockSelection;
+use std::ops::Range;
+
+// Column (block) selection. Every line of the block is edited on its own, and
+// lines shorter than the block are padded with spaces where text goes in, so the
+// block's columns stay lined up in virtual space.
+
+/// Runs `command` on the column selection. Returns false if it doesn't apply
+/// to one, in which case the caller drops the block and runs it normally.
+pub fn run_block_command(editor: &mut RopeEditor, command: &str) -> bool {
+    match command {
+        "selection.columnUp" => extend(editor, -1, 0),
+        "selection.columnDown" => extend(editor, 1, 0),
+        "selection.columnLeft" => extend(editor, 0, -1),
+        "selection.columnRight" => extend(editor, 0, 1),
+        _ if editor.block_selection().is_none() => return false,
+        "editor.deleteLeft" => delete(editor, false),
+        "editor.deleteRight" => delete(editor, true),
+        "editor.tab" => {
+            let unit = editor.language().indent_unit;
+            insert(editor, unit);
+        }
+        "editor.cut" => {
+            if copy(editor) {
+                delete(editor, false);
+            }
+        }
+        "editor.copy" => {
+            copy(editor);
+        }
+        "editor.paste" => paste(editor),
+        "selection.clear" => editor.set_block_selection(None),
+        _ => return false,
+    }
+    true
+}
+
+/// Moves the head of the column selection, starting one at the cursor.
+pub fn extend(editor: &mut RopeEditor, lines: isize, columns: isize) {
+    let mut block = editor.block_selection().unwrap_or_else(|| {
+        let cursor = editor.get_cursor();
+        BlockSelection::at(cursor.line, cursor.column)
+    });
+    block.head.0 = block.head.0.saturating_add_signed(lines);
+    block.head.1 = block.head.1.saturating_add_signed(columns);
+    editor.set_block_selection(Some(block));
+}
+
+/// Replaces the selected columns of every line with `text`.
+pub fn insert(editor: &mut RopeEditor, text: &str) {
+    if let Some(block) = editor.block_selection() {
+        let rows = vec![text; block.lines().count()];
+        replace_rows(editor, block, &rows);
+    }
+}
+
+/// Deletes the selected columns, or with a column of cursors, the character
+/// before or after each one.
+pub fn delete(editor: &mut RopeEditor, forward: bool) {
+    let Some(mut block) = editor.block_selection() else {
+        return;
+    };
+    let columns = block.columns();
+    if columns.is_empty() {
+        let column = columns.start;
+        let deleted = if forward {
+            column..column + 1
+        } else if column > 0 {
+            column - 1..column
+        } else {
+            return;
+        };
+        editor.begin_undo_group();
+        for line in block.lines().rev() {
+            delete_columns(editor, line, deleted.clone());
+        }
+        editor.end_undo_group();
+        block.anchor.1 = deleted.start;
+        block.head.1 = deleted.start;
+        editor.set_block_selection(Some(block));
+    } else {
+        let rows = vec![""; block.lines().count()];
+        replace_rows(editor, block, &rows);
+    }
+}
+
+/// Copies the selected columns as a column clipboard. Returns false when the
+/// block has no width, leaving the clipboard alone.
+pub fn copy(editor: &mut RopeEditor) -> bool {
+    let Some(block) = editor.block_selection() else {
+        return false;
+    };
+    let columns = block.columns();
+    if columns.is_empty() {
+        return false;
+    }
+    let rows = block
+        .lines()
+        .map(|line| {
+            let (start, len) = (editor.line_to_char(line), editor.line_len(line));
+            editor.slice_text(start + columns.start.min(len), start + columns.end.min(len))
+        })
+        .collect();
+    editor.set_block_clipboard(rows);
+    true
+}
+
+/// Pastes into the column selection, or a column clipboard at the cursor.
+/// Clipboard rows go one per line when their count matches the block's lines
+/// or they were copied from a column selection; a single row goes on every line.
+pub fn paste(editor: &mut RopeEditor) {
+    let clipboard = editor.clipboard().replace("\r\n", "\n");
+    let rows: Vec<&str> = clipboard.split('\n').collect();
+    let block = match editor.block_selection() {
+        Some(block) => block,
+        None if editor.clipboard_is_block() => {
+            let cursor = editor.get_cursor();
+            BlockSelection::at(cursor.line, cursor.column)
+        }
+        None => return editor.paste(),
+    };
+    let line_count = block.lines().count();
+    if rows.len() == line_count {
+        replace_rows(editor, block, &rows);
+    } else if rows.len() == 1 {
+        replace_rows(editor, block, &vec![rows[0]; line_count]);
+    } else if editor.clipboard_is_block() {
+        // The block grows or shrinks to the clipboard's rows, adding lines at
+        // the end of the buffer when it runs out
+        let first = *block.lines().start();
+        editor.begin_undo_group();
+        let missing = (first + rows.len()).saturating_sub(editor.line_count());
+        if missing > 0 {
+            editor.set_cursor_offset(editor.total_chars());
+            editor.insert_text(&"\n".repeat(missing));
+        }
+        let columns = block.columns();
+        let block = BlockSelection {
+            anchor: (first, columns.start),
+            head: (first + rows.len() - 1, columns.end),
+        };
+        replace_rows(editor, block, &rows);
+        editor.end_undo_group();
+    } else {
+        // Text of some other shape replaces the block as a whole
+        editor.begin_undo_group();
+        let first = *block.lines().start();
+        let rows = vec![""; line_count];
+        replace_rows(editor, block, &rows);
+        editor.set_block_selection(None);
+        editor.set_cursor(first, block.columns().start);
+        editor.paste();
+        editor.end_undo_group();
+    }
+}
+
+// Replaces the block's columns on each line with the row for that line, as one
+// undo step. Afterwards the block is a column of cursors after the new text,
+// or the cursor ends up after the text on the head's line when rows differ in length.
+fn replace_rows(editor: &mut RopeEditor, block: BlockSelection, rows: &[&str]) {
+    let columns = block.columns();
+    let first = *block.lines().start();
+    editor.begin_undo_group();
+    // Bottom up, so edits don't move the lines still to do
+    let edits: Vec<(usize, &&str)> = block.lines().zip(rows).collect();
+    for (line, row) in edits.into_iter().rev() {
+        delete_columns(editor, line, columns.clone());
+        if !row.is_empty() {
+            let len = editor.line_len(line);
+            let start = editor.line_to_char(line);
+            if len < columns.start {
+                editor.set_cursor_offset(start + len);
+                editor.insert_text(&" ".repeat(columns.start - len));
+            }
+            editor.set_cursor_offset(start + columns.start);
+            editor.insert_text(row);
+        }
+    }
+    editor.end_undo_group();
+
+    let widths: Vec<usize> = rows.iter().map(|row| row.chars().count()).collect();
+    if widths.iter().all(|&width| width == widths[0]) {
+        let column = columns.start + widths[0];
+        editor.set_block_selection(Some(BlockSelection {
+            anchor: (block.anchor.0, column),
+            head: (block.head.0, column),
+        }));
+    } else {
+        let head = block.head.0;
+        editor.set_block_selection(None);
+        editor.set_cursor(head, columns.start + widths[head - first]);
+    }
+}
+
+// Deletes `columns` of `line`, as far as the line reaches
+fn delete_columns(editor: &mut RopeEditor, line: usize, columns: Range<usize>) {
+    let (start, len) = (editor.line_to_char(line), editor.line_len(line));
+    if columns.start < len {
+        editor.delete_range(start + columns.start, start + columns.end.min(len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(editor: &mut RopeEditor, anchor: (usize, usize), head: (usize, usize)) {
+        editor.set_block_selection(Some(BlockSelection { anchor, head }));
+    }
+
+    #[test]
+    fn typing_replaces_the_columns_on_every_line() {
+        let mut editor = RopeEditor::from_text("abcdef\nab\nabcdef\n");
+        select(&mut editor, (0, 1), (2, 3));
+        insert(&mut editor, "X");
+        assert_eq!(editor.get_content(), "aXdef\naX\naXdef\n");
+        let block = editor.block_selection().unwrap();
+        assert_eq!((block.lines(), block.columns()), (0..=2, 2..2));
+        assert_eq!(editor.get_cursor().line, 2);
+
+        // A column of cursors deletes one character at each
+        delete(&mut editor, false);
+        assert_eq!(editor.get_content(), "adef\na\nadef\n");
+        delete(&mut editor, true);
+        assert_eq!(editor.get_content(), "aef\na\naef\n");
+    }
+
+    #[test]
+    fn short_lines_are_padded_in_one_undo_step() {
+        let mut editor = RopeEditor::from_text("aef\na\naef\n");
+        select(&mut editor, (0, 4), (1, 4));
+        insert(&mut editor, "|");
+        assert_eq!(editor.get_content(), "aef |\na   |\naef\n");
+        editor.undo();
+        assert_eq!(editor.get_content(), "aef\na\naef\n");
+    }
+
+    #[test]
+    fn block_clipboard_pastes_a_row_per_line() {
+        let mut editor = RopeEditor::from_text("one\ntwo\nthree\nx\n");
+        select(&mut editor, (0, 0), (2, 2));
+        assert!(copy(&mut editor));
+        assert!(editor.clipboard_is_block());
+        assert_eq!(editor.clipboard(), "on\ntw\nth");
+
+        // Into a block of the same height
+        select(&mut editor, (0, 3), (2, 3));
+        paste(&mut editor);
+        assert_eq!(editor.get_content(), "oneon\ntwotw\nthrthee\nx\n");
+
+        // At a plain cursor, running on past the last line
+        editor.set_block_selection(None);
+        editor.set_cursor(3, 1);
+        paste(&mut editor);
+        assert_eq!(editor.get_content(), "oneon\ntwotw\nthrthee\nxon\n tw\n th");
+
+        // One line of text goes on every line of the block
+        editor.set_clipboard("-".to_string());
+        select(&mut editor, (0, 0), (1, 0));
+        paste(&mut editor);
+        assert!(editor.get_content().starts_with("-oneon\n-twotw\n"));
+    }
+
+    #[test]
+    fn column_commands_grow_the_block_from_the_cursor() {
+        let mut editor = RopeEditor::from_text("one\ntwo\n");
+        editor.set_cursor(0, 1);
+        assert!(run_block_command(&mut editor, "selection.columnDown"));
+        assert!(run_block_command(&mut editor, "selection.columnRight"));
+        let block = editor.block_selection().unwrap();
+        assert_eq!((block.lines(), block.columns()), (0..=1, 1..2));
+        assert!(!run_block_command(&mut editor, "cursor.up"));
+        assert!(run_block_command(&mut editor, "selection.clear"));
+        assert!(editor.block_selection().is_none());
+        assert!(!run_block_command(&mut editor, "editor.deleteLeft"));
+    }
+}
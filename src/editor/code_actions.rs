@@ -0,0 +1,141 @@
+use super::workspace_edit::WorkspaceEdit;
+use serde_json::{json, Value};
+
+// Code actions a language server offers at the cursor: quick fixes for the
+// problems there, refactorings, and source actions such as organize imports.
+// Each makes an edit, runs a command on the server, or both.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeAction {
+    pub title: String,
+    /// Such as "quickfix" or "refactor.extract"; empty when not said
+    pub kind: String,
+    /// The server's pick among the fixes for a problem
+    pub preferred: bool,
+    /// The action as sent, for resolving and running it
+    pub raw: Value,
+}
+
+impl CodeAction {
+    /// A CodeAction or a bare Command. Actions the server marks disabled are
+    /// left out.
+    pub fn from_lsp(value: &Value) -> Option<Self> {
+        if value.get("disabled").is_some() {
+            return None;
+        }
+        Some(Self {
+            title: value.get("title")?.as_str()?.to_string(),
+            kind: value
+                .get("kind")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string(),
+            preferred: value
+                .get("isPreferred")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            raw: value.clone(),
+        })
+    }
+
+    pub fn is_quick_fix(&self) -> bool {
+        self.is_kind("quickfix")
+    }
+
+    /// Whether the action is of `kind` or one under it, as "source.organizeImports"
+    /// is under "source".
+    pub fn is_kind(&self, kind: &str) -> bool {
+        self.kind == kind
+            || self
+                .kind
+                .strip_prefix(kind)
+                .is_some_and(|rest| rest.starts_with('.'))
+    }
+
+    /// Whether the server left the edit out, to be had with `codeAction/resolve`.
+    pub fn needs_resolving(&self) -> bool {
+        self.raw.get("edit").is_none() && !self.raw["command"].is_string()
+    }
+
+    pub fn edit(&self) -> Option<WorkspaceEdit> {
+        self.raw.get("edit").map(WorkspaceEdit::from_lsp)
+    }
+
+    /// The command it runs, as `workspace/executeCommand` params. A bare Command
+    /// names it in `command`; an action holds a Command there.
+    pub fn command(&self) -> Option<Value> {
+        let command = match &self.raw["command"] {
+            Value::String(_) => &self.raw,
+            Value::Object(_) => &self.raw["command"],
+            _ => return None,
+        };
+        let mut params = json!({ "command": command["command"] });
+        if let Some(arguments) = command.get("arguments") {
+            params["arguments"] = arguments.clone();
+        }
+        Some(params)
+    }
+}
+
+/// The actions in a `textDocument/codeAction` answer: preferred fixes first,
+/// then other fixes, then the rest, each in the server's order.
+pub fn from_response(response: &Value) -> Vec<CodeAction> {
+    let mut actions: Vec<CodeAction> = response
+        .as_array()
+        .map(|list| list.iter().filter_map(CodeAction::from_lsp).collect())
+        .unwrap_or_default();
+    actions.sort_by_key(|action| match (action.is_quick_fix(), action.preferred) {
+        (true, true) => 0,
+        (true, false) => 1,
+        _ => 2,
+    });
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_sort_quick_fixes_first_and_drop_disabled_ones() {
+        let actions = from_response(&json!([
+            { "title": "Extract", "kind": "refactor.extract", "edit": {} },
+            { "title": "Run", "command": "run", "arguments": [1] },
+            { "title": "Fix it", "kind": "quickfix", "isPreferred": true, "edit": { "changes": {} } },
+            { "title": "Off", "kind": "quickfix", "disabled": { "reason": "no" } },
+            { "title": "Other fix", "kind": "quickfix", "command": { "title": "x", "command": "fix" } },
+            { "title": "Lazy", "kind": "source.organizeImports", "data": 1 },
+        ]));
+        let titles: Vec<&str> = actions.iter().map(|action| action.title.as_str()).collect();
+        assert_eq!(titles, ["Fix it", "Other fix", "Extract", "Run", "Lazy"]);
+        assert!(actions[0].is_quick_fix() && actions[0].preferred);
+        assert!(actions[4].is_kind("source"));
+        assert!(!actions[2].is_kind("refactor.ex"));
+    }
+
+    #[test]
+    fn actions_carry_their_edits_and_commands() {
+        let actions = from_response(&json!([
+            { "title": "Fix it", "kind": "quickfix",
+              "edit": { "changes": { "file:///tmp/a.rs": [{
+                  "range": { "start": { "line": 0, "character": 0 },
+                             "end": { "line": 0, "character": 0 } },
+                  "newText": "x" }] } } },
+            { "title": "Other fix", "kind": "quickfix", "command": { "title": "x", "command": "fix" } },
+            { "title": "Run", "command": "run", "arguments": [1] },
+            { "title": "Lazy", "kind": "source", "data": 1 },
+        ]));
+        assert_eq!(actions[0].edit().unwrap().edit_count(), 1);
+        assert!(actions[0].command().is_none());
+        assert!(!actions[0].needs_resolving());
+        // A CodeAction's command, or a bare Command itself
+        assert_eq!(actions[1].command(), Some(json!({ "command": "fix" })));
+        assert_eq!(
+            actions[2].command(),
+            Some(json!({ "command": "run", "arguments": [1] }))
+        );
+        assert!(actions[1].needs_resolving());
+        assert!(!actions[2].needs_resolving());
+        assert!(actions[3].needs_resolving());
+    }
+}
@@ -0,0 +1,865 @@
+use super::rope_engine::RopeEditor;
+use crate::commands::fuzzy_score;
+use crate::lsp::protocol;
+use ropey::Rope;
+use serde_json::Value;
+use std::ops::Range;
+
+// Completions from a language server: the items of one request, filtered by the
+// text typed since, and how an accepted item edits the buffer. Snippet items
+// leave a session for stepping through their tab stops.
+
+/// Items shown at once; the list scrolls past them.
+pub const VISIBLE_ITEMS: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionKind {
+    Text,
+    Method,
+    Function,
+    Constructor,
+    Field,
+    Variable,
+    Class,
+    Interface,
+    Module,
+    Property,
+    Unit,
+    Value,
+    Enum,
+    Keyword,
+    Snippet,
+    Color,
+    File,
+    Reference,
+    Folder,
+    EnumMember,
+    Constant,
+    Struct,
+    Event,
+    Operator,
+    TypeParameter,
+}
+
+impl CompletionKind {
+    /// The kind for the protocol's number, 1 to 25.
+    pub fn from_lsp(kind: u64) -> Self {
+        use CompletionKind::*;
+        const KINDS: [CompletionKind; 25] = [
+            Text,
+            Method,
+            Function,
+            Constructor,
+            Field,
+            Variable,
+            Class,
+            Interface,
+            Module,
+            Property,
+            Unit,
+            Value,
+            Enum,
+            Keyword,
+            Snippet,
+            Color,
+            File,
+            Reference,
+            Folder,
+            EnumMember,
+            Constant,
+            Struct,
+            Event,
+            Operator,
+            TypeParameter,
+        ];
+        kind.checked_sub(1)
+            .and_then(|index| KINDS.get(index as usize))
+            .copied()
+            .unwrap_or(Text)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CompletionKind::Text => "text",
+            CompletionKind::Method => "method",
+            CompletionKind::Function => "function",
+            CompletionKind::Constructor => "constructor",
+            CompletionKind::Field => "field",
+            CompletionKind::Variable => "variable",
+            CompletionKind::Class => "class",
+            CompletionKind::Interface => "interface",
+            CompletionKind::Module => "module",
+            CompletionKind::Property => "property",
+            CompletionKind::Unit => "unit",
+            CompletionKind::Value => "value",
+            CompletionKind::Enum => "enum",
+            CompletionKind::Keyword => "keyword",
+            CompletionKind::Snippet => "snippet",
+            CompletionKind::Color => "color",
+            CompletionKind::File => "file",
+            CompletionKind::Reference => "reference",
+            CompletionKind::Folder => "folder",
+            CompletionKind::EnumMember => "enum member",
+            CompletionKind::Constant => "constant",
+            CompletionKind::Struct => "struct",
+            CompletionKind::Event => "event",
+            CompletionKind::Operator => "operator",
+            CompletionKind::TypeParameter => "type parameter",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            CompletionKind::Method | CompletionKind::Function | CompletionKind::Constructor => "ƒ",
+            CompletionKind::Field | CompletionKind::Property => "▪",
+            CompletionKind::Variable | CompletionKind::Reference => "χ",
+            CompletionKind::Class | CompletionKind::Struct => "◆",
+            CompletionKind::Interface => "◇",
+            CompletionKind::Module => "◫",
+            CompletionKind::Enum => "∈",
+            CompletionKind::EnumMember => "∊",
+            CompletionKind::Constant | CompletionKind::Value | CompletionKind::Unit => "π",
+            CompletionKind::Keyword => "⌘",
+            CompletionKind::Snippet => "✂",
+            CompletionKind::Color => "◐",
+            CompletionKind::File | CompletionKind::Folder => "▤",
+            CompletionKind::Event => "ϟ",
+            CompletionKind::Operator => "±",
+            CompletionKind::TypeParameter => "τ",
+            CompletionKind::Text => "≡",
+        }
+    }
+}
+
+/// A replacement of chars `range` in the text as it was when completion was requested.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+    filter_text: String,
+    sort_text: String,
+    insert_text: String,
+    is_snippet: bool,
+    /// What the item replaces, when the server says
+    range: Option<Range<usize>>,
+    additional_edits: Vec<Edit>,
+    commit_characters: Vec<char>,
+    preselect: bool,
+    /// The item as the server sent it, for `completionItem/resolve`
+    pub raw: Value,
+    /// Asked to be resolved, so it isn't asked for twice
+    pub resolving: bool,
+    /// Filled in by `completionItem/resolve`
+    pub resolved: bool,
+}
+
+impl CompletionItem {
+    /// Reads an item, converting its ranges through `rope`.
+    pub fn from_json(rope: &Rope, value: &Value) -> Option<Self> {
+        let label = value.get("label")?.as_str()?.to_string();
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+        // An insert-or-replace edit is used for inserting, like a plain one
+        let edit = value.get("textEdit");
+        let range = edit
+            .and_then(|edit| edit.get("range").or_else(|| edit.get("insert")))
+            .and_then(|range| protocol::range_to_chars(rope, range));
+        let insert_text = edit
+            .and_then(|edit| edit.get("newText"))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| text("insertText"))
+            .unwrap_or_else(|| label.clone());
+        let mut item = Self {
+            kind: CompletionKind::from_lsp(value.get("kind").and_then(Value::as_u64).unwrap_or(1)),
+            detail: None,
+            documentation: None,
+            filter_text: text("filterText").unwrap_or_else(|| label.clone()),
+            sort_text: text("sortText").unwrap_or_else(|| label.clone()),
+            insert_text,
+            is_snippet: value.get("insertTextFormat").and_then(Value::as_u64) == Some(2),
+            range,
+            additional_edits: Vec::new(),
+            commit_characters: value
+                .get("commitCharacters")
+                .and_then(Value::as_array)
+                .map(|list| {
+                    list.iter()
+                        .filter_map(Value::as_str)
+                        .filter_map(|c| c.chars().next())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            preselect: value
+                .get("preselect")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            raw: value.clone(),
+            resolving: false,
+            resolved: false,
+            label,
+        };
+        item.merge(rope, value);
+        Some(item)
+    }
+
+    /// Takes the details a `completionItem/resolve` answer fills in.
+    pub fn merge(&mut self, rope: &Rope, value: &Value) {
+        if let Some(detail) = value
+            .get("detail")
+            .and_then(Value::as_str)
+            .filter(|detail| !detail.is_empty())
+        {
+            self.detail = Some(detail.to_string());
+        }
+        if let Some(documentation) = value.get("documentation").and_then(documentation_text) {
+            self.documentation = Some(documentation);
+        }
+        if let Some(edits) = value.get("additionalTextEdits").and_then(Value::as_array) {
+            self.additional_edits = edits
+                .iter()
+                .filter_map(|edit| {
+                    Some(Edit {
+                        range: protocol::range_to_chars(rope, edit.get("range")?)?,
+                        text: edit.get("newText")?.as_str()?.to_string(),
+                    })
+                })
+                .collect();
+        }
+    }
+}
+
+/// The items of one completion request, narrowed by what was typed since.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Completion {
+    /// Where the word being completed starts, and the cursor when requested
+    pub start: usize,
+    pub requested_at: usize,
+    pub items: Vec<CompletionItem>,
+    /// Indices of the items matching `query`, best first
+    pub matches: Vec<usize>,
+    pub query: String,
+    pub selected: usize,
+    /// First match shown in the list
+    pub scroll: usize,
+    /// The server wants asking again as the word grows
+    pub incomplete: bool,
+    /// Commit characters of every item, from the server's capabilities
+    commit_characters: Vec<char>,
+}
+
+impl Completion {
+    /// Reads a `textDocument/completion` answer: a list of items, or an object
+    /// holding them.
+    pub fn new(
+        editor: &RopeEditor,
+        start: usize,
+        requested_at: usize,
+        response: &Value,
+        commit_characters: Vec<char>,
+    ) -> Self {
+        let (list, incomplete) = match response {
+            Value::Array(list) => (list.as_slice(), false),
+            Value::Object(_) => (
+                response
+                    .get("items")
+                    .and_then(Value::as_array)
+                    .map_or(&[][..], Vec::as_slice),
+                response
+                    .get("isIncomplete")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            ),
+            _ => (&[][..], false),
+        };
+        let rope = editor.rope();
+        Self {
+            start,
+            requested_at,
+            items: list
+                .iter()
+                .filter_map(|item| CompletionItem::from_json(rope, item))
+                .collect(),
+            matches: Vec::new(),
+            query: String::new(),
+            selected: 0,
+            scroll: 0,
+            incomplete,
+            commit_characters,
+        }
+    }
+
+    /// Keeps the items matching `query`, best first, selecting the first or the
+    /// one the server preselected.
+    pub fn filter(&mut self, query: &str) {
+        let mut scored: Vec<(i32, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                fuzzy_score(query, &item.filter_text).map(|score| (score, index))
+            })
+            .collect();
+        let items = &self.items;
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| items[*a].sort_text.cmp(&items[*b].sort_text))
+        });
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.query = query.to_string();
+        self.selected = if query.is_empty() {
+            self.matches
+                .iter()
+                .position(|&index| self.items[index].preselect)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        self.scroll = self.selected.saturating_sub(VISIBLE_ITEMS - 1);
+    }
+
+    pub fn selected_item(&self) -> Option<&CompletionItem> {
+        self.matches
+            .get(self.selected)
+            .map(|&index| &self.items[index])
+    }
+
+    /// Moves the selection by `delta`, wrapping at either end only from the last
+    /// or first item.
+    pub fn move_selection(&mut self, delta: isize) {
+        let count = self.matches.len();
+        if count == 0 {
+            return;
+        }
+        let last = count - 1;
+        self.selected = match (self.selected, delta.signum()) {
+            (0, -1) => last,
+            (selected, 1) if selected == last => 0,
+            (selected, _) => selected.saturating_add_signed(delta).min(last),
+        };
+        self.scroll_to_selected();
+    }
+
+    /// Scrolls the list by `delta` items, keeping the selection.
+    pub fn scroll_by(&mut self, delta: isize) {
+        let max = self.matches.len().saturating_sub(VISIBLE_ITEMS);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(max);
+    }
+
+    fn scroll_to_selected(&mut self) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + VISIBLE_ITEMS {
+            self.scroll = self.selected + 1 - VISIBLE_ITEMS;
+        }
+    }
+
+    /// Whether typing `c` accepts the selected item first.
+    pub fn is_commit_character(&self, c: char) -> bool {
+        self.selected_item().is_some_and(|item| {
+            let characters = if item.commit_characters.is_empty() {
+                &self.commit_characters
+            } else {
+                &item.commit_characters
+            };
+            characters.contains(&c)
+        })
+    }
+}
+
+/// Start of the word that ends at `offset`.
+pub fn word_start(editor: &RopeEditor, offset: usize) -> usize {
+    let line_start = editor.line_to_char(editor.char_to_line(offset));
+    let mut start = offset;
+    while start > line_start
+        && editor
+            .char_at(start - 1)
+            .is_some_and(|c| editor.is_word_char(c))
+    {
+        start -= 1;
+    }
+    start
+}
+
+/// The text typed since `completion` started, or None once the cursor has left
+/// the word, which closes it.
+pub fn query(editor: &RopeEditor, completion: &Completion) -> Option<String> {
+    let cursor = editor.get_cursor().byte_offset;
+    if cursor < completion.start
+        || editor.char_to_line(cursor) != editor.char_to_line(completion.start)
+    {
+        return None;
+    }
+    let query = editor.slice_text(completion.start, cursor);
+    query
+        .chars()
+        .all(|c| editor.is_word_char(c))
+        .then_some(query)
+}
+
+/// Tab stops of an inserted snippet, visited in order with tab.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnippetSession {
+    stops: Vec<Range<usize>>,
+    current: usize,
+    /// Length of the text when the current stop was entered, to tell how much
+    /// was typed into it
+    len: usize,
+}
+
+impl SnippetSession {
+    /// Moves to the next stop, or back one, selecting its placeholder. Returns
+    /// whether there are stops left, or None when the cursor has left the
+    /// current stop. Either way the session is over unless it returns true.
+    pub fn step(&mut self, editor: &mut RopeEditor, forward: bool) -> Option<bool> {
+        let delta = editor.total_chars() as isize - self.len as isize;
+        let current = self.stops[self.current].clone();
+        let end = current
+            .end
+            .checked_add_signed(delta)
+            .filter(|end| *end >= current.start)?;
+        let cursor = editor.get_cursor().byte_offset;
+        if !(current.start..=end).contains(&cursor) {
+            return None;
+        }
+        // Whatever was typed went into the current stop; stops after it move
+        for stop in &mut self.stops {
+            if stop.start >= current.end && *stop != current {
+                *stop =
+                    stop.start.saturating_add_signed(delta)..stop.end.saturating_add_signed(delta);
+            }
+        }
+        self.stops[self.current] = current.start..end;
+        let next = match forward {
+            true => self.current + 1,
+            false => match self.current.checked_sub(1) {
+                Some(previous) => previous,
+                None => return Some(true),
+            },
+        };
+        self.current = next;
+        self.len = editor.total_chars();
+        select(editor, self.stops[next].clone());
+        Some(next + 1 < self.stops.len())
+    }
+}
+
+/// Makes `item`'s edits as one undo step: its text over the word typed so far
+/// and the extra edits, such as an import. Returns the session of a snippet
+/// with tab stops.
+pub fn accept(
+    editor: &mut RopeEditor,
+    completion: &Completion,
+    item: &CompletionItem,
+) -> Option<SnippetSession> {
+    let cursor = editor.get_cursor().byte_offset;
+    // Offsets from the request, moved past what was typed since
+    let typed = cursor as isize - completion.requested_at as isize;
+    let moved = |offset: usize| match offset >= completion.requested_at {
+        true => offset.saturating_add_signed(typed),
+        false => offset,
+    };
+    let main = match &item.range {
+        Some(range) => range.start..moved(range.end).max(cursor),
+        None => completion.start..cursor,
+    };
+
+    let line = editor.char_to_line(main.start);
+    let indent: String = editor
+        .line_chars(line)
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+    let unit = editor.language().indent_unit;
+    let (text, stops) = if item.is_snippet {
+        parse_snippet(&item.insert_text, &indent, unit)
+    } else {
+        (
+            item.insert_text.replace('\n', &format!("\n{indent}")),
+            Vec::new(),
+        )
+    };
+
+    let mut edits: Vec<(Range<usize>, String)> = item
+        .additional_edits
+        .iter()
+        .map(|edit| {
+            (
+                moved(edit.range.start)..moved(edit.range.end),
+                edit.text.clone(),
+            )
+        })
+        .filter(|(range, _)| range.end <= main.start || range.start >= main.end)
+        .collect();
+    // Edits before the item's move it
+    let shift: isize = edits
+        .iter()
+        .filter(|(range, _)| range.end <= main.start)
+        .map(|(range, text)| text.chars().count() as isize - range.len() as isize)
+        .sum();
+    let inserted = text.chars().count();
+    edits.push((main.clone(), text));
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+    editor.begin_undo_group();
+    editor.set_selection_anchor(None);
+    for (range, text) in edits {
+        if !range.is_empty() {
+            editor.delete_range(range.start, range.end);
+        }
+        editor.set_cursor_offset(range.start);
+        if !text.is_empty() {
+            editor.insert_text(&text);
+        }
+    }
+    editor.end_undo_group();
+
+    let start = main.start.saturating_add_signed(shift);
+    let stops: Vec<Range<usize>> = stops
+        .into_iter()
+        .map(|stop| start + stop.start..start + stop.end)
+        .collect();
+    match stops.split_first() {
+        None => {
+            editor.set_cursor_offset(start + inserted);
+            None
+        }
+        Some((first, rest)) => {
+            select(editor, first.clone());
+            (!rest.is_empty()).then(|| SnippetSession {
+                stops,
+                current: 0,
+                len: editor.total_chars(),
+            })
+        }
+    }
+}
+
+// Selects `range`, with the cursor at its end
+fn select(editor: &mut RopeEditor, range: Range<usize>) {
+    editor.set_selection_anchor((!range.is_empty()).then_some(range.start));
+    editor.set_cursor_offset(range.end);
+}
+
+/// Expands a snippet into its text and tab stops, in the order tab visits
+/// them: $1, $2, ... and $0, or the end of the text, last. Lines after the
+/// first get `indent`, and tabs become `unit`.
+pub fn parse_snippet(snippet: &str, indent: &str, unit: &str) -> (String, Vec<Range<usize>>) {
+    let chars: Vec<char> = snippet.chars().collect();
+    let mut out = SnippetText {
+        text: String::new(),
+        len: 0,
+        indent,
+        unit,
+        stops: Vec::new(),
+    };
+    let mut position = 0;
+    out.parse(&chars, &mut position, false);
+
+    let mut stops = std::mem::take(&mut out.stops);
+    // Stops of the same number mirror the first; only it is visited
+    stops.sort_by_key(|(number, _)| if *number == 0 { usize::MAX } else { *number });
+    stops.dedup_by_key(|(number, _)| *number);
+    if stops.last().is_none_or(|(number, _)| *number != 0) {
+        stops.push((0, out.len..out.len));
+    }
+    (
+        out.text,
+        stops.into_iter().map(|(_, range)| range).collect(),
+    )
+}
+
+struct SnippetText<'a> {
+    text: String,
+    len: usize,
+    indent: &'a str,
+    unit: &'a str,
+    stops: Vec<(usize, Range<usize>)>,
+}
+
+impl SnippetText<'_> {
+    fn push(&mut self, c: char) {
+        match c {
+            '\n' => {
+                self.text.push('\n');
+                self.text.push_str(self.indent);
+                self.len += 1 + self.indent.chars().count();
+            }
+            '\t' => {
+                self.text.push_str(self.unit);
+                self.len += self.unit.chars().count();
+            }
+            c => {
+                self.text.push(c);
+                self.len += 1;
+            }
+        }
+    }
+
+    // Reads up to the end, or up to the `}` closing a placeholder when `nested`
+    fn parse(&mut self, chars: &[char], i: &mut usize, nested: bool) {
+        while let Some(&c) = chars.get(*i) {
+            *i += 1;
+            match c {
+                '\\' => match chars.get(*i) {
+                    Some(&next @ ('$' | '}' | '\\' | ',' | '|')) => {
+                        *i += 1;
+                        self.push(next);
+                    }
+                    _ => self.push('\\'),
+                },
+                '}' if nested => return,
+                '$' => self.dollar(chars, i),
+                c => self.push(c),
+            }
+        }
+    }
+
+    // After a `$`: a tab stop, placeholder, choice or variable
+    fn dollar(&mut self, chars: &[char], i: &mut usize) {
+        let number = |i: &mut usize| {
+            let digits: String = chars[*i..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            *i += digits.len();
+            digits.parse::<usize>().ok()
+        };
+        let name = |i: &mut usize| {
+            let name: String = chars[*i..]
+                .iter()
+                .take_while(|c| c.is_alphanumeric() || **c == '_')
+                .collect();
+            *i += name.chars().count();
+            name
+        };
+        match chars.get(*i) {
+            Some(c) if c.is_ascii_digit() => {
+                let number = number(i).unwrap_or_default();
+                self.stops.push((number, self.len..self.len));
+            }
+            Some('{') => {
+                *i += 1;
+                let start = self.len;
+                if let Some(number) = number(i) {
+                    match chars.get(*i) {
+                        Some(':') => {
+                            *i += 1;
+                            self.parse(chars, i, true);
+                        }
+                        // The first choice stands in for the rest
+                        Some('|') => {
+                            *i += 1;
+                            while let Some(&c) = chars.get(*i) {
+                                *i += 1;
+                                match c {
+                                    '\\' if chars.get(*i).is_some() => {
+                                        self.push(chars[*i]);
+                                        *i += 1;
+                                    }
+                                    ',' | '|' => break,
+                                    c => self.push(c),
+                                }
+                            }
+                            skip_past_brace(chars, i);
+                        }
+                        _ => skip_past_brace(chars, i),
+                    }
+                    self.stops.push((number, start..self.len));
+                } else {
+                    // Variables aren't known here; their default, if any, is used
+                    name(i);
+                    match chars.get(*i) {
+                        Some(':') => {
+                            *i += 1;
+                            self.parse(chars, i, true);
+                        }
+                        _ => skip_past_brace(chars, i),
+                    }
+                }
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                name(i);
+            }
+            _ => self.push('$'),
+        }
+    }
+}
+
+fn skip_past_brace(chars: &[char], i: &mut usize) {
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '\\' => *i += 1,
+            '}' => return,
+            _ => {}
+        }
+    }
+}
+
+// Plain text or markdown, without the fences around code blocks
+fn documentation_text(value: &Value) -> Option<String> {
+    let text = value.as_str().or_else(|| value.get("value")?.as_str())?;
+    let text: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .collect();
+    let text = text.join("\n").trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    fn editor(text: &str, cursor: usize) -> RopeEditor {
+        let mut editor = RopeEditor::from_named_text("a.rs", text);
+        editor.set_cursor_offset(cursor);
+        editor
+    }
+
+    #[test]
+    fn snippets_expand_to_text_and_tab_stops() {
+        let (text, stops) = parse_snippet("fn ${1:name}(${2}) {\n\t$0\n}", "  ", "    ");
+        assert_eq!(text, "fn name() {\n      \n  }");
+        assert_eq!(stops, [3..7, 8..8, 18..18]);
+
+        let (text, stops) = parse_snippet(
+            "${1|one,two|} \\$x ${TM_FILENAME:file} $VAR ${2:a ${3:b}}",
+            "",
+            "\t",
+        );
+        assert_eq!(text, "one $x file  a b");
+        assert_eq!(stops, [0..3, 13..16, 15..16, 16..16]);
+
+        // A stop used twice is visited at its first place, then its second
+        let (text, stops) = parse_snippet("foo($1, $1)", "", "");
+        assert_eq!(text, "foo(, )");
+        assert_eq!(stops, [4..4, 7..7]);
+    }
+
+    #[test]
+    fn items_filter_and_sort_by_the_typed_word() {
+        let editor = editor("fn main() { ve }", 14);
+        let response = json!({ "isIncomplete": false, "items": [
+            { "label": "vec!", "kind": 3, "sortText": "2" },
+            { "label": "Vec", "kind": 22, "sortText": "1" },
+            { "label": "reverse", "kind": 2 },
+            { "label": "other", "preselect": true },
+        ]});
+        let mut completion =
+            Completion::new(&editor, word_start(&editor, 14), 14, &response, vec![]);
+        assert_eq!(completion.start, 12);
+        completion.filter("");
+        assert_eq!(completion.selected_item().unwrap().label, "other");
+
+        completion.filter(&query(&editor, &completion).unwrap());
+        let labels: Vec<&str> = completion
+            .matches
+            .iter()
+            .map(|&i| completion.items[i].label.as_str())
+            .collect();
+        assert_eq!(labels, ["Vec", "vec!", "reverse"]);
+        assert_eq!(completion.items[1].kind, CompletionKind::Struct);
+        // Selection wraps both ways
+        completion.move_selection(-1);
+        assert_eq!(completion.selected, 2);
+        completion.move_selection(1);
+        assert_eq!(completion.selected, 0);
+    }
+
+    #[test]
+    fn accepting_applies_the_text_edit_and_imports_as_one_undo() {
+        // Asked for at "Ha", then "sh" typed
+        let text = "use a;\nfn main() { Hash }";
+        let mut editor = editor(text, 22);
+        let range = |line: u64, start: u64, end: u64| {
+            json!({ "start": { "line": line, "character": start },
+                    "end": { "line": line, "character": end } })
+        };
+        let response = json!([{
+            "label": "HashMap",
+            "textEdit": { "range": range(1, 12, 14), "newText": "HashMap" },
+            "additionalTextEdits": [
+                { "range": range(0, 6, 6), "newText": "\nuse std::collections::HashMap;" },
+            ],
+            "commitCharacters": ["("],
+        }]);
+        let mut completion = Completion::new(&editor, 18, 20, &response, vec![]);
+        completion.filter("Hash");
+        assert!(completion.is_commit_character('('));
+        assert!(!completion.is_commit_character('.'));
+
+        let item = completion.selected_item().unwrap().clone();
+        assert!(accept(&mut editor, &completion, &item).is_none());
+        assert_eq!(
+            editor.get_content(),
+            "use a;\nuse std::collections::HashMap;\nfn main() { HashMap }"
+        );
+        assert_eq!(
+            editor.get_cursor().byte_offset,
+            editor.get_content().find(" }").unwrap()
+        );
+        editor.undo();
+        assert_eq!(editor.get_content(), text);
+    }
+
+    #[test]
+    fn snippet_sessions_step_through_the_stops() {
+        let mut editor = editor("    ma\n", 6);
+        let response = json!([{
+            "label": "match",
+            "insertTextFormat": 2,
+            "insertText": "match ${1:expr} {\n\t${2:pat} => $0\n}",
+        }]);
+        let mut completion = Completion::new(&editor, 4, 6, &response, vec![]);
+        completion.filter("ma");
+        let item = completion.selected_item().unwrap().clone();
+        let mut session = accept(&mut editor, &completion, &item).unwrap();
+        assert_eq!(
+            editor.get_content(),
+            "    match expr {\n        pat => \n    }\n"
+        );
+        assert_eq!(editor.selected_text().as_deref(), Some("expr"));
+
+        // Typed over the placeholder, which the stops after it move with
+        let (start, end) = editor.selection_range().unwrap();
+        editor.delete_range(start, end);
+        editor.set_selection_anchor(None);
+        editor.insert_text("value");
+        assert_eq!(session.step(&mut editor, true), Some(true));
+        assert_eq!(editor.selected_text().as_deref(), Some("pat"));
+        assert_eq!(session.step(&mut editor, false), Some(true));
+        assert_eq!(editor.selected_text().as_deref(), Some("value"));
+        assert_eq!(session.step(&mut editor, true), Some(true));
+        assert_eq!(session.step(&mut editor, true), Some(false));
+        assert_eq!(
+            editor.get_cursor().byte_offset,
+            editor.get_content().find("=> ").unwrap() + 3
+        );
+
+        // Moving off the stop ends the session
+        let mut left = session.clone();
+        editor.set_cursor_offset(0);
+        assert_eq!(left.step(&mut editor, true), None);
+    }
+
+    #[test]
+    fn the_query_ends_outside_the_word() {
+        let mut editor = editor("ab.cd", 5);
+        let completion = Completion::new(&editor, 3, 5, &json!([]), vec![]);
+        assert_eq!(query(&editor, &completion).as_deref(), Some("cd"));
+        editor.set_cursor_offset(2);
+        assert_eq!(query(&editor, &completion), None);
+        editor.set_cursor_offset(5);
+        editor.insert_text(" ");
+        assert_eq!(query(&editor, &completion), None);
+    }
+}
@@ -0,0 +1,413 @@
+use super::rope_engine::RopeEditor;
+use super::wrap::{LineLayout, VisualRow};
+use dioxus::prelude::*;
+use ropey::Rope;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+// Inline decorations: text drawn among a line's characters without being part
+// of it, such as the types and parameter names a language server hints at.
+// They take room on screen but none in the buffer, so the caret steps over
+// them and a click on one lands beside it.
+
+/// Where a file's decorations come from; each source replaces only its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Source {
+    InlayHints,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecorationKind {
+    Type,
+    Parameter,
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineDecoration {
+    /// Column of the char it is drawn before; the line's length puts it at the end
+    pub column: usize,
+    pub text: String,
+    pub kind: DecorationKind,
+    /// Whether a column is left blank before and after the text
+    pub padding_left: bool,
+    pub padding_right: bool,
+    /// Shown when the pointer rests on it
+    pub tooltip: Option<String>,
+}
+
+impl InlineDecoration {
+    /// Screen columns it takes.
+    pub fn width(&self) -> usize {
+        self.text.chars().count() + self.padding_left as usize + self.padding_right as usize
+    }
+}
+
+/// The decorations of a file from one source, by line, each line with the
+/// text it had when they were made.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layer {
+    lines: BTreeMap<usize, (String, Vec<InlineDecoration>)>,
+}
+
+impl Layer {
+    /// Adds `decoration` to `line` of `text`.
+    pub fn push(&mut self, text: &Rope, line: usize, decoration: InlineDecoration) {
+        let (_, decorations) = self.lines.entry(line).or_insert_with(|| {
+            let content = text.line(line).to_string();
+            (
+                content.trim_end_matches(['\n', '\r']).to_string(),
+                Vec::new(),
+            )
+        });
+        decorations.push(decoration);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileDecorations {
+    /// Bumped whenever a layer changes, so views know to lay the file out again
+    pub generation: u64,
+    layers: BTreeMap<Source, Layer>,
+}
+
+pub static DECORATIONS: GlobalSignal<HashMap<PathBuf, FileDecorations>> =
+    Signal::global(HashMap::new);
+
+/// Replaces `source`'s decorations of `path`.
+pub fn set_layer(path: &Path, source: Source, layer: Layer) {
+    if layer.is_empty() {
+        return clear_layer(path, source);
+    }
+    if DECORATIONS
+        .peek()
+        .get(path)
+        .and_then(|file| file.layers.get(&source))
+        == Some(&layer)
+    {
+        return;
+    }
+    let mut decorations = DECORATIONS.write();
+    let file = decorations.entry(path.to_path_buf()).or_default();
+    file.generation += 1;
+    file.layers.insert(source, layer);
+}
+
+pub fn clear_layer(path: &Path, source: Source) {
+    let has_layer = DECORATIONS
+        .peek()
+        .get(path)
+        .is_some_and(|file| file.layers.contains_key(&source));
+    if !has_layer {
+        return;
+    }
+    // The file stays, so that its generation keeps counting up
+    if let Some(file) = DECORATIONS.write().get_mut(path) {
+        file.layers.remove(&source);
+        file.generation += 1;
+    }
+}
+
+/// A file's decorations as a buffer draws them: those of the lines that still
+/// read as they did when the decorations were made, in column order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LineDecorations {
+    lines: HashMap<usize, Vec<InlineDecoration>>,
+}
+
+impl LineDecorations {
+    pub fn new(editor: &RopeEditor, file: Option<&FileDecorations>) -> Self {
+        let mut lines: HashMap<usize, Vec<InlineDecoration>> = HashMap::new();
+        for layer in file.into_iter().flat_map(|file| file.layers.values()) {
+            for (&line, (text, decorations)) in &layer.lines {
+                // An edited line goes without until its source catches up
+                let unchanged = line < editor.line_count()
+                    && editor.line_len(line) == text.chars().count()
+                    && editor
+                        .line_chars(line)
+                        .zip(text.chars())
+                        .all(|(a, b)| a == b);
+                if unchanged {
+                    lines
+                        .entry(line)
+                        .or_default()
+                        .extend(decorations.iter().cloned());
+                }
+            }
+        }
+        // Sorting is stable, so decorations at one column keep their order
+        for decorations in lines.values_mut() {
+            decorations.sort_by_key(|decoration| decoration.column);
+        }
+        Self { lines }
+    }
+
+    /// The decorations `row` draws, in order.
+    pub fn on_row(&self, row: &VisualRow) -> &[InlineDecoration] {
+        let Some(decorations) = self.lines.get(&row.line) else {
+            return &[];
+        };
+        let from = decorations.partition_point(|decoration| decoration.column < row.start);
+        let to = match row.end {
+            // The end column itself is drawn at the start of the next row
+            Some(end) => decorations.partition_point(|decoration| decoration.column < end),
+            None => decorations.len(),
+        };
+        &decorations[from..to.max(from)]
+    }
+
+    /// Screen columns the decorations of `row` take.
+    pub fn row_width(&self, row: &VisualRow) -> usize {
+        self.on_row(row).iter().map(InlineDecoration::width).sum()
+    }
+
+    /// Screen column of `column` on `row`, counted from the start of the text
+    /// area. The caret at a decoration's column sits before it.
+    pub fn x_of(&self, row: &VisualRow, layout: &LineLayout, column: usize) -> usize {
+        let before: usize = self
+            .on_row(row)
+            .iter()
+            .take_while(|decoration| decoration.column < column)
+            .map(InlineDecoration::width)
+            .sum();
+        row.x_of(layout, column) + before
+    }
+
+    /// Buffer column shown at screen column `x` of `row`. A decoration's room
+    /// belongs to the column it stands at.
+    pub fn column_at(&self, row: &VisualRow, layout: &LineLayout, x: usize) -> usize {
+        let mut skipped = 0;
+        for decoration in self.on_row(row) {
+            let start = row.x_of(layout, decoration.column) + skipped;
+            if x < start {
+                break;
+            }
+            if x <= start + decoration.width() {
+                return row.column_at(layout, row.x_of(layout, decoration.column));
+            }
+            skipped += decoration.width();
+        }
+        row.column_at(layout, x.saturating_sub(skipped))
+    }
+}
+
+/// A piece of a row as drawn: text, or a decoration between texts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Piece<T> {
+    Text(String, T),
+    Decoration(InlineDecoration),
+}
+
+/// Splits `tokens`, the text of a row from column `start` on, where
+/// `decorations` go between them.
+pub fn interleave<T: Copy>(
+    tokens: Vec<(String, T)>,
+    start: usize,
+    decorations: &[InlineDecoration],
+) -> Vec<Piece<T>> {
+    let mut pieces = Vec::with_capacity(tokens.len() + decorations.len());
+    let mut pending = decorations
+        .iter()
+        .filter(|decoration| decoration.column >= start)
+        .peekable();
+    let mut column = start;
+    for (text, class_) in tokens {
+        let mut rest = text.as_str();
+        let mut len = rest.chars().count();
+        while let Some(decoration) = pending.next_if(|decoration| decoration.column < column + len)
+        {
+            let split = decoration.column.saturating_sub(column);
+            if split > 0 {
+                let at = rest
+                    .char_indices()
+                    .nth(split)
+                    .map_or(rest.len(), |(index, _)| index);
+                pieces.push(Piece::Text(rest[..at].to_string(), class_));
+                rest = &rest[at..];
+                len -= split;
+                column += split;
+            }
+            pieces.push(Piece::Decoration(decoration.clone()));
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Text(rest.to_string(), class_));
+        }
+        column += len;
+    }
+    // Those at the end of the text, or past it
+    pieces.extend(pending.cloned().map(Piece::Decoration));
+    pieces
+}
+
+/// Pads the tabs among `pieces`, the text of a row from column `start` on,
+/// as `LineLayout::expand_tabs` does for a run of text.
+pub fn expand_tabs<T>(pieces: Vec<Piece<T>>, start: usize, layout: &LineLayout) -> Vec<Piece<T>> {
+    let mut column = start;
+    pieces
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text, class_) => {
+                let expanded = layout.expand_tabs(&text, column);
+                column += text.chars().count();
+                Piece::Text(expanded, class_)
+            }
+            decoration => decoration,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::folding::FoldState;
+    use crate::editor::wrap::DisplayMap;
+    use std::rc::Rc;
+
+    fn hint(column: usize, text: &str, padding_right: bool) -> InlineDecoration {
+        InlineDecoration {
+            column,
+            text: text.to_string(),
+            kind: DecorationKind::Type,
+            padding_left: false,
+            padding_right,
+            tooltip: None,
+        }
+    }
+
+    fn row(line: usize, start: usize, end: Option<usize>, indent: usize) -> VisualRow {
+        VisualRow {
+            line,
+            start,
+            end,
+            indent,
+        }
+    }
+
+    fn text(piece: &Piece<u8>) -> String {
+        match piece {
+            Piece::Text(text, _) => text.clone(),
+            Piece::Decoration(decoration) => format!("[{}]", decoration.text),
+        }
+    }
+
+    #[test]
+    fn expand_tabs_pads_to_the_tab_stops_of_the_line() {
+        let layout = LineLayout::new("a\tb\t\tc".chars(), 4);
+        let pieces = vec![
+            Piece::Text("a\tb".to_string(), 0),
+            Piece::Text("\t\tc".to_string(), 1),
+        ];
+        let shown: Vec<String> = expand_tabs(pieces, 0, &layout).iter().map(text).collect();
+        assert_eq!(shown, vec!["a   b", "       c"]);
+
+        // Drawn from a later column, with the tab shown as a glyph
+        let pieces = vec![Piece::Text("\u{2192}c".to_string(), 0)];
+        let shown: Vec<String> = expand_tabs(pieces, 4, &layout).iter().map(text).collect();
+        assert_eq!(shown, vec!["\u{2192}   c"]);
+    }
+
+    #[test]
+    fn the_caret_steps_over_decorations() {
+        // ": i32" after `x`, and "a: " before `1`
+        let editor = RopeEditor::from_text("let x = foo(1);\nnext\n");
+        let mut layer = Layer::default();
+        layer.push(editor.rope(), 0, hint(5, ": i32", false));
+        layer.push(editor.rope(), 0, hint(12, "a:", true));
+        let file = FileDecorations {
+            generation: 1,
+            layers: BTreeMap::from([(Source::InlayHints, layer)]),
+        };
+        let decorations = LineDecorations::new(&editor, Some(&file));
+        let layout = LineLayout::new("".chars(), 4);
+        let first = row(0, 0, None, 0);
+        assert_eq!(decorations.on_row(&first).len(), 2);
+        assert_eq!(decorations.row_width(&first), 8);
+        let xs: Vec<usize> = [5, 6, 12, 13]
+            .iter()
+            .map(|&column| decorations.x_of(&first, &layout, column))
+            .collect();
+        assert_eq!(xs, [5, 11, 17, 21]);
+        // A click on a decoration lands on the column before it
+        let columns: Vec<usize> = [4, 5, 10, 11, 16, 17, 20, 21, 100]
+            .iter()
+            .map(|&x| decorations.column_at(&first, &layout, x))
+            .collect();
+        assert_eq!(columns, [4, 5, 5, 6, 11, 12, 12, 13, 92]);
+        assert_eq!(decorations.x_of(&row(1, 0, None, 0), &layout, 3), 3);
+
+        // Each belongs to the wrapped row holding its column
+        let wrapped = row(0, 12, None, 2);
+        assert_eq!(decorations.on_row(&row(0, 0, Some(12), 0)).len(), 1);
+        assert_eq!(decorations.on_row(&wrapped).len(), 1);
+        assert_eq!(decorations.x_of(&wrapped, &layout, 12), 2);
+        assert_eq!(decorations.x_of(&wrapped, &layout, 13), 6);
+        assert_eq!(decorations.column_at(&wrapped, &layout, 5), 12);
+
+        // A line edited since loses them
+        let edited = LineDecorations::new(
+            &RopeEditor::from_text("let xy = foo(1);\nnext\n"),
+            Some(&file),
+        );
+        assert!(edited.on_row(&first).is_empty());
+    }
+
+    #[test]
+    fn interleave_puts_decorations_between_tokens() {
+        let tokens = vec![
+            ("let ".to_string(), 1),
+            ("x".to_string(), 2),
+            (" = foo(1);".to_string(), 3),
+        ];
+        let decorations = vec![
+            hint(5, ": i32", false),
+            hint(12, "a:", true),
+            hint(15, "end", false),
+        ];
+        let shown: Vec<String> = interleave(tokens, 0, &decorations)
+            .iter()
+            .map(text)
+            .collect();
+        assert_eq!(
+            shown,
+            ["let ", "x", "[: i32]", " = foo(", "[a:]", "1);", "[end]"]
+        );
+        // Drawn from column 9, the first decoration is off the left edge
+        let pieces = interleave(vec![("oo(1);".to_string(), 0)], 9, &decorations);
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces[0], Piece::Text("oo(".to_string(), 0));
+    }
+
+    #[test]
+    fn layers_move_the_caret_by_screen_column() {
+        VirtualDom::new(|| rsx! {}).in_runtime(|| {
+            let path = PathBuf::from("/tmp/decorations_test.rs");
+            let mut editor =
+                RopeEditor::from_text("fn main() {\n    let value = compute(1, 2);\n}\n");
+            let mut layer = Layer::default();
+            layer.push(editor.rope(), 1, hint(13, ": u32", false));
+            set_layer(&path, Source::InlayHints, layer.clone());
+            let generation = DECORATIONS.peek()[&path].generation;
+            // The same layer again changes nothing
+            set_layer(&path, Source::InlayHints, layer);
+            assert_eq!(DECORATIONS.peek()[&path].generation, generation);
+
+            let decorations = Rc::new(LineDecorations::new(&editor, DECORATIONS.peek().get(&path)));
+            let folds = FoldState::default().fold_map(editor.line_count());
+            let display = DisplayMap::new(&editor, folds, None).with_decorations(decorations);
+            // Column 11 is before the hint on the next line, so stays 11
+            editor.set_cursor(0, 11);
+            display.move_vertically(&mut editor, true);
+            assert_eq!(editor.get_cursor().column, 11);
+            assert_eq!(display.x_of(&display.row(1), 20), 25);
+
+            clear_layer(&path, Source::InlayHints);
+            assert!(DECORATIONS.peek()[&path].generation > generation);
+            let cleared = LineDecorations::new(&editor, DECORATIONS.peek().get(&path));
+            assert!(cleared.on_row(&row(1, 0, None, 0)).is_empty());
+        });
+    }
+}
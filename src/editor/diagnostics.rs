@@ -0,0 +1,32 @@
+use dioxus::prelude::*;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Problems reported for open files, by language servers and other checkers.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// A problem over a range of the text, in lines and char columns.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// What reported it, such as "rustc"
+    pub source: Option<String>,
+    /// The report as a language server sent it, for asking it for fixes
+    pub lsp: Option<Value>,
+}
+
+pub static DIAGNOSTICS: GlobalSignal<HashMap<PathBuf, Vec<Diagnostic>>> =
+    Signal::global(HashMap::new);
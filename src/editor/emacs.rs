@@ -0,0 +1,545 @@
+use super::rope_engine::RopeEditor;
+use dioxus::prelude::*;
+
+// Emacs keybinding profile. Keys the profile doesn't bind are reported back as
+// `EmacsEffect::Unhandled` so the view's default shortcuts and typing still work.
+
+const KILL_RING_MAX: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmacsKey {
+    Char(char),
+    Ctrl(char),
+    Meta(char),
+    MetaBackspace,
+    Enter,
+    Backspace,
+    Escape,
+    Other,
+}
+
+impl EmacsKey {
+    pub fn from_key(key: &Key, ctrl: bool, alt: bool) -> Self {
+        match key {
+            Key::Character(s) if s.chars().count() == 1 => {
+                let c = s.chars().next().unwrap_or(' ');
+                if ctrl {
+                    EmacsKey::Ctrl(c.to_ascii_lowercase())
+                } else if alt {
+                    EmacsKey::Meta(c)
+                } else {
+                    EmacsKey::Char(c)
+                }
+            }
+            Key::Backspace if alt => EmacsKey::MetaBackspace,
+            Key::Backspace => EmacsKey::Backspace,
+            Key::Enter => EmacsKey::Enter,
+            Key::Escape => EmacsKey::Escape,
+            _ => EmacsKey::Other,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmacsEffect {
+    Unhandled,
+    Handled,
+    Save,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LastCommand {
+    Kill,
+    Yank,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+struct Isearch {
+    query: String,
+    forward: bool,
+    origin: usize,
+    found: Option<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct EmacsState {
+    pub enabled: bool,
+    kill_ring: Vec<String>,
+    yank_index: usize,
+    last_yank: Option<(usize, usize)>,
+    last_command: LastCommand,
+    ctrl_x_prefix: bool,
+    isearch: Option<Isearch>,
+    last_search: String,
+    // Where the last search started, which C-x C-x goes back to while there is no region
+    mark: Option<usize>,
+    message: Option<String>,
+}
+
+pub static EMACS: GlobalSignal<EmacsState> = Signal::global(EmacsState::new);
+
+impl EmacsState {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            kill_ring: Vec::new(),
+            yank_index: 0,
+            last_yank: None,
+            last_command: LastCommand::Other,
+            ctrl_x_prefix: false,
+            isearch: None,
+            last_search: String::new(),
+            mark: None,
+            message: None,
+        }
+    }
+
+    /// Echo-area text for the status bar: the search prompt, a pending prefix or a message.
+    pub fn status_text(&self) -> String {
+        if let Some(search) = &self.isearch {
+            let failing = if search.found.is_none() && !search.query.is_empty() {
+                "Failing "
+            } else {
+                ""
+            };
+            let direction = if search.forward { "" } else { " backward" };
+            format!("{}I-search{}: {}", failing, direction, search.query)
+        } else if self.ctrl_x_prefix {
+            "C-x-".to_string()
+        } else {
+            self.message.clone().unwrap_or_default()
+        }
+    }
+
+    /// Query of the incremental search in progress, if any.
+    pub fn search_query(&self) -> Option<&str> {
+        self.isearch
+            .as_ref()
+            .map(|search| search.query.as_str())
+            .filter(|query| !query.is_empty())
+    }
+
+    pub fn set_enabled(&mut self, editor: &mut RopeEditor, enabled: bool) {
+        self.enabled = enabled;
+        self.ctrl_x_prefix = false;
+        self.isearch = None;
+        self.message = None;
+        editor.set_selection_anchor(None);
+    }
+
+    pub fn handle_key(&mut self, editor: &mut RopeEditor, key: EmacsKey) -> EmacsEffect {
+        self.message = None;
+
+        if self.isearch.is_some() {
+            if let Some(effect) = self.isearch_key(editor, key) {
+                return effect;
+            }
+        }
+
+        if self.ctrl_x_prefix {
+            self.ctrl_x_prefix = false;
+            self.last_command = LastCommand::Other;
+            return self.ctrl_x_key(editor, key);
+        }
+
+        let last_command = std::mem::replace(&mut self.last_command, LastCommand::Other);
+        let pos = editor.get_cursor().byte_offset;
+        let line = editor.get_cursor().line;
+
+        match key {
+            EmacsKey::Ctrl('a') => editor.move_cursor_to_line_start(),
+            EmacsKey::Ctrl('e') => editor.move_cursor_to_line_end(),
+            EmacsKey::Ctrl('f') => editor.move_cursor_right(),
+            EmacsKey::Ctrl('b') => editor.move_cursor_left(),
+            EmacsKey::Ctrl('n') => editor.move_cursor_down(),
+            EmacsKey::Ctrl('p') => editor.move_cursor_up(),
+            EmacsKey::Meta('f') => editor.set_cursor_offset(forward_word(editor, pos)),
+            EmacsKey::Meta('b') => editor.set_cursor_offset(backward_word(editor, pos)),
+            EmacsKey::Meta('<') => editor.set_cursor_offset(0),
+            EmacsKey::Meta('>') => editor.set_cursor_offset(editor.total_chars()),
+            EmacsKey::Ctrl('d') => editor.delete(),
+            EmacsKey::Ctrl('k') => {
+                let line_end = editor.line_to_char(line) + editor.line_len(line);
+                // At the end of a line C-k kills the line break instead
+                let end = if pos >= line_end {
+                    (line_end + 1).min(editor.total_chars())
+                } else {
+                    line_end
+                };
+                self.kill(editor, pos, end, last_command, false);
+            }
+            EmacsKey::Meta('d') => {
+                let end = forward_word(editor, pos);
+                self.kill(editor, pos, end, last_command, false);
+            }
+            EmacsKey::MetaBackspace => {
+                let start = backward_word(editor, pos);
+                self.kill(editor, start, pos, last_command, true);
+            }
+            EmacsKey::Ctrl('w') => match editor.selection_range() {
+                Some((start, end)) => {
+                    self.kill(editor, start, end, last_command, pos == end);
+                    editor.set_selection_anchor(None);
+                }
+                None => self.message = Some("The mark is not set now".to_string()),
+            },
+            EmacsKey::Meta('w') => match editor.selected_text() {
+                Some(text) => {
+                    self.push_kill(editor, text);
+                    editor.set_selection_anchor(None);
+                }
+                None => self.message = Some("The mark is not set now".to_string()),
+            },
+            EmacsKey::Ctrl('y') => self.yank(editor),
+            EmacsKey::Meta('y') => {
+                if last_command == LastCommand::Yank {
+                    self.yank_pop(editor);
+                } else {
+                    self.message = Some("Previous command was not a yank".to_string());
+                }
+            }
+            EmacsKey::Ctrl(' ') | EmacsKey::Ctrl('@') => {
+                editor.set_selection_anchor(Some(pos));
+                self.message = Some("Mark set".to_string());
+            }
+            EmacsKey::Ctrl('g') | EmacsKey::Escape => {
+                editor.set_selection_anchor(None);
+                self.message = Some("Quit".to_string());
+            }
+            EmacsKey::Ctrl('/') | EmacsKey::Ctrl('_') => {
+                if !editor.undo() {
+                    self.message = Some("No further undo information".to_string());
+                }
+            }
+            EmacsKey::Ctrl('x') => self.ctrl_x_prefix = true,
+            EmacsKey::Ctrl('s') | EmacsKey::Ctrl('r') => {
+                self.isearch = Some(Isearch {
+                    query: String::new(),
+                    forward: key == EmacsKey::Ctrl('s'),
+                    origin: pos,
+                    found: None,
+                });
+            }
+            EmacsKey::Char(_) | EmacsKey::Enter | EmacsKey::Backspace => {
+                // Plain editing goes through the default handlers; it ends the region
+                editor.set_selection_anchor(None);
+                return EmacsEffect::Unhandled;
+            }
+            _ => return EmacsEffect::Unhandled,
+        }
+        EmacsEffect::Handled
+    }
+
+    fn ctrl_x_key(&mut self, editor: &mut RopeEditor, key: EmacsKey) -> EmacsEffect {
+        match key {
+            EmacsKey::Ctrl('s') => return EmacsEffect::Save,
+            EmacsKey::Char('u') => {
+                editor.undo();
+            }
+            EmacsKey::Char('h') => {
+                editor.set_selection_anchor(Some(0));
+                editor.set_cursor_offset(editor.total_chars());
+            }
+            EmacsKey::Ctrl('x') => {
+                if let Some(mark) = editor.selection_anchor().or(self.mark.take()) {
+                    let pos = editor.get_cursor().byte_offset;
+                    editor.set_selection_anchor(Some(pos));
+                    editor.set_cursor_offset(mark);
+                }
+            }
+            EmacsKey::Ctrl('g') => self.message = Some("Quit".to_string()),
+            _ => self.message = Some("C-x prefix: key is undefined".to_string()),
+        }
+        EmacsEffect::Handled
+    }
+
+    // Returns `None` when the key ends the search and should run as a normal command
+    fn isearch_key(&mut self, editor: &mut RopeEditor, key: EmacsKey) -> Option<EmacsEffect> {
+        let mut search = self.isearch.take()?;
+        match key {
+            EmacsKey::Char(c) => {
+                search.query.push(c);
+                // Growing the query keeps the match where it starts
+                let from = search.found.map_or(search.origin, |(start, _)| start);
+                search.found = find_match(editor, &search.query, from, search.forward);
+            }
+            EmacsKey::Backspace => {
+                search.query.pop();
+                search.found = find_match(editor, &search.query, search.origin, search.forward);
+            }
+            EmacsKey::Ctrl('s') | EmacsKey::Ctrl('r') => {
+                let forward = key == EmacsKey::Ctrl('s');
+                if search.query.is_empty() {
+                    search.query = self.last_search.clone();
+                }
+                let from = match (search.found, forward) {
+                    (Some((start, _)), true) => start + 1,
+                    (Some((start, _)), false) => start.saturating_sub(1),
+                    // A failing search wraps around to the other end
+                    (None, true) if !search.query.is_empty() && search.forward == forward => 0,
+                    (None, false) if !search.query.is_empty() && search.forward == forward => {
+                        editor.total_chars()
+                    }
+                    (None, _) => search.origin,
+                };
+                search.forward = forward;
+                search.found = find_match(editor, &search.query, from, forward);
+            }
+            EmacsKey::Ctrl('g') => {
+                editor.set_selection_anchor(None);
+                editor.set_cursor_offset(search.origin);
+                return Some(EmacsEffect::Handled);
+            }
+            EmacsKey::Enter | EmacsKey::Escape => {
+                self.finish_isearch(editor, &search);
+                return Some(EmacsEffect::Handled);
+            }
+            _ => {
+                self.finish_isearch(editor, &search);
+                return None;
+            }
+        }
+
+        if let Some((start, end)) = search.found {
+            // Highlight the match, with point on the far side in the search direction
+            if search.forward {
+                editor.set_selection_anchor(Some(start));
+                editor.set_cursor_offset(end);
+            } else {
+                editor.set_selection_anchor(Some(end));
+                editor.set_cursor_offset(start);
+            }
+        }
+        self.isearch = Some(search);
+        Some(EmacsEffect::Handled)
+    }
+
+    fn finish_isearch(&mut self, editor: &mut RopeEditor, search: &Isearch) {
+        editor.set_selection_anchor(None);
+        if !search.query.is_empty() {
+            self.last_search = search.query.clone();
+        }
+        if search.found.is_some() {
+            self.mark = Some(search.origin);
+            self.message = Some("Mark saved where search started".to_string());
+        }
+    }
+
+    fn kill(
+        &mut self,
+        editor: &mut RopeEditor,
+        start: usize,
+        end: usize,
+        last_command: LastCommand,
+        backward: bool,
+    ) {
+        if start >= end {
+            self.last_command = last_command;
+            return;
+        }
+        let text = editor.slice_text(start, end);
+        match self.kill_ring.last_mut() {
+            // Consecutive kills build up a single kill ring entry
+            Some(entry) if last_command == LastCommand::Kill => {
+                if backward {
+                    entry.insert_str(0, &text);
+                } else {
+                    entry.push_str(&text);
+                }
+                editor.set_clipboard(entry.clone());
+            }
+            _ => self.push_kill(editor, text),
+        }
+        editor.delete_range(start, end);
+        self.last_command = LastCommand::Kill;
+    }
+
+    fn push_kill(&mut self, editor: &mut RopeEditor, text: String) {
+        editor.set_clipboard(text.clone());
+        self.kill_ring.push(text);
+        if self.kill_ring.len() > KILL_RING_MAX {
+            self.kill_ring.remove(0);
+        }
+    }
+
+    fn yank(&mut self, editor: &mut RopeEditor) {
+        // Text copied outside the profile becomes the newest kill
+        let clipboard = editor.clipboard().to_string();
+        if !clipboard.is_empty() && self.kill_ring.last() != Some(&clipboard) {
+            self.push_kill(editor, clipboard);
+        }
+        let Some(text) = self.kill_ring.last().cloned() else {
+            self.message = Some("Kill ring is empty".to_string());
+            return;
+        };
+        let start = editor.get_cursor().byte_offset;
+        editor.set_selection_anchor(None);
+        editor.insert_text(&text);
+        self.yank_index = self.kill_ring.len() - 1;
+        self.last_yank = Some((start, start + text.chars().count()));
+        self.last_command = LastCommand::Yank;
+    }
+
+    fn yank_pop(&mut self, editor: &mut RopeEditor) {
+        let Some((start, end)) = self.last_yank else {
+            return;
+        };
+        if self.kill_ring.is_empty() {
+            return;
+        }
+        self.yank_index = (self.yank_index + self.kill_ring.len() - 1) % self.kill_ring.len();
+        let text = self.kill_ring[self.yank_index].clone();
+        editor.replace_range(start, end, &text);
+        self.last_yank = Some((start, start + text.chars().count()));
+        self.last_command = LastCommand::Yank;
+    }
+}
+
+fn find_match(
+    editor: &RopeEditor,
+    query: &str,
+    from: usize,
+    forward: bool,
+) -> Option<(usize, usize)> {
+    // Lowercase queries search case-insensitively, like Emacs' case-fold-search
+    let ignore_case = !query.chars().any(|c| c.is_uppercase());
+    let from = if forward { from } else { from + 1 };
+    editor
+        .find_text(query, from, forward, ignore_case)
+        .map(|start| (start, start + query.chars().count()))
+}
+
+fn forward_word(editor: &RopeEditor, pos: usize) -> usize {
+    let len = editor.total_chars();
+    let mut p = pos;
+    while p < len && !editor.char_at(p).is_some_and(|c| editor.is_word_char(c)) {
+        p += 1;
+    }
+    while p < len && editor.char_at(p).is_some_and(|c| editor.is_word_char(c)) {
+        p += 1;
+    }
+    p
+}
+
+fn backward_word(editor: &RopeEditor, pos: usize) -> usize {
+    let mut p = pos;
+    while p > 0
+        && !editor
+            .char_at(p - 1)
+            .is_some_and(|c| editor.is_word_char(c))
+    {
+        p -= 1;
+    }
+    while p > 0
+        && editor
+            .char_at(p - 1)
+            .is_some_and(|c| editor.is_word_char(c))
+    {
+        p -= 1;
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmacsKey::*;
+    use super::*;
+
+    // Keys the profile leaves alone are typed, as the view would
+    fn press(emacs: &mut EmacsState, editor: &mut RopeEditor, keys: &[EmacsKey]) {
+        for key in keys {
+            if emacs.handle_key(editor, *key) == EmacsEffect::Unhandled {
+                if let Char(c) = key {
+                    editor.insert_text(&c.to_string());
+                }
+            }
+        }
+    }
+
+    fn emacs() -> EmacsState {
+        let mut emacs = EmacsState::new();
+        emacs.enabled = true;
+        emacs
+    }
+
+    #[test]
+    fn kill_line_appends_and_yanks() {
+        let mut editor = RopeEditor::from_text("abc\ndef\nghi");
+        let mut emacs = emacs();
+        press(&mut emacs, &mut editor, &[Ctrl('k'), Ctrl('k'), Ctrl('k')]);
+        assert_eq!(editor.get_content(), "\nghi");
+        press(&mut emacs, &mut editor, &[Ctrl('y')]);
+        assert_eq!(editor.get_content(), "abc\ndef\nghi");
+    }
+
+    #[test]
+    fn yank_pop_cycles_the_kill_ring() {
+        let mut editor = RopeEditor::from_text("one two three");
+        let mut emacs = emacs();
+        let keys = [Meta('d'), Ctrl('f'), Meta('d'), Ctrl('e'), Ctrl('y')];
+        press(&mut emacs, &mut editor, &keys);
+        assert_eq!(editor.get_content(), "  threetwo");
+        press(&mut emacs, &mut editor, &[Meta('y')]);
+        assert_eq!(editor.get_content(), "  threeone");
+    }
+
+    #[test]
+    fn region_kill_and_undo() {
+        let mut editor = RopeEditor::from_text("hello world");
+        let mut emacs = emacs();
+        let keys = [Ctrl(' '), Meta('f'), Ctrl('w'), Meta('>'), Ctrl('y')];
+        press(&mut emacs, &mut editor, &keys);
+        assert_eq!(editor.get_content(), " worldhello");
+        press(&mut emacs, &mut editor, &[Ctrl('/')]);
+        assert_eq!(editor.get_content(), " world");
+    }
+
+    #[test]
+    fn isearch_both_ways() {
+        let mut editor = RopeEditor::from_text("foo bar foo baz");
+        let mut emacs = emacs();
+        let keys = [Ctrl('s'), Char('f'), Char('o'), Ctrl('s'), Enter];
+        press(&mut emacs, &mut editor, &keys);
+        assert_eq!(editor.get_cursor().byte_offset, 10);
+        press(
+            &mut emacs,
+            &mut editor,
+            &[Ctrl('r'), Char('b'), Char('a'), Enter],
+        );
+        assert_eq!(editor.get_cursor().byte_offset, 4);
+    }
+
+    #[test]
+    fn isearch_leaves_the_mark_where_it_started() {
+        let mut editor = RopeEditor::from_text("foo bar baz");
+        let mut emacs = emacs();
+        editor.set_cursor_offset(2);
+        press(
+            &mut emacs,
+            &mut editor,
+            &[Ctrl('s'), Char('b'), Char('a'), Char('z'), Enter],
+        );
+        assert_eq!(editor.get_cursor().byte_offset, 11);
+        assert_eq!(editor.selection_anchor(), None);
+        assert_eq!(emacs.status_text(), "Mark saved where search started");
+
+        press(&mut emacs, &mut editor, &[Ctrl('x'), Ctrl('x')]);
+        assert_eq!(editor.get_cursor().byte_offset, 2);
+        assert_eq!(editor.selection_anchor(), Some(11));
+    }
+
+    #[test]
+    fn find_text_counts_chars() {
+        let editor = RopeEditor::from_text("héllo wörld\nHÉLLO wörld");
+        assert_eq!(editor.find_text("wörld", 0, true, false), Some(6));
+        assert_eq!(editor.find_text("wörld", 7, true, false), Some(18));
+        assert_eq!(editor.find_text("wörld", 19, true, false), None);
+        assert_eq!(editor.find_text("hÉllo", 1, true, true), Some(12));
+        // Backward finds matches starting before `from`
+        assert_eq!(editor.find_text("wörld", 18, false, false), Some(6));
+        assert_eq!(editor.find_text("wörld", 19, false, false), Some(18));
+        assert_eq!(editor.find_text("wörld", 6, false, false), None);
+        assert_eq!(editor.find_text("", 0, true, false), None);
+    }
+}
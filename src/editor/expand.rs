@@ -0,0 +1,270 @@
+use super::mouse;
+use super::rope_engine::RopeEditor;
+use std::ops::Range;
+use tree_sitter::Node;
+
+// Growing and shrinking the selection along the syntax tree: word, expression,
+// argument list, statement, block, function, item. Each step is the smallest
+// node that strictly contains the selection, and for a bracketed node the inside
+// of the brackets comes first. Files without a parser step through quote and
+// bracket pairs instead.
+
+const QUOTES: &[char] = &['"', '\'', '`'];
+
+// How far the bracket fallback looks either way before giving up
+const MAX_PAIR_SCAN: usize = 100_000;
+
+/// Grows the selection one step outwards.
+pub fn expand(editor: &mut RopeEditor) {
+    let current = match editor.selection_range() {
+        Some((start, end)) => start..end,
+        None => {
+            let offset = editor.get_cursor().byte_offset;
+            offset..offset
+        }
+    };
+    // A selection changed since the last step starts a new history
+    if editor.last_expansion() != Some(current.clone()) {
+        editor.clear_expansions();
+    }
+    let Some(next) = enclosing(editor, current) else {
+        return;
+    };
+    let previous = (editor.selection_anchor(), editor.get_cursor().byte_offset);
+    editor.push_expansion(previous, next.clone());
+    editor.set_cursor_offset(next.end);
+    editor.set_selection_anchor(Some(next.start));
+}
+
+/// Goes back to the selection the last expand grew from.
+pub fn shrink(editor: &mut RopeEditor) {
+    let current = editor.selection_range().map(|(start, end)| start..end);
+    if current.is_none() || editor.last_expansion() != current {
+        editor.clear_expansions();
+        return;
+    }
+    if let Some(((anchor, cursor), _)) = editor.pop_expansion() {
+        editor.set_cursor_offset(cursor);
+        editor.set_selection_anchor(anchor);
+    }
+}
+
+/// The next range out from `range`: the word under an empty selection, then
+/// syntax nodes, or pairs without a parser.
+pub fn enclosing(editor: &RopeEditor, range: Range<usize>) -> Option<Range<usize>> {
+    if range.is_empty() {
+        if let Some(word) = word_at(editor, range.start) {
+            return Some(word);
+        }
+    }
+    match editor.with_syntax_tree(|root, rope| {
+        let start = rope.char_to_byte(range.start);
+        let end = rope.char_to_byte(range.end);
+        node_step(root, start..end)
+            .map(|bytes| rope.byte_to_char(bytes.start)..rope.byte_to_char(bytes.end))
+    }) {
+        Some(step) => step,
+        None => pair_step(editor, range),
+    }
+}
+
+// The word at or just before `offset`, if there is one
+fn word_at(editor: &RopeEditor, offset: usize) -> Option<Range<usize>> {
+    let is_word = |i: usize| editor.char_at(i).is_some_and(|c| editor.is_word_char(c));
+    let probe = if is_word(offset) {
+        offset
+    } else if offset > 0 && is_word(offset - 1) {
+        offset - 1
+    } else {
+        return None;
+    };
+    let (from, to) = mouse::word_range(editor, probe);
+    Some(from..to)
+}
+
+// Byte range of the smallest named node, or inside of a bracketed one, that
+// strictly contains `bytes`
+fn node_step(root: Node, bytes: Range<usize>) -> Option<Range<usize>> {
+    let grows = |candidate: &Range<usize>| {
+        candidate.start <= bytes.start && bytes.end <= candidate.end && *candidate != bytes
+    };
+    let mut node = root.named_descendant_for_byte_range(bytes.start, bytes.end)?;
+    loop {
+        if let Some(inner) = bracket_inside(node).filter(|inner| grows(inner)) {
+            return Some(inner);
+        }
+        if grows(&node.byte_range()) {
+            return Some(node.byte_range());
+        }
+        node = node.parent()?;
+    }
+}
+
+// From the first to the last child between a node's opening and closing
+// bracket or quote, if it has them
+fn bracket_inside(node: Node) -> Option<Range<usize>> {
+    let count = node.child_count();
+    if count < 3 {
+        return None;
+    }
+    let open = node.child(0)?;
+    let close = node.child(count - 1)?;
+    if open.is_named() || close.is_named() {
+        return None;
+    }
+    let paired = matches!(
+        (open.kind(), close.kind()),
+        ("(", ")") | ("[", "]") | ("{", "}") | ("<", ">") | ("\"", "\"") | ("'", "'") | ("`", "`")
+    );
+    if !paired {
+        return None;
+    }
+    Some(node.child(1)?.start_byte()..node.child(count - 2)?.end_byte())
+}
+
+// The smallest quote or bracket pair, inside first, that strictly contains
+// `range`
+fn pair_step(editor: &RopeEditor, range: Range<usize>) -> Option<Range<usize>> {
+    [
+        quote_pair(editor, range.clone()),
+        bracket_pair(editor, range.clone()),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|(open, close)| [open + 1..close, open..close + 1])
+    .filter(|candidate| {
+        candidate.start <= range.start && range.end <= candidate.end && *candidate != range
+    })
+    .min_by_key(|candidate| candidate.len())
+}
+
+// Offsets of the quotes around `range` on its line. A quote is open when an odd
+// number of the same quote comes before it on the line.
+fn quote_pair(editor: &RopeEditor, range: Range<usize>) -> Option<(usize, usize)> {
+    let line = editor.char_to_line(range.start);
+    let start = editor.line_to_char(line);
+    let end = start + editor.line_len(line);
+    if range.end > end {
+        return None;
+    }
+    let chars: Vec<char> = editor.slice_text(start, end).chars().collect();
+    let (from, to) = (range.start - start, range.end - start);
+    QUOTES
+        .iter()
+        .filter_map(|&quote| {
+            let before: Vec<usize> = (0..from).filter(|&i| chars[i] == quote).collect();
+            if before.len().is_multiple_of(2) {
+                return None;
+            }
+            let close = (to..chars.len()).find(|&i| chars[i] == quote)?;
+            Some((start + before.last()?, start + close))
+        })
+        .max_by_key(|&(open, _)| open)
+}
+
+// Offsets of the innermost bracket pair of the language around `range`
+fn bracket_pair(editor: &RopeEditor, range: Range<usize>) -> Option<(usize, usize)> {
+    let brackets = editor.language().brackets;
+    let opener_of = |c: char| {
+        brackets
+            .iter()
+            .find(|(_, close)| *close == c)
+            .map(|(open, _)| *open)
+    };
+
+    // Back to an opener that isn't closed before the selection
+    let mut unclosed: Vec<char> = Vec::new();
+    let mut open_at = None;
+    for i in (range.start.saturating_sub(MAX_PAIR_SCAN)..range.start).rev() {
+        let Some(c) = editor.char_at(i) else { continue };
+        if let Some(open) = opener_of(c) {
+            unclosed.push(open);
+        } else if brackets.iter().any(|(open, _)| *open == c) {
+            if unclosed.last() == Some(&c) {
+                unclosed.pop();
+            } else if unclosed.is_empty() {
+                open_at = Some((i, c));
+                break;
+            }
+        }
+    }
+    let (open_at, open) = open_at?;
+    let close = editor.language().closing_bracket(open)?;
+
+    // On to its closer, counting nested pairs of the same kind
+    let mut depth = 0usize;
+    let end = (range.end + MAX_PAIR_SCAN).min(editor.total_chars());
+    for i in range.end..end {
+        match editor.char_at(i) {
+            Some(c) if c == open => depth += 1,
+            Some(c) if c == close && depth == 0 => return Some((open_at, i)),
+            Some(c) if c == close => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(extension: &str, text: &str, cursor: usize) -> RopeEditor {
+        let mut editor = RopeEditor::from_named_text(&format!("a.{extension}"), text);
+        editor.set_cursor_offset(cursor);
+        editor
+    }
+
+    fn expansions(editor: &mut RopeEditor, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| {
+                expand(editor);
+                editor.selected_text().unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn expands_along_the_syntax_tree_and_shrinks_back() {
+        let text = "fn main() {\n    let x = foo(a, bar + 1);\n}\n";
+        let at = text.find("bar").unwrap() + 1;
+        let mut editor = editor("rs", text, at);
+        assert_eq!(
+            expansions(&mut editor, 6),
+            [
+                "bar",
+                "bar + 1",
+                "a, bar + 1",
+                "(a, bar + 1)",
+                "foo(a, bar + 1)",
+                "let x = foo(a, bar + 1);",
+            ]
+        );
+        shrink(&mut editor);
+        assert_eq!(editor.selected_text().unwrap(), "foo(a, bar + 1)");
+        for _ in 0..5 {
+            shrink(&mut editor);
+        }
+        assert_eq!(editor.selected_text(), None);
+        assert_eq!(editor.get_cursor().byte_offset, at);
+
+        // Ends at the whole file
+        assert_eq!(expansions(&mut editor, 12).last().unwrap(), text);
+    }
+
+    #[test]
+    fn falls_back_to_quote_and_bracket_pairs() {
+        let text = "say (hello \"big world\" [x]) now\n";
+        let mut editor = editor("txt", text, text.find("world").unwrap());
+        assert_eq!(
+            expansions(&mut editor, 5),
+            [
+                "world",
+                "big world",
+                "\"big world\"",
+                "hello \"big world\" [x]",
+                "(hello \"big world\" [x])",
+            ]
+        );
+    }
+}
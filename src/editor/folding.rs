@@ -0,0 +1,582 @@
+use super::rope_engine::RopeEditor;
+use dioxus::prelude::*;
+use ropey::Rope;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use tree_sitter::Node;
+
+// Code folding. Where the language has a grammar, a region is a syntax node
+// spanning several lines, less a last line that only closes it. Otherwise
+// regions come from indentation: a line starts a region when the next
+// non-blank line is indented deeper, and the region runs to the last line of
+// that deeper block. A collapsed region keeps its first line visible.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FoldRange {
+    pub start: usize,
+    /// Last line hidden when the region is collapsed
+    pub end: usize,
+    /// 1 for top-level regions
+    pub level: usize,
+}
+
+/// Collapsed regions of one file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FoldState {
+    /// First line -> last hidden line
+    collapsed: BTreeMap<usize, usize>,
+    /// Buffer and edit count the collapsed ranges were last moved to
+    synced: Option<(u64, u64)>,
+}
+
+/// Folds are kept per file for the whole session, so reopening a file restores them.
+pub static FOLDS: GlobalSignal<HashMap<PathBuf, FoldState>> = Signal::global(HashMap::new);
+
+// Indentation width in columns, None for blank lines
+fn indent_of(chars: impl Iterator<Item = char>, tab_width: usize) -> Option<usize> {
+    let mut width = 0;
+    for c in chars {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += tab_width - width % tab_width,
+            '\n' | '\r' => return None,
+            _ => return Some(width),
+        }
+    }
+    None
+}
+
+/// Indentation width of `line` in columns, None for blank lines.
+pub fn line_indent(editor: &RopeEditor, line: usize) -> Option<usize> {
+    // Only reads up to the first non-blank, so long lines stay cheap
+    indent_of(editor.line_chars(line), editor.tab_width())
+}
+
+/// Whether a fold region starts at `line`. Cheap enough to call for every
+/// rendered line: it only walks down the syntax tree to `line`, or looks at
+/// the next non-blank line.
+pub fn is_fold_start(editor: &RopeEditor, line: usize) -> bool {
+    if let Some(region) = editor.with_syntax_tree(|root, rope| syntax_region_at(root, rope, line)) {
+        return region.is_some();
+    }
+    let Some(indent) = line_indent(editor, line) else {
+        return false;
+    };
+    (line + 1..editor.line_count())
+        .find_map(|next| line_indent(editor, next))
+        .is_some_and(|next_indent| next_indent > indent)
+}
+
+/// The region starting at `line`, if any. Its level is left at 0.
+pub fn region_at(editor: &RopeEditor, line: usize) -> Option<FoldRange> {
+    match editor.with_syntax_tree(|root, rope| syntax_region_at(root, rope, line)) {
+        Some(region) => region,
+        None => indent_region_at(editor, line),
+    }
+}
+
+/// The region starting at `line` by indentation alone.
+pub fn indent_region_at(editor: &RopeEditor, line: usize) -> Option<FoldRange> {
+    let indent = line_indent(editor, line)?;
+    let mut end = line;
+    for next in line + 1..editor.line_count() {
+        match line_indent(editor, next) {
+            Some(next_indent) if next_indent > indent => end = next,
+            Some(_) => break,
+            None => {}
+        }
+    }
+    (end > line).then_some(FoldRange {
+        start: line,
+        end,
+        level: 0,
+    })
+}
+
+/// First lines of the regions that contain `line`, outermost first. A blank
+/// line belongs with the next line that has text.
+pub fn enclosing_scopes(editor: &RopeEditor, line: usize) -> Vec<usize> {
+    let Some(mut indent) = (line..editor.line_count()).find_map(|next| line_indent(editor, next))
+    else {
+        return Vec::new();
+    };
+    let mut headers = Vec::new();
+    for previous in (0..line).rev() {
+        if indent == 0 {
+            break;
+        }
+        match line_indent(editor, previous) {
+            Some(previous_indent) if previous_indent < indent => {
+                headers.push(previous);
+                indent = previous_indent;
+            }
+            _ => {}
+        }
+    }
+    headers.reverse();
+    headers
+}
+
+// Last line a node's region hides: the node's last line, unless that only
+// holds the end of the node, such as a closing brace, which stays in view
+fn node_fold_end(node: Node, rope: &Rope) -> usize {
+    let end = node.end_position();
+    let line = rope.line(end.row);
+    let column = line
+        .try_byte_to_char(end.column)
+        .unwrap_or(line.len_chars());
+    let closes_line = line
+        .chars()
+        .take(column)
+        .filter(|c| !c.is_whitespace())
+        .count()
+        <= 1;
+    if closes_line && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
+// The outermost node that starts on `line` and folds, as found by walking
+// down through the nodes that span `line`
+fn syntax_region_at(root: Node, rope: &Rope, line: usize) -> Option<FoldRange> {
+    let mut node = root;
+    'descend: loop {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            let (start, end) = (child.start_position().row, child.end_position().row);
+            if start > line {
+                break;
+            }
+            if end <= line {
+                continue;
+            }
+            if start == line {
+                // Nodes that start here nested inside this one end no later
+                let end = node_fold_end(child, rope);
+                if end > line {
+                    return Some(FoldRange {
+                        start: line,
+                        end,
+                        level: 0,
+                    });
+                }
+            }
+            node = child;
+            continue 'descend;
+        }
+        return None;
+    }
+}
+
+// Every folding node, outermost first where several start on one line
+fn syntax_regions(root: Node, rope: &Rope) -> Vec<FoldRange> {
+    let mut regions: Vec<FoldRange> = Vec::new();
+    // Last lines of the regions around the current node
+    let mut open: Vec<usize> = Vec::new();
+    let mut cursor = root.walk();
+    let mut visit = |node: Node| {
+        let start = node.start_position().row;
+        if node.end_position().row == start
+            || regions.last().is_some_and(|last| last.start == start)
+        {
+            return;
+        }
+        let end = node_fold_end(node, rope);
+        if end == start {
+            return;
+        }
+        while open.last().is_some_and(|&open_end| open_end < start) {
+            open.pop();
+        }
+        regions.push(FoldRange {
+            start,
+            end,
+            level: open.len() + 1,
+        });
+        open.push(end);
+    };
+    // Preorder, so regions come out by where they start
+    if cursor.goto_first_child() {
+        'walk: loop {
+            if cursor.node().is_named() {
+                visit(cursor.node());
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() || cursor.node() == root {
+                    break 'walk;
+                }
+            }
+        }
+    }
+    regions
+}
+
+/// Every fold region in the buffer, ordered by first line.
+pub fn fold_regions(editor: &RopeEditor) -> Vec<FoldRange> {
+    if let Some(regions) = editor.with_syntax_tree(syntax_regions) {
+        return regions;
+    }
+    let mut regions = Vec::new();
+    // Open regions as (start, indent), outermost first
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut last_non_blank = 0;
+    fn close(open: &mut Vec<(usize, usize)>, regions: &mut Vec<FoldRange>, end: usize) {
+        if let Some((start, _)) = open.pop() {
+            if end > start {
+                regions.push(FoldRange {
+                    start,
+                    end,
+                    level: open.len() + 1,
+                });
+            }
+        }
+    }
+
+    for line in 0..editor.line_count() {
+        let Some(indent) = line_indent(editor, line) else {
+            continue;
+        };
+        while open
+            .last()
+            .is_some_and(|&(_, open_indent)| open_indent >= indent)
+        {
+            close(&mut open, &mut regions, last_non_blank);
+        }
+        open.push((line, indent));
+        last_non_blank = line;
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut regions, last_non_blank);
+    }
+
+    // Nested regions close first; list them by where they start
+    regions.sort_by_key(|region| region.start);
+    regions
+}
+
+impl FoldState {
+    pub fn is_collapsed(&self, line: usize) -> bool {
+        self.collapsed.contains_key(&line)
+    }
+
+    pub fn collapse(&mut self, region: FoldRange) {
+        self.collapsed.insert(region.start, region.end);
+    }
+
+    pub fn expand(&mut self, start: usize) {
+        self.collapsed.remove(&start);
+    }
+
+    pub fn expand_all(&mut self) {
+        self.collapsed.clear();
+    }
+
+    /// First line of the outermost collapsed region that hides `line`.
+    pub fn hidden_by(&self, line: usize) -> Option<usize> {
+        self.collapsed
+            .range(..line)
+            .find(|&(_, &end)| end >= line)
+            .map(|(&start, _)| start)
+    }
+
+    /// Expands every collapsed region that hides `line`.
+    pub fn reveal(&mut self, line: usize) {
+        self.collapsed
+            .retain(|&start, &mut end| !(start < line && line <= end));
+    }
+
+    /// Moves the collapsed regions along with the edits made to `editor` since
+    /// the last call, then re-measures them against the new text.
+    pub fn sync(&mut self, editor: &RopeEditor) {
+        let now = (editor.id(), editor.edit_count());
+        let since = match self.synced {
+            Some(synced) if synced == now => return,
+            Some((id, seen)) if id == editor.id() => editor.edits_since(seen),
+            _ => None,
+        };
+        self.synced = Some(now);
+        if self.collapsed.is_empty() {
+            return;
+        }
+
+        let mut starts: Vec<usize> = self.collapsed.keys().copied().collect();
+        // Edits made in another buffer of the file, or before it was loaded
+        // again, can't be followed; the regions stay on their lines
+        if let Some(edits) = since {
+            for edit in edits {
+                starts.retain_mut(|start| match edit.moved_line(*start) {
+                    Some(moved) => {
+                        *start = moved;
+                        true
+                    }
+                    None => false,
+                });
+            }
+        }
+        self.collapsed.clear();
+        for start in starts {
+            if let Some(region) = region_at(editor, start) {
+                self.collapse(region);
+            }
+        }
+    }
+
+    pub fn fold_map(&self, line_count: usize) -> FoldMap {
+        // Merge the hidden ranges of nested and overlapping regions
+        let mut hidden: Vec<(usize, usize)> = Vec::new();
+        for (&start, &end) in &self.collapsed {
+            let (first, last) = (start + 1, end.min(line_count.saturating_sub(1)));
+            if first > last {
+                continue;
+            }
+            match hidden.last_mut() {
+                Some((_, previous_last)) if first <= *previous_last + 1 => {
+                    *previous_last = (*previous_last).max(last);
+                }
+                _ => hidden.push((first, last)),
+            }
+        }
+        FoldMap { hidden, line_count }
+    }
+}
+
+/// Maps between buffer lines and visible rows once collapsed regions are hidden.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FoldMap {
+    /// Disjoint, sorted, inclusive ranges of hidden lines
+    hidden: Vec<(usize, usize)>,
+    line_count: usize,
+}
+
+impl FoldMap {
+    pub fn visible_count(&self) -> usize {
+        let hidden: usize = self
+            .hidden
+            .iter()
+            .map(|(first, last)| last - first + 1)
+            .sum();
+        self.line_count.saturating_sub(hidden).max(1)
+    }
+
+    pub fn is_hidden(&self, line: usize) -> bool {
+        self.hidden
+            .iter()
+            .any(|&(first, last)| first <= line && line <= last)
+    }
+
+    /// Row a line is shown on; hidden lines map to the row of their fold.
+    pub fn to_row(&self, line: usize) -> usize {
+        let mut row = line;
+        for &(first, last) in &self.hidden {
+            if line < first {
+                break;
+            }
+            row -= line.min(last) - first + 1;
+        }
+        row
+    }
+
+    /// Buffer line shown on `row`.
+    pub fn to_line(&self, row: usize) -> usize {
+        let mut line = row;
+        for &(first, last) in &self.hidden {
+            if line < first {
+                break;
+            }
+            line += last - first + 1;
+        }
+        line.min(self.line_count.saturating_sub(1))
+    }
+}
+
+/// Row mapping for `editor`'s file; identity when nothing is folded.
+pub fn fold_map(editor: &RopeEditor) -> FoldMap {
+    let line_count = editor.line_count();
+    match FOLDS.read().get(editor.file_path()) {
+        Some(state) => state.fold_map(line_count),
+        None => FoldMap {
+            hidden: Vec::new(),
+            line_count,
+        },
+    }
+}
+
+/// Runs `change` on the fold state of `editor`'s file.
+pub fn update_folds(editor: &RopeEditor, change: impl FnOnce(&mut FoldState)) {
+    let mut folds = FOLDS.write();
+    let state = folds
+        .entry(editor.file_path().clone())
+        .or_insert_with(|| FoldState {
+            synced: Some((editor.id(), editor.edit_count())),
+            ..FoldState::default()
+        });
+    change(state);
+}
+
+/// Innermost region containing `line` that is not collapsed yet.
+pub fn region_to_fold(editor: &RopeEditor, state: &FoldState, line: usize) -> Option<FoldRange> {
+    fold_regions(editor)
+        .into_iter()
+        .filter(|region| {
+            region.start <= line && line <= region.end && !state.is_collapsed(region.start)
+        })
+        .max_by_key(|region| region.start)
+}
+
+/// Collapses every region at `level` or deeper and expands the shallower ones.
+pub fn fold_to_level(editor: &RopeEditor, state: &mut FoldState, level: usize) {
+    state.expand_all();
+    for region in fold_regions(editor) {
+        if region.level >= level {
+            state.collapse(region);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTED: &str = "fn a() {\n    let x = 1;\n    if x {\n        y();\n\n        z();\n    }\n}\n\nfn b() {\n    c();\n}\n";
+
+    fn range(start: usize, end: usize, level: usize) -> FoldRange {
+        FoldRange { start, end, level }
+    }
+
+    // Starts `state` off at the editor's current edit, as `update_folds` does
+    fn update_state(editor: &RopeEditor, state: &mut FoldState) {
+        state.synced = Some((editor.id(), editor.edit_count()));
+    }
+
+    #[test]
+    fn indentation_regions_run_to_the_last_deeper_line() {
+        let editor = RopeEditor::from_text(NESTED);
+        assert_eq!(
+            fold_regions(&editor),
+            vec![range(0, 6, 1), range(2, 5, 2), range(9, 10, 1)]
+        );
+        assert!(is_fold_start(&editor, 0));
+        assert!(!is_fold_start(&editor, 1));
+        assert_eq!(region_at(&editor, 2), Some(range(2, 5, 0)));
+        assert_eq!(region_at(&editor, 4), None);
+    }
+
+    #[test]
+    fn syntax_regions_keep_the_closing_line_in_view() {
+        let editor = RopeEditor::from_named_text("a.rs", NESTED);
+        assert_eq!(
+            fold_regions(&editor),
+            vec![range(0, 6, 1), range(2, 5, 2), range(9, 10, 1)]
+        );
+        assert_eq!(region_at(&editor, 0), Some(range(0, 6, 0)));
+        assert_eq!(region_at(&editor, 1), None);
+        assert!(is_fold_start(&editor, 9));
+        assert!(!is_fold_start(&editor, 11));
+    }
+
+    #[test]
+    fn syntax_regions_follow_nodes_that_indentation_misses() {
+        // The call's arguments are not indented, and the string spans lines
+        let text = "fn a() {\nfoo(\n1,\n2);\nlet s = \"one\ntwo\";\n}\n";
+        let editor = RopeEditor::from_named_text("a.rs", text);
+        assert_eq!(indent_region_at(&editor, 1), None);
+        assert_eq!(region_at(&editor, 1), Some(range(1, 3, 0)));
+        assert_eq!(region_at(&editor, 4), Some(range(4, 5, 0)));
+        assert_eq!(
+            fold_regions(&editor),
+            vec![range(0, 5, 1), range(1, 3, 2), range(4, 5, 2)]
+        );
+    }
+
+    #[test]
+    fn collapsed_regions_map_lines_to_rows() {
+        let editor = RopeEditor::from_text(NESTED);
+        let mut state = FoldState::default();
+        state.collapse(region_at(&editor, 2).unwrap());
+        state.collapse(region_at(&editor, 9).unwrap());
+        let map = state.fold_map(editor.line_count());
+        assert_eq!(editor.line_count(), 13);
+        assert_eq!(map.visible_count(), 9);
+        assert_eq!((map.to_row(2), map.to_row(4), map.to_row(6)), (2, 2, 3));
+        assert_eq!((map.to_line(3), map.to_line(6), map.to_line(7)), (6, 9, 11));
+        for row in 0..map.visible_count() {
+            assert_eq!(map.to_row(map.to_line(row)), row);
+        }
+        assert!(map.is_hidden(10) && !map.is_hidden(9));
+        assert_eq!(state.hidden_by(4), Some(2));
+
+        state.collapse(region_at(&editor, 0).unwrap());
+        assert_eq!(state.hidden_by(4), Some(0));
+        assert_eq!(state.fold_map(editor.line_count()).to_line(1), 7);
+    }
+
+    #[test]
+    fn fold_to_level_and_reveal() {
+        let editor = RopeEditor::from_text(NESTED);
+        let mut state = FoldState::default();
+        fold_to_level(&editor, &mut state, 2);
+        assert!(state.is_collapsed(2) && !state.is_collapsed(0));
+        assert_eq!(
+            region_to_fold(&editor, &FoldState::default(), 3).map(|region| region.start),
+            Some(2)
+        );
+        state.reveal(4);
+        assert!(!state.is_collapsed(2));
+    }
+
+    #[test]
+    fn sync_moves_folds_with_edits_away_from_the_cursor() {
+        let mut editor = RopeEditor::from_named_text("a.rs", NESTED);
+        let mut state = FoldState::default();
+        update_state(&editor, &mut state);
+        state.collapse(region_at(&editor, 9).unwrap());
+
+        // Lines put in above the fold while the cursor stays below it
+        editor.set_cursor(0, 0);
+        editor.insert_text("// one\n// two\n");
+        editor.set_cursor(13, 0);
+        state.sync(&editor);
+        assert!(state.is_collapsed(11) && !state.is_collapsed(9));
+        assert_eq!(state.fold_map(editor.line_count()).to_line(12), 13);
+
+        // Undo takes them out again
+        assert!(editor.undo());
+        state.sync(&editor);
+        assert!(state.is_collapsed(9) && !state.is_collapsed(11));
+    }
+
+    #[test]
+    fn sync_drops_folds_whose_first_line_was_deleted() {
+        let mut editor = RopeEditor::from_text(NESTED);
+        let mut state = FoldState::default();
+        update_state(&editor, &mut state);
+        state.collapse(region_at(&editor, 2).unwrap());
+        state.collapse(region_at(&editor, 9).unwrap());
+
+        // Lines 1 to 3, taking the start of the inner region with them
+        let (start, end) = (editor.line_to_char(1), editor.line_to_char(4));
+        editor.delete_range(start, end);
+        state.sync(&editor);
+        assert!(state.is_collapsed(6));
+        assert!(!state.is_collapsed(1) && !state.is_collapsed(2));
+    }
+
+    #[test]
+    fn sync_remeasures_a_fold_after_typing_inside_it() {
+        let mut editor = RopeEditor::from_text(NESTED);
+        let mut state = FoldState::default();
+        update_state(&editor, &mut state);
+        state.collapse(region_at(&editor, 9).unwrap());
+
+        editor.set_cursor(10, 0);
+        editor.insert_text("    d();\n");
+        state.sync(&editor);
+        assert!(state.fold_map(editor.line_count()).is_hidden(11));
+        assert_eq!(state.hidden_by(11), Some(9));
+    }
+}
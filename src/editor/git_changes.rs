@@ -0,0 +1,204 @@
+use dioxus::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Lines changed since the last commit, from a line diff of the buffer against
+// the file at HEAD.
+
+/// Beyond this many differing lines the diff gives up and marks the whole
+/// changed stretch as modified
+const MAX_EDITS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    /// Lines were removed just before `start`
+    Deleted,
+}
+
+/// A run of changed buffer lines, `start..end`; empty for deletions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineChange {
+    pub kind: ChangeKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Changes of every open file that git tracks.
+pub static GIT_CHANGES: GlobalSignal<HashMap<PathBuf, Vec<LineChange>>> =
+    Signal::global(HashMap::new);
+
+/// Compares the lines of `base` with those of `current`.
+pub fn diff_lines(base: &str, current: &str) -> Vec<LineChange> {
+    let base: Vec<&str> = base.lines().collect();
+    let current: Vec<&str> = current.lines().collect();
+
+    // Most edits are local, so only diff what lies between the common ends
+    let prefix = base
+        .iter()
+        .zip(&current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &base[prefix..base.len() - suffix];
+    let b = &current[prefix..current.len() - suffix];
+
+    let matches = matching_lines(a, b).unwrap_or_default();
+    let mut changes = Vec::new();
+    let (mut a_next, mut b_next) = (0, 0);
+    for (a_match, b_match) in matches.into_iter().chain([(a.len(), b.len())]) {
+        let (removed, added) = (a_match - a_next, b_match - b_next);
+        let kind = match (removed, added) {
+            (0, 0) => None,
+            (0, _) => Some(ChangeKind::Added),
+            (_, 0) => Some(ChangeKind::Deleted),
+            _ => Some(ChangeKind::Modified),
+        };
+        if let Some(kind) = kind {
+            changes.push(LineChange {
+                kind,
+                start: prefix + b_next,
+                end: prefix + b_match,
+            });
+        }
+        (a_next, b_next) = (a_match + 1, b_match + 1);
+    }
+    changes
+}
+
+// Myers' diff: the pairs of equal lines, in order, of a shortest edit from `a`
+// to `b`. None when that edit is longer than MAX_EDITS
+fn matching_lines(a: &[&str], b: &[&str]) -> Option<Vec<(usize, usize)>> {
+    if a.is_empty() || b.is_empty() {
+        return Some(Vec::new());
+    }
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(MAX_EDITS as isize);
+    let offset = max + 1;
+    // Furthest x reached on each diagonal k = x - y
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // v for diagonals -d..=d after each step d, to walk the edit back
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, d, n, m));
+            }
+        }
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], edits: isize, n: isize, m: isize) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=edits).rev() {
+        let previous = &trace[d as usize - 1];
+        let at = |k: isize| previous[(k + d - 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+    matches.reverse();
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(kind: ChangeKind, start: usize, end: usize) -> LineChange {
+        LineChange { kind, start, end }
+    }
+
+    #[test]
+    fn diff_lines_tells_added_modified_and_deleted_lines_apart() {
+        assert_eq!(diff_lines("a\nb\nc\n", "a\nb\nc\n"), vec![]);
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nx\nb\nc\n"),
+            vec![change(ChangeKind::Added, 1, 2)]
+        );
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nc\n"),
+            vec![change(ChangeKind::Deleted, 1, 1)]
+        );
+        assert_eq!(
+            diff_lines("a\nb\nc\n", "a\nB\nc\n"),
+            vec![change(ChangeKind::Modified, 1, 2)]
+        );
+        assert_eq!(
+            diff_lines("", "a\nb\n"),
+            vec![change(ChangeKind::Added, 0, 2)]
+        );
+        assert_eq!(
+            diff_lines("a\nb\n", ""),
+            vec![change(ChangeKind::Deleted, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn diff_lines_finds_every_hunk() {
+        assert_eq!(
+            diff_lines("1\n2\n3\n4\n5\n6\n7\n8\n", "1\nX\n3\n4\n5\nnew\n6\n8\n"),
+            vec![
+                change(ChangeKind::Modified, 1, 2),
+                change(ChangeKind::Added, 5, 6),
+                change(ChangeKind::Deleted, 7, 7),
+            ]
+        );
+        assert_eq!(
+            diff_lines("a\nb\nc\nd\n", "b\nx\nd\ne\n"),
+            vec![
+                change(ChangeKind::Deleted, 0, 0),
+                change(ChangeKind::Modified, 1, 2),
+                change(ChangeKind::Added, 3, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_lines_marks_everything_modified_when_the_diff_is_too_costly() {
+        let base: String = (0..3000).map(|i| format!("a{i}\n")).collect();
+        let current: String = (0..3000).map(|i| format!("b{i}\n")).collect();
+        assert_eq!(
+            diff_lines(&base, &current),
+            vec![change(ChangeKind::Modified, 0, 3000)]
+        );
+    }
+}
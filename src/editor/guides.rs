@@ -0,0 +1,183 @@
+use super::folding;
+use super::rope_engine::RopeEditor;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+// Aids drawn over the text: whitespace glyphs and indent guides. Rulers don't
+// depend on the lines, so the view draws them once across the whole height.
+
+pub const SPACE_GLYPH: char = '\u{00B7}';
+pub const TAB_GLYPH: char = '\u{2192}';
+
+/// Which whitespace is drawn as faint glyphs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WhitespaceMode {
+    #[default]
+    None,
+    /// Only whitespace at the end of lines
+    Trailing,
+    All,
+}
+
+impl WhitespaceMode {
+    pub const ALL: [WhitespaceMode; 3] = [
+        WhitespaceMode::None,
+        WhitespaceMode::Trailing,
+        WhitespaceMode::All,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WhitespaceMode::None => "none",
+            WhitespaceMode::Trailing => "trailing",
+            WhitespaceMode::All => "all",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// Splits the whitespace out of highlighted tokens into tokens of class
+/// `glyph_class`, drawn as glyphs. Columns count from the first token; in
+/// `Trailing` mode only whitespace from column `trailing` on is shown.
+pub fn show_whitespace<C: Copy>(
+    tokens: Vec<(String, C)>,
+    mode: WhitespaceMode,
+    trailing: usize,
+    glyph_class: C,
+) -> Vec<(String, C)> {
+    let from = match mode {
+        WhitespaceMode::None => return tokens,
+        WhitespaceMode::Trailing => trailing,
+        WhitespaceMode::All => 0,
+    };
+    let mut shown = Vec::with_capacity(tokens.len());
+    let mut column = 0;
+    for (text, class_) in tokens {
+        let (mut plain, mut glyphs) = (String::new(), String::new());
+        for c in text.chars() {
+            let glyph = match c {
+                ' ' => Some(SPACE_GLYPH),
+                '\t' => Some(TAB_GLYPH),
+                _ => None,
+            };
+            match glyph.filter(|_| column >= from) {
+                Some(glyph) => {
+                    if !plain.is_empty() {
+                        shown.push((std::mem::take(&mut plain), class_));
+                    }
+                    glyphs.push(glyph);
+                }
+                None => {
+                    if !glyphs.is_empty() {
+                        shown.push((std::mem::take(&mut glyphs), glyph_class));
+                    }
+                    plain.push(c);
+                }
+            }
+            column += 1;
+        }
+        if !plain.is_empty() {
+            shown.push((plain, class_));
+        }
+        if !glyphs.is_empty() {
+            shown.push((glyphs, glyph_class));
+        }
+    }
+    shown
+}
+
+/// Indent guides of `line`: the screen column of each tab stop within its
+/// indentation, which is also the indentation width the guide marks. A blank
+/// line takes the guides of the deeper of its neighbours, so guides run on
+/// through blank lines in a block.
+pub fn indent_guides(editor: &RopeEditor, line: usize) -> Vec<usize> {
+    let indent = folding::line_indent(editor, line).or_else(|| {
+        let previous = (0..line)
+            .rev()
+            .find_map(|previous| folding::line_indent(editor, previous));
+        let next =
+            (line + 1..editor.line_count()).find_map(|next| folding::line_indent(editor, next));
+        previous.max(next)
+    });
+    match indent {
+        Some(indent) => (0..indent).step_by(editor.tab_width()).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The innermost scope around `line`: the indentation width whose guide is
+/// highlighted and the lines it is highlighted on.
+pub fn active_scope(editor: &RopeEditor, line: usize) -> Option<(usize, RangeInclusive<usize>)> {
+    let header = *folding::enclosing_scopes(editor, line).last()?;
+    let region = folding::indent_region_at(editor, header)?;
+    Some((
+        folding::line_indent(editor, header)?,
+        region.start + 1..=region.end,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTED: &str = "fn a() {\n    let x = 1;\n    if x {\n        y();\n\n        z();\n    }\n}\n\n\tfn b() {\n\t\tc();\n";
+
+    #[test]
+    fn guides_stand_at_the_tab_stops_of_the_indentation() {
+        let mut editor = RopeEditor::from_text(NESTED);
+        assert!(indent_guides(&editor, 0).is_empty());
+        assert_eq!(indent_guides(&editor, 1), vec![0]);
+        assert_eq!(indent_guides(&editor, 3), vec![0, 4]);
+        // Blank lines take the guides of the deeper neighbour
+        assert_eq!(indent_guides(&editor, 4), vec![0, 4]);
+        assert_eq!(indent_guides(&editor, 8), vec![0]);
+        // Tabs are measured, not counted
+        assert_eq!(indent_guides(&editor, 10), vec![0, 4]);
+        editor.set_tab_width(8);
+        assert_eq!(indent_guides(&editor, 10), vec![0, 8]);
+        assert_eq!(indent_guides(&editor, 3), vec![0]);
+    }
+
+    #[test]
+    fn active_scope_is_the_innermost_block() {
+        let editor = RopeEditor::from_text(NESTED);
+        assert_eq!(active_scope(&editor, 3), Some((4, 3..=5)));
+        assert_eq!(active_scope(&editor, 4), Some((4, 3..=5)));
+        assert_eq!(active_scope(&editor, 1), Some((0, 1..=6)));
+        assert_eq!(active_scope(&editor, 7), None);
+    }
+
+    #[test]
+    fn show_whitespace_splits_out_glyphs() {
+        let tokens = vec![("  a b ".to_string(), 1u8), ("\t".to_string(), 2u8)];
+        let glyphs = |text: &str| (text.to_string(), 9u8);
+        let text = |text: &str| (text.to_string(), 1u8);
+        assert_eq!(
+            show_whitespace(tokens.clone(), WhitespaceMode::None, 0, 9),
+            tokens
+        );
+        assert_eq!(
+            show_whitespace(tokens.clone(), WhitespaceMode::All, 0, 9),
+            vec![
+                glyphs("\u{B7}\u{B7}"),
+                text("a"),
+                glyphs("\u{B7}"),
+                text("b"),
+                glyphs("\u{B7}"),
+                glyphs("\u{2192}"),
+            ]
+        );
+        assert_eq!(
+            show_whitespace(tokens, WhitespaceMode::Trailing, 5, 9),
+            vec![text("  a b"), glyphs("\u{B7}"), glyphs("\u{2192}")]
+        );
+        assert_eq!(
+            WhitespaceMode::from_name("trailing"),
+            Some(WhitespaceMode::Trailing)
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use super::decorations::{DecorationKind, InlineDecoration, Layer};
+use crate::lsp::protocol::Position;
+use dioxus::prelude::*;
+use ropey::Rope;
+use serde_json::Value;
+
+// Inlay hints: the types and parameter names a language server would have the
+// code spell out, drawn as decorations where they would be written.
+
+/// Bumped when a server asks for its hints to be fetched again.
+pub static REFRESH: GlobalSignal<u64> = Signal::global(|| 0);
+
+pub fn refresh() {
+    *REFRESH.write() += 1;
+}
+
+/// The decorations for a `textDocument/inlayHint` answer about `text`.
+pub fn from_response(text: &Rope, response: &Value) -> Layer {
+    let mut layer = Layer::default();
+    for hint in response.as_array().into_iter().flatten() {
+        let Some(position) = hint.get("position").and_then(Position::from_json) else {
+            continue;
+        };
+        let label = label(&hint["label"]);
+        if label.trim().is_empty() {
+            continue;
+        }
+        let offset = position.to_char(text);
+        let line = text.char_to_line(offset);
+        let kind = match hint["kind"].as_u64() {
+            Some(1) => DecorationKind::Type,
+            Some(2) => DecorationKind::Parameter,
+            _ => DecorationKind::Other,
+        };
+        layer.push(
+            text,
+            line,
+            InlineDecoration {
+                column: offset - text.line_to_char(line),
+                text: label,
+                kind,
+                padding_left: hint["paddingLeft"] == true,
+                padding_right: hint["paddingRight"] == true,
+                tooltip: tooltip(&hint["tooltip"]),
+            },
+        );
+    }
+    layer
+}
+
+// A label is a string, or parts of one that may each link somewhere
+fn label(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["value"].as_str())
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+// A tooltip is a string or MarkupContent
+fn tooltip(value: &Value) -> Option<String> {
+    let text = value.as_str().or_else(|| value["value"].as_str())?;
+    (!text.trim().is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::decorations::{self, LineDecorations, Source, DECORATIONS};
+    use crate::editor::rope_engine::RopeEditor;
+    use crate::editor::wrap::VisualRow;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    #[test]
+    fn hints_become_decorations_at_char_columns() {
+        let mut editor = RopeEditor::new();
+        editor.insert_text("let \u{e9} = f(1);\n");
+        let response = json!([
+            { "position": { "line": 0, "character": 5 }, "label": ": u8", "kind": 1 },
+            { "position": { "line": 0, "character": 10 },
+              "label": [{ "value": "a" }, { "value": ":" }], "kind": 2, "paddingRight": true,
+              "tooltip": { "kind": "markdown", "value": "the a" } },
+            // Nothing to show
+            { "position": { "line": 0, "character": 3 }, "label": "  " },
+        ]);
+        let layer = from_response(editor.rope(), &response);
+        let decorations = VirtualDom::new(|| rsx! {}).in_runtime(|| {
+            let path = PathBuf::from("/tmp/inlay_hints_test.rs");
+            decorations::set_layer(&path, Source::InlayHints, layer);
+            LineDecorations::new(&editor, DECORATIONS.peek().get(&path))
+        });
+        let shown = decorations.on_row(&VisualRow {
+            line: 0,
+            start: 0,
+            end: None,
+            indent: 0,
+        });
+        assert_eq!(shown.len(), 2);
+        assert_eq!(
+            (shown[0].column, shown[0].text.as_str(), shown[0].kind),
+            (5, ": u8", DecorationKind::Type)
+        );
+        assert_eq!(
+            (shown[1].column, shown[1].text.as_str(), shown[1].kind),
+            (10, "a:", DecorationKind::Parameter)
+        );
+        assert!(shown[1].padding_right && !shown[1].padding_left);
+        assert_eq!(shown[1].tooltip.as_deref(), Some("the a"));
+    }
+}
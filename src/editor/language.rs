@@ -0,0 +1,506 @@
+use super::syntax::Grammar;
+use dioxus::prelude::*;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// The languages the editor knows. A buffer's language is picked from a modeline,
+// the file name, its extension or a shebang line, in that order, and can be
+// overridden per file from the status bar.
+
+// Lines at the start and end of a file searched for a Vim modeline, as Vim does
+const MODELINE_LINES: usize = 5;
+
+/// Language picker entry that drops a file's override.
+pub const AUTO_DETECT: &str = "Auto Detect";
+
+#[derive(Debug, PartialEq)]
+pub struct Language {
+    /// Identifier used by modelines and overrides, e.g. "rust"
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Shown in the file tree
+    pub icon: &'static str,
+    pub extensions: &'static [&'static str],
+    /// Whole file names, for files without a telling extension
+    pub file_names: &'static [&'static str],
+    /// Interpreters named on a shebang line, without version numbers
+    pub interpreters: &'static [&'static str],
+    /// Other names Vim and Emacs modelines use for it
+    pub aliases: &'static [&'static str],
+    pub grammar: Option<Grammar>,
+    pub line_comment: Option<&'static str>,
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub brackets: &'static [(char, char)],
+    /// Inserted for one level of indentation
+    pub indent_unit: &'static str,
+    /// Line endings besides opening brackets that indent the next line
+    pub indent_after: &'static [&'static str],
+    /// Characters besides letters and digits that belong to words
+    pub word_chars: &'static str,
+}
+
+const C_BRACKETS: &[(char, char)] = &[('{', '}'), ('[', ']'), ('(', ')')];
+
+pub const PLAIN_TEXT: Language = Language {
+    id: "plaintext",
+    name: "Plain Text",
+    icon: "📄",
+    extensions: &["txt", "text", "log"],
+    file_names: &[],
+    interpreters: &[],
+    aliases: &["text", "fundamental"],
+    grammar: None,
+    line_comment: None,
+    block_comment: None,
+    brackets: C_BRACKETS,
+    indent_unit: "    ",
+    indent_after: &[],
+    word_chars: "_",
+};
+
+pub static LANGUAGES: &[Language] = &[
+    PLAIN_TEXT,
+    Language {
+        id: "rust",
+        name: "Rust",
+        icon: "🦀",
+        extensions: &["rs"],
+        file_names: &[],
+        interpreters: &[],
+        aliases: &[],
+        grammar: Some(Grammar::Rust),
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        brackets: C_BRACKETS,
+        indent_unit: "    ",
+        indent_after: &[],
+        word_chars: "_",
+    },
+    Language {
+        id: "toml",
+        name: "TOML",
+        icon: "⚙️",
+        extensions: &["toml"],
+        file_names: &["Cargo.lock", "Pipfile", "poetry.lock"],
+        interpreters: &[],
+        aliases: &["conf-toml"],
+        grammar: Some(Grammar::Toml),
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: &[('[', ']'), ('{', '}')],
+        indent_unit: "    ",
+        indent_after: &[],
+        word_chars: "_-",
+    },
+    Language {
+        id: "json",
+        name: "JSON",
+        icon: "🧾",
+        extensions: &["json", "jsonc", "json5"],
+        file_names: &[".babelrc", "composer.lock"],
+        interpreters: &[],
+        aliases: &[],
+        grammar: Some(Grammar::Json),
+        line_comment: None,
+        block_comment: None,
+        brackets: &[('{', '}'), ('[', ']')],
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_",
+    },
+    Language {
+        id: "markdown",
+        name: "Markdown",
+        icon: "📝",
+        extensions: &["md", "markdown", "mdx"],
+        file_names: &[],
+        interpreters: &[],
+        aliases: &["md", "gfm"],
+        grammar: Some(Grammar::Markdown),
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        brackets: &[('[', ']'), ('(', ')')],
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_",
+    },
+    Language {
+        id: "javascript",
+        name: "JavaScript",
+        icon: "🟨",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        file_names: &[],
+        interpreters: &["node", "nodejs", "deno", "bun"],
+        aliases: &["js", "js2", "rjsx"],
+        grammar: Some(Grammar::JavaScript),
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        brackets: C_BRACKETS,
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_$",
+    },
+    Language {
+        id: "typescript",
+        name: "TypeScript",
+        icon: "🟦",
+        extensions: &["ts", "mts", "cts"],
+        file_names: &[],
+        interpreters: &["ts-node", "tsx"],
+        aliases: &["ts"],
+        grammar: Some(Grammar::TypeScript),
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        brackets: C_BRACKETS,
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_$",
+    },
+    Language {
+        id: "typescriptreact",
+        name: "TypeScript JSX",
+        icon: "🟦",
+        extensions: &["tsx"],
+        file_names: &[],
+        interpreters: &[],
+        aliases: &["tsx"],
+        grammar: Some(Grammar::Tsx),
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        brackets: C_BRACKETS,
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_$",
+    },
+    Language {
+        id: "python",
+        name: "Python",
+        icon: "🐍",
+        extensions: &["py", "pyi", "pyw"],
+        file_names: &["SConstruct", "SConscript"],
+        interpreters: &["python", "pypy"],
+        aliases: &["py"],
+        grammar: Some(Grammar::Python),
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: C_BRACKETS,
+        indent_unit: "    ",
+        indent_after: &[":"],
+        word_chars: "_",
+    },
+    Language {
+        id: "html",
+        name: "HTML",
+        icon: "🌐",
+        extensions: &["html", "htm", "xhtml"],
+        file_names: &[],
+        interpreters: &[],
+        aliases: &["mhtml", "web"],
+        grammar: Some(Grammar::Html),
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+        brackets: &[('<', '>'), ('{', '}'), ('(', ')')],
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_-",
+    },
+    Language {
+        id: "css",
+        name: "CSS",
+        icon: "🎨",
+        extensions: &["css"],
+        file_names: &[],
+        interpreters: &[],
+        aliases: &[],
+        grammar: Some(Grammar::Css),
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+        brackets: C_BRACKETS,
+        indent_unit: "  ",
+        indent_after: &[],
+        word_chars: "_-",
+    },
+    Language {
+        id: "shellscript",
+        name: "Shell Script",
+        icon: "🐚",
+        extensions: &["sh", "bash", "zsh", "ksh"],
+        file_names: &[".bashrc", ".bash_profile", ".zshrc", ".profile", "PKGBUILD"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh"],
+        aliases: &["sh", "bash", "zsh", "shell-script"],
+        grammar: Some(Grammar::Shell),
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: C_BRACKETS,
+        indent_unit: "    ",
+        indent_after: &[" then", " do", " in"],
+        word_chars: "_",
+    },
+    Language {
+        id: "yaml",
+        name: "YAML",
+        icon: "📜",
+        extensions: &["yaml", "yml"],
+        file_names: &[".clang-format"],
+        interpreters: &[],
+        aliases: &["yml"],
+        grammar: None,
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: &[('{', '}'), ('[', ']')],
+        indent_unit: "  ",
+        indent_after: &[":"],
+        word_chars: "_-",
+    },
+    Language {
+        id: "makefile",
+        name: "Makefile",
+        icon: "🛠",
+        extensions: &["mk", "mak"],
+        file_names: &["Makefile", "makefile", "GNUmakefile"],
+        interpreters: &["make"],
+        aliases: &["make", "makefile-gmake"],
+        grammar: None,
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: &[('(', ')'), ('{', '}')],
+        // Recipes must be indented with tabs
+        indent_unit: "\t",
+        indent_after: &[":"],
+        word_chars: "_-.",
+    },
+    Language {
+        id: "dockerfile",
+        name: "Dockerfile",
+        icon: "🐳",
+        extensions: &["dockerfile"],
+        file_names: &["Dockerfile", "Containerfile"],
+        interpreters: &[],
+        aliases: &["docker"],
+        grammar: None,
+        line_comment: Some("#"),
+        block_comment: None,
+        brackets: &[('[', ']'), ('{', '}'), ('(', ')')],
+        indent_unit: "    ",
+        indent_after: &["\\"],
+        word_chars: "_-",
+    },
+];
+
+/// Languages chosen for files from the status bar, for the rest of the session.
+pub static LANGUAGE_OVERRIDES: GlobalSignal<HashMap<PathBuf, &'static Language>> =
+    Signal::global(HashMap::new);
+
+impl Language {
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.word_chars.contains(c)
+    }
+
+    /// The closing bracket for `open`, if it opens a pair.
+    pub fn closing_bracket(&self, open: char) -> Option<char> {
+        self.brackets
+            .iter()
+            .find(|(o, _)| *o == open)
+            .map(|(_, close)| *close)
+    }
+
+    fn is_called(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_suffix("-mode").unwrap_or(&name);
+        self.id == name || self.name.eq_ignore_ascii_case(name) || self.aliases.contains(&name)
+    }
+}
+
+/// The language with this id, name or modeline alias.
+pub fn by_name(name: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.is_called(name.trim()))
+}
+
+/// The language for a file from its name alone.
+pub fn for_path(path: &Path) -> Option<&'static Language> {
+    let name = path.file_name()?.to_str()?;
+    if let Some(language) = LANGUAGES
+        .iter()
+        .find(|language| language.file_names.contains(&name))
+    {
+        return Some(language);
+    }
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|language| language.extensions.contains(&extension.as_str()))
+}
+
+/// The language for a file from its name and text.
+pub fn detect(path: &Path, text: &Rope) -> &'static Language {
+    let lines = text.len_lines();
+    let head = 0..lines.min(MODELINE_LINES);
+    let tail = lines.saturating_sub(MODELINE_LINES).max(head.end)..lines;
+    head.chain(tail)
+        .find_map(|line| modeline(&text.line(line).to_string()))
+        .or_else(|| for_path(path))
+        .or_else(|| shebang(&text.line(0).to_string()))
+        .unwrap_or(&PLAIN_TEXT)
+}
+
+// A Vim modeline ("vim: set ft=rust:") or an Emacs one ("-*- mode: rust -*-")
+fn modeline(line: &str) -> Option<&'static Language> {
+    if let Some((_, rest)) = line.split_once("-*-") {
+        let (settings, _) = rest.split_once("-*-")?;
+        let mode = settings
+            .split(';')
+            .find_map(|setting| match setting.split_once(':') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("mode") => Some(value),
+                Some(_) => None,
+                None => Some(setting),
+            })?;
+        return by_name(mode);
+    }
+    // Vim only takes the marker at the start of the line or after a blank
+    let rest = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let (start, _) = line.match_indices(marker).find(|(start, _)| {
+            line[..*start]
+                .chars()
+                .next_back()
+                .is_none_or(char::is_whitespace)
+        })?;
+        Some(&line[start + marker.len()..])
+    })?;
+    rest.split([' ', ':', '\t']).find_map(|option| {
+        let (key, value) = option.split_once('=')?;
+        matches!(key, "ft" | "filetype" | "syntax")
+            .then(|| by_name(value))
+            .flatten()
+    })
+}
+
+// "#!/bin/sh" or "#!/usr/bin/env -S python3 -u"
+fn shebang(line: &str) -> Option<&'static Language> {
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let interpreter = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES
+        .iter()
+        .find(|language| language.interpreters.contains(&interpreter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::actions::run_edit_command;
+    use crate::editor::rope_engine::RopeEditor;
+
+    fn detected(name: &str, text: &str) -> &'static str {
+        detect(Path::new(name), &Rope::from_str(text)).id
+    }
+
+    #[test]
+    fn detect_by_extension_file_name_and_shebang() {
+        assert_eq!(detected("a.rs", ""), "rust");
+        assert_eq!(detected("Cargo.lock", ""), "toml");
+        assert_eq!(detected("Makefile", ""), "makefile");
+        assert_eq!(detected("Dockerfile", ""), "dockerfile");
+        assert_eq!(detected("script", "#!/usr/bin/env python3.11\n"), "python");
+        assert_eq!(detected("script", "#!/bin/bash -e\n"), "shellscript");
+        assert_eq!(
+            detected("script", "#!/usr/bin/env -S node --x\n"),
+            "javascript"
+        );
+        assert_eq!(detected("unknown", ""), "plaintext");
+    }
+
+    #[test]
+    fn modelines_come_first() {
+        assert_eq!(
+            detected("a.txt", "# -*- mode: rust; coding: utf-8 -*-\n"),
+            "rust"
+        );
+        assert_eq!(detected("a.txt", "# -*- python -*-\n"), "python");
+        assert_eq!(
+            detected("a.txt", "x\n\n\n\n\n\n\n\n# vim: set ft=sh ts=4:\n"),
+            "shellscript"
+        );
+        // Only in a modeline
+        assert_eq!(detected("a.txt", "regex: ft=rust\n"), "plaintext");
+        assert_eq!(detected("a.rs", "# vim: ft=python\n"), "python");
+    }
+
+    #[test]
+    fn by_name_knows_ids_names_and_aliases() {
+        assert_eq!(by_name("js2-mode").unwrap().id, "javascript");
+        assert_eq!(by_name("TypeScript").unwrap().id, "typescript");
+        assert!(by_name("cobol").is_none());
+    }
+
+    #[test]
+    fn indent_units_are_spaces_except_in_makefiles() {
+        for language in LANGUAGES.iter().chain([&PLAIN_TEXT]) {
+            assert!(
+                !language.indent_unit.is_empty() && language.indent_unit.trim().is_empty(),
+                "{}",
+                language.id
+            );
+            let tab = language.id == "makefile";
+            assert_eq!(language.indent_unit.contains('\t'), tab, "{}", language.id);
+        }
+    }
+
+    #[test]
+    fn newline_indents_after_an_opening_line() {
+        let mut editor = RopeEditor::from_named_text("a.rs", "    fn a() {}\n");
+        editor.set_cursor(0, 12);
+        run_edit_command(&mut editor, "editor.newline");
+        assert_eq!(editor.get_content(), "    fn a() {\n        \n    }\n");
+        let cursor = editor.get_cursor();
+        assert_eq!((cursor.line, cursor.column), (1, 8));
+        editor.undo();
+        assert_eq!(editor.get_content(), "    fn a() {}\n");
+
+        let mut editor = RopeEditor::from_named_text("a.py", "  if x:\n");
+        editor.set_cursor(0, 7);
+        run_edit_command(&mut editor, "editor.newline");
+        assert_eq!(editor.get_content(), "  if x:\n      \n");
+
+        let mut editor = RopeEditor::from_named_text("Makefile", "all:\n");
+        editor.set_cursor(0, 4);
+        run_edit_command(&mut editor, "editor.newline");
+        assert_eq!(editor.get_content(), "all:\n\t\n");
+    }
+
+    #[test]
+    fn toggle_line_comment_uses_the_language_comments() {
+        let mut editor = RopeEditor::from_named_text("a.rs", "fn a() {\n    b();\n\n    c();\n}\n");
+        editor.set_selection_anchor(Some(editor.line_to_char(1)));
+        editor.set_cursor(3, 2);
+        run_edit_command(&mut editor, "editor.toggleLineComment");
+        assert_eq!(
+            editor.get_content(),
+            "fn a() {\n    // b();\n\n    // c();\n}\n"
+        );
+        run_edit_command(&mut editor, "editor.toggleLineComment");
+        assert_eq!(editor.get_content(), "fn a() {\n    b();\n\n    c();\n}\n");
+
+        // Languages without line comments wrap the line in a block comment
+        let mut editor = RopeEditor::from_named_text("a.css", "a {\n  color: red;\n}\n");
+        editor.set_cursor(1, 4);
+        run_edit_command(&mut editor, "editor.toggleLineComment");
+        assert_eq!(editor.get_content(), "a {\n  /* color: red; */\n}\n");
+        assert_eq!(editor.get_cursor().column, 7);
+        run_edit_command(&mut editor, "editor.toggleLineComment");
+        assert_eq!(editor.get_content(), "a {\n  color: red;\n}\n");
+        assert_eq!(editor.get_cursor().column, 4);
+    }
+
+    #[test]
+    fn word_chars_follow_the_language() {
+        assert!(RopeEditor::from_named_text("a.css", "a-b\n").is_word_char('-'));
+        assert!(!RopeEditor::from_named_text("a.rs", "a-b\n").is_word_char('-'));
+    }
+}
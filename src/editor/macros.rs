@@ -0,0 +1,231 @@
+use super::actions::{is_edit_command, run_edit_command, type_text};
+use super::rope_engine::RopeEditor;
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Keyboard macros: a recorded list of edit commands and typed text, replayed
+// through the same `RopeEditor` operations.
+
+// Upper bound for "play to end of file", in case a macro never reaches the end
+const MAX_PLAYBACKS: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MacroStep {
+    Command(String),
+    Text(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Repeat {
+    Times(usize),
+    /// Until a run no longer moves the cursor to a later line, which happens
+    /// once the last line of the file has been processed
+    ToEnd,
+}
+
+#[derive(Debug, Default)]
+pub struct MacroState {
+    recording: Option<Vec<MacroStep>>,
+    last: Option<Vec<MacroStep>>,
+}
+
+pub static MACROS: GlobalSignal<MacroState> = Signal::global(MacroState::default);
+
+impl MacroState {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            // An empty recording keeps the previous macro
+            Some(steps) if !steps.is_empty() => self.last = Some(steps),
+            Some(_) => {}
+            None => self.recording = Some(Vec::new()),
+        }
+    }
+
+    pub fn record_command(&mut self, command: &str) {
+        if let Some(steps) = &mut self.recording {
+            if is_edit_command(command) {
+                steps.push(MacroStep::Command(command.to_string()));
+            }
+        }
+    }
+
+    pub fn record_text(&mut self, text: &str) {
+        if let Some(steps) = &mut self.recording {
+            // Typing arrives a key at a time; keep it as one step
+            match steps.last_mut() {
+                Some(MacroStep::Text(existing)) => existing.push_str(text),
+                _ => steps.push(MacroStep::Text(text.to_string())),
+            }
+        }
+    }
+
+    pub fn last(&self) -> Option<&[MacroStep]> {
+        self.last.as_deref()
+    }
+}
+
+/// Where a macro plays: at the cursor, or with a column selection, on each of
+/// its lines at the cursor's column, or the line's end where that is shorter.
+pub fn cursors(editor: &RopeEditor) -> Vec<usize> {
+    let Some(block) = editor.block_selection() else {
+        return vec![editor.get_cursor().byte_offset];
+    };
+    block
+        .lines()
+        .map(|line| editor.line_to_char(line) + block.head.1.min(editor.line_len(line)))
+        .collect()
+}
+
+/// Plays `steps` at each cursor as a single undo step. Returns how many times the
+/// macro ran in total.
+pub fn play(
+    editor: &mut RopeEditor,
+    steps: &[MacroStep],
+    repeat: Repeat,
+    cursors: &[usize],
+) -> usize {
+    let mut cursors = cursors.to_vec();
+    cursors.sort_unstable();
+    cursors.dedup();
+    // The steps act on plain cursors, not on the column selection
+    editor.set_block_selection(None);
+
+    editor.begin_undo_group();
+    // Later cursors first, so edits don't shift the offsets still to visit
+    let runs = cursors
+        .iter()
+        .rev()
+        .map(|&offset| {
+            editor.set_cursor_offset(offset);
+            play_at_cursor(editor, steps, repeat)
+        })
+        .sum();
+    editor.end_undo_group();
+    runs
+}
+
+fn play_at_cursor(editor: &mut RopeEditor, steps: &[MacroStep], repeat: Repeat) -> usize {
+    let limit = match repeat {
+        Repeat::Times(count) => count.min(MAX_PLAYBACKS),
+        Repeat::ToEnd => MAX_PLAYBACKS,
+    };
+    let mut runs = 0;
+    while runs < limit {
+        let before_line = editor.get_cursor().line;
+        for step in steps {
+            match step {
+                MacroStep::Command(command) => {
+                    run_edit_command(editor, command);
+                }
+                MacroStep::Text(text) => type_text(editor, text),
+            }
+        }
+        runs += 1;
+
+        if repeat == Repeat::ToEnd && editor.get_cursor().line <= before_line {
+            break;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::types::BlockSelection;
+
+    fn command(name: &str) -> MacroStep {
+        MacroStep::Command(name.to_string())
+    }
+
+    fn text(text: &str) -> MacroStep {
+        MacroStep::Text(text.to_string())
+    }
+
+    // Puts "- " before a line and moves down
+    fn bullet() -> Vec<MacroStep> {
+        vec![
+            command("cursor.lineStart"),
+            text("- "),
+            command("cursor.down"),
+        ]
+    }
+
+    #[test]
+    fn records_edits_and_joins_typing() {
+        let mut macros = MacroState::default();
+        macros.toggle_recording();
+        macros.record_text("- ");
+        macros.record_command("cursor.lineStart");
+        macros.record_command("editor.save");
+        macros.record_text("x");
+        macros.record_text("y");
+        macros.toggle_recording();
+        assert_eq!(
+            macros.last().unwrap(),
+            [text("- "), command("cursor.lineStart"), text("xy")]
+        );
+        // An empty recording keeps the macro before it
+        macros.toggle_recording();
+        macros.toggle_recording();
+        assert_eq!(macros.last().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn plays_a_number_of_times_as_one_undo_step() {
+        let mut editor = RopeEditor::from_text("a\nb\nc\nd");
+        assert_eq!(play(&mut editor, &bullet(), Repeat::Times(2), &[0]), 2);
+        assert_eq!(editor.get_content(), "- a\n- b\nc\nd");
+        editor.undo();
+        assert_eq!(editor.get_content(), "a\nb\nc\nd");
+    }
+
+    #[test]
+    fn plays_to_the_end_of_the_file() {
+        let mut editor = RopeEditor::from_text("a\nb\nc\nd");
+        assert_eq!(play(&mut editor, &bullet(), Repeat::ToEnd, &[0]), 4);
+        assert_eq!(editor.get_content(), "- a\n- b\n- c\n- d");
+    }
+
+    #[test]
+    fn plays_at_every_cursor() {
+        let mut editor = RopeEditor::from_text("one\ntwo\nthree");
+        let steps = [text("<"), command("cursor.lineEnd"), text(">")];
+        assert_eq!(play(&mut editor, &steps, Repeat::Times(1), &[8, 0, 4]), 3);
+        assert_eq!(editor.get_content(), "<one>\n<two>\n<three>");
+        editor.undo();
+        assert_eq!(editor.get_content(), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn plays_on_every_line_of_a_column_selection() {
+        let mut editor = RopeEditor::from_text("one\nt\nthree\nfour");
+        editor.set_block_selection(Some(BlockSelection {
+            anchor: (0, 2),
+            head: (2, 2),
+        }));
+        let cursors = cursors(&editor);
+        assert_eq!(cursors, [2, 5, 8]);
+
+        let steps = [text("<"), command("cursor.lineEnd"), text(">")];
+        assert_eq!(play(&mut editor, &steps, Repeat::Times(1), &cursors), 3);
+        assert_eq!(editor.get_content(), "on<e>\nt<>\nth<ree>\nfour");
+        assert!(editor.block_selection().is_none());
+        editor.undo();
+        assert_eq!(editor.get_content(), "one\nt\nthree\nfour");
+    }
+
+    #[test]
+    fn saved_macros_survive_the_settings_file() {
+        let mut settings = crate::settings::Settings::default();
+        settings.macros.insert("bullet".to_string(), bullet());
+        let text = toml::to_string_pretty(&settings).unwrap();
+        let read: crate::settings::Settings = toml::from_str(&text).unwrap();
+        assert_eq!(read, settings, "{text}");
+    }
+}
@@ -0,0 +1,338 @@
+// Markdown as language servers write it in hover text: headings, paragraphs,
+// lists, fenced code and rules, with inline code, emphasis and links. Anything
+// else is kept as plain text.
+
+use serde_json::Value;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Heading(usize, Vec<Inline>),
+    Paragraph(Vec<Inline>),
+    /// Items of a bulleted or numbered list, with their markers
+    List(Vec<(String, Vec<Inline>)>),
+    Code {
+        language: String,
+        text: String,
+    },
+    Rule,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Strong(String),
+    Emphasis(String),
+    Link { text: String, url: String },
+}
+
+/// Hover contents as markdown: MarkupContent, or one or more MarkedStrings,
+/// where those with a language are code. Plain text comes back escaped.
+pub fn from_lsp(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => {
+            let parts: Vec<String> = items
+                .iter()
+                .map(from_lsp)
+                .filter(|part| !part.is_empty())
+                .collect();
+            parts.join("\n\n---\n\n")
+        }
+        Value::Object(_) => {
+            let value = contents
+                .get("value")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match (
+                contents.get("kind").and_then(Value::as_str),
+                contents.get("language").and_then(Value::as_str),
+            ) {
+                (Some("plaintext"), _) => escape(value),
+                (_, Some(language)) => format!("```{language}\n{value}\n```"),
+                _ => value.to_string(),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+// Text that reads the same once parsed as markdown
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | '#') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The blocks of `text` in order.
+pub fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(parse_inline(&paragraph.join(" "))));
+            paragraph.clear();
+        }
+    };
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if let Some(fence) = ["```", "~~~"]
+            .into_iter()
+            .find(|fence| trimmed.starts_with(fence))
+        {
+            flush(&mut paragraph, &mut blocks);
+            let language = trimmed.trim_start_matches(fence).trim().to_string();
+            let code: Vec<&str> = lines
+                .by_ref()
+                .take_while(|line| !line.trim().starts_with(fence))
+                .collect();
+            blocks.push(Block::Code {
+                language,
+                text: code.join("\n"),
+            });
+        } else if trimmed.is_empty() {
+            flush(&mut paragraph, &mut blocks);
+        } else if let Some((level, title)) = heading(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Heading(level, parse_inline(title)));
+        } else if is_rule(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            blocks.push(Block::Rule);
+        } else if let Some((marker, item)) = list_item(trimmed) {
+            flush(&mut paragraph, &mut blocks);
+            let item = (marker, parse_inline(item));
+            match blocks.last_mut() {
+                Some(Block::List(items)) => items.push(item),
+                _ => blocks.push(Block::List(vec![item])),
+            }
+        } else if paragraph.is_empty()
+            && line.starts_with([' ', '\t'])
+            && matches!(blocks.last(), Some(Block::List(_)))
+        {
+            // An indented line carries on the item above it
+            if let Some(Block::List(items)) = blocks.last_mut() {
+                let (_, inlines) = items.last_mut().expect("lists have items");
+                inlines.push(Inline::Text(" ".to_string()));
+                inlines.extend(parse_inline(trimmed));
+            }
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+// "## Title" as level 2
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')))
+        .then(|| (level, rest.trim()))
+}
+
+// Three or more of the same -, * or _, spaces allowed
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && matches!(marks[0], '-' | '*' | '_') && marks.iter().all(|&c| c == marks[0])
+}
+
+// "- item", "* item", "+ item", "1. item" or "1) item"
+fn list_item(line: &str) -> Option<(String, &str)> {
+    if let Some(item) = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+    {
+        return Some(("•".to_string(), item.trim_start()));
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    let rest = &line[digits..];
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+    let item = rest
+        .strip_prefix(". ")
+        .or_else(|| rest.strip_prefix(") "))?;
+    Some((format!("{}.", &line[..digits]), item.trim_start()))
+}
+
+/// Inline code, emphasis and links in a line of text.
+pub fn parse_inline(text: &str) -> Vec<Inline> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut inlines = Vec::new();
+    let mut plain = String::new();
+    let mut i = 0;
+    let push = |inlines: &mut Vec<Inline>, plain: &mut String, inline: Inline| {
+        if !plain.is_empty() {
+            inlines.push(Inline::Text(std::mem::take(plain)));
+        }
+        inlines.push(inline);
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1).is_some_and(char::is_ascii_punctuation) => {
+                plain.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                // A run of backticks closes on a run as long
+                let ticks = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence = vec!['`'; ticks];
+                if let Some(end) = find(&chars, i + ticks, &fence) {
+                    let code: String = chars[i + ticks..end].iter().collect();
+                    push(
+                        &mut inlines,
+                        &mut plain,
+                        Inline::Code(code.trim().to_string()),
+                    );
+                    i = end + ticks;
+                    continue;
+                }
+            }
+            '*' | '_' => {
+                let strong = chars.get(i + 1) == Some(&c);
+                let marker = if strong { vec![c, c] } else { vec![c] };
+                // snake_case words aren't emphasis
+                let inside_word = c == '_' && i > 0 && chars[i - 1].is_alphanumeric();
+                let start = i + marker.len();
+                let end = find(&chars, start, &marker).filter(|&end| end > start && !inside_word);
+                if let Some(end) = end {
+                    let inner: String = chars[start..end].iter().collect();
+                    let inline = if strong {
+                        Inline::Strong(inner)
+                    } else {
+                        Inline::Emphasis(inner)
+                    };
+                    push(&mut inlines, &mut plain, inline);
+                    i = end + marker.len();
+                    continue;
+                }
+            }
+            '[' => {
+                let link = find(&chars, i + 1, &[']', '(']).and_then(|middle| {
+                    let end = find(&chars, middle + 2, &[')'])?;
+                    Some((middle, end))
+                });
+                if let Some((middle, end)) = link {
+                    let text = chars[i + 1..middle].iter().collect();
+                    let url = chars[middle + 2..end].iter().collect();
+                    push(&mut inlines, &mut plain, Inline::Link { text, url });
+                    i = end + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        plain.push(c);
+        i += 1;
+    }
+    if !plain.is_empty() {
+        inlines.push(Inline::Text(plain));
+    }
+    inlines
+}
+
+// Index of the first `pattern` at or after `from`
+fn find(chars: &[char], from: usize, pattern: &[char]) -> Option<usize> {
+    (from..=chars.len().checked_sub(pattern.len())?)
+        .find(|&i| chars[i..i + pattern.len()] == *pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    #[test]
+    fn blocks_split_at_headings_fences_rules_and_lists() {
+        let blocks = parse(
+            "# Title\n\nSome `code` and **bold** and *it* and snake_case_name.\nSecond line\n\n```rust\nfn f() {}\n\n// x\n```\n---\n- one\n  more\n* two\n1. first\n2) second\n\n### Deep",
+        );
+        assert_eq!(
+            blocks,
+            [
+                Block::Heading(1, vec![text("Title")]),
+                Block::Paragraph(vec![
+                    text("Some "),
+                    Inline::Code("code".to_string()),
+                    text(" and "),
+                    Inline::Strong("bold".to_string()),
+                    text(" and "),
+                    Inline::Emphasis("it".to_string()),
+                    text(" and snake_case_name. Second line"),
+                ]),
+                Block::Code {
+                    language: "rust".to_string(),
+                    text: "fn f() {}\n\n// x".to_string(),
+                },
+                Block::Rule,
+                Block::List(vec![
+                    ("•".to_string(), vec![text("one"), text(" "), text("more")]),
+                    ("•".to_string(), vec![text("two")]),
+                    ("1.".to_string(), vec![text("first")]),
+                    ("2.".to_string(), vec![text("second")]),
+                ]),
+                Block::Heading(3, vec![text("Deep")]),
+            ]
+        );
+        assert!(parse("").is_empty());
+        // An unclosed fence takes the rest
+        assert_eq!(
+            parse("```\nx"),
+            [Block::Code {
+                language: String::new(),
+                text: "x".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn inlines_take_links_code_and_escapes() {
+        assert_eq!(
+            parse_inline("a [link](http://x) b"),
+            [
+                text("a "),
+                Inline::Link {
+                    text: "link".to_string(),
+                    url: "http://x".to_string(),
+                },
+                text(" b"),
+            ]
+        );
+        assert_eq!(
+            parse_inline("``a ` b`` \\*x\\* *unclosed"),
+            [Inline::Code("a ` b".to_string()), text(" *x* *unclosed")]
+        );
+        assert_eq!(parse_inline("#notheading"), [text("#notheading")]);
+    }
+
+    #[test]
+    fn hover_contents_become_markdown() {
+        assert_eq!(
+            from_lsp(&json!({ "kind": "markdown", "value": "**x**" })),
+            "**x**"
+        );
+        // Plain text is escaped, so it shows as it was
+        let plain = from_lsp(&json!({ "kind": "plaintext", "value": "a_b *c*" }));
+        assert_eq!(plain, "a\\_b \\*c\\*");
+        assert_eq!(parse(&plain), [Block::Paragraph(vec![text("a_b *c*")])]);
+        assert_eq!(
+            from_lsp(&json!([{ "language": "rust", "value": "fn f()" }, "docs"])),
+            "```rust\nfn f()\n```\n\n---\n\ndocs"
+        );
+        assert_eq!(from_lsp(&Value::Null), "");
+    }
+}
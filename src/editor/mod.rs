@@ -1,6 +1,34 @@
+pub mod actions;
+pub mod block;
+pub mod code_actions;
+pub mod completion;
+pub mod decorations;
+pub mod diagnostics;
+pub mod emacs;
+pub mod expand;
+pub mod folding;
+pub mod git_changes;
+pub mod guides;
+pub mod inlay_hints;
+pub mod language;
+pub mod macros;
+pub mod markdown;
+pub mod mouse;
+pub mod navigation;
+pub mod outline;
+pub mod overview;
+pub mod references;
 pub mod rope_engine;
+pub mod scroll;
+pub mod signature_help;
+pub mod sticky;
+pub mod syntax;
 pub mod types;
+pub mod typography;
+pub mod vim;
 pub mod virtual_view;
+pub mod workspace_edit;
+pub mod wrap;
 
 pub use rope_engine::RopeEditor;
 pub use virtual_view::VirtualEditorView;
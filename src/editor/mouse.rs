@@ -0,0 +1,371 @@
+use super::rope_engine::RopeEditor;
+use super::types::BlockSelection;
+use super::typography::{Metrics, FOLD_MARKER_WIDTH};
+use super::wrap::DisplayMap;
+use std::time::{Duration, Instant};
+
+// Mouse selection: mapping pointer positions to buffer offsets, and growing the
+// selection by characters, words or lines while the pointer is dragged.
+
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Area {
+    Text,
+    LineNumbers,
+    /// Fold markers handle their own clicks
+    FoldMarkers,
+}
+
+/// What is under the pointer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub area: Area,
+    pub line: usize,
+    /// Nearest char boundary; the line start over the gutter
+    pub offset: usize,
+    /// Column under the pointer, which may lie past the end of the line
+    pub column: usize,
+}
+
+/// Maps a point relative to the top-left of the viewport to a buffer position,
+/// with the text scrolled to `first_row` and `scroll_x` pixels to the right.
+pub fn hit_test(
+    editor: &RopeEditor,
+    display: &DisplayMap,
+    metrics: &Metrics,
+    first_row: usize,
+    scroll_x: f64,
+    x: f64,
+    y: f64,
+) -> Hit {
+    let row =
+        (first_row + (y.max(0.0) / metrics.line_height) as usize).min(display.row_count() - 1);
+    let visual = display.row(row);
+    let line_start = editor.line_to_char(visual.line);
+    let gutter_width = metrics.gutter_width(editor.line_count());
+
+    let (area, offset, column) = if x < gutter_width - FOLD_MARKER_WIDTH {
+        (Area::LineNumbers, line_start, 0)
+    } else if x < gutter_width {
+        (Area::FoldMarkers, line_start, 0)
+    } else {
+        let screen_column = ((x - gutter_width + scroll_x) / metrics.char_width).round() as usize;
+        let column = display.column_at(&visual, screen_column);
+        (
+            Area::Text,
+            line_start + column.min(editor.line_len(visual.line)),
+            column,
+        )
+    };
+    Hit {
+        area,
+        line: visual.line,
+        offset,
+        column,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectUnit {
+    Char,
+    Word,
+    Line,
+    /// A column selection, dragged with alt+shift
+    Column,
+}
+
+// Word characters, blanks and punctuation each form their own runs
+fn char_class(editor: &RopeEditor, c: char) -> u8 {
+    if editor.is_word_char(c) {
+        0
+    } else if c.is_whitespace() {
+        1
+    } else {
+        2
+    }
+}
+
+/// The run of word characters, blanks or punctuation at `offset`.
+pub fn word_range(editor: &RopeEditor, offset: usize) -> (usize, usize) {
+    let line = editor.char_to_line(offset);
+    let start = editor.line_to_char(line);
+    let end = start + editor.line_len(line);
+    if start == end {
+        return (offset, offset);
+    }
+    // At the end of a line, take the word before the pointer
+    let probe = offset.clamp(start, end - 1);
+    let class = editor.char_at(probe).map_or(1, |c| char_class(editor, c));
+    let same_class = |i: usize| {
+        editor
+            .char_at(i)
+            .is_some_and(|c| char_class(editor, c) == class)
+    };
+
+    let mut from = probe;
+    while from > start && same_class(from - 1) {
+        from -= 1;
+    }
+    let mut to = probe + 1;
+    while to < end && same_class(to) {
+        to += 1;
+    }
+    (from, to)
+}
+
+/// The whole line at `offset`, including its line break.
+pub fn line_range(editor: &RopeEditor, offset: usize) -> (usize, usize) {
+    let line = editor.char_to_line(offset);
+    let start = editor.line_to_char(line);
+    let end = if line + 1 < editor.line_count() {
+        editor.line_to_char(line + 1)
+    } else {
+        editor.total_chars()
+    };
+    (start, end)
+}
+
+fn unit_range(editor: &RopeEditor, offset: usize, unit: SelectUnit) -> (usize, usize) {
+    match unit {
+        SelectUnit::Char | SelectUnit::Column => (offset, offset),
+        SelectUnit::Word => word_range(editor, offset),
+        SelectUnit::Line => line_range(editor, offset),
+    }
+}
+
+/// A selection made with the mouse, grown by `unit` from what the first click
+/// selected. A column selection's origin is the (line, column) it started at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drag {
+    unit: SelectUnit,
+    origin: (usize, usize),
+}
+
+impl Drag {
+    /// Starts a selection at `hit`. With `extend` (shift-click) the current
+    /// selection anchor is kept and the selection grows to `hit`.
+    pub fn start(editor: &mut RopeEditor, hit: Hit, unit: SelectUnit, extend: bool) -> Self {
+        editor.set_block_selection(None);
+        let offset = hit.offset;
+        let drag = if unit == SelectUnit::Column {
+            Self {
+                unit,
+                origin: (hit.line, hit.column),
+            }
+        } else if extend {
+            let anchor = editor
+                .selection_anchor()
+                .unwrap_or(editor.get_cursor().byte_offset);
+            Self {
+                unit,
+                origin: (anchor, anchor),
+            }
+        } else {
+            Self {
+                unit,
+                origin: unit_range(editor, offset, unit),
+            }
+        };
+        drag.extend_to(editor, hit);
+        drag
+    }
+
+    /// Selects from the first click's range to the unit at `hit`.
+    pub fn extend_to(&self, editor: &mut RopeEditor, hit: Hit) {
+        if self.unit == SelectUnit::Column {
+            editor.set_block_selection(Some(BlockSelection {
+                anchor: self.origin,
+                head: (hit.line, hit.column),
+            }));
+            return;
+        }
+        let (start, end) = unit_range(editor, hit.offset, self.unit);
+        let (anchor, cursor) = if start < self.origin.0 {
+            (self.origin.1, start)
+        } else {
+            (self.origin.0, end.max(self.origin.1))
+        };
+        editor.set_selection_anchor((anchor != cursor).then_some(anchor));
+        editor.set_cursor_offset(cursor);
+    }
+}
+
+/// Tells single, double and triple clicks apart.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClickCounter {
+    last: Option<(Instant, usize)>,
+    count: u32,
+}
+
+impl ClickCounter {
+    /// Registers a click on `line` and returns 1, 2 or 3; a fourth quick click
+    /// starts over.
+    pub fn click(&mut self, line: usize) -> u32 {
+        let now = Instant::now();
+        let repeated = self.last.is_some_and(|(time, last_line)| {
+            now - time < MULTI_CLICK_INTERVAL && last_line == line
+        });
+        self.count = if repeated { self.count % 3 + 1 } else { 1 };
+        self.last = Some((now, line));
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::folding::FoldState;
+    fn display(editor: &RopeEditor) -> DisplayMap {
+        DisplayMap::new(
+            editor,
+            FoldState::default().fold_map(editor.line_count()),
+            None,
+        )
+    }
+
+    // Ten pixels to a column makes the sums easy
+    fn metrics() -> Metrics {
+        Metrics {
+            font_size: 10.0,
+            line_height: 20.0,
+            char_width: 10.0,
+        }
+    }
+
+    // A hit on the text at `offset`
+    fn hit(editor: &RopeEditor, offset: usize) -> Hit {
+        let line = editor.char_to_line(offset);
+        Hit {
+            area: Area::Text,
+            line,
+            offset,
+            column: offset - editor.line_to_char(line),
+        }
+    }
+
+    #[test]
+    fn hit_test_finds_the_area_and_nearest_column() {
+        let editor = RopeEditor::from_text("hello world\nfoo\nbar\n");
+        let display = display(&editor);
+        let metrics = metrics();
+        // Padding, four digits of line numbers and the fold markers
+        let gutter = metrics.gutter_width(editor.line_count());
+        assert_eq!(gutter, 62.0);
+
+        let numbers = hit_test(&editor, &display, &metrics, 0, 0.0, 10.0, 5.0);
+        assert_eq!((numbers.area, numbers.offset), (Area::LineNumbers, 0));
+        let markers = hit_test(&editor, &display, &metrics, 0, 0.0, 55.0, 25.0);
+        assert_eq!(markers.area, Area::FoldMarkers);
+
+        let near = hit_test(&editor, &display, &metrics, 0, 0.0, gutter + 14.0, 5.0);
+        assert_eq!(near.offset, 1);
+        // Past the end of "foo" the offset stops at the line end, the column doesn't
+        let past = hit_test(&editor, &display, &metrics, 0, 0.0, gutter + 46.0, 25.0);
+        assert_eq!(
+            (past.area, past.line, past.offset, past.column),
+            (Area::Text, 1, 15, 5)
+        );
+
+        assert_eq!(
+            hit_test(&editor, &display, &metrics, 1, 0.0, gutter, 25.0).line,
+            2
+        );
+        assert_eq!(
+            hit_test(&editor, &display, &metrics, 0, 0.0, gutter, 500.0).line,
+            3
+        );
+        let scrolled = hit_test(&editor, &display, &metrics, 0, 30.0, gutter + 20.0, 5.0);
+        assert_eq!(scrolled.offset, 5);
+    }
+
+    #[test]
+    fn hit_test_lands_on_the_nearer_side_of_a_tab() {
+        let editor = RopeEditor::from_text("\tx\n");
+        let display = display(&editor);
+        let metrics = metrics();
+        let gutter = metrics.gutter_width(editor.line_count());
+        let at = |x: f64| hit_test(&editor, &display, &metrics, 0, 0.0, gutter + x, 5.0).offset;
+        assert_eq!(at(10.0), 0);
+        assert_eq!(at(20.0), 0);
+        assert_eq!(at(30.0), 1);
+        assert_eq!(at(50.0), 2);
+    }
+
+    #[test]
+    fn word_and_line_ranges() {
+        let editor = RopeEditor::from_text("let foo_bar = 1;\nx\n");
+        assert_eq!(word_range(&editor, 5), (4, 11));
+        assert_eq!(word_range(&editor, 3), (3, 4));
+        assert_eq!(word_range(&editor, 16), (15, 16));
+        assert_eq!(line_range(&editor, 5), (0, 17));
+        assert_eq!(line_range(&editor, 17), (17, 19));
+    }
+
+    #[test]
+    fn drag_grows_the_selection_by_its_unit() {
+        let mut editor = RopeEditor::from_text("one two three\nfour five\n");
+        let selection =
+            |editor: &RopeEditor| (editor.selection_anchor(), editor.get_cursor().byte_offset);
+
+        let at = hit(&editor, 5);
+        let drag = Drag::start(&mut editor, at, SelectUnit::Word, false);
+        assert_eq!(selection(&editor), (Some(4), 7));
+        let at = hit(&editor, 10);
+        drag.extend_to(&mut editor, at);
+        assert_eq!(selection(&editor), (Some(4), 13));
+        // Dragging back past the start keeps the first word selected
+        let at = hit(&editor, 1);
+        drag.extend_to(&mut editor, at);
+        assert_eq!(selection(&editor), (Some(7), 0));
+
+        let at = hit(&editor, 3);
+        let drag = Drag::start(&mut editor, at, SelectUnit::Char, false);
+        assert_eq!(editor.selection_anchor(), None);
+        let at = hit(&editor, 16);
+        drag.extend_to(&mut editor, at);
+        assert_eq!(selection(&editor), (Some(3), 16));
+
+        // Shift-click keeps the anchor
+        let at = hit(&editor, 20);
+        Drag::start(&mut editor, at, SelectUnit::Char, true);
+        assert_eq!(selection(&editor), (Some(3), 20));
+
+        let at = hit(&editor, 15);
+        Drag::start(&mut editor, at, SelectUnit::Line, false);
+        assert_eq!(selection(&editor), (Some(14), 24));
+    }
+
+    #[test]
+    fn column_drag_selects_a_block() {
+        let mut editor = RopeEditor::from_text("abcdef\nab\nabcdef\n");
+        let at = hit(&editor, 1);
+        let drag = Drag::start(&mut editor, at, SelectUnit::Column, false);
+        drag.extend_to(
+            &mut editor,
+            Hit {
+                area: Area::Text,
+                line: 2,
+                offset: 13,
+                column: 4,
+            },
+        );
+        let block = editor.block_selection().unwrap();
+        assert_eq!((block.lines(), block.columns()), (0..=2, 1..4));
+        assert_eq!(editor.selection_anchor(), None);
+
+        let at = hit(&editor, 2);
+        Drag::start(&mut editor, at, SelectUnit::Char, false);
+        assert!(editor.block_selection().is_none());
+    }
+
+    #[test]
+    fn clicks_on_one_line_count_up_to_three() {
+        let mut clicks = ClickCounter::default();
+        assert_eq!(clicks.click(1), 1);
+        assert_eq!(clicks.click(1), 2);
+        assert_eq!(clicks.click(1), 3);
+        assert_eq!(clicks.click(1), 1);
+        assert_eq!(clicks.click(2), 1);
+    }
+}
@@ -0,0 +1,369 @@
+use super::rope_engine::RopeEditor;
+use crate::utils::{config_dir, log};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+// Bookmarks and back/forward navigation, kept in navigation.toml so they outlive
+// the session. Jumps go through PENDING_JUMP: the layout opens the file and the
+// editor showing it moves its cursor there.
+
+const MAX_HISTORY: usize = 50;
+const MAX_PREVIEW: usize = 60;
+
+/// A cursor position in a file, 0-based.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Where the cursor of `editor` is.
+    pub fn of(editor: &RopeEditor) -> Self {
+        let cursor = editor.get_cursor();
+        Self {
+            path: editor.file_path().clone(),
+            line: cursor.line,
+            column: cursor.column,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Navigation {
+    /// Bookmarked lines by file
+    bookmarks: BTreeMap<PathBuf, BTreeSet<usize>>,
+    /// Where earlier jumps started, oldest first
+    back: Vec<Location>,
+    /// Where `back` came from, most recent last
+    forward: Vec<Location>,
+    /// Buffer and edit count the bookmarks of each file were last moved to
+    #[serde(skip)]
+    synced: HashMap<PathBuf, (u64, u64)>,
+}
+
+pub static NAVIGATION: GlobalSignal<Navigation> = Signal::global(Navigation::load);
+
+/// Where the editor showing the file should put its cursor next.
+pub static PENDING_JUMP: GlobalSignal<Option<Location>> = Signal::global(|| None);
+
+impl Navigation {
+    pub fn path() -> PathBuf {
+        config_dir().join("navigation.toml")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+                log::write("Navigation", format!("{}: {}", path.display(), e));
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path();
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, text).map_err(|e| e.to_string())
+    }
+
+    /// Remembers leaving `from` for somewhere else. A new jump drops the
+    /// forward history, and leaving the same line twice in a row counts once.
+    pub fn record(&mut self, from: Location) {
+        let repeated = self
+            .back
+            .last()
+            .is_some_and(|last| last.path == from.path && last.line == from.line);
+        if !repeated {
+            self.back.push(from);
+            if self.back.len() > MAX_HISTORY {
+                self.back.remove(0);
+            }
+        }
+        self.forward.clear();
+    }
+
+    /// The location before the last jump; `current` becomes the way forward.
+    pub fn back(&mut self, current: Location) -> Option<Location> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    pub fn forward(&mut self, current: Location) -> Option<Location> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+
+    pub fn toggle_bookmark(&mut self, path: &Path, line: usize) {
+        let lines = self.bookmarks.entry(path.to_path_buf()).or_default();
+        if !lines.remove(&line) {
+            lines.insert(line);
+        }
+        if lines.is_empty() {
+            self.bookmarks.remove(path);
+        }
+    }
+
+    /// Moves the bookmarks of `editor`'s file along with the edits made to it
+    /// since the last call, dropping those whose line was deleted. Returns
+    /// whether any bookmark moved.
+    pub fn sync_bookmarks(&mut self, editor: &RopeEditor) -> bool {
+        let path = editor.file_path();
+        let now = (editor.id(), editor.edit_count());
+        let since = match self.synced.insert(path.clone(), now) {
+            Some(synced) if synced == now => return false,
+            Some((id, seen)) if id == editor.id() => editor.edits_since(seen),
+            _ => None,
+        };
+        // Edits made in another buffer of the file, or before it was loaded
+        // again, can't be followed; the bookmarks stay on their lines
+        let (Some(lines), Some(edits)) = (self.bookmarks.get_mut(path), since) else {
+            return false;
+        };
+        let mut moved = lines.clone();
+        for edit in edits {
+            moved = moved
+                .into_iter()
+                .filter_map(|line| edit.moved_line(line))
+                .collect();
+        }
+        if moved == *lines {
+            return false;
+        }
+        if moved.is_empty() {
+            self.bookmarks.remove(path);
+        } else {
+            *lines = moved;
+        }
+        true
+    }
+
+    pub fn is_synced(&self, editor: &RopeEditor) -> bool {
+        self.synced.get(editor.file_path()) == Some(&(editor.id(), editor.edit_count()))
+    }
+
+    pub fn bookmarks_in(&self, path: &Path) -> Option<&BTreeSet<usize>> {
+        self.bookmarks.get(path)
+    }
+
+    /// Every bookmark, by file and then line.
+    pub fn all_bookmarks(&self) -> Vec<Location> {
+        self.bookmarks
+            .iter()
+            .flat_map(|(path, lines)| {
+                lines.iter().map(|&line| Location {
+                    path: path.clone(),
+                    line,
+                    column: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// The first bookmark after `from`, or with `forward` false the last one
+    /// before it, wrapping around past the last file.
+    pub fn next_bookmark(&self, from: &Location, forward: bool) -> Option<Location> {
+        let bookmarks = self.all_bookmarks();
+        let key = |location: &Location| (location.path.clone(), location.line);
+        let here = key(from);
+        if forward {
+            let next = bookmarks.iter().find(|bookmark| key(bookmark) > here);
+            next.or(bookmarks.first()).cloned()
+        } else {
+            let previous = bookmarks.iter().rev().find(|bookmark| key(bookmark) < here);
+            previous.or(bookmarks.last()).cloned()
+        }
+    }
+}
+
+/// Applies `change` to the navigation state and writes it to disk.
+pub fn update_navigation<R>(change: impl FnOnce(&mut Navigation) -> R) -> R {
+    let mut navigation = NAVIGATION.write();
+    let result = change(&mut navigation);
+    if let Err(e) = navigation.save() {
+        log::write("Navigation", format!("Failed to save: {}", e));
+    }
+    result
+}
+
+/// Keeps the bookmarks of `editor`'s file on their lines as it is edited,
+/// writing them to disk only when one moved.
+pub fn follow_edits(editor: &RopeEditor) {
+    if NAVIGATION.peek().is_synced(editor) {
+        return;
+    }
+    let mut navigation = NAVIGATION.write();
+    if navigation.sync_bookmarks(editor) {
+        if let Err(e) = navigation.save() {
+            log::write("Navigation", format!("Failed to save: {}", e));
+        }
+    }
+}
+
+/// Moves to `to`, remembering `from` so that going back returns there.
+pub fn jump(from: Option<Location>, to: Location) {
+    if let Some(from) = from {
+        update_navigation(|navigation| navigation.record(from));
+    }
+    *PENDING_JUMP.write() = Some(to);
+}
+
+/// Parses "line" or "line:column", both counted from 1, into a 0-based position.
+pub fn parse_line_column(text: &str) -> Option<(usize, usize)> {
+    let (line, column) = match text.split_once(':') {
+        Some((line, column)) => (line, Some(column)),
+        None => (text, None),
+    };
+    let line = line.trim().parse::<usize>().ok()?.saturating_sub(1);
+    let column = match column {
+        Some(column) => column.trim().parse::<usize>().ok()?.saturating_sub(1),
+        None => 0,
+    };
+    Some((line, column))
+}
+
+/// The bookmarks as numbered palette entries with a preview of their line.
+pub fn bookmark_list() -> Vec<String> {
+    NAVIGATION
+        .read()
+        .all_bookmarks()
+        .iter()
+        .enumerate()
+        .map(|(index, bookmark)| {
+            let name = bookmark
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let preview: String = std::fs::read_to_string(&bookmark.path)
+                .ok()
+                .and_then(|text| {
+                    text.lines()
+                        .nth(bookmark.line)
+                        .map(|line| line.trim().chars().take(MAX_PREVIEW).collect())
+                })
+                .unwrap_or_default();
+            format!("{}. {}:{}  {}", index + 1, name, bookmark.line + 1, preview)
+        })
+        .collect()
+}
+
+/// The bookmark an entry of `bookmark_list` stands for.
+pub fn bookmark_at(entry: &str) -> Option<Location> {
+    let (number, _) = entry.split_once('.')?;
+    let index = number.trim().parse::<usize>().ok()?.checked_sub(1)?;
+    NAVIGATION.read().all_bookmarks().into_iter().nth(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(path: &str, line: usize) -> Location {
+        Location {
+            path: PathBuf::from(path),
+            line,
+            column: 0,
+        }
+    }
+
+    #[test]
+    fn back_and_forward_retrace_the_jumps() {
+        let mut navigation = Navigation::default();
+        navigation.record(at("/a", 1));
+        // The same place twice in a row is kept once
+        navigation.record(at("/a", 1));
+        navigation.record(at("/b", 5));
+        assert_eq!(navigation.back(at("/c", 0)), Some(at("/b", 5)));
+        assert_eq!(navigation.back(at("/b", 5)), Some(at("/a", 1)));
+        assert_eq!(navigation.back(at("/a", 1)), None);
+        assert_eq!(navigation.forward(at("/a", 1)), Some(at("/b", 5)));
+        // A new jump drops the way forward
+        navigation.record(at("/b", 5));
+        assert_eq!(navigation.forward(at("/x", 0)), None);
+    }
+
+    #[test]
+    fn bookmarks_cycle_through_every_file() {
+        let mut navigation = Navigation::default();
+        navigation.toggle_bookmark(Path::new("/b"), 3);
+        navigation.toggle_bookmark(Path::new("/a"), 9);
+        navigation.toggle_bookmark(Path::new("/a"), 2);
+        navigation.toggle_bookmark(Path::new("/a"), 4);
+        navigation.toggle_bookmark(Path::new("/a"), 4);
+        assert_eq!(
+            navigation.all_bookmarks(),
+            vec![at("/a", 2), at("/a", 9), at("/b", 3)]
+        );
+        assert_eq!(
+            navigation.next_bookmark(&at("/a", 2), true),
+            Some(at("/a", 9))
+        );
+        assert_eq!(
+            navigation.next_bookmark(&at("/b", 3), true),
+            Some(at("/a", 2))
+        );
+        assert_eq!(
+            navigation.next_bookmark(&at("/a", 2), false),
+            Some(at("/b", 3))
+        );
+        assert_eq!(
+            navigation.next_bookmark(&at("/a", 5), false),
+            Some(at("/a", 2))
+        );
+
+        let saved = toml::to_string_pretty(&navigation).unwrap();
+        assert_eq!(toml::from_str::<Navigation>(&saved).unwrap(), navigation);
+    }
+
+    #[test]
+    fn bookmarks_follow_edits() {
+        let mut editor = RopeEditor::from_text("a\nb\nc\nd\ne\n");
+        let path = editor.file_path().clone();
+        let mut navigation = Navigation::default();
+        navigation.sync_bookmarks(&editor);
+        navigation.toggle_bookmark(&path, 1);
+        navigation.toggle_bookmark(&path, 3);
+
+        // A line put in front pushes both down
+        editor.set_cursor(0, 0);
+        editor.insert_text("x\n");
+        assert!(navigation.sync_bookmarks(&editor));
+        assert_eq!(
+            navigation.bookmarks_in(&path),
+            Some(&BTreeSet::from([2, 4]))
+        );
+        // Typing into a line leaves its bookmark there
+        editor.set_cursor(2, 1);
+        editor.insert_text("yz");
+        assert!(!navigation.sync_bookmarks(&editor));
+        // Deleting a bookmarked line drops its bookmark, and the rest move up
+        let start = editor.line_to_char(2);
+        editor.delete_range(start, editor.line_to_char(3));
+        assert!(navigation.sync_bookmarks(&editor));
+        assert_eq!(navigation.bookmarks_in(&path), Some(&BTreeSet::from([3])));
+        assert!(navigation.is_synced(&editor));
+
+        // Only the bookmarks are saved
+        let saved = toml::from_str::<Navigation>(&toml::to_string_pretty(&navigation).unwrap());
+        assert_eq!(saved.unwrap().all_bookmarks(), navigation.all_bookmarks());
+    }
+
+    #[test]
+    fn parse_line_column_counts_from_one() {
+        assert_eq!(parse_line_column("12"), Some((11, 0)));
+        assert_eq!(parse_line_column(" 12:5 "), Some((11, 4)));
+        assert_eq!(parse_line_column("x"), None);
+    }
+}
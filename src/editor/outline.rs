@@ -0,0 +1,615 @@
+use super::navigation::Location;
+use super::rope_engine::RopeEditor;
+use crate::lsp::protocol;
+use dioxus::prelude::*;
+use ropey::Rope;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use tree_sitter::Node;
+
+// Symbols of a file for the outline panel and go to symbol, read off the parse
+// tree, or given by the file's language server once it has answered. The
+// focused editor publishes its outline in OUTLINE, recomputing the symbols
+// only when the text changes.
+
+const MAX_NAME: usize = 80;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymbolKind {
+    Module,
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Class,
+    Interface,
+    Type,
+    Constant,
+    Field,
+    Variable,
+    Macro,
+    Heading,
+    Key,
+}
+
+impl SymbolKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            SymbolKind::Module => "module",
+            SymbolKind::Function => "function",
+            SymbolKind::Method => "method",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Trait => "trait",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Class => "class",
+            SymbolKind::Interface => "interface",
+            SymbolKind::Type => "type",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Field => "field",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Macro => "macro",
+            SymbolKind::Heading => "heading",
+            SymbolKind::Key => "key",
+        }
+    }
+
+    pub fn icon(self) -> &'static str {
+        match self {
+            SymbolKind::Module => "◫",
+            SymbolKind::Function | SymbolKind::Method => "ƒ",
+            SymbolKind::Struct | SymbolKind::Class => "◆",
+            SymbolKind::Enum => "∈",
+            SymbolKind::Trait | SymbolKind::Interface => "◇",
+            SymbolKind::Impl => "⊢",
+            SymbolKind::Type => "τ",
+            SymbolKind::Constant => "π",
+            SymbolKind::Field => "▪",
+            SymbolKind::Variable => "ν",
+            SymbolKind::Macro => "!",
+            SymbolKind::Heading => "#",
+            SymbolKind::Key => "•",
+        }
+    }
+}
+
+/// A symbol, with its children following it one level deeper.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub depth: usize,
+    /// Where the name is, 0-based
+    pub line: usize,
+    pub column: usize,
+    /// Lines the whole definition spans
+    pub lines: RangeInclusive<usize>,
+}
+
+impl Symbol {
+    /// The entry for the go to symbol picker; unique within a file.
+    pub fn label(&self) -> String {
+        format!(
+            "{}  ({}, line {})",
+            self.name,
+            self.kind.label(),
+            self.line + 1
+        )
+    }
+}
+
+/// The outline of the focused buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+    pub revision: u64,
+    pub symbols: Rc<Vec<Symbol>>,
+    /// Innermost symbol around the cursor
+    pub current: Option<usize>,
+    pub cursor: Location,
+}
+
+pub static OUTLINE: GlobalSignal<Option<Outline>> = Signal::global(|| None);
+
+/// The symbols language servers last gave for each file, which are preferred
+/// to the parse tree's.
+static SERVER_SYMBOLS: GlobalSignal<HashMap<PathBuf, Rc<Vec<Symbol>>>> =
+    Signal::global(HashMap::new);
+
+/// Updates OUTLINE for `editor`, reusing the symbols while the text is the same.
+pub fn publish(editor: &RopeEditor) {
+    let cursor = Location::of(editor);
+    let revision = editor.revision();
+    let symbols = match OUTLINE.peek().as_ref() {
+        Some(outline) if outline.cursor.path == cursor.path && outline.revision == revision => {
+            if outline.cursor == cursor {
+                return;
+            }
+            outline.symbols.clone()
+        }
+        _ => match SERVER_SYMBOLS.peek().get(&cursor.path) {
+            Some(symbols) => symbols.clone(),
+            None => Rc::new(tree_symbols(editor)),
+        },
+    };
+    let current = innermost(&symbols, cursor.line);
+    *OUTLINE.write() = Some(Outline {
+        revision,
+        symbols,
+        current,
+        cursor,
+    });
+}
+
+/// Replaces the symbols of `path` with those from its language server.
+pub fn publish_server_symbols(path: &Path, symbols: Vec<Symbol>) {
+    let symbols = Rc::new(symbols);
+    SERVER_SYMBOLS
+        .write()
+        .insert(path.to_path_buf(), symbols.clone());
+    let mut outline = OUTLINE.write();
+    if let Some(outline) = outline
+        .as_mut()
+        .filter(|outline| outline.cursor.path == *path)
+    {
+        outline.current = innermost(&symbols, outline.cursor.line);
+        outline.symbols = symbols;
+    }
+}
+
+/// Clears the outline if it belongs to `path`, as when its editor closes.
+pub fn withdraw(path: &Path) {
+    if SERVER_SYMBOLS.peek().contains_key(path) {
+        SERVER_SYMBOLS.write().remove(path);
+    }
+    if OUTLINE
+        .peek()
+        .as_ref()
+        .is_some_and(|outline| outline.cursor.path == *path)
+    {
+        *OUTLINE.write() = None;
+    }
+}
+
+/// The innermost symbol whose definition spans `line`.
+pub fn innermost(symbols: &[Symbol], line: usize) -> Option<usize> {
+    symbols
+        .iter()
+        .rposition(|symbol| symbol.lines.contains(&line))
+}
+
+/// The symbols of the file in document order.
+pub fn symbols(editor: &RopeEditor) -> Vec<Symbol> {
+    match SERVER_SYMBOLS.peek().get(editor.file_path()) {
+        Some(symbols) => symbols.to_vec(),
+        None => tree_symbols(editor),
+    }
+}
+
+/// The symbols of the parse tree. Empty without a parser.
+pub fn tree_symbols(editor: &RopeEditor) -> Vec<Symbol> {
+    editor
+        .with_syntax_tree(|root, rope| {
+            let mut symbols = Vec::new();
+            collect(root, rope, 0, &mut symbols);
+            symbols
+        })
+        .unwrap_or_default()
+}
+
+fn collect(node: Node, rope: &Rope, depth: usize, symbols: &mut Vec<Symbol>) {
+    let symbol = symbol_of(node, rope, depth);
+    let depth = match symbol {
+        Some(symbol) => {
+            // Headings nest by level rather than by tree
+            let next = if symbol.kind == SymbolKind::Heading {
+                depth
+            } else {
+                symbol.depth + 1
+            };
+            symbols.push(symbol);
+            next
+        }
+        None => depth,
+    };
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect(child, rope, depth, symbols);
+    }
+}
+
+fn symbol_of(node: Node, rope: &Rope, depth: usize) -> Option<Symbol> {
+    let text = |node: Node| rope.byte_slice(node.byte_range()).to_string();
+    let named = |field: &str| node.child_by_field_name(field);
+    let (kind, name_node, name) = match node.kind() {
+        // Rust
+        "mod_item" => (SymbolKind::Module, named("name")?, None),
+        "function_item" | "function_signature_item" => (function_kind(node), named("name")?, None),
+        "struct_item" | "union_item" => (SymbolKind::Struct, named("name")?, None),
+        "enum_item" => (SymbolKind::Enum, named("name")?, None),
+        "trait_item" => (SymbolKind::Trait, named("name")?, None),
+        "impl_item" => {
+            let type_node = named("type")?;
+            let name = match named("trait") {
+                Some(trait_node) => format!("impl {} for {}", text(trait_node), text(type_node)),
+                None => format!("impl {}", text(type_node)),
+            };
+            (SymbolKind::Impl, type_node, Some(name))
+        }
+        "type_item" => (SymbolKind::Type, named("name")?, None),
+        "const_item" | "static_item" => (SymbolKind::Constant, named("name")?, None),
+        "macro_definition" => (SymbolKind::Macro, named("name")?, None),
+
+        // JavaScript, TypeScript, Python and shell
+        "function_declaration" | "generator_function_declaration" | "function_definition" => {
+            (function_kind(node), named("name")?, None)
+        }
+        "method_definition" | "method_signature" => (SymbolKind::Method, named("name")?, None),
+        "class_declaration" | "class_definition" | "abstract_class_declaration" => {
+            (SymbolKind::Class, named("name")?, None)
+        }
+        "interface_declaration" => (SymbolKind::Interface, named("name")?, None),
+        "type_alias_declaration" => (SymbolKind::Type, named("name")?, None),
+        "enum_declaration" => (SymbolKind::Enum, named("name")?, None),
+        "internal_module" => (SymbolKind::Module, named("name")?, None),
+        // const f = () => ...
+        "variable_declarator" => {
+            let value = named("value")?;
+            if !matches!(
+                value.kind(),
+                "arrow_function" | "function_expression" | "function"
+            ) {
+                return None;
+            }
+            (SymbolKind::Function, named("name")?, None)
+        }
+
+        // Markdown
+        "atx_heading" | "setext_heading" => {
+            let level = heading_level(node)?;
+            let content = named("heading_content")?;
+            let name = text(content)
+                .trim()
+                .trim_end_matches('#')
+                .trim()
+                .to_string();
+            // A heading spans its section, up to the next heading as deep
+            let section = node
+                .parent()
+                .filter(|parent| parent.kind() == "section")
+                .unwrap_or(node);
+            return Some(symbol(
+                SymbolKind::Heading,
+                name,
+                level - 1,
+                section,
+                content,
+                rope,
+            ));
+        }
+
+        // JSON and TOML keys, and TOML tables
+        "pair" => {
+            let key = named("key").or_else(|| node.named_child(0))?;
+            let name = text(key).trim_matches('"').to_string();
+            (SymbolKind::Key, key, Some(name))
+        }
+        "table" | "table_array_element" => {
+            let key = node.named_child(0)?;
+            (SymbolKind::Key, key, None)
+        }
+
+        // CSS rules by their selectors
+        "rule_set" => (SymbolKind::Class, node.named_child(0)?, None),
+
+        _ => return None,
+    };
+    let name = name.unwrap_or_else(|| text(name_node));
+    Some(symbol(kind, name, depth, node, name_node, rope))
+}
+
+fn symbol(
+    kind: SymbolKind,
+    name: String,
+    depth: usize,
+    node: Node,
+    name_node: Node,
+    rope: &Rope,
+) -> Symbol {
+    let first_line = name.lines().next().unwrap_or_default().trim();
+    let mut name: String = first_line.chars().take(MAX_NAME).collect();
+    if name.is_empty() {
+        name = "(anonymous)".to_string();
+    }
+    let start = rope.byte_to_char(name_node.start_byte());
+    let line = rope.char_to_line(start);
+    let (first, end) = (node.start_position(), node.end_position());
+    // A definition ending in its line break ends on that line
+    let last = if end.column == 0 && end.row > first.row {
+        end.row - 1
+    } else {
+        end.row
+    };
+    Symbol {
+        name,
+        kind,
+        depth,
+        line,
+        column: start - rope.line_to_char(line),
+        lines: first.row..=last,
+    }
+}
+
+// Functions defined in an impl, trait or class are methods
+fn function_kind(node: Node) -> SymbolKind {
+    let mut owner = node.parent();
+    while let Some(parent) = owner.filter(|parent| {
+        matches!(
+            parent.kind(),
+            "declaration_list" | "block" | "decorated_definition"
+        )
+    }) {
+        owner = parent.parent();
+    }
+    match owner.map(|owner| owner.kind()) {
+        Some("impl_item" | "trait_item" | "class_definition") => SymbolKind::Method,
+        _ => SymbolKind::Function,
+    }
+}
+
+// 1 to 6 from the heading's marker or underline
+fn heading_level(node: Node) -> Option<usize> {
+    let mut cursor = node.walk();
+    let level = node.children(&mut cursor).find_map(|child| {
+        let kind = child.kind();
+        let level = kind
+            .strip_prefix("atx_h")
+            .or_else(|| kind.strip_prefix("setext_h"))?
+            .chars()
+            .next()?
+            .to_digit(10)?;
+        Some(level as usize)
+    });
+    level
+}
+
+/// The symbols of a `textDocument/documentSymbol` response, positioned in
+/// `rope`: DocumentSymbols nested by their children, or flat SymbolInformation.
+pub fn from_lsp(rope: &Rope, response: &Value) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for item in response.as_array().map(Vec::as_slice).unwrap_or_default() {
+        server_symbol(rope, item, 0, &mut symbols);
+    }
+    // Flat lists come in any order; nest them by the lines they span
+    if response[0].get("location").is_some() {
+        symbols.sort_by_key(|symbol| {
+            (
+                *symbol.lines.start(),
+                std::cmp::Reverse(*symbol.lines.end()),
+            )
+        });
+        let mut open: Vec<RangeInclusive<usize>> = Vec::new();
+        for symbol in &mut symbols {
+            while open
+                .last()
+                .is_some_and(|lines| !lines.contains(symbol.lines.end()))
+            {
+                open.pop();
+            }
+            symbol.depth = open.len();
+            open.push(symbol.lines.clone());
+        }
+    }
+    symbols
+}
+
+fn server_symbol(rope: &Rope, item: &Value, depth: usize, symbols: &mut Vec<Symbol>) -> Option<()> {
+    let range = item
+        .get("range")
+        .or_else(|| item.get("location")?.get("range"))?;
+    let lines = protocol::range_to_chars(rope, range)?;
+    let name_range = item.get("selectionRange").unwrap_or(range);
+    let start = protocol::range_to_chars(rope, name_range)?.start;
+    let line = rope.char_to_line(start);
+    let first_line = item
+        .get("name")?
+        .as_str()?
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .trim();
+    let name: String = first_line.chars().take(MAX_NAME).collect();
+    let first = rope.char_to_line(lines.start);
+    let end = rope.char_to_line(lines.end);
+    // A definition ending at a line start ends on the line before
+    let last = if end > first && rope.line_to_char(end) == lines.end {
+        end - 1
+    } else {
+        end
+    };
+    symbols.push(Symbol {
+        name: if name.is_empty() {
+            "(anonymous)".to_string()
+        } else {
+            name
+        },
+        kind: server_kind(item.get("kind").and_then(Value::as_u64).unwrap_or_default()),
+        depth,
+        line,
+        column: start - rope.line_to_char(line),
+        lines: first..=last,
+    });
+    for child in item
+        .get("children")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    {
+        server_symbol(rope, child, depth + 1, symbols);
+    }
+    Some(())
+}
+
+// The protocol's SymbolKind numbers
+fn server_kind(kind: u64) -> SymbolKind {
+    match kind {
+        2..=4 => SymbolKind::Module,
+        5 => SymbolKind::Class,
+        6 | 9 => SymbolKind::Method,
+        7 | 8 => SymbolKind::Field,
+        10 => SymbolKind::Enum,
+        11 => SymbolKind::Interface,
+        12 | 24 | 25 => SymbolKind::Function,
+        14 | 22 => SymbolKind::Constant,
+        19 | 20 => SymbolKind::Key,
+        23 => SymbolKind::Struct,
+        26 => SymbolKind::Type,
+        _ => SymbolKind::Variable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(extension: &str, text: &str) -> RopeEditor {
+        RopeEditor::from_named_text(&format!("a.{extension}"), text)
+    }
+
+    // "kind name", indented two spaces per level
+    fn tree(symbols: &[Symbol]) -> Vec<String> {
+        symbols
+            .iter()
+            .map(|symbol| {
+                format!(
+                    "{}{} {}",
+                    "  ".repeat(symbol.depth),
+                    symbol.kind.label(),
+                    symbol.name
+                )
+            })
+            .collect()
+    }
+
+    fn outline(extension: &str, text: &str) -> Vec<String> {
+        tree(&tree_symbols(&editor(extension, text)))
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        serde_json::json!({
+            "start": {"line": start.0, "character": start.1},
+            "end": {"line": end.0, "character": end.1},
+        })
+    }
+
+    #[test]
+    fn rust_items_nest_and_track_the_cursor() {
+        let editor = editor(
+            "rs",
+            "mod a {\n    fn inner() {}\n}\nstruct S;\nimpl Display for S {\n    fn fmt(&self) {}\n}\ntrait T {\n    fn t(&self);\n}\nenum E { A }\nconst C: u8 = 1;\nmacro_rules! m { () => {} }\n",
+        );
+        let symbols = tree_symbols(&editor);
+        assert_eq!(
+            tree(&symbols),
+            [
+                "module a",
+                "  function inner",
+                "struct S",
+                "impl impl Display for S",
+                "  method fmt",
+                "trait T",
+                "  method t",
+                "enum E",
+                "constant C",
+                "macro m",
+            ]
+        );
+        assert_eq!((symbols[4].line, symbols[4].column), (5, 7));
+        assert_eq!(innermost(&symbols, 5), Some(4));
+        assert_eq!(innermost(&symbols, 6), Some(3));
+        assert_eq!(innermost(&symbols, 3), Some(2));
+    }
+
+    #[test]
+    fn other_languages_have_outlines() {
+        assert_eq!(
+            outline(
+                "py",
+                "class A:\n    @staticmethod\n    def m():\n        pass\n\ndef f():\n    pass\n"
+            ),
+            ["class A", "  method m", "function f"]
+        );
+        assert_eq!(
+            outline("md", "# Title\n\ntext\n\n## Sub\n\nmore\n\n# Two\n"),
+            ["heading Title", "  heading Sub", "heading Two"]
+        );
+        assert_eq!(
+            outline("json", "{\"a\": {\"b\": 1}, \"c\": [ {\"d\": 2} ]}"),
+            ["key a", "  key b", "key c", "  key d"]
+        );
+        assert_eq!(
+            outline("toml", "name = 1\n[package]\nversion = \"1\"\n"),
+            ["key name", "key package", "  key version"]
+        );
+        assert_eq!(
+            outline(
+                "js",
+                "class K { go() {} }\nfunction f() {}\nconst g = () => 1;\n"
+            ),
+            ["class K", "  method go", "function f", "function g"]
+        );
+        assert_eq!(
+            outline("sh", "greet() {\n  echo hi\n}\n"),
+            ["function greet"]
+        );
+    }
+
+    #[test]
+    fn server_symbols_nest_by_children_or_by_range() {
+        let rope = Rope::from_str("struct S {\n    a: u8,\n}\nimpl S {\n    fn f() {}\n}\n");
+        let response = serde_json::json!([
+            {"name": "S", "kind": 23, "range": range((0, 0), (3, 0)),
+             "selectionRange": range((0, 7), (0, 8)), "children": [
+                {"name": "a", "kind": 8, "range": range((1, 4), (1, 9)),
+                 "selectionRange": range((1, 4), (1, 5))}
+            ]},
+            {"name": "impl S", "kind": 19, "range": range((3, 0), (5, 1)),
+             "selectionRange": range((3, 5), (3, 6)), "children": [
+                {"name": "f", "kind": 12, "range": range((4, 4), (4, 13)),
+                 "selectionRange": range((4, 7), (4, 8))}
+            ]}
+        ]);
+        let symbols = from_lsp(&rope, &response);
+        let placed: Vec<_> = symbols
+            .iter()
+            .map(|symbol| (symbol.line, symbol.column, symbol.lines.clone()))
+            .collect();
+        assert_eq!(
+            tree(&symbols),
+            ["struct S", "  field a", "key impl S", "  function f"]
+        );
+        // A range ending at column 0 stops on the line before
+        assert_eq!(
+            placed,
+            [(0, 7, 0..=2), (1, 4, 1..=1), (3, 5, 3..=5), (4, 7, 4..=4)]
+        );
+        assert_eq!(innermost(&symbols, 4), Some(3));
+
+        let location =
+            |start, end| serde_json::json!({"uri": "file:///x", "range": range(start, end)});
+        let flat = serde_json::json!([
+            {"name": "f", "kind": 12, "location": location((4, 4), (4, 13))},
+            {"name": "S", "kind": 23, "location": location((3, 0), (5, 1))},
+            {"name": "a", "kind": 8, "location": location((0, 0), (2, 1))},
+        ]);
+        assert_eq!(
+            tree(&from_lsp(&rope, &flat)),
+            ["field a", "struct S", "  function f"]
+        );
+        assert!(from_lsp(&rope, &Value::Null).is_empty());
+    }
+}
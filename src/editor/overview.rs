@@ -0,0 +1,274 @@
+use super::diagnostics::{Diagnostic, Severity};
+use super::git_changes::{ChangeKind, LineChange};
+use super::rope_engine::RopeEditor;
+use super::wrap::DisplayMap;
+use std::collections::BTreeMap;
+
+// Whole-document views beside the text: the overview ruler in the vertical
+// scrollbar, and the minimap.
+
+pub const SCROLLBAR_WIDTH: f64 = 14.0;
+pub const MINIMAP_WIDTH: f64 = 100.0;
+/// Height of a row in the minimap; every column is one pixel wide
+pub const MINIMAP_ROW_HEIGHT: f64 = 2.0;
+const MIN_MARK_HEIGHT: f64 = 3.0;
+const MAX_SEARCH_MARKS: usize = 10_000;
+
+/// Width the scrollbar and minimap take from the right of the text.
+pub fn right_inset(minimap: bool) -> f64 {
+    if minimap {
+        SCROLLBAR_WIDTH + MINIMAP_WIDTH
+    } else {
+        SCROLLBAR_WIDTH
+    }
+}
+
+/// What a mark in the scrollbar stands for. Later kinds are drawn on top.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MarkKind {
+    Added,
+    Modified,
+    Deleted,
+    SearchMatch,
+    Information,
+    Warning,
+    Error,
+}
+
+impl MarkKind {
+    /// Left edge and width within the scrollbar: git changes on the left,
+    /// search matches in the middle and problems on the right.
+    pub fn lane(&self) -> (f64, f64) {
+        let lane = SCROLLBAR_WIDTH / 3.0;
+        match self {
+            MarkKind::Added | MarkKind::Modified | MarkKind::Deleted => (0.0, lane),
+            MarkKind::SearchMatch => (lane, lane),
+            MarkKind::Information | MarkKind::Warning | MarkKind::Error => (2.0 * lane, lane),
+        }
+    }
+}
+
+/// Marks for the scrollbar as `(kind, top, height)` in a track `track` pixels
+/// tall. Marks landing on the same pixel are drawn once.
+pub fn overview_marks(
+    display: &DisplayMap,
+    track: f64,
+    changes: &[LineChange],
+    diagnostics: &[Diagnostic],
+    search_lines: &[usize],
+) -> Vec<(MarkKind, f64, f64)> {
+    let scale = track / display.row_count() as f64;
+    let mut marks: BTreeMap<(MarkKind, i64), f64> = BTreeMap::new();
+    let mut mark = |kind: MarkKind, first_line: usize, last_line: usize| {
+        let first = display.row_of(first_line, 0);
+        let last = display.row_of(last_line, 0).max(first);
+        let top = first as f64 * scale;
+        let height = ((last - first + 1) as f64 * scale).max(MIN_MARK_HEIGHT);
+        let tallest = marks.entry((kind, top as i64)).or_insert(0.0);
+        *tallest = tallest.max(height);
+    };
+
+    for change in changes {
+        let kind = match change.kind {
+            ChangeKind::Added => MarkKind::Added,
+            ChangeKind::Modified => MarkKind::Modified,
+            ChangeKind::Deleted => MarkKind::Deleted,
+        };
+        mark(kind, change.start, change.end.max(change.start + 1) - 1);
+    }
+    for diagnostic in diagnostics {
+        let kind = match diagnostic.severity {
+            Severity::Error => MarkKind::Error,
+            Severity::Warning => MarkKind::Warning,
+            Severity::Information | Severity::Hint => MarkKind::Information,
+        };
+        mark(kind, diagnostic.line, diagnostic.line);
+    }
+    for &line in search_lines {
+        mark(MarkKind::SearchMatch, line, line);
+    }
+
+    marks
+        .into_iter()
+        .map(|((kind, top), height)| (kind, top as f64, height))
+        .collect()
+}
+
+/// Lines containing `query`, matched the way incremental search matches it:
+/// ignoring case unless the query has capitals.
+pub fn search_lines(editor: &RopeEditor, query: &str) -> Vec<usize> {
+    let mut text = editor.get_content();
+    let mut query = query.to_string();
+    if !query.chars().any(char::is_uppercase) {
+        text.make_ascii_lowercase();
+        query.make_ascii_lowercase();
+    }
+    let mut lines = Vec::new();
+    let (mut line, mut scanned) = (0, 0);
+    for (index, _) in text.match_indices(&query).take(MAX_SEARCH_MARKS) {
+        line += text.as_bytes()[scanned..index]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        scanned = index;
+        if lines.last() != Some(&line) {
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// Where the minimap is scrolled to and where its viewport slider sits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MinimapLayout {
+    /// First editor row drawn at the top of the minimap
+    pub first_row: usize,
+    /// Rows the minimap has room for
+    pub rows: usize,
+    pub slider_top: f64,
+    pub slider_height: f64,
+    /// Editor rows scrolled per pixel the slider is dragged
+    pub rows_per_pixel: f64,
+}
+
+impl MinimapLayout {
+    /// A document taller than the minimap scrolls it in proportion to the editor,
+    /// so both reach their ends together.
+    pub fn new(first_visible: usize, visible_rows: usize, row_count: usize, height: f64) -> Self {
+        let rows = (height / MINIMAP_ROW_HEIGHT).floor() as usize;
+        let max_first = row_count.saturating_sub(visible_rows);
+        let (first_row, rows_per_pixel) = if row_count <= rows || max_first == 0 {
+            (0, 1.0 / MINIMAP_ROW_HEIGHT)
+        } else {
+            let first_row = first_visible.min(max_first) * (row_count - rows) / max_first;
+            let travel = rows.saturating_sub(visible_rows) as f64 * MINIMAP_ROW_HEIGHT;
+            let rows_per_pixel = if travel > 0.0 {
+                max_first as f64 / travel
+            } else {
+                0.0
+            };
+            (first_row, rows_per_pixel)
+        };
+        Self {
+            first_row,
+            rows,
+            slider_top: first_visible.saturating_sub(first_row) as f64 * MINIMAP_ROW_HEIGHT,
+            slider_height: visible_rows as f64 * MINIMAP_ROW_HEIGHT,
+            rows_per_pixel,
+        }
+    }
+
+    /// Editor row drawn `y` pixels from the top of the minimap.
+    pub fn row_at(&self, y: f64) -> usize {
+        self.first_row + (y.max(0.0) / MINIMAP_ROW_HEIGHT) as usize
+    }
+}
+
+/// Runs of non-blank characters in highlighted tokens as `(column, length, class)`,
+/// cut off at the minimap's width.
+pub fn minimap_runs<C: Copy>(tokens: &[(String, C)]) -> Vec<(usize, usize, C)> {
+    let max_columns = MINIMAP_WIDTH as usize;
+    let mut runs = Vec::new();
+    let mut column = 0;
+    for (text, class_) in tokens {
+        let mut run_start = None;
+        for c in text.chars() {
+            if column >= max_columns {
+                break;
+            }
+            match (c.is_whitespace(), run_start) {
+                (false, None) => run_start = Some(column),
+                (true, Some(start)) => {
+                    runs.push((start, column - start, *class_));
+                    run_start = None;
+                }
+                _ => {}
+            }
+            column += 1;
+        }
+        if let Some(start) = run_start {
+            runs.push((start, column - start, *class_));
+        }
+        if column >= max_columns {
+            break;
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::folding::FoldState;
+
+    #[test]
+    fn marks_for_changes_matches_and_problems() {
+        let editor = RopeEditor::from_text("Foo\nbar foo foo\nbaz\nfoo\n");
+        assert_eq!(search_lines(&editor, "foo"), vec![0, 1, 3]);
+        assert_eq!(search_lines(&editor, "Foo"), vec![0]);
+
+        let display = DisplayMap::new(
+            &editor,
+            FoldState::default().fold_map(editor.line_count()),
+            None,
+        );
+        let changes = [LineChange {
+            kind: ChangeKind::Added,
+            start: 1,
+            end: 3,
+        }];
+        let diagnostics = [Diagnostic {
+            line: 2,
+            column: 0,
+            end_line: 2,
+            end_column: 1,
+            severity: Severity::Warning,
+            message: String::new(),
+            source: None,
+            lsp: None,
+        }];
+        // Five rows in 100 pixels, 20 to a row
+        assert_eq!(
+            overview_marks(&display, 100.0, &changes, &diagnostics, &[0, 3]),
+            vec![
+                (MarkKind::Added, 20.0, 40.0),
+                (MarkKind::SearchMatch, 0.0, 20.0),
+                (MarkKind::SearchMatch, 60.0, 20.0),
+                (MarkKind::Warning, 40.0, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn minimap_scrolls_once_the_document_is_taller_than_it() {
+        let fits = MinimapLayout::new(10, 20, 100, 400.0);
+        assert_eq!(
+            (
+                fits.first_row,
+                fits.rows,
+                fits.slider_top,
+                fits.slider_height
+            ),
+            (0, 200, 20.0, 40.0)
+        );
+        assert_eq!(fits.row_at(41.0), 20);
+
+        // 200 of 1000 rows fit, and the viewport can start as far down as 980
+        let bottom = MinimapLayout::new(980, 20, 1000, 400.0);
+        assert_eq!((bottom.first_row, bottom.slider_top), (800, 360.0));
+        let top = MinimapLayout::new(0, 20, 1000, 400.0);
+        assert_eq!((top.first_row, top.slider_top), (0, 0.0));
+        assert_eq!(top.rows_per_pixel, 980.0 / 360.0);
+    }
+
+    #[test]
+    fn minimap_runs_skip_blanks_and_stop_at_its_width() {
+        let tokens = vec![
+            ("  let".to_string(), 1),
+            (" ".to_string(), 0),
+            ("a b".to_string(), 2),
+        ];
+        assert_eq!(minimap_runs(&tokens), vec![(2, 3, 1), (6, 1, 2), (8, 1, 2)]);
+        assert_eq!(minimap_runs(&[("x".repeat(500), 0)]), vec![(0, 100, 0)]);
+    }
+}
@@ -0,0 +1,242 @@
+use super::navigation::Location;
+use crate::lsp::{self, protocol};
+use dioxus::prelude::*;
+use ropey::Rope;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Places a language server points to: definitions, implementations and
+// references. Lists of more than one go to the references panel through
+// REFERENCES, and picking one jumps there through the navigation history.
+
+const MAX_PREVIEW: usize = 160;
+
+/// A place in a file, with its line for showing in a list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub location: Location,
+    /// Chars of the reference on its line
+    pub length: usize,
+    /// The line without its indentation, and where the reference starts in it
+    pub preview: String,
+    pub preview_column: usize,
+}
+
+/// The results of the last search, as the references panel shows them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct References {
+    /// What was looked for, such as "References to main"
+    pub title: String,
+    /// Where the search started
+    pub origin: Location,
+    pub results: Vec<Reference>,
+}
+
+pub static REFERENCES: GlobalSignal<Option<References>> = Signal::global(|| None);
+
+/// The places in a definition or references response: a Location, a list of
+/// them or a list of LocationLinks, by file and then position.
+pub fn from_response(response: &Value) -> Vec<Reference> {
+    let items = match response {
+        Value::Array(items) => items.as_slice(),
+        Value::Null => &[],
+        single => std::slice::from_ref(single),
+    };
+    // Each file is read once, however many results it has
+    let mut texts: HashMap<PathBuf, Option<Rope>> = HashMap::new();
+    let mut results: Vec<Reference> = items
+        .iter()
+        .filter_map(|item| {
+            // Links point at the name with their selection range
+            let uri = item
+                .get("uri")
+                .or_else(|| item.get("targetUri"))?
+                .as_str()?;
+            let range = item
+                .get("range")
+                .or_else(|| item.get("targetSelectionRange"))?;
+            let path = protocol::uri_to_path(uri)?;
+            let text = texts
+                .entry(path.clone())
+                .or_insert_with(|| text_of(&path))
+                .as_ref()?;
+            reference(text, path, range)
+        })
+        .collect();
+    let key = |reference: &Reference| {
+        let location = &reference.location;
+        (location.path.clone(), location.line, location.column)
+    };
+    results.sort_by_key(key);
+    results.dedup_by(|a, b| a.location == b.location);
+    results
+}
+
+fn reference(text: &Rope, path: PathBuf, range: &Value) -> Option<Reference> {
+    let range = protocol::range_to_chars(text, range)?;
+    let line = text.char_to_line(range.start);
+    let line_start = text.line_to_char(line);
+    let content = text.line(line).to_string();
+    let content = content.trim_end_matches(['\n', '\r']);
+    let indent = content.chars().take_while(|c| c.is_whitespace()).count();
+    let column = range.start - line_start;
+    let line_end = line_start + content.chars().count();
+    Some(Reference {
+        location: Location { path, line, column },
+        length: range.end.min(line_end).saturating_sub(range.start),
+        preview: content.chars().skip(indent).take(MAX_PREVIEW).collect(),
+        preview_column: column.saturating_sub(indent),
+    })
+}
+
+/// The text of `path` as its language server knows it, or as saved.
+pub fn text_of(path: &Path) -> Option<Rope> {
+    lsp::document_text(path).or_else(|| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|text| Rope::from_str(&text))
+    })
+}
+
+/// Up to `count` lines around `location`, starting `before` lines above it,
+/// with the number of the first.
+pub fn lines_around(
+    location: &Location,
+    before: usize,
+    count: usize,
+) -> Option<(usize, Vec<String>)> {
+    let text = text_of(&location.path)?;
+    let first = location
+        .line
+        .saturating_sub(before)
+        .min(text.len_lines().saturating_sub(1));
+    let lines = text
+        .lines_at(first)
+        .take(count)
+        .map(|line| line.to_string().trim_end_matches(['\n', '\r']).to_string())
+        .collect();
+    Some((first, lines))
+}
+
+/// A definition shown inside the editor, with a few lines around it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Peek {
+    pub target: Location,
+    pub first_line: usize,
+    pub lines: Vec<String>,
+    /// How many definitions there were; the first is shown
+    pub definitions: usize,
+}
+
+impl Peek {
+    /// The first of `results`, as `count` lines from `before` lines above it.
+    pub fn new(results: &[Reference], before: usize, count: usize) -> Option<Self> {
+        let target = results.first()?.location.clone();
+        let (first_line, lines) = lines_around(&target, before, count)?;
+        Some(Self {
+            target,
+            first_line,
+            lines,
+            definitions: results.len(),
+        })
+    }
+}
+
+/// Shows `results` in the references panel.
+pub fn show(title: String, origin: Location, results: Vec<Reference>) {
+    *REFERENCES.write() = Some(References {
+        title,
+        origin,
+        results,
+    });
+}
+
+pub fn close() {
+    *REFERENCES.write() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn range(line: u64, start: u64, end: u64) -> Value {
+        json!({ "start": { "line": line, "character": start },
+                "end": { "line": line, "character": end } })
+    }
+
+    #[test]
+    fn responses_become_sorted_references_with_previews() {
+        let dir = std::env::temp_dir().join(format!("references_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        std::fs::write(&a, "fn main() {\n    let \u{e9} = foo();\n}\n").unwrap();
+        std::fs::write(&b, "pub fn foo() {}\n").unwrap();
+        let (uri_a, uri_b) = (protocol::path_to_uri(&a), protocol::path_to_uri(&b));
+
+        VirtualDom::new(|| rsx! {}).in_runtime(|| {
+            let one = from_response(&json!({ "uri": uri_b, "range": range(0, 7, 10) }));
+            assert_eq!(
+                one,
+                [Reference {
+                    location: Location {
+                        path: b.clone(),
+                        line: 0,
+                        column: 7,
+                    },
+                    length: 3,
+                    preview: "pub fn foo() {}".to_string(),
+                    preview_column: 7,
+                }]
+            );
+
+            // By file, without repeats; columns are in chars, after the é
+            let many = from_response(&json!([
+                { "uri": uri_b, "range": range(0, 7, 10) },
+                { "uri": uri_a, "range": range(1, 12, 15) },
+                { "uri": uri_b, "range": range(0, 7, 10) },
+            ]));
+            assert_eq!(many.len(), 2);
+            assert_eq!(
+                many[0].location,
+                Location {
+                    path: a.clone(),
+                    line: 1,
+                    column: 12,
+                }
+            );
+            assert_eq!(
+                (many[0].preview.as_str(), many[0].preview_column),
+                ("let \u{e9} = foo();", 8)
+            );
+
+            // Links point at their selection range
+            let links = from_response(&json!([{
+                "targetUri": uri_b,
+                "targetRange": range(0, 0, 15),
+                "targetSelectionRange": range(0, 7, 10),
+            }]));
+            assert_eq!(links[0].location.column, 7);
+
+            assert!(from_response(&Value::Null).is_empty());
+            let missing = json!({ "uri": "file:///nonexistent/none.rs", "range": range(0, 0, 1) });
+            assert!(from_response(&missing).is_empty());
+
+            let peek = Peek::new(&many, 1, 2).unwrap();
+            assert_eq!(peek.first_line, 0);
+            assert_eq!(peek.lines, ["fn main() {", "    let \u{e9} = foo();"]);
+            assert_eq!(peek.definitions, 2);
+            let start_of_b = Location {
+                path: b.clone(),
+                line: 0,
+                column: 0,
+            };
+            assert_eq!(
+                lines_around(&start_of_b, 3, 12),
+                Some((0, vec!["pub fn foo() {}".to_string(), String::new()]))
+            );
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
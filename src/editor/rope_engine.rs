@@ -1,6 +1,14 @@
-use super::types::{CursorPosition, EditorState};
+use super::language::{self, Language, PLAIN_TEXT};
+use super::syntax::{self, LineHighlights, Syntax};
+use super::types::{BlockSelection, CursorPosition, EditorState};
+use crate::lsp::protocol::{self, ContentChange};
 use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tree_sitter::Node;
 
 #[derive(Clone, Debug)]
 pub struct EditorAction {
@@ -9,6 +17,8 @@ pub struct EditorAction {
     pub text: String,
     pub cursor_before: CursorPosition,
     pub cursor_after: CursorPosition,
+    // Actions sharing a group id are undone/redone together
+    pub group: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -17,6 +27,44 @@ pub enum ActionType {
     Delete,
 }
 
+/// Where one change to the text happened, as (line, column) in chars: the
+/// text from `start` to `old_end` became the text from `start` to `new_end`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextEdit {
+    pub start: (usize, usize),
+    pub old_end: (usize, usize),
+    pub new_end: (usize, usize),
+}
+
+impl TextEdit {
+    /// Where `line` moves with this edit, None when the edit deleted it. A line
+    /// is followed by its start, so text typed into it leaves it where it is
+    /// and lines put in front of it push it down.
+    pub fn moved_line(&self, line: usize) -> Option<usize> {
+        let at = (line, 0);
+        if at < self.start {
+            Some(line)
+        } else if at >= self.old_end {
+            Some(line - self.old_end.0 + self.new_end.0)
+        } else if self.old_end.0 == line {
+            // Its start was deleted, but its text now carries on the edit's line
+            Some(self.new_end.0)
+        } else {
+            None
+        }
+    }
+}
+
+// Recent edits kept for those that catch up on them later, such as folds
+const EDIT_LOG_LIMIT: usize = 1024;
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+static NEXT_BUFFER_ID: AtomicU64 = AtomicU64::new(1);
+
+// A selection (anchor, cursor) that expand selection grew, and the range it grew into
+pub type Expansion = ((Option<usize>, usize), Range<usize>);
+
 #[derive(Debug)]
 pub struct RopeEditor {
     rope: Rope,
@@ -26,6 +74,32 @@ pub struct RopeEditor {
     undo_stack: Vec<EditorAction>,
     redo_stack: Vec<EditorAction>,
     clipboard: String,
+    // Other end of the selection; the cursor is always the moving end
+    selection_anchor: Option<usize>,
+    // Column selection; while there is one, the anchor is unused
+    block_selection: Option<BlockSelection>,
+    // Whether the clipboard holds the rows of a column selection
+    clipboard_is_block: bool,
+    next_group: u64,
+    open_group: Option<u64>,
+    group_depth: usize,
+    // Tells buffers apart, also two of the same file
+    id: u64,
+    // The latest edits, and how many there have been in all
+    edit_log: VecDeque<TextEdit>,
+    edit_count: u64,
+    // Bumped on every change to the text, so views can cache layout per revision
+    revision: u64,
+    // Columns between tab stops, for measuring indentation and laying out text
+    tab_width: usize,
+    language: &'static Language,
+    // Parse tree for highlighting; reparsed on demand, which a view does while
+    // only holding the editor for reading
+    syntax: RefCell<Option<Syntax>>,
+    // Steps of expand selection, innermost first
+    expansions: Vec<Expansion>,
+    // Edits a language server hasn't been told of yet, while one follows the text
+    lsp_changes: RefCell<Option<Vec<ContentChange>>>,
 }
 
 impl RopeEditor {
@@ -42,9 +116,43 @@ impl RopeEditor {
             undo_stack: Vec::new(),
             redo_stack: Vec::new(),
             clipboard: String::new(),
+            selection_anchor: None,
+            block_selection: None,
+            clipboard_is_block: false,
+            next_group: 0,
+            open_group: None,
+            group_depth: 0,
+            id: NEXT_BUFFER_ID.fetch_add(1, Ordering::Relaxed),
+            edit_log: VecDeque::new(),
+            edit_count: 0,
+            revision: 0,
+            tab_width: DEFAULT_TAB_WIDTH,
+            language: &PLAIN_TEXT,
+            syntax: RefCell::new(None),
+            expansions: Vec::new(),
+            lsp_changes: RefCell::new(None),
         }
     }
 
+    /// A buffer holding `text` with no file behind it, for tests. The text is
+    /// not an edit, so there is nothing to undo.
+    #[cfg(test)]
+    pub(crate) fn from_text(text: &str) -> Self {
+        let mut editor = Self::new();
+        editor.rope = Rope::from_str(text);
+        editor
+    }
+
+    /// Like `from_text`, for a file called `name`, which picks the language
+    /// as it would on loading.
+    #[cfg(test)]
+    pub(crate) fn from_named_text(name: &str, text: &str) -> Self {
+        let mut editor = Self::from_text(text);
+        editor.file_path = PathBuf::from(name);
+        editor.detect_language();
+        editor
+    }
+
     pub fn load_file(&mut self, path: &PathBuf) -> Result<(), std::io::Error> {
         match std::fs::File::open(path) {
             Ok(file) => {
@@ -58,6 +166,20 @@ impl RopeEditor {
                 };
                 self.undo_stack.clear();
                 self.redo_stack.clear();
+                self.selection_anchor = None;
+                self.block_selection = None;
+                // Nothing can follow the old text into the new
+                self.edit_log.clear();
+                self.edit_count += 1;
+                self.revision += 1;
+                self.detect_language();
+                if let Some(changes) = self.lsp_changes.get_mut() {
+                    changes.clear();
+                    changes.push(ContentChange {
+                        range: None,
+                        text: self.rope.to_string(),
+                    });
+                }
                 Ok(())
             }
             Err(e) => Err(e),
@@ -71,13 +193,23 @@ impl RopeEditor {
         Ok(())
     }
 
+    /// Writes the text to `path`, which becomes the buffer's file.
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
+        let mut file = std::fs::File::create(&path)?;
+        self.rope.write_to(&mut file)?;
+        self.file_path = path;
+        self.is_modified = false;
+        Ok(())
+    }
+
     pub fn insert_text(&mut self, text: &str) {
         let position = self.cursor.byte_offset;
         if position <= self.rope.len_chars() && !text.is_empty() {
             let cursor_before = self.cursor.clone();
 
-            self.rope.insert(position, text);
+            self.insert_into_rope(position, text);
             self.is_modified = true;
+            self.revision += 1;
 
             // Update cursor position after insertion
             self.cursor.byte_offset = position + text.chars().count();
@@ -86,12 +218,14 @@ impl RopeEditor {
             let cursor_after = self.cursor.clone();
 
             // Add to undo stack
+            let group = self.action_group();
             self.undo_stack.push(EditorAction {
                 action_type: ActionType::Insert,
                 position,
                 text: text.to_string(),
                 cursor_before,
                 cursor_after,
+                group,
             });
 
             // Clear redo stack when new action is performed
@@ -104,20 +238,23 @@ impl RopeEditor {
             let cursor_before = self.cursor.clone();
             let deleted_text = self.rope.slice(start..end).to_string();
 
-            self.rope.remove(start..end);
+            self.remove_from_rope(start..end);
             self.is_modified = true;
+            self.revision += 1;
             self.cursor.byte_offset = start;
             self.update_cursor_from_byte_offset();
 
             let cursor_after = self.cursor.clone();
 
             // Add to undo stack
+            let group = self.action_group();
             self.undo_stack.push(EditorAction {
                 action_type: ActionType::Delete,
                 position: start,
                 text: deleted_text,
                 cursor_before,
                 cursor_after,
+                group,
             });
 
             // Clear redo stack when new action is performed
@@ -141,45 +278,132 @@ impl RopeEditor {
         }
     }
 
+    // Every change to the text goes through these two, which keep the parse tree in step
+    fn insert_into_rope(&mut self, position: usize, text: &str) {
+        self.expansions.clear();
+        if let Some(syntax) = self.syntax.get_mut() {
+            syntax.edit(&syntax::insert_edit(&self.rope, position, text));
+        }
+        if let Some(changes) = self.lsp_changes.get_mut() {
+            changes.push(protocol::insert_change(&self.rope, position, text));
+        }
+        let start = self.line_column(position);
+        self.rope.insert(position, text);
+        let new_end = self.line_column(position + text.chars().count());
+        self.log_edit(TextEdit {
+            start,
+            old_end: start,
+            new_end,
+        });
+    }
+
+    fn remove_from_rope(&mut self, range: Range<usize>) {
+        self.expansions.clear();
+        if let Some(syntax) = self.syntax.get_mut() {
+            syntax.edit(&syntax::remove_edit(&self.rope, range.clone()));
+        }
+        if let Some(changes) = self.lsp_changes.get_mut() {
+            changes.push(protocol::remove_change(&self.rope, range.clone()));
+        }
+        let start = self.line_column(range.start);
+        let old_end = self.line_column(range.end);
+        self.rope.remove(range);
+        self.log_edit(TextEdit {
+            start,
+            old_end,
+            new_end: start,
+        });
+    }
+
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = self.rope.char_to_line(offset);
+        (line, offset - self.rope.line_to_char(line))
+    }
+
+    fn log_edit(&mut self, edit: TextEdit) {
+        if self.edit_log.len() == EDIT_LOG_LIMIT {
+            self.edit_log.pop_front();
+        }
+        self.edit_log.push_back(edit);
+        self.edit_count += 1;
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// How many edits have been made to the text, for use with `edits_since`.
+    pub fn edit_count(&self) -> u64 {
+        self.edit_count
+    }
+
+    /// The edits made after the first `seen`, oldest first; None when they go
+    /// back further than the buffer remembers.
+    pub fn edits_since(&self, seen: u64) -> Option<impl Iterator<Item = &TextEdit>> {
+        let first_kept = self.edit_count - self.edit_log.len() as u64;
+        let skip = seen.checked_sub(first_kept)?;
+        Some(self.edit_log.iter().skip(skip as usize))
+    }
+
     pub fn insert_newline(&mut self) {
         self.insert_text("\n");
     }
 
     pub fn undo(&mut self) -> bool {
-        if let Some(action) = self.undo_stack.pop() {
+        let Some(group) = self.undo_stack.last().map(|action| action.group) else {
+            return false;
+        };
+
+        while self
+            .undo_stack
+            .last()
+            .is_some_and(|action| action.group == group)
+        {
+            let action = self.undo_stack.pop().unwrap();
             match action.action_type {
                 ActionType::Insert => {
                     // Reverse insertion by deleting
                     let start = action.position;
                     let end = start + action.text.chars().count();
                     if end <= self.rope.len_chars() {
-                        self.rope.remove(start..end);
+                        self.remove_from_rope(start..end);
                     }
                 }
                 ActionType::Delete => {
                     // Reverse deletion by inserting
                     if action.position <= self.rope.len_chars() {
-                        self.rope.insert(action.position, &action.text);
+                        self.insert_into_rope(action.position, &action.text);
                     }
                 }
             }
 
             self.cursor = action.cursor_before;
             self.redo_stack.push(action);
-            self.is_modified = true;
-            true
-        } else {
-            false
         }
+
+        self.selection_anchor = None;
+        self.block_selection = None;
+        self.is_modified = true;
+        self.revision += 1;
+        true
     }
 
     pub fn redo(&mut self) -> bool {
-        if let Some(action) = self.redo_stack.pop() {
+        let Some(group) = self.redo_stack.last().map(|action| action.group) else {
+            return false;
+        };
+
+        while self
+            .redo_stack
+            .last()
+            .is_some_and(|action| action.group == group)
+        {
+            let action = self.redo_stack.pop().unwrap();
             match action.action_type {
                 ActionType::Insert => {
                     // Redo insertion
                     if action.position <= self.rope.len_chars() {
-                        self.rope.insert(action.position, &action.text);
+                        self.insert_into_rope(action.position, &action.text);
                     }
                 }
                 ActionType::Delete => {
@@ -187,23 +411,53 @@ impl RopeEditor {
                     let start = action.position;
                     let end = start + action.text.chars().count();
                     if end <= self.rope.len_chars() {
-                        self.rope.remove(start..end);
+                        self.remove_from_rope(start..end);
                     }
                 }
             }
 
             self.cursor = action.cursor_after;
             self.undo_stack.push(action);
-            self.is_modified = true;
-            true
-        } else {
-            false
+        }
+
+        self.selection_anchor = None;
+        self.block_selection = None;
+        self.is_modified = true;
+        self.revision += 1;
+        true
+    }
+
+    /// Starts an undo group: every edit until the matching `end_undo_group`
+    /// is undone and redone as a single step. Groups may be nested.
+    pub fn begin_undo_group(&mut self) {
+        if self.group_depth == 0 {
+            self.open_group = Some(self.next_group);
+            self.next_group += 1;
+        }
+        self.group_depth += 1;
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.group_depth = self.group_depth.saturating_sub(1);
+        if self.group_depth == 0 {
+            self.open_group = None;
+        }
+    }
+
+    fn action_group(&mut self) -> u64 {
+        match self.open_group {
+            Some(group) => group,
+            None => {
+                let group = self.next_group;
+                self.next_group += 1;
+                group
+            }
         }
     }
 
     pub fn copy_selection(&mut self, start: usize, end: usize) {
         if start < end && end <= self.rope.len_chars() {
-            self.clipboard = self.rope.slice(start..end).to_string();
+            self.set_clipboard(self.rope.slice(start..end).to_string());
         }
     }
 
@@ -214,7 +468,7 @@ impl RopeEditor {
         } else {
             self.rope.len_chars()
         };
-        self.clipboard = self.rope.slice(line_start..line_end).to_string();
+        self.set_clipboard(self.rope.slice(line_start..line_end).to_string());
     }
 
     pub fn paste(&mut self) {
@@ -233,6 +487,14 @@ impl RopeEditor {
         }
     }
 
+    /// Characters of a line, line break included, without copying it out of the rope.
+    pub fn line_chars(&self, line_idx: usize) -> impl Iterator<Item = char> + '_ {
+        (line_idx < self.rope.len_lines())
+            .then(|| self.rope.line(line_idx).chars())
+            .into_iter()
+            .flatten()
+    }
+
     pub fn line_count(&self) -> usize {
         self.rope.len_lines()
     }
@@ -241,6 +503,80 @@ impl RopeEditor {
         self.rope.len_chars()
     }
 
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Columns from one tab stop to the next.
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    pub fn set_tab_width(&mut self, width: usize) {
+        self.tab_width = width.max(1);
+    }
+
+    pub fn language(&self) -> &'static Language {
+        self.language
+    }
+
+    /// Picks the language from the file's name and text.
+    pub fn detect_language(&mut self) {
+        self.set_language(language::detect(&self.file_path, &self.rope));
+    }
+
+    /// Switches the buffer to `language`, parsing it again with its grammar.
+    pub fn set_language(&mut self, language: &'static Language) {
+        self.language = language;
+        *self.syntax.get_mut() = language.grammar.and_then(Syntax::new);
+        self.revision += 1;
+    }
+
+    pub fn is_word_char(&self, c: char) -> bool {
+        self.language.is_word_char(c)
+    }
+
+    /// Syntax highlights for `lines`, one entry per line, with long lines
+    /// highlighted only for the columns in `window`; empty for a file without
+    /// a grammar.
+    pub fn highlight_lines(
+        &self,
+        lines: Range<usize>,
+        window: Range<usize>,
+    ) -> Vec<LineHighlights> {
+        match self.syntax.borrow_mut().as_mut() {
+            Some(syntax) => syntax.highlight(&self.rope, lines, window),
+            None => vec![Vec::new(); lines.len()],
+        }
+    }
+
+    /// Starts or stops keeping the edits for a language server, which picks
+    /// them up with `take_lsp_changes`.
+    pub fn follow_lsp_changes(&self, follow: bool) {
+        *self.lsp_changes.borrow_mut() = follow.then(Vec::new);
+    }
+
+    pub fn is_followed_by_lsp(&self) -> bool {
+        self.lsp_changes.borrow().is_some()
+    }
+
+    /// The edits since the last call, oldest first.
+    pub fn take_lsp_changes(&self) -> Vec<ContentChange> {
+        self.lsp_changes
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Runs `f` on the root of the parse tree, reparsing first if the text
+    /// changed. None for a file without a grammar.
+    pub fn with_syntax_tree<R>(&self, f: impl FnOnce(Node, &Rope) -> R) -> Option<R> {
+        let mut syntax = self.syntax.borrow_mut();
+        let tree = syntax.as_mut()?.tree(&self.rope)?;
+        Some(f(tree.root_node(), &self.rope))
+    }
+
     pub fn is_modified(&self) -> bool {
         self.is_modified
     }
@@ -249,6 +585,10 @@ impl RopeEditor {
         &self.file_path
     }
 
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
     pub fn get_content(&self) -> String {
         self.rope.to_string()
     }
@@ -351,6 +691,192 @@ impl RopeEditor {
         self.set_cursor(self.cursor.line, target_col);
     }
 
+    /// Length of a line in chars, excluding its line ending.
+    pub fn line_len(&self, line_idx: usize) -> usize {
+        if line_idx >= self.rope.len_lines() {
+            return 0;
+        }
+        let line = self.rope.line(line_idx);
+        let mut len = line.len_chars();
+        if len > 0 && line.char(len - 1) == '\n' {
+            len -= 1;
+            if len > 0 && line.char(len - 1) == '\r' {
+                len -= 1;
+            }
+        }
+        len
+    }
+
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        self.rope.line_to_char(line_idx.min(self.rope.len_lines()))
+    }
+
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        self.rope.char_to_line(char_idx.min(self.rope.len_chars()))
+    }
+
+    pub fn char_at(&self, char_idx: usize) -> Option<char> {
+        if char_idx < self.rope.len_chars() {
+            Some(self.rope.char(char_idx))
+        } else {
+            None
+        }
+    }
+
+    pub fn slice_text(&self, start: usize, end: usize) -> String {
+        let end = end.min(self.rope.len_chars());
+        if start < end {
+            self.rope.slice(start..end).to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Replaces `start..end` with `text` as a single undo step.
+    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        self.begin_undo_group();
+        self.delete_range(start, end);
+        self.set_cursor_offset(start);
+        self.insert_text(text);
+        self.end_undo_group();
+    }
+
+    pub fn set_cursor_offset(&mut self, char_idx: usize) {
+        self.cursor.byte_offset = char_idx.min(self.rope.len_chars());
+        self.update_cursor_from_byte_offset();
+    }
+
+    pub fn clipboard(&self) -> &str {
+        &self.clipboard
+    }
+
+    pub fn set_clipboard(&mut self, text: String) {
+        self.clipboard = text;
+        self.clipboard_is_block = false;
+    }
+
+    /// Puts the rows of a column selection on the clipboard, one per line.
+    pub fn set_block_clipboard(&mut self, rows: Vec<String>) {
+        self.clipboard = rows.join("\n");
+        self.clipboard_is_block = true;
+    }
+
+    pub fn clipboard_is_block(&self) -> bool {
+        self.clipboard_is_block
+    }
+
+    pub fn selection_anchor(&self) -> Option<usize> {
+        self.selection_anchor
+    }
+
+    pub fn set_selection_anchor(&mut self, anchor: Option<usize>) {
+        self.selection_anchor = anchor.map(|pos| pos.min(self.rope.len_chars()));
+    }
+
+    /// Remembers the selection (anchor, cursor) that expand selection grew
+    /// into `grown`.
+    pub fn push_expansion(&mut self, previous: (Option<usize>, usize), grown: Range<usize>) {
+        self.expansions.push((previous, grown));
+    }
+
+    pub fn pop_expansion(&mut self) -> Option<Expansion> {
+        self.expansions.pop()
+    }
+
+    /// The range the last expand selection grew into.
+    pub fn last_expansion(&self) -> Option<Range<usize>> {
+        self.expansions.last().map(|(_, grown)| grown.clone())
+    }
+
+    pub fn clear_expansions(&mut self) {
+        self.expansions.clear();
+    }
+
+    pub fn block_selection(&self) -> Option<BlockSelection> {
+        self.block_selection
+    }
+
+    /// Replaces the selection with a column selection and puts the cursor at
+    /// its head, or as near as the line allows.
+    pub fn set_block_selection(&mut self, block: Option<BlockSelection>) {
+        let last_line = self.rope.len_lines() - 1;
+        self.block_selection = block.map(|block| BlockSelection {
+            anchor: (block.anchor.0.min(last_line), block.anchor.1),
+            head: (block.head.0.min(last_line), block.head.1),
+        });
+        if let Some(block) = self.block_selection {
+            self.selection_anchor = None;
+            self.set_cursor(block.head.0, block.head.1);
+        }
+    }
+
+    /// Ordered char range between the anchor and the cursor, if any.
+    pub fn selection_range(&self) -> Option<(usize, usize)> {
+        let anchor = self.selection_anchor?;
+        let head = self.cursor.byte_offset;
+        if anchor == head {
+            None
+        } else {
+            Some((anchor.min(head), anchor.max(head)))
+        }
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection_range()
+            .map(|(start, end)| self.slice_text(start, end))
+    }
+
+    /// Finds `query` starting at or after `from` (forward), or starting before
+    /// `from` (backward). Returns the char offset of the match.
+    pub fn find_text(
+        &self,
+        query: &str,
+        from: usize,
+        forward: bool,
+        ignore_case: bool,
+    ) -> Option<usize> {
+        let fold = |c: char| {
+            if ignore_case {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            }
+        };
+        let query: Vec<char> = query.chars().map(fold).collect();
+        let len = query.len();
+        if len == 0 {
+            return None;
+        }
+        // Walks the rope with a window of the query's length rather than
+        // copying the text out, as this runs on every isearch keystroke
+        let mut window = VecDeque::with_capacity(len + 1);
+        if forward {
+            let from = from.min(self.rope.len_chars());
+            for (i, c) in self.rope.chars_at(from).enumerate() {
+                window.push_back(fold(c));
+                if window.len() > len {
+                    window.pop_front();
+                }
+                if window.len() == len && window.iter().eq(query.iter()) {
+                    return Some(from + i + 1 - len);
+                }
+            }
+        } else {
+            // A match starting just before `from` ends this far in
+            let end = (from + len - 1).min(self.rope.len_chars());
+            for (i, c) in self.rope.chars_at(end).reversed().enumerate() {
+                window.push_front(fold(c));
+                if window.len() > len {
+                    window.pop_back();
+                }
+                if window.len() == len && window.iter().eq(query.iter()) {
+                    return Some(end - i - 1);
+                }
+            }
+        }
+        None
+    }
+
     fn update_cursor_from_byte_offset(&mut self) {
         let byte_offset = self.cursor.byte_offset.min(self.rope.len_chars());
         let line = self.rope.char_to_line(byte_offset);
@@ -0,0 +1,144 @@
+use super::wrap::{LineLayout, VisualRow};
+use std::ops::Range;
+
+// Scrolling. Rows are only drawn for the columns around the viewport, so a
+// megabyte-long line of minified JSON costs no more than a short one.
+
+/// Lines longer than this are fetched and highlighted only around the viewport
+pub const LONG_LINE: usize = 10_000;
+/// Columns drawn past each edge of the viewport
+const COLUMN_BUFFER: usize = 32;
+/// Columns kept between the cursor and the edge when scrolling to reveal it
+const REVEAL_MARGIN: f64 = 4.0;
+const MIN_THUMB_LENGTH: f64 = 24.0;
+
+/// Screen columns worth drawing when scrolled `scroll_x` pixels to the right.
+pub fn visible_columns(scroll_x: f64, text_width: f64, char_width: f64) -> Range<usize> {
+    let first = (scroll_x / char_width).floor() as usize;
+    let count = (text_width.max(0.0) / char_width).ceil() as usize;
+    first.saturating_sub(COLUMN_BUFFER)..first + count + COLUMN_BUFFER
+}
+
+/// Buffer columns of `row` that fall within the screen columns `screen`, at
+/// least partly.
+pub fn row_columns(
+    row: &VisualRow,
+    layout: &LineLayout,
+    screen: Range<usize>,
+    line_len: usize,
+) -> Range<usize> {
+    let end = row.end.unwrap_or(line_len).min(line_len);
+    let start_x = layout.x_of(row.start);
+    let columns = layout.columns_in(
+        start_x + screen.start.saturating_sub(row.indent)
+            ..start_x + screen.end.saturating_sub(row.indent),
+    );
+    let from = columns.start.clamp(row.start, end);
+    from..columns.end.clamp(from, end)
+}
+
+/// Furthest the text can scroll when its widest row is `columns` wide.
+pub fn max_scroll(columns: usize, text_width: f64, char_width: f64) -> f64 {
+    // One extra column leaves room for the caret after the last character
+    ((columns + 1) as f64 * char_width - text_width).max(0.0)
+}
+
+/// Scroll offset that shows screen column `column`, moving as little as possible.
+pub fn reveal(scroll_x: f64, column: usize, text_width: f64, char_width: f64) -> f64 {
+    let x = column as f64 * char_width;
+    let margin = (REVEAL_MARGIN * char_width).min(text_width / 3.0);
+    if x < scroll_x + margin {
+        (x - margin).max(0.0).min(scroll_x)
+    } else if x + char_width > scroll_x + text_width - margin {
+        x + char_width + margin - text_width
+    } else {
+        scroll_x
+    }
+}
+
+/// Start and length of a scrollbar thumb in a `track` pixels long, for content
+/// that shows `visible` of `visible + max` at a time and is scrolled to `position`.
+pub fn thumb(position: f64, max: f64, visible: f64, track: f64) -> (f64, f64) {
+    let length = (track * visible / (visible + max))
+        .max(MIN_THUMB_LENGTH)
+        .min(track);
+    let start = if max > 0.0 {
+        position / max * (track - length)
+    } else {
+        0.0
+    };
+    (start, length)
+}
+
+/// How far the content scrolls per pixel the thumb is dragged.
+pub fn thumb_scale(max: f64, visible: f64, track: f64) -> f64 {
+    let (_, length) = thumb(0.0, max, visible, track);
+    if track > length {
+        max / (track - length)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(start: usize, end: Option<usize>, indent: usize) -> VisualRow {
+        VisualRow {
+            line: 0,
+            start,
+            end,
+            indent,
+        }
+    }
+
+    fn no_tabs() -> LineLayout {
+        LineLayout::new("".chars(), 4)
+    }
+
+    #[test]
+    fn only_the_columns_around_the_viewport_are_drawn() {
+        assert_eq!(visible_columns(0.0, 100.0, 10.0), 0..42);
+        assert_eq!(visible_columns(1000.0, 100.0, 10.0), 68..142);
+
+        let whole = row(0, None, 0);
+        assert_eq!(row_columns(&whole, &no_tabs(), 68..142, 5_000_000), 68..142);
+        assert_eq!(row_columns(&whole, &no_tabs(), 68..142, 100), 68..100);
+        assert_eq!(row_columns(&whole, &no_tabs(), 68..142, 10), 10..10);
+        // A continuation row, indented by four columns
+        assert_eq!(
+            row_columns(&row(40, Some(80), 4), &no_tabs(), 0..20, 200),
+            40..56
+        );
+    }
+
+    #[test]
+    fn tabs_partly_in_view_are_drawn() {
+        let layout = LineLayout::new("\t\tx".chars(), 4);
+        assert_eq!(row_columns(&row(0, None, 0), &layout, 5..9, 3), 1..3);
+        assert_eq!(row_columns(&row(1, None, 0), &layout, 0..2, 3), 1..2);
+    }
+
+    #[test]
+    fn reveal_keeps_a_margin_around_the_cursor() {
+        assert_eq!(max_scroll(9, 100.0, 10.0), 0.0);
+        assert_eq!(max_scroll(100, 100.0, 10.0), 910.0);
+
+        let margin = 100.0 / 3.0;
+        assert_eq!(reveal(0.0, 5, 100.0, 10.0), 0.0);
+        assert_eq!(reveal(0.0, 20, 100.0, 10.0), 210.0 + margin - 100.0);
+        assert_eq!(reveal(500.0, 10, 100.0, 10.0), 100.0 - margin);
+        assert_eq!(reveal(500.0, 0, 100.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn thumbs_are_in_proportion_but_never_too_short() {
+        assert_eq!(thumb(0.0, 100.0, 100.0, 100.0), (0.0, 50.0));
+        assert_eq!(thumb(100.0, 100.0, 100.0, 100.0).0, 50.0);
+        assert_eq!(thumb_scale(100.0, 100.0, 100.0), 2.0);
+        // 40 rows on screen of 1000, in a 400 pixel track
+        assert_eq!(thumb(960.0, 960.0, 40.0, 400.0), (376.0, 24.0));
+        assert_eq!(thumb_scale(0.0, 40.0, 400.0), 0.0);
+    }
+}
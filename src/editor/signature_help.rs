@@ -0,0 +1,239 @@
+use super::markdown;
+use serde_json::Value;
+use std::ops::Range;
+
+// Signature help: while a call's arguments are typed, the signatures of what is
+// called, with the parameter being typed picked out.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub label: String,
+    /// Char ranges of the parameters in `label`
+    pub parameters: Vec<Range<usize>>,
+    /// Markdown for each parameter, empty where there is none
+    pub parameter_docs: Vec<String>,
+    pub documentation: String,
+    /// The parameter being typed, when the signature says so itself
+    pub active_parameter: Option<usize>,
+}
+
+impl Signature {
+    fn from_lsp(value: &Value) -> Option<Self> {
+        let label = value.get("label")?.as_str()?.to_string();
+        let mut parameters = Vec::new();
+        let mut parameter_docs = Vec::new();
+        for parameter in value["parameters"].as_array().into_iter().flatten() {
+            let searched_from = parameters
+                .last()
+                .map_or(0, |range: &Range<usize>| range.end);
+            // Kept in place even when not found, as the active parameter is an index
+            let range = parameter_range(&label, &parameter["label"], searched_from);
+            parameters.push(range.unwrap_or(searched_from..searched_from));
+            parameter_docs.push(markdown::from_lsp(&parameter["documentation"]));
+        }
+        Some(Self {
+            label,
+            parameters,
+            parameter_docs,
+            documentation: markdown::from_lsp(&value["documentation"]),
+            active_parameter: value["activeParameter"]
+                .as_u64()
+                .map(|index| index as usize),
+        })
+    }
+}
+
+// A parameter's label is its text, found in the signature's label after the
+// parameter before it, or its UTF-16 offsets into the signature's label
+fn parameter_range(label: &str, value: &Value, searched_from: usize) -> Option<Range<usize>> {
+    match value {
+        Value::String(text) if !text.is_empty() => {
+            let from = label
+                .char_indices()
+                .nth(searched_from)
+                .map_or(label.len(), |(index, _)| index);
+            let found = from + label[from..].find(text.as_str())?;
+            let start = searched_from + label[from..found].chars().count();
+            Some(start..start + text.chars().count())
+        }
+        Value::Array(offsets) => {
+            let start = utf16_to_chars(label, offsets.first()?.as_u64()? as usize);
+            let end = utf16_to_chars(label, offsets.get(1)?.as_u64()? as usize);
+            (start <= end).then_some(start..end)
+        }
+        _ => None,
+    }
+}
+
+fn utf16_to_chars(text: &str, units: usize) -> usize {
+    let mut counted = 0;
+    for (chars, c) in text.chars().enumerate() {
+        if counted >= units {
+            return chars;
+        }
+        counted += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignatureHelp {
+    pub signatures: Vec<Signature>,
+    pub active_signature: usize,
+    active_parameter: Option<usize>,
+    /// The answer as sent, for telling the server what is showing
+    pub raw: Value,
+}
+
+impl SignatureHelp {
+    /// Reads a `textDocument/signatureHelp` answer; None when there are no
+    /// signatures, as outside a call.
+    pub fn from_lsp(value: &Value) -> Option<Self> {
+        let signatures: Vec<Signature> = value["signatures"]
+            .as_array()?
+            .iter()
+            .filter_map(Signature::from_lsp)
+            .collect();
+        if signatures.is_empty() {
+            return None;
+        }
+        let active_signature = value["activeSignature"]
+            .as_u64()
+            .map_or(0, |index| index as usize);
+        Some(Self {
+            active_signature: if active_signature < signatures.len() {
+                active_signature
+            } else {
+                0
+            },
+            signatures,
+            active_parameter: value["activeParameter"]
+                .as_u64()
+                .map(|index| index as usize),
+            raw: value.clone(),
+        })
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signatures[self.active_signature]
+    }
+
+    /// The parameter being typed in the shown signature, if it has one there.
+    pub fn active_parameter(&self) -> Option<usize> {
+        let signature = self.signature();
+        signature
+            .active_parameter
+            .or(self.active_parameter)
+            .filter(|&index| index < signature.parameters.len())
+    }
+
+    /// The shown signature's label split around the parameter being typed.
+    pub fn label_parts(&self) -> (String, String, String) {
+        let signature = self.signature();
+        let chars: Vec<char> = signature.label.chars().collect();
+        let Some(range) = self
+            .active_parameter()
+            .map(|index| signature.parameters[index].clone())
+        else {
+            return (signature.label.clone(), String::new(), String::new());
+        };
+        let end = range.end.min(chars.len());
+        let start = range.start.min(end);
+        (
+            chars[..start].iter().collect(),
+            chars[start..end].iter().collect(),
+            chars[end..].iter().collect(),
+        )
+    }
+
+    /// Markdown about the parameter being typed, then about the signature.
+    pub fn documentation(&self) -> String {
+        let signature = self.signature();
+        let parameter = self
+            .active_parameter()
+            .and_then(|index| signature.parameter_docs.get(index))
+            .filter(|docs| !docs.trim().is_empty());
+        match parameter {
+            Some(docs) if !signature.documentation.trim().is_empty() => {
+                format!("{docs}\n\n{}", signature.documentation)
+            }
+            Some(docs) => docs.clone(),
+            None => signature.documentation.clone(),
+        }
+    }
+
+    /// Shows the next signature, or the one before, going round.
+    pub fn cycle(&mut self, forward: bool) {
+        let count = self.signatures.len();
+        self.active_signature = if forward {
+            (self.active_signature + 1) % count
+        } else {
+            (self.active_signature + count - 1) % count
+        };
+        self.raw["activeSignature"] = self.active_signature.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parts(before: &str, active: &str, after: &str) -> (String, String, String) {
+        (before.to_string(), active.to_string(), after.to_string())
+    }
+
+    #[test]
+    fn signatures_mark_the_active_parameter() {
+        let mut help = SignatureHelp::from_lsp(&json!({
+            "signatures": [
+                { "label": "fn add(a: i32, b: i32) -> i32",
+                  "parameters": [{ "label": "a: i32", "documentation": "first" }, { "label": "b: i32" }],
+                  "documentation": { "kind": "markdown", "value": "Adds." } },
+                // Offsets in UTF-16 units
+                { "label": "fn add(\u{e9}: u8, b: u8)",
+                  "parameters": [{ "label": [7, 12] }, { "label": [14, 19] }],
+                  "activeParameter": 0 },
+            ],
+            "activeSignature": 0,
+            "activeParameter": 1,
+        }))
+        .unwrap();
+        assert_eq!(help.signatures[0].parameters, [7..13, 15..21]);
+        assert_eq!(
+            help.label_parts(),
+            parts("fn add(a: i32, ", "b: i32", ") -> i32")
+        );
+        assert_eq!(help.documentation(), "Adds.");
+
+        help.cycle(true);
+        assert_eq!(help.active_signature, 1);
+        assert_eq!(help.raw["activeSignature"], 1);
+        assert_eq!(help.label_parts().1, "\u{e9}: u8");
+        help.cycle(true);
+        assert_eq!(help.active_signature, 0);
+        help.cycle(false);
+        assert_eq!(help.active_signature, 1);
+    }
+
+    #[test]
+    fn parameter_documentation_comes_first() {
+        let help = SignatureHelp::from_lsp(&json!({
+            "signatures": [{ "label": "f(a)",
+                             "parameters": [{ "label": "a", "documentation": "first" }],
+                             "documentation": "Does." }],
+            "activeParameter": 0,
+        }))
+        .unwrap();
+        assert_eq!(help.documentation(), "first\n\nDoes.");
+
+        let past_the_end = SignatureHelp::from_lsp(&json!({
+            "signatures": [{ "label": "f()" }],
+            "activeParameter": 3,
+        }))
+        .unwrap();
+        assert_eq!(past_the_end.label_parts(), parts("f()", "", ""));
+        assert!(SignatureHelp::from_lsp(&Value::Null).is_none());
+        assert!(SignatureHelp::from_lsp(&json!({ "signatures": [] })).is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use super::folding;
+use super::rope_engine::RopeEditor;
+use super::wrap::DisplayMap;
+
+// Sticky scroll: the first lines of the scopes around the top of the viewport
+// stay pinned above the text while their bodies scroll past.
+
+/// Lines to pin when the viewport starts at `first_row`, outermost first. When
+/// there are more than `max_lines` scopes, the innermost ones are kept.
+pub fn sticky_lines(
+    editor: &RopeEditor,
+    display: &DisplayMap,
+    first_row: usize,
+    max_lines: usize,
+) -> Vec<usize> {
+    if max_lines == 0 || first_row == 0 {
+        return Vec::new();
+    }
+    let headers_above = |row: usize| {
+        let line = display.row(row.min(display.row_count() - 1)).line;
+        let mut headers = folding::enclosing_scopes(editor, line);
+        // Headers still on screen below the pinned lines don't need pinning
+        headers.retain(|&header| display.row_of(header, 0) < row);
+        let skip = headers.len().saturating_sub(max_lines);
+        headers.split_off(skip)
+    };
+    // The pinned lines cover the top rows, so what matters is the first row
+    // left uncovered
+    let pinned = headers_above(first_row).len();
+    if pinned == 0 {
+        return Vec::new();
+    }
+    headers_above(first_row + pinned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::folding::FoldState;
+
+    const NESTED: &str = "fn a() {\n    let x = 1;\n    if x {\n        y();\n\n        z();\n    }\n}\n\nfn b() {\n    c();\n}\n";
+
+    #[test]
+    fn enclosing_scopes_are_outermost_first() {
+        let editor = RopeEditor::from_text(NESTED);
+        assert_eq!(folding::enclosing_scopes(&editor, 3), vec![0, 2]);
+        // A blank line goes with the next line that has text
+        assert_eq!(folding::enclosing_scopes(&editor, 4), vec![0, 2]);
+        assert_eq!(folding::enclosing_scopes(&editor, 6), vec![0]);
+        assert!(folding::enclosing_scopes(&editor, 7).is_empty());
+    }
+
+    #[test]
+    fn sticky_lines_pin_the_headers_scrolled_past() {
+        let editor = RopeEditor::from_text(NESTED);
+        let display = DisplayMap::new(
+            &editor,
+            FoldState::default().fold_map(editor.line_count()),
+            None,
+        );
+        assert!(sticky_lines(&editor, &display, 0, 5).is_empty());
+        assert_eq!(sticky_lines(&editor, &display, 1, 5), vec![0]);
+        assert_eq!(sticky_lines(&editor, &display, 3, 5), vec![0, 2]);
+        // Only the innermost fit
+        assert_eq!(sticky_lines(&editor, &display, 3, 1), vec![2]);
+        // With `fn a` pinned over row 6, row 7 is its closing brace
+        assert!(sticky_lines(&editor, &display, 6, 5).is_empty());
+        assert!(sticky_lines(&editor, &display, 10, 0).is_empty());
+    }
+}
@@ -0,0 +1,531 @@
+use super::scroll::LONG_LINE;
+use crate::utils::log;
+use ropey::Rope;
+use std::ops::Range;
+use std::sync::OnceLock;
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
+
+// Syntax highlighting from tree-sitter. `RopeEditor` reports every change to its
+// text as an edit on the tree, and the tree is reparsed incrementally the next
+// time highlights are asked for, which only happens for the lines shown.
+
+/// How a piece of text is coloured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    String,
+    /// Escape sequences in strings
+    Escape,
+    Number,
+    Constant,
+    Comment,
+    DocComment,
+    Lifetime,
+    Attribute,
+    Operator,
+    Punctuation,
+    Parameter,
+    /// Fields, object keys and the like
+    Property,
+    Plain,
+    /// Glyphs drawn for whitespace
+    Whitespace,
+}
+
+/// Highlighted columns of a line, in order; columns between them are plain.
+pub type LineHighlights = Vec<(Range<usize>, TokenClass)>;
+
+/// The bundled grammars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Grammar {
+    Rust,
+    Toml,
+    Json,
+    Markdown,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Python,
+    Html,
+    Css,
+    Shell,
+}
+
+impl Grammar {
+    pub const ALL: [Grammar; 11] = [
+        Grammar::Rust,
+        Grammar::Toml,
+        Grammar::Json,
+        Grammar::Markdown,
+        Grammar::JavaScript,
+        Grammar::TypeScript,
+        Grammar::Tsx,
+        Grammar::Python,
+        Grammar::Html,
+        Grammar::Css,
+        Grammar::Shell,
+    ];
+
+    pub fn language(self) -> Language {
+        match self {
+            Grammar::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Grammar::Toml => tree_sitter_toml_ng::LANGUAGE.into(),
+            Grammar::Json => tree_sitter_json::LANGUAGE.into(),
+            Grammar::Markdown => tree_sitter_md::LANGUAGE.into(),
+            Grammar::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Grammar::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Grammar::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Grammar::Python => tree_sitter_python::LANGUAGE.into(),
+            Grammar::Html => tree_sitter_html::LANGUAGE.into(),
+            Grammar::Css => tree_sitter_css::LANGUAGE.into(),
+            Grammar::Shell => tree_sitter_bash::LANGUAGE.into(),
+        }
+    }
+
+    // TypeScript's query only adds to JavaScript's; its own patterns go first so they win
+    fn highlights_source(self) -> String {
+        match self {
+            // The bundled query takes the apostrophe of a lifetime for an operator
+            Grammar::Rust => format!(
+                "(lifetime \"'\" @label)\n{}",
+                tree_sitter_rust::HIGHLIGHTS_QUERY
+            ),
+            Grammar::Toml => tree_sitter_toml_ng::HIGHLIGHTS_QUERY.to_string(),
+            Grammar::Json => tree_sitter_json::HIGHLIGHTS_QUERY.to_string(),
+            Grammar::Markdown => tree_sitter_md::HIGHLIGHT_QUERY_BLOCK.to_string(),
+            Grammar::JavaScript => {
+                format!(
+                    "{}\n{}",
+                    tree_sitter_javascript::HIGHLIGHT_QUERY,
+                    tree_sitter_javascript::JSX_HIGHLIGHT_QUERY
+                )
+            }
+            Grammar::TypeScript => {
+                format!(
+                    "{}\n{}",
+                    tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                    tree_sitter_javascript::HIGHLIGHT_QUERY
+                )
+            }
+            Grammar::Tsx => format!(
+                "{}\n{}\n{}",
+                tree_sitter_typescript::HIGHLIGHTS_QUERY,
+                tree_sitter_javascript::HIGHLIGHT_QUERY,
+                tree_sitter_javascript::JSX_HIGHLIGHT_QUERY
+            ),
+            Grammar::Python => tree_sitter_python::HIGHLIGHTS_QUERY.to_string(),
+            Grammar::Html => tree_sitter_html::HIGHLIGHTS_QUERY.to_string(),
+            Grammar::Css => tree_sitter_css::HIGHLIGHTS_QUERY.to_string(),
+            Grammar::Shell => tree_sitter_bash::HIGHLIGHT_QUERY.to_string(),
+        }
+    }
+
+    /// The highlights query, compiled the first time it is needed. None if it
+    /// doesn't compile, which leaves the file plain.
+    pub fn highlights(self) -> Option<&'static Query> {
+        static QUERIES: [OnceLock<Option<Query>>; Grammar::ALL.len()] =
+            [const { OnceLock::new() }; Grammar::ALL.len()];
+        QUERIES[self as usize]
+            .get_or_init(|| {
+                Query::new(&self.language(), &self.highlights_source())
+                    .map_err(|e| {
+                        log::write("Syntax", format!("Highlights query for {:?}: {}", self, e))
+                    })
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+/// The parse tree of a buffer.
+pub struct Syntax {
+    grammar: Grammar,
+    parser: Parser,
+    tree: Option<Tree>,
+    // Edited since the last parse
+    stale: bool,
+}
+
+impl std::fmt::Debug for Syntax {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Syntax")
+            .field("grammar", &self.grammar)
+            .field("stale", &self.stale)
+            .finish()
+    }
+}
+
+impl Syntax {
+    pub fn new(grammar: Grammar) -> Option<Self> {
+        let mut parser = Parser::new();
+        parser
+            .set_language(&grammar.language())
+            .map_err(|e| log::write("Syntax", format!("Grammar for {:?}: {}", grammar, e)))
+            .ok()?;
+        Some(Self {
+            grammar,
+            parser,
+            tree: None,
+            stale: true,
+        })
+    }
+
+    /// Tells the tree about a change to the text, so the next parse can reuse
+    /// what wasn't touched.
+    pub fn edit(&mut self, edit: &InputEdit) {
+        if let Some(tree) = &mut self.tree {
+            tree.edit(edit);
+        }
+        self.stale = true;
+    }
+
+    /// The tree for `rope`, reparsing it first if it was edited.
+    pub fn tree(&mut self, rope: &Rope) -> Option<&Tree> {
+        if self.stale {
+            let len = rope.len_bytes();
+            let tree = self.parser.parse_with(
+                &mut |byte, _| {
+                    if byte >= len {
+                        return &[][..];
+                    }
+                    let (chunk, chunk_start, _, _) = rope.chunk_at_byte(byte);
+                    &chunk.as_bytes()[byte - chunk_start..]
+                },
+                self.tree.as_ref(),
+            );
+            self.tree = tree;
+            self.stale = false;
+        }
+        self.tree.as_ref()
+    }
+
+    /// Highlights for `lines` of `rope`, one entry per line. Lines longer than
+    /// `LONG_LINE` are only highlighted for the columns in `window`.
+    pub fn highlight(
+        &mut self,
+        rope: &Rope,
+        lines: Range<usize>,
+        window: Range<usize>,
+    ) -> Vec<LineHighlights> {
+        let lines = lines.start.min(rope.len_lines())..lines.end.min(rope.len_lines());
+        let Some(query) = self.grammar.highlights() else {
+            return vec![Vec::new(); lines.len()];
+        };
+        let Some(tree) = self.tree(rope) else {
+            return vec![Vec::new(); lines.len()];
+        };
+
+        // Columns of each line to highlight, and the bytes they take. Whole
+        // lines run on into each other and are queried together
+        let columns: Vec<Range<usize>> = lines
+            .clone()
+            .map(|line| {
+                let len = rope.line(line).len_chars();
+                if len > LONG_LINE {
+                    let start = window.start.min(len);
+                    start..window.end.clamp(start, len)
+                } else {
+                    0..len
+                }
+            })
+            .collect();
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (line, columns) in lines.clone().zip(&columns) {
+            let line_start = rope.line_to_char(line);
+            let range = rope.char_to_byte(line_start + columns.start)
+                ..rope.char_to_byte(line_start + columns.end);
+            match ranges.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => ranges.push(range),
+            }
+        }
+
+        // Each byte takes the class of the innermost capture over it. Captures
+        // come outer node first, and for a node captured by several patterns the
+        // first pattern in the query wins, unless a later one narrows it down, as
+        // `comment.documentation` does `comment`
+        let mut cursor = QueryCursor::new();
+        let text = |node: Node| {
+            rope.byte_slice(node.byte_range())
+                .chunks()
+                .map(str::as_bytes)
+        };
+        let painted: Vec<(Range<usize>, Vec<Option<TokenClass>>)> = ranges
+            .into_iter()
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let mut paint = vec![None; range.len()];
+                cursor.set_byte_range(range.clone());
+                let mut captures = cursor.captures(query, tree.root_node(), text);
+                let mut last: Option<(usize, &str)> = None;
+                while let Some((found, index)) = captures.next() {
+                    let capture = found.captures[*index];
+                    let name = query.capture_names()[capture.index as usize];
+                    if let Some((node, last_name)) = last {
+                        let narrower = name
+                            .strip_prefix(last_name)
+                            .is_some_and(|rest| rest.starts_with('.'));
+                        if node == capture.node.id() && !narrower {
+                            continue;
+                        }
+                    }
+                    last = Some((capture.node.id(), name));
+                    let class = class_of(name);
+                    let from = capture.node.start_byte().max(range.start);
+                    for byte in from..capture.node.end_byte().min(range.end) {
+                        paint[byte - range.start] = Some(class);
+                    }
+                }
+                (range, paint)
+            })
+            .collect();
+
+        lines
+            .zip(columns)
+            .map(|(line, columns)| {
+                let mut byte = rope.char_to_byte(rope.line_to_char(line) + columns.start);
+                let Some((range, paint)) = painted.iter().find(|(range, _)| range.contains(&byte))
+                else {
+                    return Vec::new();
+                };
+                let mut spans: LineHighlights = Vec::new();
+                let chars = rope.line(line).chars_at(columns.start).take(columns.len());
+                for (column, ch) in (columns.start..).zip(chars) {
+                    if let Some(class) = paint[byte - range.start] {
+                        match spans.last_mut() {
+                            Some((span, last)) if span.end == column && *last == class => {
+                                span.end += 1
+                            }
+                            _ => spans.push((column..column + 1, class)),
+                        }
+                    }
+                    byte += ch.len_utf8();
+                }
+                spans
+            })
+            .collect()
+    }
+}
+
+/// The class for a highlights query capture such as `keyword` or `string.special`.
+pub fn class_of(capture: &str) -> TokenClass {
+    match capture {
+        "comment.documentation" => return TokenClass::DocComment,
+        "string.escape" => return TokenClass::Escape,
+        "string.special.key" => return TokenClass::Property,
+        "variable.parameter" => return TokenClass::Parameter,
+        "variable.builtin" => return TokenClass::Keyword,
+        "function.macro" => return TokenClass::Macro,
+        // Markdown
+        "text.title" => return TokenClass::Keyword,
+        "text.literal" => return TokenClass::String,
+        "text.uri" | "text.reference" => return TokenClass::Function,
+        _ => {}
+    }
+    match capture.split('.').next().unwrap_or(capture) {
+        "keyword" | "tag" => TokenClass::Keyword,
+        "type" | "constructor" | "namespace" | "module" => TokenClass::Type,
+        "function" | "method" => TokenClass::Function,
+        "string" | "character" => TokenClass::String,
+        "escape" => TokenClass::Escape,
+        "number" | "float" => TokenClass::Number,
+        "constant" | "boolean" => TokenClass::Constant,
+        "comment" => TokenClass::Comment,
+        // Rust's query captures lifetimes as labels
+        "label" => TokenClass::Lifetime,
+        "attribute" => TokenClass::Attribute,
+        "operator" => TokenClass::Operator,
+        "punctuation" => TokenClass::Punctuation,
+        "property" => TokenClass::Property,
+        _ => TokenClass::Plain,
+    }
+}
+
+/// Splits `text`, which starts at `column` of its line, into tokens by the
+/// line's highlights.
+pub fn line_tokens(
+    text: &str,
+    column: usize,
+    highlights: &[(Range<usize>, TokenClass)],
+) -> Vec<(String, TokenClass)> {
+    let mut tokens: Vec<(String, TokenClass)> = Vec::new();
+    let mut spans = highlights.iter().peekable();
+    for (index, ch) in text.chars().enumerate() {
+        let column = column + index;
+        while spans.next_if(|(range, _)| range.end <= column).is_some() {}
+        let class = match spans.peek() {
+            Some((range, class)) if range.contains(&column) => *class,
+            _ => TokenClass::Plain,
+        };
+        match tokens.last_mut() {
+            Some((token, last)) if *last == class => token.push(ch),
+            _ => tokens.push((ch.to_string(), class)),
+        }
+    }
+    tokens
+}
+
+/// The edit inserting `text` at char `position` of `rope`, before it goes in.
+pub fn insert_edit(rope: &Rope, position: usize, text: &str) -> InputEdit {
+    let start_byte = rope.char_to_byte(position);
+    let start_position = point_at(rope, start_byte);
+    let mut new_end_position = start_position;
+    for (index, line) in text.split('\n').enumerate() {
+        if index == 0 {
+            new_end_position.column += line.len();
+        } else {
+            new_end_position = Point::new(new_end_position.row + 1, line.len());
+        }
+    }
+    InputEdit {
+        start_byte,
+        old_end_byte: start_byte,
+        new_end_byte: start_byte + text.len(),
+        start_position,
+        old_end_position: start_position,
+        new_end_position,
+    }
+}
+
+/// The edit removing chars `range` of `rope`, before they go.
+pub fn remove_edit(rope: &Rope, range: Range<usize>) -> InputEdit {
+    let start_byte = rope.char_to_byte(range.start);
+    let old_end_byte = rope.char_to_byte(range.end);
+    let start_position = point_at(rope, start_byte);
+    InputEdit {
+        start_byte,
+        old_end_byte,
+        new_end_byte: start_byte,
+        start_position,
+        old_end_position: point_at(rope, old_end_byte),
+        new_end_position: start_position,
+    }
+}
+
+// Row and byte column. Ropey also breaks lines at a lone '\r' and Unicode line
+// separators where tree-sitter doesn't, which only throws positions off in
+// files that have them
+fn point_at(rope: &Rope, byte: usize) -> Point {
+    let row = rope.byte_to_line(byte);
+    Point::new(row, byte - rope.line_to_byte(row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::rope_engine::RopeEditor;
+
+    fn highlight(grammar: Grammar, text: &str) -> Vec<LineHighlights> {
+        let rope = Rope::from_str(text);
+        let mut syntax = Syntax::new(grammar).unwrap();
+        syntax.highlight(&rope, 0..rope.len_lines(), 0..0)
+    }
+
+    fn class_at(highlights: &[LineHighlights], line: usize, column: usize) -> TokenClass {
+        highlights[line]
+            .iter()
+            .find(|(range, _)| range.contains(&column))
+            .map_or(TokenClass::Plain, |(_, class_)| *class_)
+    }
+
+    #[test]
+    fn every_highlight_query_compiles() {
+        for grammar in Grammar::ALL {
+            assert!(grammar.highlights().is_some(), "{grammar:?}");
+        }
+    }
+
+    #[test]
+    fn comments_and_strings_span_lines() {
+        let highlights = highlight(
+            Grammar::Rust,
+            "/* a\n fn b */\nlet s = r#\"x\nfn\"#;\nfn f() {}\n",
+        );
+        assert_eq!(class_at(&highlights, 1, 1), TokenClass::Comment);
+        assert_eq!(class_at(&highlights, 3, 0), TokenClass::String);
+        assert_eq!(class_at(&highlights, 4, 0), TokenClass::Keyword);
+    }
+
+    #[test]
+    fn long_lines_are_highlighted_only_in_the_window() {
+        let padding = "x".repeat(LONG_LINE);
+        let text = format!("let s = \"{padding}\"; fn f() {{}}\nfn g() {{}}\n");
+        let rope = Rope::from_str(&text);
+        let mut syntax = Syntax::new(Grammar::Rust).unwrap();
+        let fn_column = text.find("fn f").unwrap();
+
+        let start = syntax.highlight(&rope, 0..2, 0..5);
+        assert_eq!(start[0][0], (0..3, TokenClass::Keyword));
+        assert!(start[0].iter().all(|(range, _)| range.end <= 5));
+        // Short lines are highlighted whole
+        assert_eq!(class_at(&start, 1, 0), TokenClass::Keyword);
+
+        // The closing quote is still part of the string
+        let end = syntax.highlight(&rope, 0..1, fn_column - 3..fn_column + 10);
+        assert_eq!(
+            end[0][0],
+            (fn_column - 3..fn_column - 2, TokenClass::String)
+        );
+        assert_eq!(class_at(&end, 0, fn_column), TokenClass::Keyword);
+        assert_eq!(class_at(&end, 0, 0), TokenClass::Plain);
+    }
+
+    #[test]
+    fn rust_tokens_get_the_richer_classes() {
+        let highlights = highlight(
+            Grammar::Rust,
+            "/// Docs\n#[derive(Debug)]\nstruct A<T>(Vec<T>, &'static str);\nfn f<'a>(x: u8) { let y = \"\\n\"; println!(); }\n",
+        );
+        assert_eq!(class_at(&highlights, 0, 4), TokenClass::DocComment);
+        assert_eq!(class_at(&highlights, 1, 3), TokenClass::Attribute);
+        assert_eq!(class_at(&highlights, 2, 7), TokenClass::Type);
+        assert_eq!(class_at(&highlights, 2, 12), TokenClass::Type);
+        assert_eq!(class_at(&highlights, 2, 21), TokenClass::Lifetime);
+        assert_eq!(class_at(&highlights, 3, 5), TokenClass::Lifetime);
+        assert_eq!(class_at(&highlights, 3, 9), TokenClass::Parameter);
+        assert_eq!(class_at(&highlights, 3, 13), TokenClass::Type);
+        assert_eq!(class_at(&highlights, 3, 26), TokenClass::String);
+        assert_eq!(class_at(&highlights, 3, 27), TokenClass::Escape);
+        assert_eq!(class_at(&highlights, 3, 32), TokenClass::Macro);
+    }
+
+    #[test]
+    fn line_tokens_split_at_highlight_edges() {
+        assert_eq!(
+            line_tokens("ab cd", 2, &[(3..5, TokenClass::Keyword)]),
+            vec![
+                ("a".to_string(), TokenClass::Plain),
+                ("b ".to_string(), TokenClass::Keyword),
+                ("cd".to_string(), TokenClass::Plain),
+            ]
+        );
+    }
+
+    #[test]
+    fn edited_tree_highlights_like_a_fresh_parse() {
+        let mut editor = RopeEditor::from_named_text("main.rs", "fn main() {\n    let x = 1;\n}\n");
+
+        let _ = editor.highlight_lines(0..3, 0..0);
+        // Edits with multi-byte text, highlighted in between
+        editor.set_cursor(1, 4);
+        editor.insert_text("/* \u{e9}\n");
+        let _ = editor.highlight_lines(0..5, 0..0);
+        editor.set_cursor(2, 0);
+        editor.insert_text("*/ \"\u{fc}\" ");
+        editor.delete_range(0, 3);
+        editor.undo();
+        editor.redo();
+
+        let lines = editor.line_count();
+        let edited = editor.highlight_lines(0..lines, 0..0);
+        assert_eq!(
+            edited,
+            highlight(Grammar::Rust, &editor.get_content())[..lines].to_vec()
+        );
+        assert_eq!(class_at(&edited, 1, 6), TokenClass::Comment);
+    }
+}
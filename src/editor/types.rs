@@ -1,3 +1,4 @@
+use std::ops::{Range, RangeInclusive};
 use std::path::PathBuf;
 
 #[derive(Clone, Debug, Copy)]
@@ -14,3 +15,30 @@ pub struct EditorState {
     pub scroll_position: usize,
     pub cursor: CursorPosition,
 }
+
+/// A column selection between two corners given as (line, column). Columns may
+/// lie past the end of a line, in virtual space.
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct BlockSelection {
+    pub anchor: (usize, usize),
+    /// The moving corner; the cursor follows it
+    pub head: (usize, usize),
+}
+
+impl BlockSelection {
+    pub fn at(line: usize, column: usize) -> Self {
+        Self {
+            anchor: (line, column),
+            head: (line, column),
+        }
+    }
+
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.anchor.0.min(self.head.0)..=self.anchor.0.max(self.head.0)
+    }
+
+    /// Columns selected on every line; empty when the block is a column of cursors.
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.1.min(self.head.1)..self.anchor.1.max(self.head.1)
+    }
+}
@@ -0,0 +1,156 @@
+use crate::settings::{Settings, SETTINGS};
+use dioxus::prelude::*;
+
+// Editor font settings and the glyph metrics measured from the real font. The
+// caret, selections and mouse positions are all computed from these metrics.
+
+const GUTTER_PADDING: f64 = 8.0;
+/// Column between the line numbers and the text that holds fold markers
+pub const FOLD_MARKER_WIDTH: f64 = 14.0;
+const MIN_FONT_SIZE: f64 = 6.0;
+const MAX_FONT_SIZE: f64 = 72.0;
+
+/// Zoom in font-size steps of one pixel on top of the configured size.
+pub static ZOOM: GlobalSignal<i32> = Signal::global(|| 0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metrics {
+    pub font_size: f64,
+    pub line_height: f64,
+    /// Advance width of one character of the monospace font
+    pub char_width: f64,
+}
+
+/// Metrics of the editor font, kept current by `use_font_metrics`.
+pub static METRICS: GlobalSignal<Metrics> =
+    Signal::global(|| Metrics::estimate(&Settings::default(), 0));
+
+impl Metrics {
+    // Until the font has been measured, assume a typical monospace aspect ratio
+    fn estimate(settings: &Settings, zoom: i32) -> Self {
+        let (font_size, line_height) = zoomed_size(settings, zoom);
+        Self {
+            font_size,
+            line_height,
+            char_width: font_size * 0.6,
+        }
+    }
+
+    /// Width of the line number column, wide enough for the last line's number.
+    pub fn number_width(&self, line_count: usize) -> f64 {
+        let digits = line_count.max(1).to_string().len().max(3) + 1;
+        digits as f64 * self.char_width
+    }
+
+    /// Left edge of the text: padding, line numbers and fold markers.
+    pub fn gutter_width(&self, line_count: usize) -> f64 {
+        GUTTER_PADDING + self.number_width(line_count) + FOLD_MARKER_WIDTH
+    }
+}
+
+// Font size and line height after zooming; the line height keeps its
+// proportion to the font size
+fn zoomed_size(settings: &Settings, zoom: i32) -> (f64, f64) {
+    let base_size = settings.font_size.clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    let font_size = (base_size + zoom as f64).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE);
+    let line_height = settings.line_height.max(base_size) * font_size / base_size;
+    (font_size, line_height.round())
+}
+
+/// CSS declarations for text in the editor font.
+pub fn font_css(settings: &Settings, metrics: &Metrics) -> String {
+    let ligatures = if settings.font_ligatures {
+        "normal"
+    } else {
+        "none"
+    };
+    format!(
+        "font-family: {}; font-size: {}px; line-height: {}px; font-variant-ligatures: {}; tab-size: {};",
+        settings.font_family,
+        metrics.font_size,
+        metrics.line_height,
+        ligatures,
+        settings.tab_size.max(1)
+    )
+}
+
+pub fn zoom_in() {
+    *ZOOM.write() += 1;
+}
+
+pub fn zoom_out() {
+    *ZOOM.write() -= 1;
+}
+
+pub fn reset_zoom() {
+    *ZOOM.write() = 0;
+}
+
+/// Re-measures the editor font whenever the font settings or the zoom change.
+pub fn use_font_metrics() {
+    use_effect(move || {
+        let settings = SETTINGS.read().clone();
+        let estimate = Metrics::estimate(&settings, ZOOM());
+        spawn(async move {
+            let css = font_css(&settings, &estimate);
+            let char_width = measure_char_width(&css)
+                .await
+                .unwrap_or(estimate.char_width);
+            let metrics = Metrics {
+                char_width,
+                ..estimate
+            };
+            if *METRICS.peek() != metrics {
+                *METRICS.write() = metrics;
+            }
+        });
+    });
+}
+
+// Lays out a run of characters in the font and divides its width, once the
+// webview has loaded the font
+async fn measure_char_width(css: &str) -> Option<f64> {
+    const SAMPLE: usize = 100;
+    let script = format!(
+        r#"
+        await document.fonts.ready;
+        const probe = document.createElement("span");
+        probe.style.cssText = {:?};
+        probe.textContent = "x".repeat({SAMPLE});
+        document.body.appendChild(probe);
+        const width = probe.getBoundingClientRect().width;
+        probe.remove();
+        return width;
+        "#,
+        format!(
+            "position: absolute; visibility: hidden; white-space: pre; {}",
+            css
+        )
+    );
+    let width: f64 = document::eval(&script).join().await.ok()?;
+    (width > 0.0).then(|| width / SAMPLE as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_css_sets_the_tab_size() {
+        let settings = Settings {
+            tab_size: 8,
+            ..Settings::default()
+        };
+        let css = font_css(&settings, &Metrics::estimate(&settings, 0));
+        assert!(css.contains("tab-size: 8;"), "{css}");
+        assert!(css.contains("font-size: 14px;"), "{css}");
+    }
+
+    #[test]
+    fn zoom_keeps_the_line_height_in_proportion() {
+        let settings = Settings::default();
+        assert_eq!(zoomed_size(&settings, 0), (14.0, 20.0));
+        assert_eq!(zoomed_size(&settings, 7), (21.0, 30.0));
+        assert_eq!(zoomed_size(&settings, -100).0, MIN_FONT_SIZE);
+    }
+}
@@ -0,0 +1,2492 @@
+use super::rope_engine::RopeEditor;
+use dioxus::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+// Vim emulation layer. Keys are fed one at a time through `VimState::handle_key`,
+// which interprets them against the current mode and drives the `RopeEditor`
+// through its regular editing operations (so undo, saving and rendering keep working).
+
+const INDENT: &str = "    ";
+
+// Inclusive line range given to an ex command, if it had one
+type LineRange = Option<(usize, usize)>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+    VisualBlock,
+    CommandLine,
+}
+
+impl VimMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VimMode::Normal => "NORMAL",
+            VimMode::Insert => "INSERT",
+            VimMode::Visual => "VISUAL",
+            VimMode::VisualLine => "V-LINE",
+            VimMode::VisualBlock => "V-BLOCK",
+            VimMode::CommandLine => "COMMAND",
+        }
+    }
+
+    pub fn is_visual(&self) -> bool {
+        matches!(
+            self,
+            VimMode::Visual | VimMode::VisualLine | VimMode::VisualBlock
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VimKey {
+    Char(char),
+    Ctrl(char),
+    Esc,
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+}
+
+impl VimKey {
+    /// Converts a keydown event into a Vim key. Control chords Vim doesn't use
+    /// return `None` so the regular editor shortcuts still apply.
+    pub fn from_key(key: &Key, ctrl: bool) -> Option<Self> {
+        match key {
+            Key::Character(s) => {
+                let c = s.chars().next()?;
+                if s.chars().count() != 1 {
+                    return None;
+                }
+                if ctrl {
+                    match c.to_ascii_lowercase() {
+                        '[' => Some(VimKey::Esc),
+                        'r' | 'v' | 'd' | 'u' | 'w' => Some(VimKey::Ctrl(c.to_ascii_lowercase())),
+                        _ => None,
+                    }
+                } else {
+                    Some(VimKey::Char(c))
+                }
+            }
+            _ if ctrl => None,
+            Key::Escape => Some(VimKey::Esc),
+            Key::Enter => Some(VimKey::Enter),
+            Key::Backspace => Some(VimKey::Backspace),
+            Key::Delete => Some(VimKey::Delete),
+            Key::Tab => Some(VimKey::Tab),
+            Key::ArrowLeft => Some(VimKey::Left),
+            Key::ArrowRight => Some(VimKey::Right),
+            Key::ArrowUp => Some(VimKey::Up),
+            Key::ArrowDown => Some(VimKey::Down),
+            Key::Home => Some(VimKey::Home),
+            Key::End => Some(VimKey::End),
+            _ => None,
+        }
+    }
+}
+
+/// Side effects a Vim command asks the hosting view to perform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VimEffect {
+    None,
+    Save,
+    Quit,
+    SaveAndQuit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RegisterKind {
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+#[derive(Clone, Debug)]
+struct Register {
+    text: String,
+    kind: RegisterKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+    Indent,
+    Outdent,
+    Lower,
+    Upper,
+    ToggleCase,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart(bool),
+    WordEnd(bool),
+    WordBack(bool),
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    Find { ch: char, forward: bool, till: bool },
+    RepeatFind(bool),
+    ParagraphForward,
+    ParagraphBack,
+    MatchPair,
+    Mark { name: char, exact: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ObjectKind {
+    Word,
+    BigWord,
+    Quote(char),
+    Pair(char, char),
+    Paragraph,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TextObject {
+    inner: bool,
+    kind: ObjectKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    Line,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    InsertBefore,
+    InsertAfter,
+    InsertLineStart,
+    InsertLineEnd,
+    OpenBelow,
+    OpenAbove,
+    DeleteChar,
+    DeleteCharBack,
+    SubstituteChar,
+    SubstituteLine,
+    DeleteToEnd,
+    ChangeToEnd,
+    YankLine,
+    Put { before: bool },
+    Replace(char),
+    Join,
+    ToggleCaseChar,
+    Undo,
+    Redo,
+    EnterVisual(VimMode),
+    EnterCommandLine,
+    RepeatChange,
+    SetMark(char),
+    SwapAnchor,
+    BlockInsert { append: bool },
+    ScrollHalfPage { down: bool },
+    Escape,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CommandKind {
+    Move(Motion),
+    Operate(Operator, Option<usize>, Target),
+    VisualOperate(Operator),
+    SelectObject(TextObject),
+    Act(Action),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    // Index of the first key after the register and count prefix
+    body_start: usize,
+    kind: CommandKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum Parse {
+    Incomplete,
+    Invalid,
+    Done(Command),
+}
+
+// A region of text an operator acts on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Span {
+    Chars(usize, usize),
+    Lines(usize, usize),
+    Block {
+        lines: (usize, usize),
+        cols: (usize, usize),
+    },
+}
+
+// Pending visual-block insert, replayed on the remaining rows when insert mode ends
+#[derive(Clone, Copy, Debug)]
+struct BlockInsert {
+    lines: (usize, usize),
+    col: usize,
+    start: usize,
+}
+
+#[derive(Debug)]
+pub struct VimState {
+    pub enabled: bool,
+    mode: VimMode,
+    pending: Vec<VimKey>,
+    command_line: String,
+    message: Option<String>,
+    registers: HashMap<char, Register>,
+    marks: HashMap<(PathBuf, char), (usize, usize)>,
+    last_change: Vec<VimKey>,
+    last_change_body: usize,
+    change_keys: Vec<VimKey>,
+    recording_change: bool,
+    replaying: bool,
+    last_find: Option<(char, bool, bool)>,
+    desired_col: Option<usize>,
+    block_insert: Option<BlockInsert>,
+    half_page: usize,
+}
+
+pub static VIM: GlobalSignal<VimState> = Signal::global(VimState::new);
+
+impl VimState {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mode: VimMode::Normal,
+            pending: Vec::new(),
+            command_line: String::new(),
+            message: None,
+            registers: HashMap::new(),
+            marks: HashMap::new(),
+            last_change: Vec::new(),
+            last_change_body: 0,
+            change_keys: Vec::new(),
+            recording_change: false,
+            replaying: false,
+            last_find: None,
+            desired_col: None,
+            block_insert: None,
+            half_page: 15,
+        }
+    }
+
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Text shown in the status bar next to the mode indicator.
+    pub fn status_text(&self) -> String {
+        if self.mode == VimMode::CommandLine {
+            format!(":{}", self.command_line)
+        } else if let Some(message) = &self.message {
+            message.clone()
+        } else {
+            self.pending.iter().map(key_label).collect()
+        }
+    }
+
+    pub fn set_enabled(&mut self, editor: &mut RopeEditor, enabled: bool) {
+        self.enabled = enabled;
+        self.reset(editor);
+    }
+
+    /// Number of lines moved by Ctrl-D / Ctrl-U.
+    pub fn set_half_page(&mut self, lines: usize) {
+        self.half_page = lines.max(1);
+    }
+
+    /// Drops any pending keys and returns to normal mode, e.g. when switching buffers.
+    pub fn reset(&mut self, editor: &mut RopeEditor) {
+        if self.mode == VimMode::Insert {
+            editor.end_undo_group();
+        }
+        self.mode = VimMode::Normal;
+        self.pending.clear();
+        self.command_line.clear();
+        self.message = None;
+        self.recording_change = false;
+        self.block_insert = None;
+        editor.set_selection_anchor(None);
+    }
+
+    /// Per-line `(line, start_col, end_col)` spans of the active visual selection.
+    pub fn selection_spans(
+        &self,
+        editor: &RopeEditor,
+        first_line: usize,
+        last_line: usize,
+    ) -> Vec<(usize, usize, usize)> {
+        let Some(span) = self.visual_span(editor) else {
+            return Vec::new();
+        };
+        let (l1, l2) = match span {
+            Span::Chars(start, end) => (editor.char_to_line(start), editor.char_to_line(end)),
+            Span::Lines(l1, l2) => (l1, l2),
+            Span::Block { lines, .. } => lines,
+        };
+
+        (l1.max(first_line)..=l2.min(last_line))
+            .map(|line| {
+                let len = editor.line_len(line);
+                match span {
+                    Span::Chars(start, end) => {
+                        let line_start = editor.line_to_char(line);
+                        let from = start.saturating_sub(line_start).min(len);
+                        let to = if end >= line_start + len {
+                            len + 1
+                        } else {
+                            end - line_start
+                        };
+                        (line, from, to.max(from))
+                    }
+                    Span::Lines(..) => (line, 0, len + 1),
+                    Span::Block { cols, .. } => (line, cols.0, cols.1 + 1),
+                }
+            })
+            .collect()
+    }
+
+    pub fn handle_key(&mut self, editor: &mut RopeEditor, key: VimKey) -> VimEffect {
+        self.message = None;
+        match self.mode {
+            VimMode::Insert => {
+                self.insert_key(editor, key);
+                VimEffect::None
+            }
+            VimMode::CommandLine => self.command_line_key(editor, key),
+            _ => {
+                self.pending.push(key);
+                match parse(&self.pending, self.mode) {
+                    Parse::Incomplete => VimEffect::None,
+                    Parse::Invalid => {
+                        self.pending.clear();
+                        VimEffect::None
+                    }
+                    Parse::Done(command) => {
+                        let keys = std::mem::take(&mut self.pending);
+                        let effect = self.execute(editor, command, &keys);
+                        if self.mode == VimMode::Normal {
+                            clamp_normal_cursor(editor);
+                        }
+                        effect
+                    }
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self, editor: &mut RopeEditor, command: Command, keys: &[VimKey]) -> VimEffect {
+        let count = command.count.unwrap_or(1);
+        let is_change = self.mode == VimMode::Normal && is_repeatable(&command.kind);
+        if is_change && !self.replaying {
+            self.change_keys = keys.to_vec();
+            self.last_change_body = command.body_start;
+            self.recording_change = true;
+        }
+
+        match command.kind {
+            CommandKind::Move(motion) => {
+                let origin = editor.get_cursor().byte_offset;
+                if let Some((pos, _)) =
+                    self.eval_motion(editor, motion, command.count, origin, false)
+                {
+                    if matches!(
+                        motion,
+                        Motion::FileStart | Motion::FileEnd | Motion::Mark { .. }
+                    ) {
+                        self.set_mark(editor, '\'', origin);
+                    }
+                    editor.set_cursor_offset(pos);
+                }
+            }
+            CommandKind::Operate(op, motion_count, target) => {
+                let total = match (command.count, motion_count) {
+                    (None, None) => None,
+                    (a, b) => Some(a.unwrap_or(1) * b.unwrap_or(1)),
+                };
+                if let Some(span) = self.target_span(editor, op, target, total) {
+                    self.apply_operator(editor, op, span, command.register);
+                }
+            }
+            CommandKind::VisualOperate(op) => {
+                if let Some(span) = self.visual_span(editor) {
+                    self.leave_visual(editor);
+                    self.apply_operator(editor, op, span, command.register);
+                }
+            }
+            CommandKind::SelectObject(object) => {
+                let pos = editor.get_cursor().byte_offset;
+                if let Some(span) = text_object(editor, object, pos) {
+                    match span {
+                        Span::Chars(start, end) => {
+                            editor.set_selection_anchor(Some(start));
+                            editor.set_cursor_offset(end.saturating_sub(1).max(start));
+                        }
+                        Span::Lines(l1, l2) => {
+                            self.mode = VimMode::VisualLine;
+                            editor.set_selection_anchor(Some(editor.line_to_char(l1)));
+                            editor.set_cursor_offset(editor.line_to_char(l2));
+                        }
+                        Span::Block { .. } => {}
+                    }
+                }
+            }
+            CommandKind::Act(action) => {
+                let effect = self.act(editor, action, count, command.register);
+                if effect != VimEffect::None {
+                    return effect;
+                }
+            }
+        }
+
+        if !matches!(
+            command.kind,
+            CommandKind::Move(Motion::Up | Motion::Down | Motion::LineEnd)
+        ) {
+            self.desired_col = None;
+        }
+        if self.recording_change && self.mode != VimMode::Insert {
+            self.finish_change();
+        }
+        VimEffect::None
+    }
+
+    fn finish_change(&mut self) {
+        self.recording_change = false;
+        self.last_change = std::mem::take(&mut self.change_keys);
+    }
+
+    fn act(
+        &mut self,
+        editor: &mut RopeEditor,
+        action: Action,
+        count: usize,
+        register: Option<char>,
+    ) -> VimEffect {
+        let pos = editor.get_cursor().byte_offset;
+        let line = editor.get_cursor().line;
+        match action {
+            Action::InsertBefore => self.enter_insert(editor, pos),
+            Action::InsertAfter => {
+                let at = if editor.line_len(line) == 0 {
+                    pos
+                } else {
+                    (pos + 1).min(line_end(editor, line))
+                };
+                self.enter_insert(editor, at);
+            }
+            Action::InsertLineStart => {
+                let at = editor.line_to_char(line) + first_non_blank(editor, line);
+                self.enter_insert(editor, at);
+            }
+            Action::InsertLineEnd => self.enter_insert(editor, line_end(editor, line)),
+            Action::OpenBelow => {
+                editor.begin_undo_group();
+                editor.set_cursor_offset(line_end(editor, line));
+                let indent = leading_whitespace(editor, line);
+                editor.insert_text(&format!("\n{}", indent));
+                self.mode = VimMode::Insert;
+            }
+            Action::OpenAbove => {
+                editor.begin_undo_group();
+                let indent = leading_whitespace(editor, line);
+                editor.set_cursor_offset(editor.line_to_char(line));
+                editor.insert_text(&format!("{}\n", indent));
+                editor.set_cursor_offset(editor.line_to_char(line) + indent.chars().count());
+                self.mode = VimMode::Insert;
+            }
+            Action::DeleteChar | Action::SubstituteChar => {
+                let end = (pos + count).min(line_end(editor, line));
+                let op = if action == Action::DeleteChar {
+                    Operator::Delete
+                } else {
+                    Operator::Change
+                };
+                if end > pos || op == Operator::Change {
+                    self.apply_operator(editor, op, Span::Chars(pos, end), register);
+                }
+            }
+            Action::DeleteCharBack => {
+                let start = pos.saturating_sub(count).max(editor.line_to_char(line));
+                if start < pos {
+                    self.apply_operator(
+                        editor,
+                        Operator::Delete,
+                        Span::Chars(start, pos),
+                        register,
+                    );
+                }
+            }
+            Action::SubstituteLine => {
+                let last = (line + count - 1).min(last_line(editor));
+                self.apply_operator(editor, Operator::Change, Span::Lines(line, last), register);
+            }
+            Action::DeleteToEnd | Action::ChangeToEnd => {
+                let last = (line + count - 1).min(last_line(editor));
+                let end = line_end(editor, last);
+                let op = if action == Action::DeleteToEnd {
+                    Operator::Delete
+                } else {
+                    Operator::Change
+                };
+                self.apply_operator(editor, op, Span::Chars(pos, end), register);
+            }
+            Action::YankLine => {
+                let last = (line + count - 1).min(last_line(editor));
+                self.apply_operator(editor, Operator::Yank, Span::Lines(line, last), register);
+            }
+            Action::Put { before } => {
+                if self.mode.is_visual() {
+                    // Replace the selection with the register contents
+                    if let Some(span) = self.visual_span(editor) {
+                        let content = self.read_register(editor, register);
+                        self.leave_visual(editor);
+                        editor.begin_undo_group();
+                        self.apply_operator(editor, Operator::Delete, span, Some('_'));
+                        if let Some(content) = content {
+                            // Deleting the last lines leaves the cursor above the gap
+                            let before = match span {
+                                Span::Lines(l1, _) => editor.get_cursor().line == l1,
+                                _ => true,
+                            };
+                            self.put(editor, &content, count, before);
+                        }
+                        editor.end_undo_group();
+                    }
+                } else if let Some(content) = self.read_register(editor, register) {
+                    self.put(editor, &content, count, before);
+                }
+            }
+            Action::Replace(ch) => {
+                let end = pos + count;
+                if end <= line_end(editor, line) {
+                    let text = ch.to_string().repeat(count);
+                    editor.replace_range(pos, end, &text);
+                    editor.set_cursor_offset(end - 1);
+                }
+            }
+            Action::Join => {
+                let last = (line + count.max(2) - 1).min(last_line(editor));
+                self.join_lines(editor, line, last);
+            }
+            Action::ToggleCaseChar => {
+                let end = (pos + count).min(line_end(editor, line));
+                if end > pos {
+                    self.apply_operator(editor, Operator::ToggleCase, Span::Chars(pos, end), None);
+                    editor.set_cursor_offset(end);
+                }
+            }
+            Action::Undo => {
+                for _ in 0..count {
+                    if !editor.undo() {
+                        self.message = Some("Already at oldest change".to_string());
+                        break;
+                    }
+                }
+            }
+            Action::Redo => {
+                for _ in 0..count {
+                    if !editor.redo() {
+                        self.message = Some("Already at newest change".to_string());
+                        break;
+                    }
+                }
+            }
+            Action::EnterVisual(mode) => {
+                if self.mode == mode {
+                    self.leave_visual(editor);
+                } else {
+                    if !self.mode.is_visual() {
+                        editor.set_selection_anchor(Some(pos));
+                    }
+                    self.mode = mode;
+                }
+            }
+            Action::EnterCommandLine => {
+                self.command_line = if self.mode.is_visual() {
+                    self.leave_visual(editor);
+                    "'<,'>".to_string()
+                } else {
+                    String::new()
+                };
+                self.mode = VimMode::CommandLine;
+            }
+            Action::RepeatChange => self.repeat_change(editor, count),
+            Action::SetMark(name) => self.set_mark(editor, name, pos),
+            Action::SwapAnchor => {
+                if let Some(anchor) = editor.selection_anchor() {
+                    editor.set_selection_anchor(Some(pos));
+                    editor.set_cursor_offset(anchor);
+                }
+            }
+            Action::BlockInsert { append } => {
+                if let Some(Span::Block { lines, cols }) = self.visual_span(editor) {
+                    self.leave_visual(editor);
+                    let col = if append { cols.1 + 1 } else { cols.0 };
+                    editor.begin_undo_group();
+                    let at = pad_to_column(editor, lines.0, col);
+                    editor.set_cursor_offset(at);
+                    self.block_insert = Some(BlockInsert {
+                        lines,
+                        col,
+                        start: at,
+                    });
+                    self.mode = VimMode::Insert;
+                }
+            }
+            Action::ScrollHalfPage { down } => {
+                let lines = self.half_page * count;
+                let target = if down {
+                    (line + lines).min(last_line(editor))
+                } else {
+                    line.saturating_sub(lines)
+                };
+                let col = first_non_blank(editor, target);
+                editor.set_cursor(target, col);
+            }
+            Action::Escape => {
+                if self.mode.is_visual() {
+                    self.leave_visual(editor);
+                }
+            }
+        }
+        VimEffect::None
+    }
+
+    fn enter_insert(&mut self, editor: &mut RopeEditor, at: usize) {
+        editor.begin_undo_group();
+        editor.set_cursor_offset(at);
+        self.mode = VimMode::Insert;
+    }
+
+    fn insert_key(&mut self, editor: &mut RopeEditor, key: VimKey) {
+        if self.recording_change {
+            self.change_keys.push(key);
+        }
+        match key {
+            VimKey::Esc => {
+                if let Some(block) = self.block_insert.take() {
+                    self.finish_block_insert(editor, block);
+                }
+                editor.end_undo_group();
+                self.mode = VimMode::Normal;
+                let cursor = *editor.get_cursor();
+                if cursor.column > 0 {
+                    editor.set_cursor(cursor.line, cursor.column - 1);
+                }
+                if self.recording_change {
+                    self.finish_change();
+                }
+            }
+            VimKey::Char(c) => editor.insert_text(&c.to_string()),
+            VimKey::Enter => editor.insert_newline(),
+            VimKey::Tab => editor.insert_text(INDENT),
+            VimKey::Backspace => editor.backspace(),
+            VimKey::Delete => editor.delete(),
+            VimKey::Left => editor.move_cursor_left(),
+            VimKey::Right => editor.move_cursor_right(),
+            VimKey::Up => editor.move_cursor_up(),
+            VimKey::Down => editor.move_cursor_down(),
+            VimKey::Home => editor.move_cursor_to_line_start(),
+            VimKey::End => editor.move_cursor_to_line_end(),
+            VimKey::Ctrl('w') => {
+                let pos = editor.get_cursor().byte_offset;
+                let start = word_back(editor, pos, false)
+                    .max(editor.line_to_char(editor.get_cursor().line));
+                if start < pos {
+                    editor.delete_range(start, pos);
+                }
+            }
+            VimKey::Ctrl('u') => {
+                let pos = editor.get_cursor().byte_offset;
+                let start = editor.line_to_char(editor.get_cursor().line);
+                if start < pos {
+                    editor.delete_range(start, pos);
+                }
+            }
+            VimKey::Ctrl(_) => {}
+        }
+    }
+
+    fn finish_block_insert(&mut self, editor: &mut RopeEditor, block: BlockInsert) {
+        let cursor = editor.get_cursor().byte_offset;
+        let first_line = editor.char_to_line(block.start);
+        if cursor <= block.start || editor.char_to_line(cursor) != first_line {
+            return;
+        }
+        let text = editor.slice_text(block.start, cursor);
+        for line in (block.lines.0 + 1)..=block.lines.1 {
+            if block.col > editor.line_len(line) && editor.line_len(line) == 0 {
+                continue;
+            }
+            let at = pad_to_column(editor, line, block.col);
+            editor.set_cursor_offset(at);
+            editor.insert_text(&text);
+        }
+        editor.set_cursor_offset(block.start);
+    }
+
+    fn command_line_key(&mut self, editor: &mut RopeEditor, key: VimKey) -> VimEffect {
+        match key {
+            VimKey::Esc => {
+                self.command_line.clear();
+                self.mode = VimMode::Normal;
+            }
+            VimKey::Enter => {
+                let command = std::mem::take(&mut self.command_line);
+                self.mode = VimMode::Normal;
+                let effect = self.run_ex(editor, command.trim());
+                clamp_normal_cursor(editor);
+                return effect;
+            }
+            VimKey::Backspace if self.command_line.is_empty() => self.mode = VimMode::Normal,
+            VimKey::Backspace => {
+                self.command_line.pop();
+            }
+            VimKey::Char(c) => self.command_line.push(c),
+            VimKey::Tab => self.command_line.push(' '),
+            _ => {}
+        }
+        VimEffect::None
+    }
+
+    fn run_ex(&mut self, editor: &mut RopeEditor, command: &str) -> VimEffect {
+        let (range, rest) = match self.parse_ex_range(editor, command) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                self.message = Some(message);
+                return VimEffect::None;
+            }
+        };
+        let rest = rest.trim();
+
+        match rest {
+            "w" | "write" => return VimEffect::Save,
+            "q" | "quit" | "q!" | "quit!" => {
+                if editor.is_modified() && !rest.ends_with('!') {
+                    self.message =
+                        Some("E37: No write since last change (add ! to override)".to_string());
+                    return VimEffect::None;
+                }
+                return VimEffect::Quit;
+            }
+            "wq" | "x" | "xit" => return VimEffect::SaveAndQuit,
+            "noh" | "nohlsearch" => return VimEffect::None,
+            "d" | "delete" => {
+                let cursor_line = editor.get_cursor().line;
+                let (l1, l2) = range.unwrap_or((cursor_line, cursor_line));
+                self.apply_operator(editor, Operator::Delete, Span::Lines(l1, l2), None);
+                return VimEffect::None;
+            }
+            "y" | "yank" => {
+                let cursor_line = editor.get_cursor().line;
+                let (l1, l2) = range.unwrap_or((cursor_line, cursor_line));
+                self.apply_operator(editor, Operator::Yank, Span::Lines(l1, l2), None);
+                return VimEffect::None;
+            }
+            "" => {
+                // A bare address jumps to that line
+                if let Some((_, line)) = range {
+                    let col = first_non_blank(editor, line);
+                    editor.set_cursor(line, col);
+                }
+                return VimEffect::None;
+            }
+            _ => {}
+        }
+
+        // The long form first, and only with a delimiter right after it, so
+        // that `:set` and the like are not read as `:s` with odd arguments
+        let substitute = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))
+            .filter(|args| args.starts_with(|c: char| !c.is_alphanumeric() && !c.is_whitespace()));
+        if let Some(args) = substitute {
+            let cursor_line = editor.get_cursor().line;
+            let lines = range.unwrap_or((cursor_line, cursor_line));
+            match parse_substitute(args) {
+                Some((pattern, replacement, global)) => {
+                    self.substitute(editor, lines, &pattern, &replacement, global)
+                }
+                None => self.message = Some("E486: Invalid substitute command".to_string()),
+            }
+            return VimEffect::None;
+        }
+
+        self.message = Some(format!("E492: Not an editor command: {}", command));
+        VimEffect::None
+    }
+
+    fn parse_ex_range<'a>(
+        &self,
+        editor: &RopeEditor,
+        command: &'a str,
+    ) -> Result<(LineRange, &'a str), String> {
+        if let Some(rest) = command.strip_prefix('%') {
+            return Ok((Some((0, last_line(editor))), rest));
+        }
+        let (first, rest) = self.parse_ex_address(editor, command)?;
+        let Some(first) = first else {
+            return Ok((None, rest));
+        };
+        if let Some(after_comma) = rest.strip_prefix(',') {
+            let (second, rest) = self.parse_ex_address(editor, after_comma)?;
+            let second = second.ok_or_else(|| "E14: Invalid address".to_string())?;
+            Ok((Some((first.min(second), first.max(second))), rest))
+        } else {
+            Ok((Some((first, first)), rest))
+        }
+    }
+
+    fn parse_ex_address<'a>(
+        &self,
+        editor: &RopeEditor,
+        text: &'a str,
+    ) -> Result<(Option<usize>, &'a str), String> {
+        let cursor_line = editor.get_cursor().line;
+        if let Some(rest) = text.strip_prefix('.') {
+            return Ok((Some(cursor_line), rest));
+        }
+        if let Some(rest) = text.strip_prefix('$') {
+            return Ok((Some(last_line(editor)), rest));
+        }
+        if let Some(rest) = text.strip_prefix('\'') {
+            let name = rest
+                .chars()
+                .next()
+                .ok_or_else(|| "E20: Mark not set".to_string())?;
+            let (line, _) = self
+                .get_mark(editor, name)
+                .ok_or_else(|| "E20: Mark not set".to_string())?;
+            return Ok((Some(line), &rest[name.len_utf8()..]));
+        }
+        let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 {
+            let number: usize = text[..digits].parse().map_err(|_| "E14: Invalid address")?;
+            let line = number.saturating_sub(1).min(last_line(editor));
+            return Ok((Some(line), &text[digits..]));
+        }
+        Ok((None, text))
+    }
+
+    fn substitute(
+        &mut self,
+        editor: &mut RopeEditor,
+        lines: (usize, usize),
+        pattern: &str,
+        replacement: &str,
+        global: bool,
+    ) {
+        if pattern.is_empty() {
+            self.message = Some("E35: No search pattern".to_string());
+            return;
+        }
+        let matcher = match LinePattern::parse(pattern) {
+            Ok(matcher) => matcher,
+            Err(message) => {
+                self.message = Some(message);
+                return;
+            }
+        };
+
+        let mut substitutions = 0;
+        let mut changed_lines = 0;
+        let mut last_changed = None;
+        editor.begin_undo_group();
+        // Walk bottom-up so earlier offsets stay valid while replacing
+        for line in (lines.0..=lines.1.min(last_line(editor))).rev() {
+            let Some(text) = editor.get_line(line) else {
+                continue;
+            };
+            let chars: Vec<char> = text.chars().collect();
+            let matches = matcher.find(&chars, global);
+            if matches.is_empty() {
+                continue;
+            }
+            let line_start = editor.line_to_char(line);
+            for range in matches.iter().rev() {
+                let matched: String = chars[range.clone()].iter().collect();
+                editor.replace_range(
+                    line_start + range.start,
+                    line_start + range.end,
+                    &expand_replacement(replacement, &matched),
+                );
+            }
+            substitutions += matches.len();
+            changed_lines += 1;
+            last_changed.get_or_insert(line);
+        }
+        editor.end_undo_group();
+
+        match last_changed {
+            Some(line) => {
+                let col = first_non_blank(editor, line);
+                editor.set_cursor(line, col);
+                if changed_lines > 1 || substitutions > 1 {
+                    self.message = Some(format!(
+                        "{} substitutions on {} lines",
+                        substitutions, changed_lines
+                    ));
+                }
+            }
+            None => self.message = Some(format!("E486: Pattern not found: {}", pattern)),
+        }
+    }
+
+    fn repeat_change(&mut self, editor: &mut RopeEditor, count: usize) {
+        if self.last_change.is_empty() || self.replaying {
+            return;
+        }
+        let keys = if count > 1 {
+            // A count on `.` replaces the count of the repeated command
+            let body_start = self.last_change_body.min(self.last_change.len());
+            let mut keys = Vec::new();
+            if self.last_change.first() == Some(&VimKey::Char('"')) {
+                keys.extend_from_slice(&self.last_change[..2]);
+            }
+            keys.extend(count.to_string().chars().map(VimKey::Char));
+            keys.extend_from_slice(&self.last_change[body_start..]);
+            keys
+        } else {
+            self.last_change.clone()
+        };
+
+        self.replaying = true;
+        for key in keys {
+            self.handle_key(editor, key);
+        }
+        if self.mode == VimMode::Insert {
+            self.handle_key(editor, VimKey::Esc);
+        }
+        self.replaying = false;
+    }
+
+    fn leave_visual(&mut self, editor: &mut RopeEditor) {
+        let pos = editor.get_cursor().byte_offset;
+        if let Some(anchor) = editor.selection_anchor() {
+            self.set_mark(editor, '<', anchor.min(pos));
+            self.set_mark(editor, '>', anchor.max(pos));
+        }
+        editor.set_selection_anchor(None);
+        self.mode = VimMode::Normal;
+    }
+
+    fn visual_span(&self, editor: &RopeEditor) -> Option<Span> {
+        if !self.mode.is_visual() {
+            return None;
+        }
+        let head = editor.get_cursor().byte_offset;
+        let anchor = editor.selection_anchor().unwrap_or(head);
+        let (start, end) = (anchor.min(head), anchor.max(head));
+        Some(match self.mode {
+            VimMode::Visual => Span::Chars(start, (end + 1).min(editor.total_chars())),
+            VimMode::VisualLine => {
+                Span::Lines(editor.char_to_line(start), editor.char_to_line(end))
+            }
+            _ => {
+                let (a_line, a_col) = line_col(editor, anchor);
+                let (h_line, h_col) = line_col(editor, head);
+                Span::Block {
+                    lines: (a_line.min(h_line), a_line.max(h_line)),
+                    cols: (a_col.min(h_col), a_col.max(h_col)),
+                }
+            }
+        })
+    }
+
+    fn set_mark(&mut self, editor: &RopeEditor, name: char, pos: usize) {
+        let key = (editor.file_path().clone(), name);
+        self.marks.insert(key, line_col(editor, pos));
+    }
+
+    fn get_mark(&self, editor: &RopeEditor, name: char) -> Option<(usize, usize)> {
+        let name = if name == '`' { '\'' } else { name };
+        self.marks.get(&(editor.file_path().clone(), name)).copied()
+    }
+
+    fn target_span(
+        &mut self,
+        editor: &RopeEditor,
+        op: Operator,
+        target: Target,
+        explicit_count: Option<usize>,
+    ) -> Option<Span> {
+        let pos = editor.get_cursor().byte_offset;
+        let line = editor.get_cursor().line;
+        let count = explicit_count.unwrap_or(1);
+        match target {
+            Target::Line => Some(Span::Lines(line, (line + count - 1).min(last_line(editor)))),
+            Target::Object(object) => {
+                let mut span = text_object(editor, object, pos)?;
+                // Counts on text objects grow word objects outward
+                if let (Span::Chars(start, mut end), ObjectKind::Word | ObjectKind::BigWord) =
+                    (span, object.kind)
+                {
+                    for _ in 1..count {
+                        if let Some(Span::Chars(_, next_end)) = text_object(editor, object, end) {
+                            end = next_end;
+                        }
+                    }
+                    span = Span::Chars(start, end);
+                }
+                Some(span)
+            }
+            Target::Motion(motion) => {
+                // `cw` on a word behaves like `ce`
+                let motion = match (op, motion) {
+                    (Operator::Change, Motion::WordStart(big))
+                        if editor.char_at(pos).is_some_and(|c| !c.is_whitespace()) =>
+                    {
+                        Motion::WordEnd(big)
+                    }
+                    _ => motion,
+                };
+                let (target, kind) = self.eval_motion(editor, motion, explicit_count, pos, true)?;
+                match kind {
+                    MotionKind::Linewise => {
+                        let (l1, l2) = (line, editor.char_to_line(target));
+                        Some(Span::Lines(l1.min(l2), l1.max(l2)))
+                    }
+                    MotionKind::Inclusive => Some(Span::Chars(
+                        pos.min(target),
+                        (pos.max(target) + 1).min(editor.total_chars()),
+                    )),
+                    MotionKind::Exclusive => {
+                        let (start, mut end) = (pos.min(target), pos.max(target));
+                        // An operator over `w` stops at the end of the current line
+                        if matches!(motion, Motion::WordStart(_))
+                            && editor.char_to_line(end) > editor.char_to_line(start)
+                        {
+                            end = line_end(editor, editor.char_to_line(start)).max(start);
+                        }
+                        (start < end).then_some(Span::Chars(start, end))
+                    }
+                }
+            }
+        }
+    }
+
+    fn eval_motion(
+        &mut self,
+        editor: &RopeEditor,
+        motion: Motion,
+        explicit_count: Option<usize>,
+        pos: usize,
+        operator_pending: bool,
+    ) -> Option<(usize, MotionKind)> {
+        let count = explicit_count.unwrap_or(1);
+        let (line, col) = line_col(editor, pos);
+        let len = editor.total_chars();
+        let result = match motion {
+            Motion::Left => (
+                pos.saturating_sub(count).max(editor.line_to_char(line)),
+                MotionKind::Exclusive,
+            ),
+            Motion::Right => {
+                let max = if operator_pending {
+                    line_end(editor, line)
+                } else {
+                    last_char(editor, line)
+                };
+                ((pos + count).min(max.max(pos)), MotionKind::Exclusive)
+            }
+            Motion::Up | Motion::Down => {
+                let target = if motion == Motion::Up {
+                    if line == 0 {
+                        return None;
+                    }
+                    line.saturating_sub(count)
+                } else {
+                    if line >= last_line(editor) {
+                        return None;
+                    }
+                    (line + count).min(last_line(editor))
+                };
+                let want = *self.desired_col.get_or_insert(col);
+                let col = want.min(editor.line_len(target).saturating_sub(1));
+                (editor.line_to_char(target) + col, MotionKind::Linewise)
+            }
+            Motion::WordStart(big) => {
+                let mut p = pos;
+                for _ in 0..count {
+                    p = word_start(editor, p, big);
+                }
+                (p.min(len), MotionKind::Exclusive)
+            }
+            Motion::WordEnd(big) => {
+                let mut p = pos;
+                for _ in 0..count {
+                    p = word_end(editor, p, big);
+                }
+                (p.min(len.saturating_sub(1)), MotionKind::Inclusive)
+            }
+            Motion::WordBack(big) => {
+                let mut p = pos;
+                for _ in 0..count {
+                    p = word_back(editor, p, big);
+                }
+                (p, MotionKind::Exclusive)
+            }
+            Motion::LineStart => (editor.line_to_char(line), MotionKind::Exclusive),
+            Motion::FirstNonBlank => (
+                editor.line_to_char(line) + first_non_blank(editor, line),
+                MotionKind::Exclusive,
+            ),
+            Motion::LineEnd => {
+                let target = (line + count - 1).min(last_line(editor));
+                self.desired_col = Some(usize::MAX);
+                (
+                    editor.line_to_char(target) + editor.line_len(target).saturating_sub(1),
+                    MotionKind::Inclusive,
+                )
+            }
+            Motion::FileStart | Motion::FileEnd => {
+                let target = match (explicit_count, motion) {
+                    (Some(n), _) => n.saturating_sub(1).min(last_line(editor)),
+                    (None, Motion::FileStart) => 0,
+                    (None, _) => last_line(editor),
+                };
+                (
+                    editor.line_to_char(target) + first_non_blank(editor, target),
+                    MotionKind::Linewise,
+                )
+            }
+            Motion::Find { ch, forward, till } => {
+                self.last_find = Some((ch, forward, till));
+                find_in_line(editor, pos, ch, forward, till, count, false)?
+            }
+            Motion::RepeatFind(reverse) => {
+                let (ch, forward, till) = self.last_find?;
+                find_in_line(editor, pos, ch, forward != reverse, till, count, true)?
+            }
+            Motion::ParagraphForward => {
+                let mut target = line;
+                for _ in 0..count {
+                    target += 1;
+                    while target < last_line(editor) && editor.line_len(target) == 0 {
+                        target += 1;
+                    }
+                    while target < last_line(editor) && editor.line_len(target) != 0 {
+                        target += 1;
+                    }
+                }
+                let target = target.min(last_line(editor));
+                let pos = if target == last_line(editor) && editor.line_len(target) != 0 {
+                    line_end(editor, target)
+                } else {
+                    editor.line_to_char(target)
+                };
+                (pos, MotionKind::Exclusive)
+            }
+            Motion::ParagraphBack => {
+                let mut target = line;
+                for _ in 0..count {
+                    target = target.saturating_sub(1);
+                    while target > 0 && editor.line_len(target) == 0 {
+                        target -= 1;
+                    }
+                    while target > 0 && editor.line_len(target) != 0 {
+                        target -= 1;
+                    }
+                }
+                (editor.line_to_char(target), MotionKind::Exclusive)
+            }
+            Motion::MatchPair => (match_pair(editor, pos)?, MotionKind::Inclusive),
+            Motion::Mark { name, exact } => {
+                let (mark_line, mark_col) = self.get_mark(editor, name)?;
+                let mark_line = mark_line.min(last_line(editor));
+                if exact {
+                    (
+                        editor.line_to_char(mark_line) + mark_col.min(editor.line_len(mark_line)),
+                        MotionKind::Exclusive,
+                    )
+                } else {
+                    (
+                        editor.line_to_char(mark_line) + first_non_blank(editor, mark_line),
+                        MotionKind::Linewise,
+                    )
+                }
+            }
+        };
+        Some(result)
+    }
+
+    fn apply_operator(
+        &mut self,
+        editor: &mut RopeEditor,
+        op: Operator,
+        span: Span,
+        register: Option<char>,
+    ) {
+        match op {
+            Operator::Yank => {
+                let (text, kind) = span_text(editor, span);
+                self.store_register(editor, register, text, kind, false);
+                let start = span_start(editor, span);
+                editor.set_cursor_offset(start);
+            }
+            Operator::Delete => {
+                let (text, kind) = span_text(editor, span);
+                self.store_register(editor, register, text, kind, true);
+                editor.begin_undo_group();
+                delete_span(editor, span);
+                editor.end_undo_group();
+            }
+            Operator::Change => {
+                let (text, kind) = span_text(editor, span);
+                self.store_register(editor, register, text, kind, true);
+                editor.begin_undo_group();
+                match span {
+                    Span::Chars(start, end) => {
+                        editor.delete_range(start, end);
+                        editor.set_cursor_offset(start);
+                    }
+                    Span::Lines(l1, l2) => {
+                        let indent = leading_whitespace(editor, l1).chars().count();
+                        let start = editor.line_to_char(l1) + indent;
+                        let end = line_end(editor, l2);
+                        editor.delete_range(start, end);
+                        editor.set_cursor_offset(start);
+                    }
+                    Span::Block { lines, cols } => {
+                        delete_span(editor, span);
+                        let at = pad_to_column(editor, lines.0, cols.0);
+                        editor.set_cursor_offset(at);
+                        self.block_insert = Some(BlockInsert {
+                            lines,
+                            col: cols.0,
+                            start: at,
+                        });
+                    }
+                }
+                // The undo group stays open until insert mode ends
+                self.mode = VimMode::Insert;
+            }
+            Operator::Indent | Operator::Outdent => {
+                let (l1, l2) = span_lines(editor, span);
+                editor.begin_undo_group();
+                for line in l1..=l2 {
+                    let line_start = editor.line_to_char(line);
+                    if op == Operator::Indent {
+                        if editor.line_len(line) > 0 {
+                            editor.set_cursor_offset(line_start);
+                            editor.insert_text(INDENT);
+                        }
+                    } else {
+                        let indent = leading_whitespace(editor, line);
+                        let remove = if indent.starts_with('\t') {
+                            1
+                        } else {
+                            indent
+                                .chars()
+                                .take(INDENT.len())
+                                .take_while(|c| *c == ' ')
+                                .count()
+                        };
+                        if remove > 0 {
+                            editor.delete_range(line_start, line_start + remove);
+                        }
+                    }
+                }
+                editor.end_undo_group();
+                editor.set_cursor(l1, first_non_blank(editor, l1));
+            }
+            Operator::Lower | Operator::Upper | Operator::ToggleCase => {
+                let ranges: Vec<(usize, usize)> = match span {
+                    Span::Chars(start, end) => vec![(start, end)],
+                    Span::Lines(l1, l2) => vec![(editor.line_to_char(l1), line_end(editor, l2))],
+                    Span::Block { lines, cols } => (lines.0..=lines.1)
+                        .filter_map(|line| {
+                            let len = editor.line_len(line);
+                            (cols.0 < len).then(|| {
+                                let start = editor.line_to_char(line);
+                                (start + cols.0, start + (cols.1 + 1).min(len))
+                            })
+                        })
+                        .collect(),
+                };
+                editor.begin_undo_group();
+                for (start, end) in ranges {
+                    let text = editor.slice_text(start, end);
+                    let converted: String = match op {
+                        Operator::Lower => text.to_lowercase(),
+                        Operator::Upper => text.to_uppercase(),
+                        _ => text
+                            .chars()
+                            .map(|c| {
+                                if c.is_uppercase() {
+                                    c.to_lowercase().next().unwrap_or(c)
+                                } else {
+                                    c.to_uppercase().next().unwrap_or(c)
+                                }
+                            })
+                            .collect(),
+                    };
+                    if converted != text {
+                        editor.replace_range(start, end, &converted);
+                    }
+                }
+                editor.end_undo_group();
+                let start = span_start(editor, span);
+                editor.set_cursor_offset(start);
+            }
+        }
+    }
+
+    fn join_lines(&mut self, editor: &mut RopeEditor, first: usize, last: usize) {
+        if first >= last {
+            return;
+        }
+        editor.begin_undo_group();
+        let mut join_col = 0;
+        for _ in first..last {
+            let end = line_end(editor, first);
+            let next_start = editor.line_to_char(first + 1);
+            let next_indent = leading_whitespace(editor, first + 1).chars().count();
+            let next_empty = editor.line_len(first + 1) == next_indent;
+            let ends_with_space = end > editor.line_to_char(first)
+                && editor.char_at(end - 1).is_some_and(|c| c == ' ');
+            let separator = if next_empty || ends_with_space {
+                ""
+            } else {
+                " "
+            };
+            join_col = end - editor.line_to_char(first);
+            editor.replace_range(end, next_start + next_indent, separator);
+        }
+        editor.end_undo_group();
+        editor.set_cursor(first, join_col);
+    }
+
+    fn put(&mut self, editor: &mut RopeEditor, content: &Register, count: usize, before: bool) {
+        let pos = editor.get_cursor().byte_offset;
+        let line = editor.get_cursor().line;
+        editor.begin_undo_group();
+        match content.kind {
+            RegisterKind::Charwise => {
+                let text = content.text.repeat(count);
+                let at = if before || editor.line_len(line) == 0 {
+                    pos
+                } else {
+                    (pos + 1).min(line_end(editor, line))
+                };
+                editor.set_cursor_offset(at);
+                editor.insert_text(&text);
+                let end = at + text.chars().count();
+                editor.set_cursor_offset(end.saturating_sub(1).max(at));
+            }
+            RegisterKind::Linewise => {
+                let text = content.text.repeat(count);
+                let target_line = if before {
+                    line
+                } else if line < last_line(editor) {
+                    line + 1
+                } else {
+                    // Pasting below the last line needs a line break first
+                    editor.set_cursor_offset(editor.total_chars());
+                    editor.insert_newline();
+                    let trimmed = text.strip_suffix('\n').unwrap_or(&text);
+                    editor.insert_text(trimmed);
+                    editor.set_cursor(line + 1, first_non_blank(editor, line + 1));
+                    editor.end_undo_group();
+                    return;
+                };
+                editor.set_cursor_offset(editor.line_to_char(target_line));
+                editor.insert_text(&text);
+                editor.set_cursor(target_line, first_non_blank(editor, target_line));
+            }
+            RegisterKind::Blockwise => {
+                let col = if before || editor.line_len(line) == 0 {
+                    line_col(editor, pos).1
+                } else {
+                    line_col(editor, pos).1 + 1
+                };
+                for (offset, row) in content.text.split('\n').enumerate() {
+                    let target = line + offset;
+                    if target > last_line(editor) {
+                        editor.set_cursor_offset(editor.total_chars());
+                        editor.insert_newline();
+                    }
+                    let at = pad_to_column(editor, target, col);
+                    editor.set_cursor_offset(at);
+                    editor.insert_text(&row.repeat(count));
+                }
+                editor.set_cursor(line, col);
+            }
+        }
+        editor.end_undo_group();
+    }
+
+    fn read_register(&self, editor: &RopeEditor, register: Option<char>) -> Option<Register> {
+        match register.unwrap_or('"') {
+            '+' | '*' => Some(Register {
+                text: editor.clipboard().to_string(),
+                kind: if editor.clipboard().ends_with('\n') {
+                    RegisterKind::Linewise
+                } else {
+                    RegisterKind::Charwise
+                },
+            }),
+            name => self.registers.get(&name.to_ascii_lowercase()).cloned(),
+        }
+        .filter(|register| !register.text.is_empty())
+    }
+
+    fn store_register(
+        &mut self,
+        editor: &mut RopeEditor,
+        register: Option<char>,
+        text: String,
+        kind: RegisterKind,
+        is_delete: bool,
+    ) {
+        let value = Register { text, kind };
+        match register {
+            Some('_') => return,
+            Some('+') | Some('*') => editor.set_clipboard(value.text.clone()),
+            Some(name) if name.is_ascii_uppercase() => {
+                let entry = self
+                    .registers
+                    .entry(name.to_ascii_lowercase())
+                    .or_insert(Register {
+                        text: String::new(),
+                        kind,
+                    });
+                entry.text.push_str(&value.text);
+                let appended = entry.clone();
+                self.registers.insert('"', appended);
+                return;
+            }
+            Some(name) if name.is_ascii_alphabetic() => {
+                self.registers.insert(name, value.clone());
+            }
+            _ if is_delete => {
+                if kind == RegisterKind::Charwise && !value.text.contains('\n') {
+                    self.registers.insert('-', value.clone());
+                } else {
+                    // Numbered registers shift like a small delete history
+                    for n in (1..9).rev() {
+                        let from = char::from_digit(n, 10).unwrap();
+                        let to = char::from_digit(n + 1, 10).unwrap();
+                        if let Some(previous) = self.registers.remove(&from) {
+                            self.registers.insert(to, previous);
+                        }
+                    }
+                    self.registers.insert('1', value.clone());
+                }
+            }
+            _ => {
+                self.registers.insert('0', value.clone());
+            }
+        }
+        self.registers.insert('"', value);
+    }
+}
+
+fn is_repeatable(kind: &CommandKind) -> bool {
+    match kind {
+        CommandKind::Operate(op, ..) => *op != Operator::Yank,
+        CommandKind::Act(action) => matches!(
+            action,
+            Action::InsertBefore
+                | Action::InsertAfter
+                | Action::InsertLineStart
+                | Action::InsertLineEnd
+                | Action::OpenBelow
+                | Action::OpenAbove
+                | Action::DeleteChar
+                | Action::DeleteCharBack
+                | Action::SubstituteChar
+                | Action::SubstituteLine
+                | Action::DeleteToEnd
+                | Action::ChangeToEnd
+                | Action::Put { .. }
+                | Action::Replace(_)
+                | Action::Join
+                | Action::ToggleCaseChar
+        ),
+        _ => false,
+    }
+}
+
+fn parse(keys: &[VimKey], mode: VimMode) -> Parse {
+    let visual = mode.is_visual();
+    let mut i = 0;
+    let mut register = None;
+    if keys.first() == Some(&VimKey::Char('"')) {
+        match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(VimKey::Char(c)) if c.is_ascii_alphanumeric() || "\"-_+*".contains(*c) => {
+                register = Some(*c);
+                i = 2;
+            }
+            _ => return Parse::Invalid,
+        }
+    }
+    let (count, i) = parse_count(keys, i);
+    let body_start = i;
+    let Some(&key) = keys.get(i) else {
+        return Parse::Incomplete;
+    };
+    let done = |kind| {
+        Parse::Done(Command {
+            register,
+            count,
+            body_start,
+            kind,
+        })
+    };
+
+    // Operators, either pending a motion or applied to the visual selection
+    match parse_operator(keys, i, visual) {
+        Err(parse) => return parse,
+        Ok(Some((op, j))) => {
+            if visual {
+                return done(CommandKind::VisualOperate(op));
+            }
+            let (motion_count, k) = parse_count(keys, j);
+            let Some(&next) = keys.get(k) else {
+                return Parse::Incomplete;
+            };
+            // Doubled operators (dd, yy, >>, gUU, gugu) act on whole lines
+            let op_keys = &keys[i..j];
+            let rest = &keys[k..];
+            let last_op_key = op_keys[op_keys.len() - 1];
+            if rest == op_keys || (op_keys.len() == 2 && rest == [last_op_key]) {
+                return done(CommandKind::Operate(op, motion_count, Target::Line));
+            }
+            if op_keys.len() == 2 && rest == [op_keys[0]] {
+                return Parse::Incomplete;
+            }
+            if next == VimKey::Char('i') || next == VimKey::Char('a') {
+                return match keys.get(k + 1) {
+                    None => Parse::Incomplete,
+                    Some(VimKey::Char(c)) => match text_object_kind(*c) {
+                        Some(kind) => done(CommandKind::Operate(
+                            op,
+                            motion_count,
+                            Target::Object(TextObject {
+                                inner: next == VimKey::Char('i'),
+                                kind,
+                            }),
+                        )),
+                        None => Parse::Invalid,
+                    },
+                    _ => Parse::Invalid,
+                };
+            }
+            return match parse_motion(keys, k) {
+                Ok(motion) => done(CommandKind::Operate(
+                    op,
+                    motion_count,
+                    Target::Motion(motion),
+                )),
+                Err(parse) => parse,
+            };
+        }
+        Ok(None) => {}
+    }
+
+    match parse_motion(keys, i) {
+        Ok(motion) => return done(CommandKind::Move(motion)),
+        Err(Parse::Incomplete) => return Parse::Incomplete,
+        Err(_) => {}
+    }
+
+    if visual && (key == VimKey::Char('i') || key == VimKey::Char('a')) {
+        return match keys.get(i + 1) {
+            None => Parse::Incomplete,
+            Some(VimKey::Char(c)) => match text_object_kind(*c) {
+                Some(kind) => done(CommandKind::SelectObject(TextObject {
+                    inner: key == VimKey::Char('i'),
+                    kind,
+                })),
+                None => Parse::Invalid,
+            },
+            _ => Parse::Invalid,
+        };
+    }
+
+    // Keys that take a character argument
+    let with_arg = |make: fn(char) -> Action| match keys.get(i + 1) {
+        None => Parse::Incomplete,
+        Some(VimKey::Char(c)) => done(CommandKind::Act(make(*c))),
+        _ => Parse::Invalid,
+    };
+
+    let action = match key {
+        VimKey::Esc => Action::Escape,
+        VimKey::Ctrl('r') => Action::Redo,
+        VimKey::Ctrl('v') => Action::EnterVisual(VimMode::VisualBlock),
+        VimKey::Ctrl('d') => Action::ScrollHalfPage { down: true },
+        VimKey::Ctrl('u') => Action::ScrollHalfPage { down: false },
+        VimKey::Char(c) => match (c, visual) {
+            ('i', false) => Action::InsertBefore,
+            ('a', false) => Action::InsertAfter,
+            ('I', false) => Action::InsertLineStart,
+            ('A', false) => Action::InsertLineEnd,
+            ('I', true) if mode == VimMode::VisualBlock => Action::BlockInsert { append: false },
+            ('A', true) if mode == VimMode::VisualBlock => Action::BlockInsert { append: true },
+            ('o', false) => Action::OpenBelow,
+            ('O', false) => Action::OpenAbove,
+            ('o', true) | ('O', true) => Action::SwapAnchor,
+            ('x', false) => Action::DeleteChar,
+            ('X', false) => Action::DeleteCharBack,
+            ('s', false) => Action::SubstituteChar,
+            ('S', false) => Action::SubstituteLine,
+            ('D', false) => Action::DeleteToEnd,
+            ('C', false) => Action::ChangeToEnd,
+            ('Y', false) => Action::YankLine,
+            ('p', _) => Action::Put { before: false },
+            ('P', _) => Action::Put { before: true },
+            ('r', false) => return with_arg(Action::Replace),
+            ('m', false) => return with_arg(Action::SetMark),
+            ('J', false) => Action::Join,
+            ('~', false) => Action::ToggleCaseChar,
+            ('u', false) => Action::Undo,
+            ('v', _) => Action::EnterVisual(VimMode::Visual),
+            ('V', _) => Action::EnterVisual(VimMode::VisualLine),
+            (':', _) => Action::EnterCommandLine,
+            ('.', false) => Action::RepeatChange,
+            _ => return Parse::Invalid,
+        },
+        _ => return Parse::Invalid,
+    };
+    done(CommandKind::Act(action))
+}
+
+fn parse_count(keys: &[VimKey], mut i: usize) -> (Option<usize>, usize) {
+    let mut count: Option<usize> = None;
+    while let Some(VimKey::Char(c)) = keys.get(i) {
+        let Some(digit) = c.to_digit(10) else {
+            break;
+        };
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        count = Some(
+            count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit as usize),
+        );
+        i += 1;
+    }
+    (count, i)
+}
+
+fn parse_operator(
+    keys: &[VimKey],
+    i: usize,
+    visual: bool,
+) -> Result<Option<(Operator, usize)>, Parse> {
+    let Some(VimKey::Char(c)) = keys.get(i) else {
+        return Ok(None);
+    };
+    let op = match (*c, visual) {
+        ('d', _) => Operator::Delete,
+        ('x', true) => Operator::Delete,
+        ('c', _) | ('s', true) => Operator::Change,
+        ('y', _) => Operator::Yank,
+        ('>', _) => Operator::Indent,
+        ('<', _) => Operator::Outdent,
+        ('u', true) => Operator::Lower,
+        ('U', true) => Operator::Upper,
+        ('~', true) => Operator::ToggleCase,
+        ('g', _) => {
+            return match keys.get(i + 1) {
+                None => Err(Parse::Incomplete),
+                Some(VimKey::Char('u')) => Ok(Some((Operator::Lower, i + 2))),
+                Some(VimKey::Char('U')) => Ok(Some((Operator::Upper, i + 2))),
+                Some(VimKey::Char('~')) => Ok(Some((Operator::ToggleCase, i + 2))),
+                _ => Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some((op, i + 1)))
+}
+
+fn parse_motion(keys: &[VimKey], i: usize) -> Result<Motion, Parse> {
+    let Some(&key) = keys.get(i) else {
+        return Err(Parse::Incomplete);
+    };
+    let arg = || match keys.get(i + 1) {
+        None => Err(Parse::Incomplete),
+        Some(VimKey::Char(c)) => Ok(*c),
+        _ => Err(Parse::Invalid),
+    };
+    let motion = match key {
+        VimKey::Left | VimKey::Backspace => Motion::Left,
+        VimKey::Right => Motion::Right,
+        VimKey::Up => Motion::Up,
+        VimKey::Down | VimKey::Enter => Motion::Down,
+        VimKey::Home => Motion::LineStart,
+        VimKey::End => Motion::LineEnd,
+        VimKey::Char(c) => match c {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'k' => Motion::Up,
+            'j' => Motion::Down,
+            'w' => Motion::WordStart(false),
+            'W' => Motion::WordStart(true),
+            'e' => Motion::WordEnd(false),
+            'E' => Motion::WordEnd(true),
+            'b' => Motion::WordBack(false),
+            'B' => Motion::WordBack(true),
+            '0' => Motion::LineStart,
+            '^' => Motion::FirstNonBlank,
+            '$' => Motion::LineEnd,
+            'G' => Motion::FileEnd,
+            '}' => Motion::ParagraphForward,
+            '{' => Motion::ParagraphBack,
+            '%' => Motion::MatchPair,
+            ';' => Motion::RepeatFind(false),
+            ',' => Motion::RepeatFind(true),
+            'g' => match keys.get(i + 1) {
+                None => return Err(Parse::Incomplete),
+                Some(VimKey::Char('g')) => Motion::FileStart,
+                _ => return Err(Parse::Invalid),
+            },
+            'f' | 'F' | 't' | 'T' => Motion::Find {
+                ch: arg()?,
+                forward: c == 'f' || c == 't',
+                till: c == 't' || c == 'T',
+            },
+            '\'' | '`' => Motion::Mark {
+                name: arg()?,
+                exact: c == '`',
+            },
+            _ => return Err(Parse::Invalid),
+        },
+        _ => return Err(Parse::Invalid),
+    };
+    Ok(motion)
+}
+
+fn text_object_kind(c: char) -> Option<ObjectKind> {
+    Some(match c {
+        'w' => ObjectKind::Word,
+        'W' => ObjectKind::BigWord,
+        '"' | '\'' | '`' => ObjectKind::Quote(c),
+        '(' | ')' | 'b' => ObjectKind::Pair('(', ')'),
+        '[' | ']' => ObjectKind::Pair('[', ']'),
+        '{' | '}' | 'B' => ObjectKind::Pair('{', '}'),
+        '<' | '>' => ObjectKind::Pair('<', '>'),
+        'p' => ObjectKind::Paragraph,
+        _ => return None,
+    })
+}
+
+fn key_label(key: &VimKey) -> String {
+    match key {
+        VimKey::Char(c) => c.to_string(),
+        VimKey::Ctrl(c) => format!("^{}", c.to_ascii_uppercase()),
+        _ => String::new(),
+    }
+}
+
+// Buffer helpers working on char offsets
+
+fn line_col(editor: &RopeEditor, pos: usize) -> (usize, usize) {
+    let line = editor.char_to_line(pos);
+    (line, pos - editor.line_to_char(line))
+}
+
+fn last_line(editor: &RopeEditor) -> usize {
+    editor.line_count().saturating_sub(1)
+}
+
+fn line_end(editor: &RopeEditor, line: usize) -> usize {
+    editor.line_to_char(line) + editor.line_len(line)
+}
+
+// Offset of the last character on a line, where the normal-mode cursor rests
+fn last_char(editor: &RopeEditor, line: usize) -> usize {
+    editor.line_to_char(line) + editor.line_len(line).saturating_sub(1)
+}
+
+fn first_non_blank(editor: &RopeEditor, line: usize) -> usize {
+    let indent = leading_whitespace(editor, line).chars().count();
+    indent.min(editor.line_len(line).saturating_sub(1))
+}
+
+fn leading_whitespace(editor: &RopeEditor, line: usize) -> String {
+    editor
+        .get_line(line)
+        .unwrap_or_default()
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+fn clamp_normal_cursor(editor: &mut RopeEditor) {
+    let cursor = *editor.get_cursor();
+    let max_col = editor.line_len(cursor.line).saturating_sub(1);
+    if cursor.column > max_col {
+        editor.set_cursor(cursor.line, max_col);
+    }
+}
+
+// Pads a short line with spaces so `col` exists, returning its char offset
+fn pad_to_column(editor: &mut RopeEditor, line: usize, col: usize) -> usize {
+    let len = editor.line_len(line);
+    if len < col {
+        editor.set_cursor_offset(line_end(editor, line));
+        editor.insert_text(&" ".repeat(col - len));
+    }
+    editor.line_to_char(line) + col
+}
+
+fn char_class(editor: &RopeEditor, c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || editor.is_word_char(c) {
+        1
+    } else {
+        2
+    }
+}
+
+fn class_at(editor: &RopeEditor, pos: usize, big: bool) -> u8 {
+    editor
+        .char_at(pos)
+        .map_or(0, |c| char_class(editor, c, big))
+}
+
+fn word_start(editor: &RopeEditor, pos: usize, big: bool) -> usize {
+    let len = editor.total_chars();
+    let mut p = pos;
+    let class = class_at(editor, p, big);
+    if class != 0 {
+        while p < len && class_at(editor, p, big) == class {
+            p += 1;
+        }
+    }
+    while p < len && class_at(editor, p, big) == 0 {
+        // An empty line counts as a word of its own
+        if editor.char_at(p) == Some('\n') && editor.char_at(p + 1) == Some('\n') {
+            return p + 1;
+        }
+        p += 1;
+    }
+    p
+}
+
+fn word_end(editor: &RopeEditor, pos: usize, big: bool) -> usize {
+    let len = editor.total_chars();
+    let mut p = pos + 1;
+    while p < len && class_at(editor, p, big) == 0 {
+        p += 1;
+    }
+    let class = class_at(editor, p, big);
+    while p + 1 < len && class_at(editor, p + 1, big) == class {
+        p += 1;
+    }
+    p
+}
+
+fn word_back(editor: &RopeEditor, pos: usize, big: bool) -> usize {
+    if pos == 0 {
+        return 0;
+    }
+    let mut p = pos - 1;
+    while p > 0 && class_at(editor, p, big) == 0 {
+        p -= 1;
+    }
+    let class = class_at(editor, p, big);
+    while p > 0 && class_at(editor, p - 1, big) == class {
+        p -= 1;
+    }
+    p
+}
+
+fn find_in_line(
+    editor: &RopeEditor,
+    pos: usize,
+    ch: char,
+    forward: bool,
+    till: bool,
+    count: usize,
+    repeat: bool,
+) -> Option<(usize, MotionKind)> {
+    let (line, _) = line_col(editor, pos);
+    let start = editor.line_to_char(line);
+    let end = line_end(editor, line);
+    let mut p = pos;
+    for n in 0..count {
+        // Repeating a `t` search must skip the match right next to the cursor
+        let skip = if till && repeat && n == 0 { 1 } else { 0 };
+        p = if forward {
+            ((p + 1 + skip)..end).find(|&q| editor.char_at(q) == Some(ch))?
+        } else {
+            (start..p.saturating_sub(skip))
+                .rev()
+                .find(|&q| editor.char_at(q) == Some(ch))?
+        };
+    }
+    if till {
+        p = if forward { p - 1 } else { p + 1 };
+    }
+    let kind = if forward {
+        MotionKind::Inclusive
+    } else {
+        MotionKind::Exclusive
+    };
+    Some((p, kind))
+}
+
+fn match_pair(editor: &RopeEditor, pos: usize) -> Option<usize> {
+    let (line, _) = line_col(editor, pos);
+    let end = line_end(editor, line);
+    let (at, c) = (pos..end)
+        .filter_map(|p| editor.char_at(p).map(|c| (p, c)))
+        .find(|(_, c)| "()[]{}".contains(*c))?;
+    let (open, close, forward) = match c {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => ('(', ')', false),
+        ']' => ('[', ']', false),
+        _ => ('{', '}', false),
+    };
+    if forward {
+        find_close(editor, at + 1, open, close)
+    } else {
+        find_open(editor, at, open, close)
+    }
+}
+
+// Finds the unmatched `close` at or after `from`
+fn find_close(editor: &RopeEditor, from: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    for p in from..editor.total_chars() {
+        match editor.char_at(p) {
+            Some(c) if c == open => depth += 1,
+            Some(c) if c == close => {
+                if depth == 0 {
+                    return Some(p);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Finds the unmatched `open` before `before`
+fn find_open(editor: &RopeEditor, before: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    for p in (0..before).rev() {
+        match editor.char_at(p) {
+            Some(c) if c == close => depth += 1,
+            Some(c) if c == open => {
+                if depth == 0 {
+                    return Some(p);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn text_object(editor: &RopeEditor, object: TextObject, pos: usize) -> Option<Span> {
+    let (line, col) = line_col(editor, pos);
+    let line_start = editor.line_to_char(line);
+    let len = editor.line_len(line);
+    match object.kind {
+        ObjectKind::Word | ObjectKind::BigWord => {
+            if len == 0 {
+                return None;
+            }
+            let big = object.kind == ObjectKind::BigWord;
+            let end_of_line = line_start + len;
+            let pos = pos.min(end_of_line - 1);
+            let class = class_at(editor, pos, big);
+            let mut start = pos;
+            while start > line_start && class_at(editor, start - 1, big) == class {
+                start -= 1;
+            }
+            let mut end = pos + 1;
+            while end < end_of_line && class_at(editor, end, big) == class {
+                end += 1;
+            }
+            if !object.inner {
+                // `aw` takes trailing whitespace, or leading whitespace at line end
+                let mut trailing = end;
+                while trailing < end_of_line && class_at(editor, trailing, big) == 0 {
+                    trailing += 1;
+                }
+                if trailing > end && class != 0 {
+                    end = trailing;
+                } else if class != 0 {
+                    while start > line_start && class_at(editor, start - 1, big) == 0 {
+                        start -= 1;
+                    }
+                }
+            }
+            Some(Span::Chars(start, end))
+        }
+        ObjectKind::Quote(quote) => {
+            let text: Vec<char> = editor.get_line(line)?.chars().collect();
+            let mut quotes = Vec::new();
+            let mut escaped = false;
+            for (idx, c) in text.iter().enumerate() {
+                if escaped {
+                    escaped = false;
+                } else if *c == '\\' {
+                    escaped = true;
+                } else if *c == quote {
+                    quotes.push(idx);
+                }
+            }
+            // Quotes pair up left to right; prefer the pair around the cursor
+            let pairs: Vec<(usize, usize)> = quotes
+                .chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .map(|pair| (pair[0], pair[1]))
+                .collect();
+            let (open, close) = pairs
+                .iter()
+                .find(|(open, close)| (*open..=*close).contains(&col))
+                .or_else(|| pairs.iter().find(|(open, _)| *open > col))
+                .copied()?;
+            if object.inner {
+                Some(Span::Chars(line_start + open + 1, line_start + close))
+            } else {
+                let mut end = close + 1;
+                while end < text.len() && text[end] == ' ' {
+                    end += 1;
+                }
+                Some(Span::Chars(line_start + open, line_start + end))
+            }
+        }
+        ObjectKind::Pair(open, close) => {
+            let at_open = editor.char_at(pos) == Some(open);
+            let start = if at_open {
+                pos
+            } else {
+                find_open(editor, pos, open, close)?
+            };
+            let end = find_close(editor, start + 1, open, close)?;
+            if object.inner {
+                let mut inner_start = start + 1;
+                let mut inner_end = end;
+                // Braces on their own lines: keep the lines, drop the line breaks
+                if editor.char_at(inner_start) == Some('\n') {
+                    inner_start += 1;
+                    let close_line = editor.char_to_line(end);
+                    let close_line_start = editor.line_to_char(close_line);
+                    if inner_start < close_line_start
+                        && editor.slice_text(close_line_start, end).trim().is_empty()
+                    {
+                        inner_end = close_line_start;
+                    }
+                }
+                Some(Span::Chars(inner_start, inner_end.max(inner_start)))
+            } else {
+                Some(Span::Chars(start, end + 1))
+            }
+        }
+        ObjectKind::Paragraph => {
+            let blank = |l: usize| editor.line_len(l) == 0;
+            let is_blank = blank(line);
+            let mut first = line;
+            while first > 0 && blank(first - 1) == is_blank {
+                first -= 1;
+            }
+            let mut last = line;
+            while last < last_line(editor) && blank(last + 1) == is_blank {
+                last += 1;
+            }
+            if !object.inner {
+                while last < last_line(editor) && blank(last + 1) != is_blank {
+                    last += 1;
+                }
+            }
+            Some(Span::Lines(first, last))
+        }
+    }
+}
+
+fn span_start(editor: &RopeEditor, span: Span) -> usize {
+    match span {
+        Span::Chars(start, _) => start,
+        Span::Lines(l1, _) => editor.line_to_char(l1) + first_non_blank(editor, l1),
+        Span::Block { lines, cols } => {
+            editor.line_to_char(lines.0) + cols.0.min(editor.line_len(lines.0))
+        }
+    }
+}
+
+fn span_lines(editor: &RopeEditor, span: Span) -> (usize, usize) {
+    match span {
+        Span::Chars(start, end) => (
+            editor.char_to_line(start),
+            editor.char_to_line(end.saturating_sub(1).max(start)),
+        ),
+        Span::Lines(l1, l2) => (l1, l2),
+        Span::Block { lines, .. } => lines,
+    }
+}
+
+fn span_text(editor: &RopeEditor, span: Span) -> (String, RegisterKind) {
+    match span {
+        Span::Chars(start, end) => (editor.slice_text(start, end), RegisterKind::Charwise),
+        Span::Lines(l1, l2) => {
+            let mut text = editor.slice_text(editor.line_to_char(l1), editor.line_to_char(l2 + 1));
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            (text, RegisterKind::Linewise)
+        }
+        Span::Block { lines, cols } => {
+            let rows: Vec<String> = (lines.0..=lines.1)
+                .map(|line| {
+                    let len = editor.line_len(line);
+                    let start = editor.line_to_char(line);
+                    editor.slice_text(start + cols.0.min(len), start + (cols.1 + 1).min(len))
+                })
+                .collect();
+            (rows.join("\n"), RegisterKind::Blockwise)
+        }
+    }
+}
+
+fn delete_span(editor: &mut RopeEditor, span: Span) {
+    match span {
+        Span::Chars(start, end) => {
+            editor.delete_range(start, end);
+            editor.set_cursor_offset(start);
+        }
+        Span::Lines(l1, l2) => {
+            let mut start = editor.line_to_char(l1);
+            let end = editor.line_to_char(l2 + 1);
+            // Removing the final lines also takes the line break before them
+            let ends_with_newline = end > start && editor.char_at(end - 1) == Some('\n');
+            if !ends_with_newline && start > 0 {
+                start -= 1;
+            }
+            editor.delete_range(start, end);
+            let line = l1.min(last_line(editor));
+            editor.set_cursor(line, first_non_blank(editor, line));
+        }
+        Span::Block { lines, cols } => {
+            for line in (lines.0..=lines.1).rev() {
+                let len = editor.line_len(line);
+                let start = editor.line_to_char(line);
+                if cols.0 < len {
+                    editor.delete_range(start + cols.0, start + (cols.1 + 1).min(len));
+                }
+            }
+            let col = cols.0.min(editor.line_len(lines.0));
+            editor.set_cursor(lines.0, col);
+        }
+    }
+}
+
+// The part of Vim's patterns that `:s` understands: `^` and `$` tie a match
+// to the start and end of the line, `.` matches any char, and a backslash
+// takes the char after it as it is
+struct LinePattern {
+    // None for `.`
+    atoms: Vec<Option<char>>,
+    at_start: bool,
+    at_end: bool,
+}
+
+impl LinePattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let unsupported = || format!("Only ^, $ and . are special in patterns: {}", pattern);
+        let mut chars = pattern.chars().peekable();
+        let at_start = chars.next_if_eq(&'^').is_some();
+        let mut atoms = Vec::new();
+        let mut ends_with_dollar = false;
+        while let Some(c) = chars.next() {
+            ends_with_dollar = c == '$';
+            match c {
+                '\\' => match chars.next() {
+                    Some(c @ ('\\' | '.' | '^' | '$' | '*' | '[' | ']' | '~' | '/' | '&')) => {
+                        atoms.push(Some(c))
+                    }
+                    _ => return Err(unsupported()),
+                },
+                '.' => atoms.push(None),
+                '*' | '[' | '~' => return Err(unsupported()),
+                c => atoms.push(Some(c)),
+            }
+        }
+        // `$` is only special at the end
+        if ends_with_dollar {
+            atoms.pop();
+        }
+        Ok(Self {
+            atoms,
+            at_start,
+            at_end: ends_with_dollar,
+        })
+    }
+
+    // Char ranges of the matches in `line`, leftmost first; only the first
+    // unless `all`
+    fn find(&self, line: &[char], all: bool) -> Vec<Range<usize>> {
+        let end = line
+            .iter()
+            .position(|&c| c == '\n' || c == '\r')
+            .unwrap_or(line.len());
+        let len = self.atoms.len();
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start + len <= line.len() && !(self.at_start && start > 0) {
+            let hit = (!self.at_end || start + len == end)
+                && self.atoms.iter().enumerate().all(|(i, atom)| match atom {
+                    Some(c) => line[start + i] == *c,
+                    // `.` stops at the end of the line
+                    None => start + i < end,
+                });
+            if !hit {
+                start += 1;
+                continue;
+            }
+            matches.push(start..start + len);
+            if !all {
+                break;
+            }
+            // An empty match would otherwise be found again in the same place
+            start += len.max(1);
+        }
+        matches
+    }
+}
+
+// `replacement` with each `&` standing for the matched text; `\&` is a plain `&`
+fn expand_replacement(replacement: &str, matched: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str(matched),
+            '\\' => match chars.next() {
+                Some(c @ ('&' | '\\')) => expanded.push(c),
+                Some(c) => {
+                    expanded.push('\\');
+                    expanded.push(c);
+                }
+                None => expanded.push('\\'),
+            },
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+fn parse_substitute(args: &str) -> Option<(String, String, bool)> {
+    let mut chars = args.chars();
+    let delimiter = chars.next()?;
+    if delimiter.is_alphanumeric() || delimiter.is_whitespace() {
+        return None;
+    }
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        if escaped {
+            let part = parts.last_mut()?;
+            match c {
+                'n' => part.push('\n'),
+                't' => part.push('\t'),
+                c if c == delimiter => part.push(c),
+                c => {
+                    part.push('\\');
+                    part.push(c);
+                }
+            }
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter && parts.len() < 3 {
+            parts.push(String::new());
+        } else {
+            parts.last_mut()?.push(c);
+        }
+    }
+    let pattern = parts.first()?.clone();
+    let replacement = parts.get(1).cloned().unwrap_or_default();
+    let flags = parts.get(2).cloned().unwrap_or_default();
+    Some((pattern, replacement, flags.contains('g')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Esc, Enter, ^V and ^R are spelled as "\x1b", "\n", "^V" and "^R"
+    fn feed(vim: &mut VimState, editor: &mut RopeEditor, keys: &str) -> VimEffect {
+        let mut effect = VimEffect::None;
+        let mut chars = keys.chars().peekable();
+        while let Some(c) = chars.next() {
+            let key = match c {
+                '\x1b' => VimKey::Esc,
+                '\n' => VimKey::Enter,
+                '^' if matches!(chars.peek(), Some('V' | 'R')) => {
+                    VimKey::Ctrl(chars.next().unwrap().to_ascii_lowercase())
+                }
+                c => VimKey::Char(c),
+            };
+            effect = vim.handle_key(editor, key);
+        }
+        effect
+    }
+
+    fn run(text: &str, keys: &str) -> (RopeEditor, VimState) {
+        let mut editor = RopeEditor::from_text(text);
+        let mut vim = VimState::new();
+        vim.enabled = true;
+        feed(&mut vim, &mut editor, keys);
+        (editor, vim)
+    }
+
+    fn content(text: &str, keys: &str) -> String {
+        run(text, keys).0.get_content()
+    }
+
+    fn cursor(text: &str, keys: &str) -> (usize, usize) {
+        let (editor, _) = run(text, keys);
+        let cursor = editor.get_cursor();
+        (cursor.line, cursor.column)
+    }
+
+    #[test]
+    fn operators_take_counts_and_repeat() {
+        assert_eq!(content("foo bar baz", "dw"), "bar baz");
+        assert_eq!(content("foo bar baz", "2dw"), "baz");
+        assert_eq!(content("a b c d e f", "d2w."), "e f");
+        assert_eq!(content("a b c d e f", "dw3."), "e f");
+        assert_eq!(content("foo bar", "cwxy\x1bw."), "xy xy");
+        assert_eq!(content("a\nb", "A;\x1bj."), "a;\nb;");
+        assert_eq!(content("abc,def", "dt,"), ",def");
+        assert_eq!(content("a\nb", ">j"), "    a\n    b");
+        assert_eq!(content("foo bar", "gUiw"), "FOO bar");
+        assert_eq!(content("a\n  b", "J"), "a b");
+    }
+
+    #[test]
+    fn linewise_registers_and_put() {
+        assert_eq!(content("a\nb\nc", "jdd"), "a\nc");
+        assert_eq!(content("a\nb\nc", "Gdd"), "a\nb");
+        assert_eq!(content("a\nb\nc", "ddp"), "b\na\nc");
+        assert_eq!(content("a\nb", "\"ayyj\"ap"), "a\nb\na");
+        assert_eq!(content("foo bar", "yiwwviwp"), "foo foo");
+    }
+
+    #[test]
+    fn text_objects() {
+        assert_eq!(content("foo bar baz", "wdiw"), "foo  baz");
+        assert_eq!(content("foo bar baz", "wdaw"), "foo baz");
+        assert_eq!(content("x = \"hello\";", "fedi\""), "x = \"\";");
+        assert_eq!(content("f(a, b)", "fbci(z\x1b"), "f(z)");
+        assert_eq!(content("f(a, b)", "fbda("), "f");
+        assert_eq!(content("a\nb\n\nc", "dip"), "\nc");
+    }
+
+    #[test]
+    fn undo_groups_an_insert() {
+        assert_eq!(content("abc", "ihello\x1bu"), "abc");
+        assert_eq!(content("abc", "ihello\x1bu^R"), "helloabc");
+    }
+
+    #[test]
+    fn visual_modes() {
+        assert_eq!(content("hello world", "vlld"), "lo world");
+        assert_eq!(content("a\nb\nc", "Vjd"), "c");
+        assert_eq!(content("abc\ndef\nghi", "l^Vjld"), "a\nd\nghi");
+        assert_eq!(content("abc\ndef", "^VjIX\x1b"), "Xabc\nXdef");
+    }
+
+    #[test]
+    fn motions() {
+        assert_eq!(cursor("hello", "$"), (0, 4));
+        assert_eq!(cursor("abcabc", "fc;"), (0, 5));
+        assert_eq!(cursor("(a(b)c)", "%"), (0, 6));
+        assert_eq!(cursor("a\nb\nc", "Ggg"), (0, 0));
+        assert_eq!(cursor("a\nb\nc", "2G"), (1, 0));
+        assert_eq!(cursor("a\nb\nc", "majj'a"), (0, 0));
+        assert_eq!(content("a\nb\nc\nd", "majjd'a"), "d");
+    }
+
+    #[test]
+    fn ex_commands() {
+        let (mut editor, mut vim) = run("x", "");
+        assert_eq!(feed(&mut vim, &mut editor, ":w\n"), VimEffect::Save);
+        assert_eq!(content("a\nb\nc\nd", ":2,3d\n"), "a\nd");
+        assert_eq!(cursor("a\nb\nc", ":3\n"), (2, 0));
+
+        let (_, vim) = run("x", ":set number\n");
+        assert_eq!(
+            vim.message.as_deref(),
+            Some("E492: Not an editor command: set number")
+        );
+    }
+
+    #[test]
+    fn substitute() {
+        assert_eq!(content("a a\na", ":%s/a/b/g\n"), "b b\nb");
+        assert_eq!(content("a a\na", ":s/a/b/\n"), "b a\na");
+        assert_eq!(content("a a\na", ":substitute/a/b/\n"), "b a\na");
+        assert_eq!(content("a/b", ":s#/#-#\n"), "a-b");
+        // A small part of the pattern syntax
+        assert_eq!(content("a.c abc", ":s/a.c/x/g\n"), "x x");
+        assert_eq!(content("a.c abc", ":s/a\\.c/x/g\n"), "x abc");
+        assert_eq!(content("aa\nba", ":%s/^a/x/g\n"), "xa\nba");
+        assert_eq!(content("aa\nba", ":%s/a$/x/\n"), "ax\nbx");
+        assert_eq!(content("a\nb", ":%s/^/# /\n"), "# a\n# b");
+        assert_eq!(content("ab", ":s/$/;/\n"), "ab;");
+        assert_eq!(content("a b", ":s/./<&>/g\n"), "<a>< ><b>");
+        assert_eq!(content("a", ":s/a/\\&/\n"), "&");
+
+        let (editor, vim) = run("ab", ":s/a*/x/\n");
+        assert_eq!(editor.get_content(), "ab");
+        assert_eq!(
+            vim.message.as_deref(),
+            Some("Only ^, $ and . are special in patterns: a*")
+        );
+
+        let (_, vim) = run("abc", ":s/z/y/\n");
+        assert_eq!(vim.message.as_deref(), Some("E486: Pattern not found: z"));
+        let (_, vim) = run("abc", ":s//y/\n");
+        assert_eq!(vim.message.as_deref(), Some("E35: No search pattern"));
+    }
+}
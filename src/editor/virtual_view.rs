@@ -1,19 +1,118 @@
+use super::actions;
+use super::code_actions::{self, CodeAction};
+use super::completion::{self, Completion, CompletionKind, SnippetSession, VISIBLE_ITEMS};
+use super::decorations::{
+    self, DecorationKind, InlineDecoration, LineDecorations, Piece, Source, DECORATIONS,
+};
+use super::diagnostics::{Severity, DIAGNOSTICS};
+use super::emacs::{EmacsEffect, EmacsKey, EMACS};
+use super::folding::{self, FOLDS};
+use super::git_changes::{self, GIT_CHANGES};
+use super::guides::{self, WhitespaceMode};
+use super::inlay_hints;
+use super::language::{self, AUTO_DETECT, LANGUAGES, LANGUAGE_OVERRIDES};
+use super::macros::{self, Repeat, MACROS};
+use super::markdown;
+use super::mouse::{self, Area, ClickCounter, Drag, Hit, SelectUnit};
+use super::navigation::{self, Location, NAVIGATION, PENDING_JUMP};
+use super::outline;
+use super::overview::{
+    self, MarkKind, MinimapLayout, MINIMAP_ROW_HEIGHT, MINIMAP_WIDTH, SCROLLBAR_WIDTH,
+};
+use super::references::{self, Peek};
 use super::rope_engine::RopeEditor;
-use crate::theme::use_theme;
+use super::scroll;
+use super::signature_help::SignatureHelp;
+use super::sticky;
+use super::syntax::{self, LineHighlights, TokenClass};
+use super::typography::{self, FOLD_MARKER_WIDTH, METRICS};
+use super::vim::{VimEffect, VimKey, VimMode, VIM};
+use super::workspace_edit::{self, WorkspaceEdit, REPORT};
+use super::wrap::{DisplayMap, LineLayout, VisualRow, WrapMode};
+use crate::commands::{self, Handler, Target, ACTIVE_EDITOR};
+use crate::components::markdown_view::MarkdownView;
+use crate::keymap::{resolve_key_event, KeyContext, KeyFocus, FOCUS, KEYMAP};
+use crate::lsp::{self, protocol, ServerStatus};
+use crate::settings::SETTINGS;
+use crate::theme::{use_theme, FontStyle, ThemeColors};
+use crate::utils::{git, log};
+use dioxus::html::input_data::MouseButton;
 use dioxus::prelude::*;
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::ops::{Range, RangeInclusive};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// Line range and long-line window of a set of highlights
+type HighlightKey = (Range<usize>, Range<usize>);
+
+// Width of the kind icons in the suggestion list, which sit left of the word
+const SUGGEST_ICON_WIDTH: f64 = 18.0;
+// How long the pointer rests on a word before its hover text is asked for
+const HOVER_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+// Lines of a peeked definition, and how many of them come before it
+const PEEK_LINES: usize = 12;
+const PEEK_CONTEXT: usize = 3;
+// Quiet time after an edit before the outline asks for the server's symbols
+const SYMBOLS_DELAY: std::time::Duration = std::time::Duration::from_millis(400);
+// How long the cursor rests before the lightbulb asks for code actions
+const CODE_ACTIONS_DELAY: std::time::Duration = std::time::Duration::from_millis(300);
+// Quiet time after an edit before inlay hints are asked for again
+const INLAY_HINTS_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+// Hover text for chars `range`, shown until the text changes
+#[derive(Clone, Debug, PartialEq)]
+struct HoverText {
+    revision: u64,
+    range: Range<usize>,
+    markdown: String,
+}
+
+// A peeked definition, shown under `line` until the text changes
+#[derive(Clone, Debug, PartialEq)]
+struct PeekView {
+    revision: u64,
+    line: usize,
+    peek: Peek,
+}
+
+// Code actions for the cursor on `line`, offered until the text changes
+#[derive(Clone, Debug, PartialEq)]
+struct ActionsAt {
+    revision: u64,
+    line: usize,
+    actions: Vec<CodeAction>,
+}
+
+// What to do with the places a language server points to
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Goal {
+    /// Jump to a single answer, or list several
+    Jump,
+    Peek,
+    List,
+}
 
 // Cached line data to avoid repeated allocations
 #[derive(Clone, Debug)]
 struct CachedLine {
     content: String,
-    char_count: usize,
     last_accessed: u64,
+    // Buffers may be edited from elsewhere, as by a rename
+    revision: u64,
 }
 
 // Performance-optimized virtual editor with multiple improvements
 #[component]
-pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>) -> Element {
+pub fn VirtualEditorView(
+    editor: Signal<RopeEditor>,
+    on_save: EventHandler<()>,
+    /// Called with the file picked for Save As
+    on_save_as: EventHandler<PathBuf>,
+    on_close: Option<EventHandler<()>>,
+) -> Element {
     // Create a truly unique component ID for this specific editor instance
     let component_id = use_signal(|| {
         use std::collections::hash_map::DefaultHasher;
@@ -37,6 +136,29 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
     let mut is_focused = use_signal(|| false);
     let mut blink_visible = use_signal(|| true);
     let mut viewport_height = use_signal(|| 800.0);
+    let mut viewport_width = use_signal(|| 800.0);
+    // Horizontal scroll of the text in pixels; the gutter stays put
+    let mut scroll_x = use_signal(|| 0.0f64);
+    // Horizontal scrollbar drag: pointer x, scroll_x at grab
+    let mut thumb_drag = use_signal(|| None::<(f64, f64)>);
+    // Vertical scrollbar or minimap drag: pointer y, first row at grab, rows per pixel
+    let mut row_drag = use_signal(|| None::<(f64, usize, f64)>);
+    // The file at HEAD, loaded once per file
+    let mut git_base = use_signal(|| None::<(PathBuf, Option<Arc<String>>)>);
+    let mut search_cache = use_signal(|| None::<(u64, String, Rc<Vec<usize>>)>);
+    // Pinned scope headers by (revision, first row, most lines, row count)
+    let mut sticky_cache = use_signal(|| None::<((u64, usize, usize, usize), Rc<Vec<usize>>)>);
+    // Scope whose indent guide is highlighted, by (revision, cursor line)
+    let mut scope_cache =
+        use_signal(|| None::<((u64, usize), Option<(usize, RangeInclusive<usize>)>)>);
+    // Syntax highlights by line range and long-line window, for one revision:
+    // those of the lines on screen, the sticky rows and the minimap
+    let mut highlight_cache = use_signal(|| {
+        (
+            0u64,
+            HashMap::<HighlightKey, Rc<Vec<LineHighlights>>>::new(),
+        )
+    });
     let mut frame_counter = use_signal(|| 0u64);
 
     // Performance optimizations - isolated per buffer
@@ -44,13 +166,48 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
     let _last_render_hash = use_signal(|| 0u64);
     let mut smooth_scroll_target = use_signal(|| None::<usize>);
     let mut scroll_momentum = use_signal(|| 0.0f64);
+    let mut display_cache = use_signal(|| None::<Rc<DisplayMap>>);
+
+    // Mouse selection state
+    let mut viewport_mount = use_signal(|| None::<Rc<MountedData>>);
+    let mut viewport_rect = use_signal(|| (0.0f64, 0.0f64, 0.0f64)); // left, top, height
+    let mut drag = use_signal(|| None::<Drag>);
+    let mut drag_scroll = use_signal(|| 0isize);
+    let mut pointer = use_signal(|| (0.0f64, 0.0f64));
+    let mut click_counter = use_signal(ClickCounter::default);
+
+    // Suggestions at the caret, a count that makes answers to older requests
+    // stale, and the snippet whose tab stops are being visited
+    let mut completion = use_signal(|| None::<Completion>);
+    let mut completion_generation = use_signal(|| 0u64);
+    let mut snippet = use_signal(|| None::<SnippetSession>);
+
+    // Hover text, with a count that cancels pending requests for it as the
+    // pointer moves, and the definition peeked at under the caret line
+    let mut hover = use_signal(|| None::<HoverText>);
+    let mut hover_generation = use_signal(|| 0u64);
+    let mut peek = use_signal(|| None::<PeekView>);
+    // The file and revision the server's symbols were last asked for
+    let mut symbols_requested = use_signal(|| None::<(PathBuf, u64)>);
+    // Code actions at the cursor for the lightbulb, with a count that makes
+    // answers to older requests stale, and where a rename was started: the
+    // revision, char offset and old name
+    let mut code_actions = use_signal(|| None::<ActionsAt>);
+    let mut actions_generation = use_signal(|| 0u64);
+    let mut rename_target = use_signal(|| None::<(u64, usize, String)>);
+    // Signature help for the call being typed, with a count that makes answers
+    // to older requests stale
+    let mut signature_help = use_signal(|| None::<SignatureHelp>);
+    let mut signature_generation = use_signal(|| 0u64);
+    // The file's decorations laid out for this buffer by (file, revision,
+    // generation), and the (file, revision, refresh) inlay hints were asked for
+    let mut decoration_cache = use_signal(|| None::<((PathBuf, u64, u64), Rc<LineDecorations>)>);
+    let mut hints_requested = use_signal(|| None::<(PathBuf, u64, u64)>);
 
     // Constants - moved outside render loop with perfect alignment
-    const LINE_HEIGHT: f64 = 20.0;
-    const CHAR_WIDTH: f64 = 8.4;
-    const LINE_NUMBERS_WIDTH: f64 = 50.0; // Increased for better spacing
     const BUFFER_SIZE: usize = 15; // Optimized buffer size
     const MAX_CACHE_SIZE: usize = 200; // Prevent memory bloat
+    const MAX_HIGHLIGHT_RANGES: usize = 16;
     const SMOOTH_SCROLL_FACTOR: f64 = 0.85; // Smoothing factor
 
     // Optimized cursor blinking - isolated per buffer
@@ -79,6 +236,1458 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
         });
     });
 
+    // Leaving a buffer mid-insert must not keep its undo group open
+    use_drop(move || {
+        if let Ok(mut editor_write) = editor.try_write() {
+            VIM.write().reset(&mut editor_write);
+        }
+    });
+
+    // Lays tabs out at the configured width, also in buffers loaded later
+    use_effect(move || {
+        let tab_size = SETTINGS.read().tab_size.max(1);
+        if editor.read().tab_width() != tab_size {
+            editor.write().set_tab_width(tab_size);
+        }
+    });
+
+    // Switching to another file counts as a jump for Go Back. Jumps record
+    // where they started themselves
+    use_drop(move || {
+        if PENDING_JUMP.peek().is_none() {
+            if let Ok(editor_read) = editor.try_read() {
+                navigation::update_navigation(|navigation| {
+                    navigation.record(Location::of(&editor_read))
+                });
+            }
+        }
+    });
+
+    // The outline panel follows the focused buffer and its cursor
+    use_effect(move || {
+        if *ACTIVE_EDITOR.read() == Some(component_id()) {
+            outline::publish(&editor.read());
+        }
+    });
+    use_drop(move || {
+        if let Ok(editor_read) = editor.try_read() {
+            outline::withdraw(editor_read.file_path());
+        }
+    });
+
+    // Once the text settles, the outline asks the language server for its
+    // symbols, which replace the parse tree's
+    let revision = use_memo(move || editor.read().revision());
+    use_effect(move || {
+        let revision = revision();
+        if *ACTIVE_EDITOR.read() != Some(component_id()) {
+            return;
+        }
+        let path = editor.peek().file_path().clone();
+        let running = matches!(lsp::status_of(&path), Some((_, ServerStatus::Running)));
+        let key = Some((path.clone(), revision));
+        if !running
+            || !lsp::provides(&path, "documentSymbolProvider")
+            || *symbols_requested.peek() == key
+        {
+            return;
+        }
+        symbols_requested.set(key);
+        spawn(async move {
+            async_std::task::sleep(SYMBOLS_DELAY).await;
+            if editor.peek().revision() != revision {
+                return;
+            }
+            lsp::flush_changes(&editor.peek());
+            let params = json!({ "textDocument": { "uri": protocol::path_to_uri(&path) } });
+            let Some(Ok(response)) =
+                lsp::request(&path, "textDocument/documentSymbol", params).await
+            else {
+                return;
+            };
+            let editor_read = editor.peek();
+            if response.is_null()
+                || editor_read.revision() != revision
+                || *editor_read.file_path() != path
+            {
+                return;
+            }
+            outline::publish_server_symbols(
+                &path,
+                outline::from_lsp(editor_read.rope(), &response),
+            );
+        });
+    });
+
+    // Once the text settles, asks the language server for the inlay hints of
+    // the file, which are drawn among its text. Servers may ask for them again
+    use_effect(move || {
+        let revision = revision();
+        let refresh = *inlay_hints::REFRESH.read();
+        let path = editor.peek().file_path().clone();
+        if !SETTINGS.read().inlay_hints {
+            decorations::clear_layer(&path, Source::InlayHints);
+            return;
+        }
+        let running = matches!(lsp::status_of(&path), Some((_, ServerStatus::Running)));
+        let key = Some((path.clone(), revision, refresh));
+        if !running || !lsp::provides(&path, "inlayHintProvider") || *hints_requested.peek() == key
+        {
+            return;
+        }
+        hints_requested.set(key);
+        spawn(async move {
+            async_std::task::sleep(INLAY_HINTS_DELAY).await;
+            if editor.peek().revision() != revision {
+                return;
+            }
+            let params = {
+                let editor_read = editor.peek();
+                lsp::flush_changes(&editor_read);
+                lsp::inlay_hint_params(&editor_read)
+            };
+            let Some(Ok(response)) = lsp::request(&path, "textDocument/inlayHint", params).await
+            else {
+                return;
+            };
+            let editor_read = editor.peek();
+            if editor_read.revision() != revision
+                || *editor_read.file_path() != path
+                || !SETTINGS.peek().inlay_hints
+            {
+                return;
+            }
+            decorations::set_layer(
+                &path,
+                Source::InlayHints,
+                inlay_hints::from_response(editor_read.rope(), &response),
+            );
+        });
+    });
+
+    // Keeps folds and bookmarks in step with edits, and opens any fold the
+    // cursor moves into
+    use_effect(move || {
+        let editor_read = editor.read();
+        navigation::follow_edits(&editor_read);
+        let cursor_line = editor_read.get_cursor().line;
+        let path = editor_read.file_path();
+        let Some(state) = FOLDS.peek().get(path).cloned() else {
+            return;
+        };
+        let mut synced = state.clone();
+        synced.sync(&editor_read);
+        synced.reveal(cursor_line);
+        if synced != state {
+            FOLDS.write().insert(path.clone(), synced);
+        }
+    });
+
+    // Highlights of `lines`, long lines only for the columns in `window`,
+    // worked out once per revision of the text
+    let mut cached_highlights =
+        move |editor_read: &RopeEditor, lines: Range<usize>, window: Range<usize>| {
+            let revision = editor_read.revision();
+            let key = (lines, window);
+            {
+                let cache = highlight_cache.peek();
+                if let Some(highlights) = cache.1.get(&key).filter(|_| cache.0 == revision) {
+                    return highlights.clone();
+                }
+            }
+            let highlights = Rc::new(editor_read.highlight_lines(key.0.clone(), key.1.clone()));
+            let mut cache = highlight_cache.write();
+            if cache.0 != revision || cache.1.len() >= MAX_HIGHLIGHT_RANGES {
+                *cache = (revision, HashMap::new());
+            }
+            cache.1.insert(key, highlights.clone());
+            highlights
+        };
+
+    // Rows that fit in the viewport at the current line height
+    let viewport_rows = move || (viewport_height() / METRICS.read().line_height).ceil() as usize;
+
+    // Width left for text between the gutter and the scrollbar, less padding
+    let text_area_width = move || {
+        let inset = overview::right_inset(SETTINGS.read().minimap);
+        viewport_width() - METRICS.read().gutter_width(editor.read().line_count()) - inset - 16.0
+    };
+
+    // Rows on screen after folding and soft wrap, rebuilt only when something changed
+    let mut display_map = move || {
+        let editor_read = editor.read();
+        let folds = folding::fold_map(&editor_read);
+        let wrap_width = {
+            let settings = SETTINGS.read();
+            match settings.word_wrap {
+                WrapMode::Off => None,
+                WrapMode::Viewport => Some(
+                    (text_area_width() / METRICS.read().char_width)
+                        .floor()
+                        .max(1.0) as usize,
+                ),
+                _ => settings.wrap_column(),
+            }
+        };
+        // Decorations of lines edited since they were made are dropped, so they
+        // are laid out again with every revision
+        let decorations = {
+            let files = DECORATIONS.read();
+            let file = files.get(editor_read.file_path());
+            let key = (
+                editor_read.file_path().clone(),
+                editor_read.revision(),
+                file.map_or(0, |file| file.generation),
+            );
+            let cached = decoration_cache
+                .peek()
+                .as_ref()
+                .filter(|(cached_key, _)| *cached_key == key)
+                .map(|(_, decorations)| decorations.clone());
+            cached.unwrap_or_else(|| {
+                let decorations = Rc::new(LineDecorations::new(&editor_read, file));
+                decoration_cache.set(Some((key, decorations.clone())));
+                decorations
+            })
+        };
+        let mut cache = display_cache.write();
+        match cache.as_ref() {
+            Some(map)
+                if map.is_current(&editor_read, &folds, wrap_width)
+                    && Rc::ptr_eq(map.decorations(), &decorations) =>
+            {
+                map.clone()
+            }
+            _ => {
+                let map = Rc::new(
+                    DisplayMap::new(&editor_read, folds, wrap_width).with_decorations(decorations),
+                );
+                *cache = Some(map.clone());
+                map
+            }
+        }
+    };
+
+    // Keeps the cursor line inside the viewport after jumps
+    let mut reveal_cursor = move || {
+        let cursor_line = {
+            let cursor = *editor.read().get_cursor();
+            display_map().row_of(cursor.line, cursor.column)
+        };
+        let lines_in_viewport = viewport_rows();
+        let first = first_visible_line();
+        if cursor_line < first {
+            first_visible_line.set(cursor_line.saturating_sub(5));
+        } else if cursor_line + 5 >= first + lines_in_viewport {
+            first_visible_line.set((cursor_line + 5).saturating_sub(lines_in_viewport));
+        }
+    };
+
+    // Asks the language server for suggestions at the cursor; `trigger` is the
+    // character whose typing asked for them
+    let mut request_completion = move |trigger: Option<char>| {
+        let (path, params, start, cursor) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            let cursor = editor_read.get_cursor().byte_offset;
+            let retrigger = completion
+                .peek()
+                .as_ref()
+                .is_some_and(|list| list.incomplete);
+            let params = lsp::completion_params(&editor_read, trigger, retrigger);
+            (
+                editor_read.file_path().clone(),
+                params,
+                completion::word_start(&editor_read, cursor),
+                cursor,
+            )
+        };
+        // A trigger character ends the word the open list was for
+        if trigger.is_some() {
+            completion.set(None);
+        }
+        let generation = *completion_generation.peek() + 1;
+        completion_generation.set(generation);
+        spawn(async move {
+            let Some(Ok(response)) = lsp::request(&path, "textDocument/completion", params).await
+            else {
+                return;
+            };
+            if *completion_generation.peek() != generation {
+                return;
+            }
+            let list = {
+                let editor_read = editor.read();
+                let mut list = Completion::new(
+                    &editor_read,
+                    start,
+                    cursor,
+                    &response,
+                    lsp::commit_characters(&path),
+                );
+                completion::query(&editor_read, &list).map(|query| {
+                    list.filter(&query);
+                    list
+                })
+            };
+            completion.set(list.filter(|list| !list.matches.is_empty()));
+        });
+    };
+
+    // Asks the language server for the signatures of the call at the cursor;
+    // `trigger` is the character whose typing asked for them
+    let mut request_signature_help = move |trigger: Option<char>| {
+        let (path, params) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            let showing = signature_help.peek().as_ref().map(|help| help.raw.clone());
+            (
+                editor_read.file_path().clone(),
+                lsp::signature_help_params(&editor_read, trigger, showing.as_ref()),
+            )
+        };
+        if !lsp::provides(&path, "signatureHelpProvider") {
+            return;
+        }
+        let generation = *signature_generation.peek() + 1;
+        signature_generation.set(generation);
+        spawn(async move {
+            let Some(Ok(response)) =
+                lsp::request(&path, "textDocument/signatureHelp", params).await
+            else {
+                return;
+            };
+            if *signature_generation.peek() == generation {
+                signature_help.set(SignatureHelp::from_lsp(&response));
+            }
+        });
+    };
+
+    // Applies the selected suggestion, then types `then`, a commit character.
+    // Servers may only give an item's extra edits, such as imports, on resolving it
+    let mut accept_completion = move |then: Option<String>| {
+        let Some(list) = completion.take() else {
+            return;
+        };
+        let Some(mut item) = list.selected_item().cloned() else {
+            return;
+        };
+        completion_generation += 1;
+        let path = editor.peek().file_path().clone();
+        spawn(async move {
+            if !item.resolved && lsp::resolves_completions(&path) {
+                if let Some(Ok(resolved)) =
+                    lsp::request(&path, "completionItem/resolve", item.raw.clone()).await
+                {
+                    item.merge(editor.peek().rope(), &resolved);
+                }
+            }
+            {
+                let mut editor_write = editor.write();
+                let session = completion::accept(&mut editor_write, &list, &item);
+                snippet.set(session);
+                if let Some(text) = &then {
+                    actions::type_text(&mut editor_write, text);
+                }
+            }
+            line_cache.write().clear();
+            reveal_cursor();
+        });
+    };
+
+    // Narrows the suggestions as the word grows, asking again when the server
+    // said the list is incomplete, and closes them once the cursor leaves the word
+    use_effect(move || {
+        let query = {
+            let editor_read = editor.read();
+            let list = completion.peek();
+            let Some(list) = list.as_ref() else {
+                return;
+            };
+            match completion::query(&editor_read, list) {
+                Some(query) if query == list.query => return,
+                query => query.map(|query| (query, list.incomplete)),
+            }
+        };
+        match query {
+            Some((_, true)) => request_completion(None),
+            Some((query, false)) => {
+                let mut list = completion.write();
+                if let Some(current) = list.as_mut() {
+                    current.filter(&query);
+                    if current.matches.is_empty() {
+                        *list = None;
+                    }
+                }
+            }
+            None => {
+                completion_generation += 1;
+                completion.set(None);
+            }
+        }
+    });
+
+    // Fills in the documentation of the selected suggestion when it is first shown
+    use_effect(move || {
+        let Some((index, raw)) = completion.read().as_ref().and_then(|list| {
+            let item = list.selected_item().filter(|item| !item.resolving)?;
+            Some((list.matches[list.selected], item.raw.clone()))
+        }) else {
+            return;
+        };
+        let path = editor.peek().file_path().clone();
+        if !lsp::resolves_completions(&path) {
+            return;
+        }
+        let generation = *completion_generation.peek();
+        spawn(async move {
+            if let Some(list) = completion.write().as_mut() {
+                list.items[index].resolving = true;
+            }
+            let Some(Ok(resolved)) = lsp::request(&path, "completionItem/resolve", raw).await
+            else {
+                return;
+            };
+            if *completion_generation.peek() != generation {
+                return;
+            }
+            let editor_read = editor.peek();
+            if let Some(list) = completion.write().as_mut() {
+                list.items[index].merge(editor_read.rope(), &resolved);
+                list.items[index].resolved = true;
+            }
+        });
+    });
+
+    // Takes jumps into this buffer's file, from go to line, bookmarks and the
+    // navigation history, and shows their line mid-screen
+    use_effect(move || {
+        let Some(jump) = PENDING_JUMP.read().clone() else {
+            return;
+        };
+        if jump.path != *editor.peek().file_path() {
+            return;
+        }
+        *PENDING_JUMP.write() = None;
+        let line = {
+            let mut editor_write = editor.write();
+            let line = jump.line.min(editor_write.line_count() - 1);
+            editor_write.set_selection_anchor(None);
+            editor_write.set_block_selection(None);
+            editor_write.set_cursor(line, jump.column);
+            folding::update_folds(&editor_write, |state| state.reveal(line));
+            line
+        };
+        let row = display_map().row_of(line, jump.column);
+        first_visible_line.set(row.saturating_sub(viewport_rows() / 2));
+        blink_visible.set(true);
+        document::eval(&format!(
+            "document.getElementById('editor_{}')?.focus()",
+            component_id()
+        ));
+    });
+
+    // How far the text can scroll sideways, judged by the widest row on screen
+    let mut max_scroll_x = move || {
+        let display = display_map();
+        let editor_read = editor.read();
+        let first = first_visible_line().min(display.row_count() - 1);
+        let end = (first + viewport_rows()).min(display.row_count());
+        let columns = (first..end)
+            .map(|row| {
+                let visual = display.row(row);
+                let end = visual
+                    .end
+                    .unwrap_or_else(|| editor_read.line_len(visual.line));
+                visual.x_of(&display.layout(visual.line), end)
+                    + display.decorations().row_width(&visual)
+            })
+            .max()
+            .unwrap_or(0);
+        scroll::max_scroll(columns, text_area_width(), METRICS.read().char_width)
+    };
+
+    // Scrolls sideways to keep the cursor in view as it moves
+    use_effect(move || {
+        let cursor = *editor.read().get_cursor();
+        let display = display_map();
+        let visual = display.row(display.row_of(cursor.line, cursor.column));
+        let current = *scroll_x.peek();
+        let target = scroll::reveal(
+            current,
+            display.x_of(&visual, cursor.column),
+            text_area_width(),
+            METRICS.read().char_width,
+        );
+        if target != current {
+            scroll_x.set(target);
+        }
+    });
+
+    // Diffs the buffer against the file at HEAD once typing pauses
+    use_effect(move || {
+        let (path, revision) = {
+            let editor_read = editor.read();
+            (editor_read.file_path().clone(), editor_read.revision())
+        };
+        spawn(async move {
+            async_std::task::sleep(std::time::Duration::from_millis(300)).await;
+            let is_stale =
+                || editor.peek().revision() != revision || editor.peek().file_path() != &path;
+            if is_stale() {
+                return;
+            }
+            let cached = git_base
+                .peek()
+                .clone()
+                .filter(|(base_path, _)| base_path == &path);
+            let base = match cached {
+                Some((_, base)) => base,
+                None => {
+                    let head_path = path.clone();
+                    let base = async_std::task::spawn_blocking(move || git::head_text(&head_path))
+                        .await
+                        .map(Arc::new);
+                    git_base.set(Some((path.clone(), base.clone())));
+                    base
+                }
+            };
+            let Some(base) = base else {
+                GIT_CHANGES.write().remove(&path);
+                return;
+            };
+            let current = editor.peek().get_content();
+            let changes =
+                async_std::task::spawn_blocking(move || git_changes::diff_lines(&base, &current))
+                    .await;
+            if !is_stale() {
+                GIT_CHANGES.write().insert(path, changes);
+            }
+        });
+    });
+
+    // Paints the minimap whenever the text, the scroll position or the theme change
+    use_effect(move || {
+        if !SETTINGS.read().minimap {
+            return;
+        }
+        let display = display_map();
+        let height = viewport_rect().2;
+        let layout = MinimapLayout::new(
+            first_visible_line(),
+            viewport_rows(),
+            display.row_count(),
+            height,
+        );
+        let colors = use_theme().colors();
+        let editor_read = editor.read();
+
+        // Rows as [[column, length, color], ...], only for the columns the minimap shows
+        let mut rows = String::new();
+        let shown = layout.first_row..(layout.first_row + layout.rows).min(display.row_count());
+        let first_line = if shown.is_empty() {
+            0
+        } else {
+            display.row(shown.start).line
+        };
+        let end_line = if shown.is_empty() {
+            0
+        } else {
+            display.row(shown.end - 1).line + 1
+        };
+        let window = long_line_window(
+            &editor_read,
+            &display,
+            shown.clone(),
+            0..MINIMAP_WIDTH as usize,
+        );
+        let highlights = cached_highlights(&editor_read, first_line..end_line, window);
+        for row in shown {
+            let visual = display.row(row);
+            let layout = display.layout(visual.line);
+            let columns = scroll::row_columns(
+                &visual,
+                &layout,
+                0..MINIMAP_WIDTH as usize,
+                editor_read.line_len(visual.line),
+            );
+            let line_start = editor_read.line_to_char(visual.line);
+            let text = editor_read.slice_text(line_start + columns.start, line_start + columns.end);
+            let line_highlights = highlights
+                .get(visual.line - first_line)
+                .map_or(&[][..], Vec::as_slice);
+            let tokens = syntax::line_tokens(&text, columns.start, line_highlights);
+            rows.push('[');
+            for (column, length, class_) in overview::minimap_runs(&tokens) {
+                // Runs are counted in chars; tabs before them push them right
+                let x = visual.x_of(&layout, columns.start + column);
+                rows.push_str(&format!(
+                    "[{},{},{:?}],",
+                    x,
+                    visual.x_of(&layout, columns.start + column + length) - x,
+                    token_color(class_, &colors)
+                ));
+            }
+            rows.push_str("],");
+        }
+        document::eval(&format!(
+            r#"
+            const canvas = document.getElementById("minimap_{}");
+            if (!canvas) return;
+            const scale = window.devicePixelRatio || 1;
+            canvas.width = {MINIMAP_WIDTH} * scale;
+            canvas.height = {height} * scale;
+            const context = canvas.getContext("2d");
+            context.scale(scale, scale);
+            context.globalAlpha = 0.7;
+            [{rows}].forEach((runs, row) => runs.forEach(([x, width, color]) => {{
+                context.fillStyle = color;
+                context.fillRect(x, row * {MINIMAP_ROW_HEIGHT}, width, {MINIMAP_ROW_HEIGHT} - 0.5);
+            }}));
+            "#,
+            component_id()
+        ));
+    });
+
+    // Buffer position under a point in client coordinates
+    let mut hit_at = move |x: f64, y: f64| -> Hit {
+        let display = display_map();
+        let (left, top, _) = viewport_rect();
+        let metrics = *METRICS.read();
+        let first_row = first_visible_line().min(display.row_count() - 1);
+        let scroll_left = scroll_x().min(max_scroll_x());
+        mouse::hit_test(
+            &editor.read(),
+            &display,
+            &metrics,
+            first_row,
+            scroll_left,
+            x - left,
+            y - top,
+        )
+    };
+
+    // Asks the language server about the symbol at char `offset`, and shows
+    // its answer over the symbol
+    let mut request_hover = move |offset: usize| {
+        let (path, params, revision) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            (
+                editor_read.file_path().clone(),
+                lsp::position_params_at(&editor_read, offset),
+                editor_read.revision(),
+            )
+        };
+        if !lsp::provides(&path, "hoverProvider") {
+            return;
+        }
+        let generation = *hover_generation.peek() + 1;
+        hover_generation.set(generation);
+        spawn(async move {
+            let Some(Ok(response)) = lsp::request(&path, "textDocument/hover", params).await else {
+                return;
+            };
+            let markdown = markdown::from_lsp(&response["contents"]);
+            let editor_read = editor.peek();
+            if *hover_generation.peek() != generation
+                || editor_read.revision() != revision
+                || markdown.trim().is_empty()
+            {
+                return;
+            }
+            // The server may say which chars it is about; otherwise the word
+            let range = protocol::range_to_chars(editor_read.rope(), &response["range"])
+                .unwrap_or_else(|| {
+                    let (start, end) = mouse::word_range(&editor_read, offset);
+                    start..end
+                });
+            hover.set(Some(HoverText {
+                revision,
+                range,
+                markdown,
+            }));
+        });
+    };
+
+    // Shows hover text once the pointer has rested on a word for a moment
+    let mut hover_at = move |x: f64, y: f64| {
+        let hit = hit_at(x, y);
+        let offset = {
+            let editor_read = editor.read();
+            let is_word = |offset: usize| {
+                editor_read
+                    .char_at(offset)
+                    .is_some_and(|c| editor_read.is_word_char(c))
+            };
+            // Hits round to the nearest char boundary, so the word may end there
+            let over_text = hit.area == Area::Text && hit.column <= editor_read.line_len(hit.line);
+            if over_text && is_word(hit.offset) {
+                Some(hit.offset)
+            } else if over_text && hit.offset > 0 && is_word(hit.offset - 1) {
+                Some(hit.offset - 1)
+            } else {
+                None
+            }
+        };
+        let shown = hover.peek().as_ref().map(|shown| shown.range.clone());
+        if shown
+            .as_ref()
+            .is_some_and(|range| offset.is_some_and(|offset| range.contains(&offset)))
+        {
+            return;
+        }
+        if shown.is_some() {
+            hover.set(None);
+        }
+        hover_generation += 1;
+        let Some(offset) = offset else {
+            return;
+        };
+        let generation = *hover_generation.peek();
+        spawn(async move {
+            async_std::task::sleep(HOVER_DELAY).await;
+            if *hover_generation.peek() == generation {
+                request_hover(offset);
+            }
+        });
+    };
+
+    // Says `markdown` over the caret, for commands with nothing else to show
+    let mut tell = move |markdown: String| {
+        let (revision, offset) = {
+            let editor_read = editor.peek();
+            (editor_read.revision(), editor_read.get_cursor().byte_offset)
+        };
+        hover.set(Some(HoverText {
+            revision,
+            range: offset..offset,
+            markdown,
+        }));
+    };
+
+    // Asks the language server where the symbol at the cursor is defined, or
+    // used, with `method`, and jumps there, peeks at it or lists the places
+    let locate = move |method: &'static str, goal: Goal| {
+        let (path, mut params, here, word) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            let (start, end) =
+                mouse::word_range(&editor_read, editor_read.get_cursor().byte_offset);
+            let word = editor_read.slice_text(start, end).trim().to_string();
+            (
+                editor_read.file_path().clone(),
+                lsp::position_params(&editor_read),
+                Location::of(&editor_read),
+                word,
+            )
+        };
+        if goal == Goal::List {
+            params["context"] = json!({ "includeDeclaration": true });
+        }
+        spawn(async move {
+            let Some(Ok(response)) = lsp::request(&path, method, params).await else {
+                return;
+            };
+            // Nothing happens if the cursor has moved on meanwhile
+            if Location::of(&editor.peek()) != here {
+                return;
+            }
+            let results = references::from_response(&response);
+            let (title, noun) = match method {
+                "textDocument/references" => (format!("References to {word}"), "references"),
+                "textDocument/implementation" => {
+                    (format!("Implementations of {word}"), "implementation")
+                }
+                "textDocument/typeDefinition" => {
+                    (format!("Type definitions of {word}"), "type definition")
+                }
+                _ => (format!("Definitions of {word}"), "definition"),
+            };
+            match goal {
+                Goal::List => references::show(title, here, results),
+                _ if results.is_empty() => tell(if word.is_empty() {
+                    format!("No {noun} found")
+                } else {
+                    format!("No {noun} found for `{word}`")
+                }),
+                Goal::Jump if results.len() == 1 => {
+                    navigation::jump(Some(here), results[0].location.clone())
+                }
+                Goal::Jump => references::show(title, here, results),
+                Goal::Peek => {
+                    let revision = editor.peek().revision();
+                    peek.set(
+                        Peek::new(&results, PEEK_CONTEXT, PEEK_LINES).map(|peek| PeekView {
+                            revision,
+                            line: here.line,
+                            peek,
+                        }),
+                    );
+                }
+            }
+        });
+    };
+
+    // Once the cursor rests, asks for the code actions there, which the
+    // lightbulb beside its line offers. New problems may bring new fixes
+    let caret = use_memo(move || {
+        let editor_read = editor.read();
+        (
+            editor_read.revision(),
+            editor_read.get_cursor().byte_offset,
+            editor_read.selection_range(),
+        )
+    });
+    use_effect(move || {
+        let (revision, offset, selection) = caret();
+        let path = editor.peek().file_path().clone();
+        let _ = DIAGNOSTICS.read().get(&path).map(Vec::len);
+        actions_generation += 1;
+        if *ACTIVE_EDITOR.read() != Some(component_id())
+            || !lsp::provides(&path, "codeActionProvider")
+        {
+            return;
+        }
+        let generation = *actions_generation.peek();
+        spawn(async move {
+            async_std::task::sleep(CODE_ACTIONS_DELAY).await;
+            if *actions_generation.peek() != generation {
+                return;
+            }
+            let range = selection.map_or(offset..offset, |(start, end)| start..end);
+            let Some(actions) = code_actions_at(editor, range, None, false).await else {
+                return;
+            };
+            if *actions_generation.peek() != generation {
+                return;
+            }
+            let line = editor.peek().char_to_line(offset);
+            code_actions.set((!actions.is_empty()).then_some(ActionsAt {
+                revision,
+                line,
+                actions,
+            }));
+        });
+    });
+
+    // While signature help shows, asks again as the cursor moves or the text
+    // changes; the server answers with nothing once the cursor leaves the call
+    use_effect(move || {
+        let _ = caret();
+        if signature_help.peek().is_some() {
+            request_signature_help(None);
+        }
+    });
+
+    // Makes a code action's edit, asking the server for it first if it was
+    // left out, then runs the action's command on the server
+    let run_code_action = move |action: CodeAction| {
+        let path = editor.peek().file_path().clone();
+        spawn(async move {
+            let mut action = action;
+            if action.needs_resolving() && lsp::resolves_code_actions(&path) {
+                if let Some(Ok(resolved)) =
+                    lsp::request(&path, "codeAction/resolve", action.raw.clone()).await
+                {
+                    action = CodeAction::from_lsp(&resolved).unwrap_or(action);
+                }
+            }
+            if let Some(edit) = action.edit() {
+                workspace_edit::apply_and_report(&action.title, &edit);
+            }
+            // Commands make their edits through workspace/applyEdit
+            if let Some(command) = action.command() {
+                if let Some(Err(error)) =
+                    lsp::request(&path, "workspace/executeCommand", command).await
+                {
+                    tell(error.message);
+                }
+            }
+            reveal_cursor();
+        });
+    };
+
+    // Lists the code actions for the selection, or the cursor, to pick one from
+    let mut show_code_actions = move || {
+        let (path, range, revision, line) = {
+            let editor_read = editor.read();
+            let offset = editor_read.get_cursor().byte_offset;
+            let range = editor_read
+                .selection_range()
+                .map_or(offset..offset, |(start, end)| start..end);
+            (
+                editor_read.file_path().clone(),
+                range,
+                editor_read.revision(),
+                editor_read.char_to_line(offset),
+            )
+        };
+        if !lsp::provides(&path, "codeActionProvider") {
+            tell("No code actions available".to_string());
+            return;
+        }
+        actions_generation += 1;
+        spawn(async move {
+            let Some(actions) = code_actions_at(editor, range, None, true).await else {
+                return;
+            };
+            if editor.peek().revision() != revision {
+                return;
+            }
+            if actions.is_empty() {
+                code_actions.set(None);
+                tell("No code actions available".to_string());
+                return;
+            }
+            let titles = actions.iter().map(|action| action.title.clone()).collect();
+            code_actions.set(Some(ActionsAt {
+                revision,
+                line,
+                actions,
+            }));
+            commands::prompt("Code action to apply", "editor.applyCodeAction", titles);
+        });
+    };
+
+    // Has the server sort and prune the file's imports
+    let mut organize_imports = move || {
+        let (path, range) = {
+            let editor_read = editor.read();
+            (
+                editor_read.file_path().clone(),
+                0..editor_read.total_chars(),
+            )
+        };
+        if !lsp::provides(&path, "codeActionProvider") {
+            tell("Organize imports isn't available for this file".to_string());
+            return;
+        }
+        spawn(async move {
+            let Some(actions) =
+                code_actions_at(editor, range, Some("source.organizeImports"), true).await
+            else {
+                return;
+            };
+            match actions
+                .into_iter()
+                .find(|action| action.is_kind("source.organizeImports"))
+            {
+                Some(action) => run_code_action(action),
+                None => tell("No imports to organize".to_string()),
+            }
+        });
+    };
+
+    // Asks for a new name for the symbol at the cursor. Servers that can say
+    // first whether it can be renamed, and what its name is, are asked
+    let mut start_rename = move || {
+        let (path, params, revision, offset, word) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            let offset = editor_read.get_cursor().byte_offset;
+            let (start, end) = mouse::word_range(&editor_read, offset);
+            let word = editor_read.slice_text(start, end).trim().to_string();
+            (
+                editor_read.file_path().clone(),
+                lsp::position_params(&editor_read),
+                editor_read.revision(),
+                offset,
+                word,
+            )
+        };
+        if !lsp::provides(&path, "renameProvider") {
+            tell("Rename isn't available for this file".to_string());
+            return;
+        }
+        spawn(async move {
+            let mut name = word;
+            if lsp::prepares_rename(&path) {
+                match lsp::request(&path, "textDocument/prepareRename", params).await {
+                    None => return,
+                    Some(Err(error)) => {
+                        tell(error.message);
+                        return;
+                    }
+                    Some(Ok(Value::Null)) => {
+                        tell("This can't be renamed".to_string());
+                        return;
+                    }
+                    // A range, a range with a placeholder, or the word
+                    Some(Ok(answer)) => {
+                        let range = protocol::range_to_chars(
+                            editor.peek().rope(),
+                            answer.get("range").unwrap_or(&answer),
+                        );
+                        if let Some(placeholder) = answer.get("placeholder").and_then(Value::as_str)
+                        {
+                            name = placeholder.to_string();
+                        } else if let Some(range) = range {
+                            name = editor.peek().slice_text(range.start, range.end);
+                        }
+                    }
+                }
+            }
+            if editor.peek().revision() != revision {
+                return;
+            }
+            rename_target.set(Some((revision, offset, name.clone())));
+            commands::prompt(&format!("Rename {name} to"), "editor.rename", vec![name]);
+        });
+    };
+
+    // Asks the server to rename the symbol where the rename started, or at the
+    // cursor, to `new_name`, and shows its edit for applying
+    let mut rename_to = move |new_name: String| {
+        let (path, revision, cursor, word, here) = {
+            let editor_read = editor.read();
+            lsp::flush_changes(&editor_read);
+            let cursor = editor_read.get_cursor().byte_offset;
+            let (start, end) = mouse::word_range(&editor_read, cursor);
+            let word = editor_read.slice_text(start, end).trim().to_string();
+            (
+                editor_read.file_path().clone(),
+                editor_read.revision(),
+                cursor,
+                word,
+                Location::of(&editor_read),
+            )
+        };
+        let (offset, old_name) = match rename_target
+            .take()
+            .filter(|(started, _, _)| *started == revision)
+        {
+            Some((_, offset, old_name)) => (offset, old_name),
+            None => (cursor, word),
+        };
+        if new_name.is_empty() || new_name == old_name {
+            return;
+        }
+        let mut params = lsp::position_params_at(&editor.read(), offset);
+        params["newName"] = json!(new_name);
+        spawn(async move {
+            match lsp::request(&path, "textDocument/rename", params).await {
+                Some(Ok(response)) => {
+                    let edit = WorkspaceEdit::from_lsp(&response);
+                    if edit.is_empty() {
+                        tell(format!("Nothing to rename for `{old_name}`"));
+                    } else {
+                        workspace_edit::preview(
+                            format!("Rename {old_name} to {new_name}"),
+                            here,
+                            edit,
+                        );
+                    }
+                }
+                Some(Err(error)) => tell(error.message),
+                None => {}
+            }
+        });
+    };
+
+    // While a drag selection is held at the top or bottom edge, keep scrolling
+    // and extending the selection
+    use_effect(move || {
+        spawn(async move {
+            loop {
+                async_std::task::sleep(std::time::Duration::from_millis(50)).await;
+                let direction = *drag_scroll.peek();
+                let Some(active) = *drag.peek() else {
+                    continue;
+                };
+                if direction == 0 {
+                    continue;
+                }
+                let max_first = display_map().row_count().saturating_sub(viewport_rows()) as isize;
+                let first = (*first_visible_line.peek() as isize + direction).clamp(0, max_first);
+                first_visible_line.set(first as usize);
+
+                let (x, y) = *pointer.peek();
+                let (_, top, height) = *viewport_rect.peek();
+                let hit = hit_at(x, y.clamp(top, top + (height - 1.0).max(0.0)));
+                active.extend_to(&mut editor.write(), hit);
+            }
+        });
+    });
+
+    // Replays the last recorded macro, or a saved one by name
+    let mut play_macro = move |name: Option<&str>, repeat: Repeat| {
+        let steps = match name {
+            Some(name) => SETTINGS.read().macros.get(name).cloned(),
+            None => MACROS.read().last().map(|steps| steps.to_vec()),
+        };
+        if let Some(steps) = steps {
+            let cursors = macros::cursors(&editor.read());
+            macros::play(&mut editor.write(), &steps, repeat, &cursors);
+            line_cache.write().clear();
+            reveal_cursor();
+        }
+    };
+
+    // Fold commands act on the region around the cursor, or on `line` for gutter clicks
+    let mut run_fold_command = move |command: &str, line: Option<usize>, argument: Option<&str>| {
+        let editor_read = editor.read();
+        let line = line.unwrap_or(editor_read.get_cursor().line);
+        folding::update_folds(&editor_read, |state| match command {
+            "editor.fold" => {
+                if let Some(region) = folding::region_to_fold(&editor_read, state, line) {
+                    state.collapse(region);
+                }
+            }
+            "editor.unfold" => state.expand(line),
+            "editor.toggleFold" => {
+                if state.is_collapsed(line) {
+                    state.expand(line);
+                } else if let Some(region) = folding::region_at(&editor_read, line) {
+                    state.collapse(region);
+                }
+            }
+            "editor.foldAll" => folding::fold_to_level(&editor_read, state, 1),
+            "editor.unfoldAll" => state.expand_all(),
+            "editor.foldLevel" => {
+                if let Some(level) = argument.and_then(|level| level.trim().parse::<usize>().ok()) {
+                    folding::fold_to_level(&editor_read, state, level.max(1));
+                }
+            }
+            _ => {}
+        });
+
+        // A new fold may hide the cursor; move it to the fold's first line
+        let cursor = *editor_read.get_cursor();
+        let fold_start = FOLDS
+            .read()
+            .get(editor_read.file_path())
+            .and_then(|state| state.hidden_by(cursor.line));
+        drop(editor_read);
+        if let Some(start) = fold_start {
+            editor.write().set_cursor(start, cursor.column);
+        }
+        reveal_cursor();
+    };
+
+    // Runs an editor command from a key binding, menu or the command palette.
+    // Commands may carry an argument after a colon, e.g. "macro.play:3".
+    let mut run_editor_command = move |full_command: &str| {
+        MACROS.write().record_command(full_command);
+        let (command, argument) = match full_command.split_once(':') {
+            Some((command, argument)) => (command, Some(argument)),
+            None => (full_command, None),
+        };
+        match command {
+            // File operations
+            "editor.save" => {
+                on_save.call(());
+            }
+            "file.saveAs" => {
+                let path = editor.read().file_path().clone();
+                let mut dialog = rfd::FileDialog::new();
+                if let Some(dir) = path.parent() {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(name) = path.file_name() {
+                    dialog = dialog.set_file_name(name.to_string_lossy());
+                }
+                if let Some(new_path) = dialog.save_file() {
+                    on_save_as.call(new_path);
+                }
+            }
+            // Undo/Redo
+            "editor.undo" => {
+                let mut editor_write = editor.write();
+                editor_write.undo();
+                line_cache.write().clear();
+            }
+            "editor.redo" => {
+                let mut editor_write = editor.write();
+                editor_write.redo();
+                line_cache.write().clear();
+            }
+            // Keybinding profiles; Vim and Emacs are mutually exclusive
+            "editor.toggleVim" => {
+                let enabled = !VIM.read().enabled;
+                if enabled {
+                    EMACS.write().set_enabled(&mut editor.write(), false);
+                }
+                VIM.write().set_enabled(&mut editor.write(), enabled);
+                line_cache.write().clear();
+            }
+            "editor.toggleEmacs" => {
+                let enabled = !EMACS.read().enabled;
+                if enabled {
+                    VIM.write().set_enabled(&mut editor.write(), false);
+                }
+                EMACS.write().set_enabled(&mut editor.write(), enabled);
+                line_cache.write().clear();
+            }
+
+            // Optimized cursor navigation with smart scrolling; moves by visual row,
+            // skipping folded lines
+            "cursor.up" => {
+                let display = display_map();
+                let cursor_row = display.move_vertically(&mut editor.write(), false);
+
+                // Smart auto-scroll with look-ahead
+                if cursor_row < first_visible_line() {
+                    let new_first = cursor_row.saturating_sub(5); // Keep cursor away from edge
+                    smooth_scroll_target.set(Some(new_first));
+                }
+            }
+            "cursor.down" => {
+                let display = display_map();
+                let cursor_row = display.move_vertically(&mut editor.write(), true);
+
+                let lines_in_viewport = viewport_rows();
+                if cursor_row >= first_visible_line() + lines_in_viewport.saturating_sub(5) {
+                    let new_first = cursor_row.saturating_sub(lines_in_viewport.saturating_sub(10));
+                    smooth_scroll_target.set(Some(new_first));
+                }
+            }
+
+            // Fast page navigation
+            "editor.pageUp" => {
+                let lines_in_viewport = viewport_rows();
+                let new_first = first_visible_line().saturating_sub(lines_in_viewport);
+                smooth_scroll_target.set(Some(new_first));
+            }
+            "editor.pageDown" => {
+                let row_count = display_map().row_count();
+
+                let lines_in_viewport = viewport_rows();
+                let max_first_line = row_count.saturating_sub(lines_in_viewport);
+                let new_first = (first_visible_line() + lines_in_viewport).min(max_first_line);
+                smooth_scroll_target.set(Some(new_first));
+            }
+
+            // Folding
+            "editor.fold" | "editor.unfold" | "editor.toggleFold" | "editor.foldAll"
+            | "editor.unfoldAll" | "editor.foldLevel" => run_fold_command(command, None, argument),
+
+            // Go to line and bookmarks, remembered in the navigation history
+            "editor.goToLine" => match argument.and_then(navigation::parse_line_column) {
+                Some((line, column)) => {
+                    let here = Location::of(&editor.read());
+                    let path = here.path.clone();
+                    navigation::jump(Some(here), Location { path, line, column });
+                }
+                None => {
+                    let lines = editor.read().line_count();
+                    commands::prompt(
+                        &format!("Line to go to (1 - {lines}), or line:column"),
+                        "editor.goToLine",
+                        Vec::new(),
+                    );
+                }
+            },
+            "editor.goToSymbol" => {
+                let symbols = outline::symbols(&editor.read());
+                match argument {
+                    Some(label) => {
+                        if let Some(symbol) = symbols.iter().find(|symbol| symbol.label() == label)
+                        {
+                            let here = Location::of(&editor.read());
+                            let path = here.path.clone();
+                            navigation::jump(
+                                Some(here),
+                                Location {
+                                    path,
+                                    line: symbol.line,
+                                    column: symbol.column,
+                                },
+                            );
+                        }
+                    }
+                    None => {
+                        let labels = symbols.iter().map(|symbol| symbol.label()).collect();
+                        commands::prompt("Symbol to go to", "editor.goToSymbol", labels);
+                    }
+                }
+            }
+            // Answers from the language server about the symbol at the cursor
+            "editor.goToDefinition" => locate("textDocument/definition", Goal::Jump),
+            "editor.goToTypeDefinition" => locate("textDocument/typeDefinition", Goal::Jump),
+            "editor.goToImplementation" => locate("textDocument/implementation", Goal::Jump),
+            "editor.peekDefinition" => locate("textDocument/definition", Goal::Peek),
+            "editor.findAllReferences" => locate("textDocument/references", Goal::List),
+            "editor.closePeek" => peek.set(None),
+            "editor.showHover" => {
+                let offset = editor.read().get_cursor().byte_offset;
+                request_hover(offset);
+            }
+            // Refactoring through the language server
+            "editor.rename" => match argument {
+                Some(name) => rename_to(name.trim().to_string()),
+                None => start_rename(),
+            },
+            "editor.quickFix" => show_code_actions(),
+            "editor.applyCodeAction" => {
+                let action = code_actions.read().as_ref().and_then(|offered| {
+                    offered
+                        .actions
+                        .iter()
+                        .find(|action| Some(action.title.as_str()) == argument)
+                        .cloned()
+                });
+                if let Some(action) = action {
+                    run_code_action(action);
+                }
+            }
+            "editor.organizeImports" => organize_imports(),
+            // Suggestions and snippet tab stops
+            "editor.triggerSuggest" => request_completion(None),
+            "suggest.next" | "suggest.previous" | "suggest.nextPage" | "suggest.previousPage" => {
+                let step = match command {
+                    "suggest.next" => 1,
+                    "suggest.previous" => -1,
+                    "suggest.nextPage" => VISIBLE_ITEMS as isize,
+                    _ => -(VISIBLE_ITEMS as isize),
+                };
+                if let Some(list) = completion.write().as_mut() {
+                    list.move_selection(step);
+                }
+            }
+            "suggest.accept" => accept_completion(None),
+            "suggest.hide" => {
+                completion_generation += 1;
+                completion.set(None);
+            }
+            "snippet.next" | "snippet.previous" => {
+                let forward = command == "snippet.next";
+                let stepped = snippet
+                    .write()
+                    .as_mut()
+                    .and_then(|session| session.step(&mut editor.write(), forward));
+                if stepped != Some(true) {
+                    snippet.set(None);
+                }
+                // Tab indents again once the cursor has left the snippet
+                if stepped.is_none() && forward {
+                    actions::run_edit_command(&mut editor.write(), "editor.tab");
+                }
+                line_cache.write().clear();
+                reveal_cursor();
+            }
+            "snippet.exit" => snippet.set(None),
+            // Signatures of the call being typed
+            "editor.triggerParameterHints" => request_signature_help(None),
+            "parameterHints.next" | "parameterHints.previous" => {
+                if let Some(help) = signature_help.write().as_mut() {
+                    help.cycle(command == "parameterHints.next");
+                }
+            }
+            "parameterHints.hide" => {
+                signature_generation += 1;
+                signature_help.set(None);
+            }
+            "lsp.restartServer" => {
+                let path = editor.read().file_path().clone();
+                lsp::restart(&path);
+            }
+            "editor.changeLanguage" => match argument {
+                Some(AUTO_DETECT) => {
+                    let path = editor.read().file_path().clone();
+                    LANGUAGE_OVERRIDES.write().remove(&path);
+                    editor.write().detect_language();
+                }
+                Some(name) => {
+                    if let Some(language) = language::by_name(name) {
+                        let path = editor.read().file_path().clone();
+                        LANGUAGE_OVERRIDES.write().insert(path, language);
+                        editor.write().set_language(language);
+                    }
+                }
+                None => {
+                    let current = editor.read().language().name;
+                    let mut names = vec![AUTO_DETECT.to_string()];
+                    names.extend(LANGUAGES.iter().map(|language| language.name.to_string()));
+                    commands::prompt(
+                        &format!("Language mode (currently {current})"),
+                        "editor.changeLanguage",
+                        names,
+                    );
+                }
+            },
+            "editor.toggleBookmark" => {
+                let here = Location::of(&editor.read());
+                navigation::update_navigation(|navigation| {
+                    navigation.toggle_bookmark(&here.path, here.line)
+                });
+            }
+            "editor.nextBookmark" | "editor.previousBookmark" => {
+                let here = Location::of(&editor.read());
+                let next = NAVIGATION
+                    .read()
+                    .next_bookmark(&here, command == "editor.nextBookmark");
+                if let Some(bookmark) = next {
+                    navigation::jump(Some(here), bookmark);
+                }
+            }
+            "editor.openBookmark" => {
+                if let Some(bookmark) = argument.and_then(navigation::bookmark_at) {
+                    navigation::jump(Some(Location::of(&editor.read())), bookmark);
+                }
+            }
+            "navigation.back" | "navigation.forward" => {
+                let here = Location::of(&editor.read());
+                let target = navigation::update_navigation(|navigation| {
+                    if command == "navigation.back" {
+                        navigation.back(here)
+                    } else {
+                        navigation.forward(here)
+                    }
+                });
+                if target.is_some() {
+                    *PENDING_JUMP.write() = target;
+                }
+            }
+
+            // Macros
+            "macro.toggleRecording" => MACROS.write().toggle_recording(),
+            "macro.play" => {
+                let times = argument.and_then(|n| n.trim().parse().ok()).unwrap_or(1);
+                play_macro(None, Repeat::Times(times));
+            }
+            "macro.playToEnd" => play_macro(None, Repeat::ToEnd),
+            "macro.run" => {
+                if let Some(name) = argument {
+                    play_macro(Some(name), Repeat::Times(1));
+                }
+            }
+
+            // Remaining buffer edits
+            other if actions::run_edit_command(&mut editor.write(), other) => {
+                line_cache.write().clear();
+                reveal_cursor();
+            }
+            // Key bindings reach the focused editor first; other components'
+            // commands go on to them
+            _ => match commands::find(command).map(|found| found.handler) {
+                Some(Handler::Send(Target::Editor)) | None => log::write(
+                    "Commands",
+                    format!("The editor has no handler for {full_command}"),
+                ),
+                Some(_) => commands::execute(full_command),
+            },
+        }
+    };
+
+    // Commands queued from menus and the palette go to the last focused editor
+    use_effect(move || {
+        if *ACTIVE_EDITOR.read() != Some(component_id()) {
+            return;
+        }
+        let queued = commands::take_queued(Target::Editor);
+        if queued.is_empty() {
+            return;
+        }
+        for command in queued {
+            run_editor_command(&command);
+        }
+        document::eval(&format!(
+            "document.getElementById('editor_{}')?.focus()",
+            component_id()
+        ));
+    });
+
     // Smooth scrolling animation - isolated per buffer
     use_effect(move || {
         if let Some(target) = smooth_scroll_target() {
@@ -101,17 +1710,167 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
         }
     });
 
+    // Caret, selection and row positions all come from the measured font
+    let metrics = *METRICS.read();
+    let (line_height, char_width) = (metrics.line_height, metrics.char_width);
+    let number_width = metrics.number_width(editor.read().line_count());
+    let gutter_width = metrics.gutter_width(editor.read().line_count());
+    let text_width = text_area_width();
+    let max_scroll = max_scroll_x();
+    let scroll_left = scroll_x().min(max_scroll);
+
+    // Vertical scrollbar with its overview ruler, and the minimap
+    let minimap_enabled = SETTINGS.read().minimap;
+    let track_height = viewport_rect().2;
+    let (scrollbar_thumb, row_scale, overview_marks, cursor_mark, minimap) = {
+        let display = display_map();
+        let editor_read = editor.read();
+        let (row_count, visible_rows) = (display.row_count(), viewport_rows());
+        let max_first = row_count.saturating_sub(visible_rows);
+        let first = first_visible_line().min(max_first);
+        let thumb = scroll::thumb(
+            first as f64,
+            max_first as f64,
+            visible_rows as f64,
+            track_height,
+        );
+        let row_scale = scroll::thumb_scale(max_first as f64, visible_rows as f64, track_height);
+
+        // Matching lines are searched once per query and revision
+        let query = EMACS.read().search_query().map(str::to_string);
+        let search_lines = match query {
+            Some(query) => {
+                let revision = editor_read.revision();
+                let cached = search_cache
+                    .peek()
+                    .as_ref()
+                    .filter(|(cached_revision, cached_query, _)| {
+                        *cached_revision == revision && *cached_query == query
+                    })
+                    .map(|(_, _, lines)| lines.clone());
+                cached.unwrap_or_else(|| {
+                    let lines = Rc::new(overview::search_lines(&editor_read, &query));
+                    search_cache.set(Some((revision, query, lines.clone())));
+                    lines
+                })
+            }
+            None => Rc::default(),
+        };
+        let path = editor_read.file_path();
+        let marks = overview::overview_marks(
+            &display,
+            track_height,
+            GIT_CHANGES
+                .read()
+                .get(path)
+                .map_or(&[], |changes| changes.as_slice()),
+            DIAGNOSTICS
+                .read()
+                .get(path)
+                .map_or(&[], |diagnostics| diagnostics.as_slice()),
+            &search_lines,
+        );
+        let cursor = editor_read.get_cursor();
+        let cursor_mark =
+            display.row_of(cursor.line, cursor.column) as f64 * track_height / row_count as f64;
+        let minimap = MinimapLayout::new(first, visible_rows, row_count, track_height);
+        (thumb, row_scale, marks, cursor_mark, minimap)
+    };
+
+    // First lines of the scopes the viewport is scrolled into, pinned above the text
+    let sticky_rows = {
+        let max_lines = {
+            let settings = SETTINGS.read();
+            if settings.sticky_scroll {
+                settings.sticky_scroll_max_lines.min(viewport_rows() / 3)
+            } else {
+                0
+            }
+        };
+        let display = display_map();
+        let editor_read = editor.read();
+        let first = first_visible_line().min(display.row_count() - 1);
+        let key = (
+            editor_read.revision(),
+            first,
+            max_lines,
+            display.row_count(),
+        );
+        let cached = sticky_cache
+            .peek()
+            .as_ref()
+            .filter(|(cached_key, _)| *cached_key == key)
+            .map(|(_, lines)| lines.clone());
+        let lines = cached.unwrap_or_else(|| {
+            let lines = Rc::new(sticky::sticky_lines(
+                &editor_read,
+                &display,
+                first,
+                max_lines,
+            ));
+            sticky_cache.set(Some((key, lines.clone())));
+            lines
+        });
+        let screen_columns = scroll::visible_columns(scroll_left, text_width, char_width);
+        lines
+            .iter()
+            .map(|&line| {
+                let visual = display.row(display.row_of(line, 0));
+                let (content, content_start, columns) =
+                    row_content(&editor_read, &display, &visual, screen_columns.clone());
+                let text_left =
+                    display.x_of(&visual, columns.start) as f64 * char_width - scroll_left;
+                let highlights = cached_highlights(&editor_read, line..line + 1, columns.clone())
+                    .first()
+                    .cloned()
+                    .unwrap_or_default();
+                let decorations = row_decorations(&display, &visual, &columns);
+                (
+                    visual,
+                    content,
+                    content_start,
+                    columns,
+                    text_left,
+                    highlights,
+                    display.layout(line),
+                    decorations,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let sticky_height = sticky_rows.len() as f64 * line_height;
+    let (rulers, whitespace) = {
+        let settings = SETTINGS.read();
+        (settings.rulers.clone(), settings.render_whitespace)
+    };
+    let right_inset = overview::right_inset(minimap_enabled);
+
     // Optimized virtual rendering with caching and memoization - isolated per buffer
-    let (cursor_line, cursor_col, lines_data, cursor_top, cursor_left, _render_stats) = {
+    let (
+        cursor_line,
+        cursor_col,
+        lines_data,
+        cursor_top,
+        cursor_left,
+        selection_rects,
+        diagnostic_rects,
+        block_cursors,
+        _render_stats,
+    ) = {
+        // The viewport scrolls by rows: lines left visible by folding, split by soft wrap
+        let display = display_map();
         let editor_read = editor.read();
         let line_count = editor_read.line_count();
-        let first_line = first_visible_line();
         let current_frame = frame_counter();
+        let revision = editor_read.revision();
+        let row_count = display.row_count();
+        let first_line = first_visible_line().min(row_count - 1);
 
         // Calculate viewport efficiently
-        let lines_in_viewport = ((viewport_height() / LINE_HEIGHT) as f64).ceil() as usize;
-        let start_line = first_line;
-        let end_line = (first_line + lines_in_viewport + BUFFER_SIZE).min(line_count);
+        let lines_in_viewport = viewport_rows();
+        let end_row = (first_line + lines_in_viewport + BUFFER_SIZE).min(row_count);
+        let start_line = display.row(first_line).line;
+        let end_line = display.row(end_row.saturating_sub(1)).line + 1;
 
         // Create render hash for change detection - simplified
         let _render_hash = (first_line, end_line, editor_read.get_cursor().line);
@@ -123,60 +1882,95 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
         // Cursor positioning - optimized calculations
         let cursor_pixel_line = cursor.line;
         let cursor_pixel_col = cursor.column;
-        let cursor_top = (cursor_pixel_line.saturating_sub(first_line)) as f64 * LINE_HEIGHT;
+        let cursor_row = display.row_of(cursor_pixel_line, cursor_pixel_col);
+        let cursor_visual = display.row(cursor_row);
+        let cursor_top = (cursor_row.saturating_sub(first_line)) as f64 * line_height;
 
-        // Optimized cursor left calculation with caching
+        // Only the line length matters here, so long lines aren't copied out. The
+        // head of a column selection may sit in virtual space past the line end
         let cursor_left = {
-            if cursor_pixel_line < line_count {
-                let cache_key = cursor_pixel_line;
-                let mut cache = line_cache.write();
-
-                // Check cache first
-                if let Some(cached) = cache.get(&cache_key) {
-                    if cached.char_count > cursor_pixel_col {
-                        LINE_NUMBERS_WIDTH + (cursor_pixel_col as f64 * CHAR_WIDTH)
-                    } else {
-                        LINE_NUMBERS_WIDTH + (cached.char_count as f64 * CHAR_WIDTH)
-                    }
-                } else if let Some(line_text) = editor_read.get_line(cursor_pixel_line) {
-                    let char_count = line_text.chars().count();
-
-                    // Cache the line data
-                    cache.insert(
-                        cache_key,
-                        CachedLine {
-                            content: line_text.clone(),
-                            char_count,
-                            last_accessed: current_frame,
-                        },
-                    );
-
-                    let cursor_chars = cursor_pixel_col.min(char_count);
-                    LINE_NUMBERS_WIDTH + (cursor_chars as f64 * CHAR_WIDTH)
-                } else {
-                    LINE_NUMBERS_WIDTH
-                }
-            } else {
-                LINE_NUMBERS_WIDTH
-            }
+            let cursor_chars = match editor_read.block_selection() {
+                Some(block) => block.head.1,
+                None => cursor_pixel_col.min(editor_read.line_len(cursor_pixel_line)),
+            };
+            gutter_width + display.x_of(&cursor_visual, cursor_chars) as f64 * char_width
+                - scroll_left
         };
 
         // Optimized line data collection with caching
-        let mut lines_data = Vec::with_capacity(end_line - start_line);
+        let mut lines_data = Vec::with_capacity(end_row - first_line);
         let mut cache = line_cache.write();
+        let folds = FOLDS.read();
+        let fold_state = folds.get(editor_read.file_path());
 
         // Clean old cache entries periodically
         if current_frame % 60 == 0 {
             cache.retain(|_, cached| current_frame - cached.last_accessed < 300);
         }
 
-        for line_idx in start_line..end_line {
-            let cache_key = line_idx;
+        // Only the columns around the viewport are drawn
+        let screen_columns = scroll::visible_columns(scroll_left, text_width, char_width);
+
+        let show_guides = SETTINGS.read().indent_guides;
+        let diagnostics = DIAGNOSTICS
+            .read()
+            .get(editor_read.file_path())
+            .cloned()
+            .unwrap_or_default();
+        let bookmarks = NAVIGATION
+            .read()
+            .bookmarks_in(editor_read.file_path())
+            .cloned()
+            .unwrap_or_default();
+        let active_scope = if show_guides {
+            let key = (editor_read.revision(), cursor_pixel_line);
+            let cached = scope_cache
+                .peek()
+                .as_ref()
+                .filter(|(cached_key, _)| *cached_key == key)
+                .map(|(_, scope)| scope.clone());
+            cached.unwrap_or_else(|| {
+                let scope = guides::active_scope(&editor_read, cursor_pixel_line);
+                scope_cache.set(Some((key, scope.clone())));
+                scope
+            })
+        } else {
+            None
+        };
+
+        // Long lines are highlighted only for the columns drawn
+        let window = long_line_window(
+            &editor_read,
+            &display,
+            first_line..end_row,
+            screen_columns.clone(),
+        );
+        let highlights = cached_highlights(&editor_read, start_line..end_line, window);
 
-            // Try cache first
-            let line_content = if let Some(cached) = cache.get_mut(&cache_key) {
+        for row in first_line..end_row {
+            let visual = display.row(row);
+            let line_idx = visual.line;
+            let cache_key = line_idx;
+            let line_len = editor_read.line_len(line_idx);
+            let columns = scroll::row_columns(
+                &visual,
+                &display.layout(line_idx),
+                screen_columns.clone(),
+                line_len,
+            );
+
+            // Long lines are fetched, and highlighted, only for the columns drawn
+            let (line_content, content_start) = if line_len > scroll::LONG_LINE {
+                let line_start = editor_read.line_to_char(line_idx);
+                let content =
+                    editor_read.slice_text(line_start + columns.start, line_start + columns.end);
+                (content, columns.start)
+            } else if let Some(cached) = cache
+                .get_mut(&cache_key)
+                .filter(|cached| cached.revision == revision)
+            {
                 cached.last_accessed = current_frame;
-                cached.content.clone()
+                (cached.content.clone(), 0)
             } else if let Some(fresh_content) = editor_read.get_line(line_idx) {
                 // Cache miss - fetch and cache
                 if cache.len() < MAX_CACHE_SIZE {
@@ -184,22 +1978,198 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                         cache_key,
                         CachedLine {
                             content: fresh_content.clone(),
-                            char_count: fresh_content.chars().count(),
                             last_accessed: current_frame,
+                            revision,
                         },
                     );
                 }
-                fresh_content
+                (fresh_content, 0)
             } else {
                 continue;
             };
 
             let is_cursor_line = line_idx == cursor_pixel_line;
-            let y_position = (line_idx.saturating_sub(first_line)) as f64 * LINE_HEIGHT;
-            lines_data.push((line_idx, line_content, is_cursor_line, y_position));
+            let y_position = (row - first_line) as f64 * line_height;
+            // Gutter marker: Some(collapsed) where a fold region starts
+            let fold = if fold_state.is_some_and(|state| state.is_collapsed(line_idx)) {
+                Some(true)
+            } else {
+                folding::is_fold_start(&editor_read, line_idx).then_some(false)
+            };
+            let text_left = display.x_of(&visual, columns.start) as f64 * char_width - scroll_left;
+            let decorations = row_decorations(&display, &visual, &columns);
+            // Guides as (left, active); continuation rows are indented like the line
+            let indent_guides = if show_guides {
+                guides::indent_guides(&editor_read, line_idx)
+                    .into_iter()
+                    .map(|column| {
+                        let active = active_scope.as_ref().is_some_and(|(scope_width, lines)| {
+                            *scope_width == column && lines.contains(&line_idx)
+                        });
+                        (column as f64 * char_width - scroll_left, active)
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let bookmarked = visual.start == 0 && bookmarks.contains(&line_idx);
+            let line_highlights = highlights
+                .get(line_idx - start_line)
+                .cloned()
+                .unwrap_or_default();
+            // The worst problem starting on the line, with all of their messages
+            let line_diagnostic = if visual.start == 0 {
+                let on_line: Vec<_> = diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.line == line_idx)
+                    .collect();
+                on_line
+                    .iter()
+                    .map(|diagnostic| diagnostic.severity)
+                    .min()
+                    .map(|severity| {
+                        let messages: Vec<&str> = on_line
+                            .iter()
+                            .map(|diagnostic| diagnostic.message.as_str())
+                            .collect();
+                        (severity, messages.join("\n"))
+                    })
+            } else {
+                None
+            };
+            lines_data.push((
+                visual,
+                line_content,
+                content_start,
+                columns,
+                text_left,
+                decorations,
+                display.layout(line_idx),
+                is_cursor_line,
+                y_position,
+                fold,
+                indent_guides,
+                bookmarked,
+                line_highlights,
+                line_diagnostic,
+            ));
         }
 
         drop(cache);
+        drop(folds);
+
+        // Selection highlight, one rectangle per visible line
+        let spans = {
+            let vim = VIM.read();
+            if vim.enabled && vim.mode().is_visual() {
+                vim.selection_spans(&editor_read, start_line, end_line.saturating_sub(1))
+            } else if let Some(block) = editor_read.block_selection() {
+                // Columns past the end of a line are selected too
+                let columns = block.columns();
+                block
+                    .lines()
+                    .filter(|line| (start_line..end_line).contains(line))
+                    .map(|line| (line, columns.start, columns.end))
+                    .collect()
+            } else if let Some((start, end)) = editor_read.selection_range() {
+                range_spans(
+                    &editor_read,
+                    start,
+                    end,
+                    start_line,
+                    end_line.saturating_sub(1),
+                )
+            } else {
+                Vec::new()
+            }
+        };
+        // A span of a line as one rectangle per row it touches, for wrapped lines
+        let span_rects = |line: usize, start_col: usize, end_col: usize| {
+            let display = &display;
+            display
+                .rows_of_line(line)
+                .filter(|row| (first_line..end_row).contains(row))
+                .filter_map(move |row| {
+                    let visual = display.row(row);
+                    let from = start_col.max(visual.start);
+                    let to = visual.end.map_or(end_col, |end| end_col.min(end));
+                    // Parts scrolled under the gutter are cut off. Decorations
+                    // inside the span are covered too
+                    let x_of = |column| {
+                        gutter_width + display.x_of(&visual, column) as f64 * char_width
+                            - scroll_left
+                    };
+                    let (left, right) = (x_of(from), x_of(to));
+                    (from < to && right > gutter_width).then(|| {
+                        (
+                            (row - first_line) as f64 * line_height,
+                            left.max(gutter_width),
+                            right - left.max(gutter_width),
+                        )
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+        let selection_rects: Vec<(f64, f64, f64)> = spans
+            .into_iter()
+            .flat_map(|(line, start_col, end_col)| span_rects(line, start_col, end_col))
+            .collect();
+        // Squiggles under the problems on screen. An empty range still marks
+        // the character it sits before
+        let diagnostic_rects: Vec<(f64, f64, f64, Severity)> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.line < end_line && diagnostic.end_line >= start_line)
+            .flat_map(|diagnostic| {
+                let lines = diagnostic.line.max(start_line)..=diagnostic.end_line.min(end_line - 1);
+                let editor_read = &editor_read;
+                let span_rects = &span_rects;
+                lines.flat_map(move |line| {
+                    let len = editor_read.line_len(line);
+                    let from = if line == diagnostic.line {
+                        diagnostic.column.min(len)
+                    } else {
+                        0
+                    };
+                    let to = if line == diagnostic.end_line {
+                        diagnostic.end_column.min(len)
+                    } else {
+                        len
+                    };
+                    let (from, to) = if from < to {
+                        (from, to)
+                    } else {
+                        (
+                            from.min(len.saturating_sub(1)),
+                            from.min(len.saturating_sub(1)) + 1,
+                        )
+                    };
+                    span_rects(line, from, to)
+                        .into_iter()
+                        .map(move |(top, left, width)| (top, left, width, diagnostic.severity))
+                })
+            })
+            .collect();
+        // A column selection without width shows a cursor on each of its lines
+        let block_cursors: Vec<(f64, f64)> = editor_read
+            .block_selection()
+            .filter(|block| block.columns().is_empty())
+            .map(|block| {
+                let column = block.head.1;
+                block
+                    .lines()
+                    .filter(|&line| line != block.head.0)
+                    .filter_map(|line| {
+                        let row = display.row_of(line, column);
+                        (first_line..end_row).contains(&row).then(|| {
+                            let left = gutter_width
+                                + display.x_of(&display.row(row), column) as f64 * char_width
+                                - scroll_left;
+                            ((row - first_line) as f64 * line_height, left)
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
         drop(editor_read);
 
         // Performance stats for monitoring
@@ -216,15 +2186,161 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
             lines_data,
             cursor_top,
             cursor_left,
+            selection_rects,
+            diagnostic_rects,
+            block_cursors,
             render_stats,
         )
     };
 
+    let (vim_enabled, vim_mode, vim_status) = {
+        let vim = VIM.read();
+        (vim.enabled, vim.mode(), vim.status_text())
+    };
+    let (emacs_enabled, emacs_status) = {
+        let emacs = EMACS.read();
+        (emacs.enabled, emacs.status_text())
+    };
+    let (keymap_hint, keymap_problems) = {
+        let keymap = KEYMAP.read();
+        let hint = keymap.pending_text().unwrap_or_else(|| {
+            let save_keys = keymap.keys_for("editor.save").unwrap_or_default();
+            format!("{save_keys}: Save • PgUp/PgDn: Scroll • Smooth Virtual Scrolling Active")
+        });
+        (hint, keymap.problems())
+    };
+    // The suggestion rows in view as (label, kind, detail, selected), the first
+    // one's index, the selected item's detail and documentation, and the length
+    // of the word typed so far
+    let suggestions = completion.read().as_ref().map(|list| {
+        let rows: Vec<_> = list
+            .matches
+            .iter()
+            .enumerate()
+            .skip(list.scroll)
+            .take(VISIBLE_ITEMS)
+            .map(|(position, &index)| {
+                let item = &list.items[index];
+                (
+                    item.label.clone(),
+                    item.kind,
+                    item.detail.clone().unwrap_or_default(),
+                    position == list.selected,
+                )
+            })
+            .collect();
+        let details = list.selected_item().and_then(|item| {
+            item.documentation
+                .clone()
+                .map(|documentation| (item.detail.clone().unwrap_or_default(), documentation))
+        });
+        (rows, list.scroll, details, list.query.chars().count())
+    });
+    let font_css = typography::font_css(&SETTINGS.read(), &metrics);
+    // The signature of the call being typed, split around the parameter being
+    // typed, with its documentation and which of several signatures it is.
+    // Vim shows it only while inserting
+    let signature_box = signature_help
+        .read()
+        .as_ref()
+        .filter(|_| is_focused() && (!vim_enabled || vim_mode == VimMode::Insert))
+        .map(|help| {
+            let (before, active, after) = help.label_parts();
+            let count = (help.signatures.len() > 1)
+                .then(|| format!("{}/{}", help.active_signature + 1, help.signatures.len()));
+            (before, active, after, help.documentation(), count)
+        });
+    // Where the hover text and the peeked definition go, while the text they
+    // were asked about is unchanged: the top of the symbol's row and its left,
+    // and the top of the row under the peeked line
+    let (hover_box, peek_box) = {
+        let display = display_map();
+        let editor_read = editor.read();
+        let first = first_visible_line().min(display.row_count() - 1);
+        let revision = editor_read.revision();
+        let hover_box = hover
+            .read()
+            .as_ref()
+            .filter(|shown| shown.revision == revision)
+            .and_then(|shown| {
+                let line = editor_read.char_to_line(shown.range.start);
+                let column = shown.range.start - editor_read.line_to_char(line);
+                let row = display.row_of(line, column);
+                let top = row.checked_sub(first)? as f64 * line_height;
+                let left = gutter_width
+                    + display.x_of(&display.row(row), column) as f64 * char_width
+                    - scroll_left;
+                Some((top, left.max(gutter_width), shown.markdown.clone()))
+            });
+        let peek_box = peek
+            .read()
+            .as_ref()
+            .filter(|view| view.revision == revision)
+            .and_then(|view| {
+                let row = display.row_of(view.line, editor_read.line_len(view.line));
+                let top = (row + 1).checked_sub(first)? as f64 * line_height;
+                Some((top, view.peek.clone()))
+            });
+        (hover_box, peek_box)
+    };
+    // The lightbulb beside the cursor line while there are code actions for
+    // it: its top, and whether they include a fix
+    let lightbulb = {
+        let display = display_map();
+        let editor_read = editor.read();
+        let first = first_visible_line().min(display.row_count() - 1);
+        let cursor_line = editor_read.get_cursor().line;
+        code_actions
+            .read()
+            .as_ref()
+            .filter(|offered| {
+                offered.revision == editor_read.revision() && offered.line == cursor_line
+            })
+            .and_then(|offered| {
+                let top = display.row_of(offered.line, 0).checked_sub(first)? as f64 * line_height;
+                Some((top, offered.actions.iter().any(CodeAction::is_quick_fix)))
+            })
+    };
+    // What the last edit across files changed, in the focused editor's status bar
+    let report = REPORT
+        .read()
+        .as_ref()
+        .filter(|_| *ACTIVE_EDITOR.read() == Some(component_id()))
+        .map(|summary| (summary.headline(), summary.details()));
+    let macro_recording = MACROS.read().is_recording();
+    let language_name = editor.read().language().name;
+    let (error_count, warning_count, server) = {
+        let editor_read = editor.read();
+        let path = editor_read.file_path();
+        let diagnostics = DIAGNOSTICS.read();
+        let count = |severity| {
+            diagnostics.get(path).map_or(0, |diagnostics| {
+                diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == severity)
+                    .count()
+            })
+        };
+        (
+            count(Severity::Error),
+            count(Severity::Warning),
+            lsp::status_of(path),
+        )
+    };
+    let keymap_problems_text = keymap_problems.join("\n");
+    // Block cursor outside insert-like modes
+    let cursor_width = if vim_enabled && !matches!(vim_mode, VimMode::Insert | VimMode::CommandLine)
+    {
+        char_width
+    } else {
+        1.0
+    };
+
     // Optimized line rendering with reduced allocations
     let visible_lines_rsx =
         lines_data
             .into_iter()
-            .map(|(line_idx, line_content, is_cursor_line, y_position)| {
+            .map(|(visual, line_content, content_start, columns, text_left, decorations, layout, is_cursor_line, y_position, fold, indent_guides, bookmarked, highlights, diagnostic)| {
                 let theme_colors = use_theme().colors();
                 let bg_color = if is_cursor_line {
                     theme_colors.editor_selection
@@ -233,12 +2349,28 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                 };
                 rsx! {
                     OptimizedLineComponent {
-                        key: "{line_idx}",
-                        line_idx: line_idx,
+                        key: "{visual.line}_{visual.start}",
+                        line_idx: visual.line,
+                        wrap_start: visual.start,
+                        wrap_end: visual.end,
                         top_val: y_position,
+                        number_width: number_width,
                         line_content: line_content,
+                        content_start: content_start,
+                        draw_start: columns.start,
+                        draw_end: columns.end,
+                        highlights: highlights,
+                        text_left: text_left,
+                        layout: layout,
+                        decorations: decorations,
                         bg_color: bg_color,
                         is_cursor_line: is_cursor_line,
+                        fold: fold,
+                        whitespace: whitespace,
+                        indent_guides: indent_guides,
+                        bookmarked: bookmarked,
+                        diagnostic: diagnostic,
+                        on_toggle_fold: move |line| run_fold_command("editor.toggleFold", Some(line), None),
                     }
                 }
             });
@@ -255,14 +2387,20 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                     let colors = use_theme().colors();
                     format!("flex: 1; background-color: {}; position: relative; outline: none; height: 100%; overflow: hidden; will-change: transform; contain: layout style paint; z-index: 1;", colors.editor_bg)
                 },
+                id: "editor_{component_id()}",
                 tabindex: 0,
                 autofocus: true,
 
                 onfocusin: move |_| {
+                    *FOCUS.write() = KeyFocus::Editor;
+                    *ACTIVE_EDITOR.write() = Some(component_id());
                     is_focused.set(true);
                     blink_visible.set(true);
                 },
                 onfocusout: move |_| {
+                    if *FOCUS.read() == KeyFocus::Editor {
+                        *FOCUS.write() = KeyFocus::None;
+                    }
                     is_focused.set(false);
                     blink_visible.set(false);
                 },
@@ -274,19 +2412,27 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
 
                 // Optimized mouse wheel with momentum and smoothing
                 onwheel: move |evt| {
-                    let delta_y = evt.delta().strip_units().y;
+                    // Touchpads scroll sideways directly; a mouse wheel does with shift
+                    let delta = evt.delta().strip_units();
+                    let (delta_x, delta_y) = if evt.modifiers().shift() && delta.x == 0.0 {
+                        (delta.y, 0.0)
+                    } else {
+                        (delta.x, delta.y)
+                    };
+                    if delta_x != 0.0 {
+                        let max_scroll = max_scroll_x();
+                        scroll_x.set((scroll_x().min(max_scroll) + delta_x).clamp(0.0, max_scroll));
+                    }
                     let momentum = scroll_momentum();
 
                     // Apply momentum and smoothing
                     let effective_delta = delta_y + momentum * 0.1;
-                    let lines_to_scroll = (effective_delta / LINE_HEIGHT * 2.5) as i32;
+                    let lines_to_scroll = (effective_delta / METRICS.read().line_height * 2.5) as i32;
 
                     if lines_to_scroll != 0 {
-                        let editor_read = editor.read();
-                        let line_count = editor_read.line_count();
-                        let lines_in_viewport = ((viewport_height() / LINE_HEIGHT) as f64).ceil() as usize;
-                        let max_first_line = line_count.saturating_sub(lines_in_viewport);
-                        drop(editor_read);
+                        let row_count = display_map().row_count();
+                        let lines_in_viewport = viewport_rows();
+                        let max_first_line = row_count.saturating_sub(lines_in_viewport);
 
                         let current_first = first_visible_line() as i32;
                         let new_first = (current_first + lines_to_scroll)
@@ -308,158 +2454,135 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
 
                 // Optimized keyboard handling
                 onkeypress: move |evt| {
+                    // Vim handles text input itself from keydown
+                    if VIM.read().enabled {
+                        return;
+                    }
                     if !evt.modifiers().ctrl() && !evt.modifiers().alt() {
                         if let Key::Character(ref s) = evt.key() {
                             if s.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
-                                let mut editor_write = editor.write();
-                                editor_write.insert_text(s);
+                                let typed = s.chars().next().filter(|_| s.chars().count() == 1);
+                                // A commit character accepts the selected suggestion before it
+                                if typed.is_some_and(|c| completion.peek().as_ref().is_some_and(|list| list.is_commit_character(c))) {
+                                    MACROS.write().record_text(s);
+                                    evt.prevent_default();
+                                    accept_completion(Some(s.clone()));
+                                    return;
+                                }
+                                {
+                                    let mut editor_write = editor.write();
+                                    // Typing over a snippet's placeholder replaces it
+                                    match editor_write.selection_range().filter(|_| snippet.peek().is_some()) {
+                                        Some((start, end)) => {
+                                            editor_write.begin_undo_group();
+                                            editor_write.delete_range(start, end);
+                                            editor_write.set_selection_anchor(None);
+                                            actions::type_text(&mut editor_write, s);
+                                            editor_write.end_undo_group();
+                                        }
+                                        None => actions::type_text(&mut editor_write, s),
+                                    }
+                                }
+                                MACROS.write().record_text(s);
                                 evt.prevent_default();
 
-                                // Clear cache on modification
-                                line_cache.write().clear();
-                            }
-                        }
-                    }
-                },
-
-                onkeydown: move |evt| {
-                    let key = evt.key();
-                    let ctrl = evt.modifiers().ctrl();
-                    let shift = evt.modifiers().shift();
-
-                    match (ctrl, shift, key) {
-                        // File operations
-                        (true, false, Key::Character(ref s)) if s == "s" => {
-                            evt.prevent_default();
-                            on_save.call(());
-                        }
-                        // Undo/Redo
-                        (true, false, Key::Character(ref s)) if s == "z" => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.undo();
-                            line_cache.write().clear();
-                        }
-                        (true, true, Key::Character(ref s)) if s == "z" => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.redo();
-                            line_cache.write().clear();
-                        }
-                        (true, false, Key::Character(ref s)) if s == "y" => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.redo();
-                            line_cache.write().clear();
-                        }
-                        // Copy/Paste
-                        (true, false, Key::Character(ref s)) if s == "c" => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.copy_line();
-                        }
-                        (true, false, Key::Character(ref s)) if s == "v" => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.paste();
-                            line_cache.write().clear();
-                        }
-
-                        // Optimized cursor navigation with smart scrolling
-                        (false, false, Key::ArrowUp) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_up();
-                            let cursor_line = editor_write.get_cursor().line;
-                            drop(editor_write);
-
-                            // Smart auto-scroll with look-ahead
-                            if cursor_line < first_visible_line() {
-                                let new_first = cursor_line.saturating_sub(5); // Keep cursor away from edge
-                                smooth_scroll_target.set(Some(new_first));
-                            }
-                        }
-                        (false, false, Key::ArrowDown) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_down();
-                            let cursor_line = editor_write.get_cursor().line;
-                            drop(editor_write);
-
-                            let lines_in_viewport = ((viewport_height() / LINE_HEIGHT) as f64).ceil() as usize;
-                            if cursor_line >= first_visible_line() + lines_in_viewport.saturating_sub(5) {
-                                let new_first = cursor_line.saturating_sub(lines_in_viewport.saturating_sub(10));
-                                smooth_scroll_target.set(Some(new_first));
-                            }
-                        }
-                        (false, false, Key::ArrowLeft) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_left();
-                        }
-                        (false, false, Key::ArrowRight) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_right();
-                        }
-
-                        // Home/End
-                        (false, false, Key::Home) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_to_line_start();
-                        }
-                        (false, false, Key::End) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.move_cursor_to_line_end();
-                        }
-
-                        // Fast page navigation
-                        (false, false, Key::PageUp) => {
-                            evt.prevent_default();
-                            let lines_in_viewport = ((viewport_height() / LINE_HEIGHT) as f64).ceil() as usize;
-                            let new_first = first_visible_line().saturating_sub(lines_in_viewport);
-                            smooth_scroll_target.set(Some(new_first));
-                        }
-                        (false, false, Key::PageDown) => {
-                            evt.prevent_default();
-                            let editor_read = editor.read();
-                            let line_count = editor_read.line_count();
-                            drop(editor_read);
+                                // Clear cache on modification
+                                line_cache.write().clear();
 
-                            let lines_in_viewport = ((viewport_height() / LINE_HEIGHT) as f64).ceil() as usize;
-                            let max_first_line = line_count.saturating_sub(lines_in_viewport);
-                            let new_first = (first_visible_line() + lines_in_viewport).min(max_first_line);
-                            smooth_scroll_target.set(Some(new_first));
+                                let path = editor.peek().file_path().clone();
+                                if typed.is_some_and(|c| lsp::completion_triggers(&path).contains(&c)) {
+                                    request_completion(typed);
+                                }
+                                // Once showing, signature help follows the cursor by itself
+                                if signature_help.peek().is_none() && typed.is_some_and(|c| lsp::signature_help_triggers(&path).contains(&c)) {
+                                    request_signature_help(typed);
+                                }
+                            }
                         }
+                    }
+                },
 
-                        // Text editing
-                        (false, false, Key::Backspace) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.backspace();
-                            line_cache.write().clear();
-                        }
-                        (false, false, Key::Delete) => {
+                onkeydown: move |evt| {
+                    let key = evt.key();
+                    let ctrl = evt.modifiers().ctrl();
+                    // Any key but a modifier puts hover text away
+                    if hover.peek().is_some() && !matches!(key, Key::Control | Key::Shift | Key::Alt | Key::Meta) {
+                        hover_generation += 1;
+                        hover.set(None);
+                    }
+                    // Keys for the suggestion list skip Vim while it shows, as
+                    // do escape for closing a peeked definition and alt+arrows
+                    // for going through signatures
+                    let suggesting = (completion.peek().is_some()
+                        && matches!(key, Key::ArrowUp | Key::ArrowDown | Key::PageUp | Key::PageDown | Key::Enter | Key::Tab | Key::Escape))
+                        || (peek.peek().is_some() && key == Key::Escape)
+                        || (signature_help.peek().is_some() && evt.modifiers().alt() && matches!(key, Key::ArrowUp | Key::ArrowDown));
+
+                    if VIM.read().enabled && !suggesting {
+                        if let Some(vim_key) = VimKey::from_key(&key, ctrl) {
                             evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.delete();
+                            let lines_in_viewport = viewport_rows();
+                            let effect = {
+                                let mut vim = VIM.write();
+                                vim.set_half_page(lines_in_viewport / 2);
+                                vim.handle_key(&mut editor.write(), vim_key)
+                            };
                             line_cache.write().clear();
+                            reveal_cursor();
+
+                            match effect {
+                                VimEffect::Save => on_save.call(()),
+                                VimEffect::Quit => {
+                                    if let Some(on_close) = &on_close {
+                                        on_close.call(());
+                                    }
+                                }
+                                VimEffect::SaveAndQuit => {
+                                    on_save.call(());
+                                    if let Some(on_close) = &on_close {
+                                        on_close.call(());
+                                    }
+                                }
+                                VimEffect::None => {}
+                            }
+                            return;
                         }
-                        (false, false, Key::Enter) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.insert_newline();
-                            line_cache.write().clear();
+                    }
+
+                    if EMACS.read().enabled {
+                        let emacs_key = EmacsKey::from_key(&key, ctrl, evt.modifiers().alt());
+                        let effect = EMACS.write().handle_key(&mut editor.write(), emacs_key);
+                        match effect {
+                            EmacsEffect::Handled => {
+                                evt.prevent_default();
+                                line_cache.write().clear();
+                                reveal_cursor();
+                                return;
+                            }
+                            EmacsEffect::Save => {
+                                evt.prevent_default();
+                                on_save.call(());
+                                return;
+                            }
+                            EmacsEffect::Unhandled => {}
                         }
-                        (false, false, Key::Tab) => {
-                            evt.prevent_default();
-                            let mut editor_write = editor.write();
-                            editor_write.insert_text("    ");
-                            line_cache.write().clear();
+                    }
+
+                    let context = {
+                        let editor_read = editor.read();
+                        let has_block_selection = editor_read.block_selection().is_some();
+                        KeyContext {
+                            has_selection: editor_read.selection_range().is_some() || has_block_selection,
+                            has_block_selection,
+                            suggest_visible: completion.peek().is_some(),
+                            in_snippet: snippet.peek().is_some(),
+                            peek_visible: peek.peek().is_some(),
+                            parameter_hints_visible: signature_help.peek().is_some(),
+                            ..KeyContext::current()
                         }
-                        _ => {}
+                    };
+                    if let Some(command) = resolve_key_event(&evt, &context) {
+                        run_editor_command(&command);
                     }
                 },
 
@@ -479,7 +2602,11 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                     }
                 },
 
-                onresize: move |_| {
+                onresize: move |evt| {
+                    // Soft wrap at the viewport follows the editor's width
+                    if let Ok(size) = evt.get_content_box_size() {
+                        viewport_width.set(size.width);
+                    }
                     // Re-calculate viewport height on resize
                     spawn(async move {
                         async_std::task::sleep(std::time::Duration::from_millis(100)).await;
@@ -493,16 +2620,622 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                 // Optimized virtual viewport with proper height calculation and unique identity
                 div {
                     key: "viewport_{component_id()}",
-                    style: "position: relative; width: 100%; height: calc(100% - 30px); overflow: hidden; will-change: transform; transform: translateZ(0);",
+                    style: "position: relative; width: 100%; height: calc(100% - 30px); overflow: hidden; will-change: transform; transform: translateZ(0); cursor: text;",
+
+                    onmounted: move |evt| viewport_mount.set(Some(evt.data())),
+                    // The scrollbar and minimap span the viewport's height
+                    onresize: move |evt| {
+                        if let Ok(size) = evt.get_content_box_size() {
+                            viewport_rect.write().2 = size.height;
+                        }
+                    },
+
+                    // Click places the cursor, double-click selects a word, triple-click or
+                    // a click on a line number selects the line; shift extends the selection
+                    // and alt+shift drags out a column selection
+                    onmousedown: move |evt| {
+                        if evt.trigger_button() != Some(MouseButton::Primary) {
+                            return;
+                        }
+                        evt.prevent_default();
+                        if completion.peek().is_some() {
+                            completion_generation += 1;
+                            completion.set(None);
+                        }
+                        hover_generation += 1;
+                        if hover.peek().is_some() {
+                            hover.set(None);
+                        }
+                        if peek.peek().is_some() {
+                            peek.set(None);
+                        }
+                        let point = evt.client_coordinates();
+                        let extend = evt.modifiers().shift();
+                        let column = extend && evt.modifiers().alt();
+                        // Ctrl+click goes to the definition of what is clicked
+                        let go_to_definition = evt.modifiers().ctrl() && !extend;
+                        document::eval(&format!("document.getElementById('editor_{}')?.focus()", component_id()));
+                        spawn(async move {
+                            // The viewport moves with the surrounding layout, so measure it per click
+                            let mounted = viewport_mount();
+                            if let Some(mounted) = mounted {
+                                if let Ok(rect) = mounted.get_client_rect().await {
+                                    viewport_rect.set((rect.origin.x, rect.origin.y, rect.size.height));
+                                }
+                            }
+                            let hit = hit_at(point.x, point.y);
+                            if hit.area == Area::FoldMarkers {
+                                return;
+                            }
+                            if go_to_definition && hit.area == Area::Text {
+                                editor.write().set_cursor_offset(hit.offset);
+                                locate("textDocument/definition", Goal::Jump);
+                                return;
+                            }
+                            let unit = match (hit.area, click_counter.write().click(hit.line)) {
+                                (Area::Text, _) if column => SelectUnit::Column,
+                                (Area::LineNumbers, _) | (_, 3) => SelectUnit::Line,
+                                (_, 2) => SelectUnit::Word,
+                                _ => SelectUnit::Char,
+                            };
+                            drag.set(Some(Drag::start(&mut editor.write(), hit, unit, extend)));
+                            pointer.set((point.x, point.y));
+                            blink_visible.set(true);
+                        });
+                    },
+
+                    onmousemove: move |evt| {
+                        if let Some((grab_y, grab_first, rows_per_pixel)) = row_drag() {
+                            if evt.held_buttons().contains(MouseButton::Primary) {
+                                let dy = evt.client_coordinates().y - grab_y;
+                                let max_first = display_map().row_count().saturating_sub(viewport_rows());
+                                let first = (grab_first as f64 + dy * rows_per_pixel).round().max(0.0) as usize;
+                                first_visible_line.set(first.min(max_first));
+                            } else {
+                                row_drag.set(None);
+                            }
+                            return;
+                        }
+                        if let Some((grab_x, grab_scroll)) = thumb_drag() {
+                            if evt.held_buttons().contains(MouseButton::Primary) {
+                                let dx = evt.client_coordinates().x - grab_x;
+                                let scale = scroll::thumb_scale(max_scroll_x(), text_area_width(), text_area_width());
+                                scroll_x.set((grab_scroll + dx * scale).clamp(0.0, max_scroll_x()));
+                            } else {
+                                thumb_drag.set(None);
+                            }
+                            return;
+                        }
+                        let Some(active) = drag() else {
+                            let point = evt.client_coordinates();
+                            hover_at(point.x, point.y);
+                            return;
+                        };
+                        // The button was released outside the editor
+                        if !evt.held_buttons().contains(MouseButton::Primary) {
+                            drag.set(None);
+                            drag_scroll.set(0);
+                            return;
+                        }
+                        let point = evt.client_coordinates();
+                        pointer.set((point.x, point.y));
+                        let hit = hit_at(point.x, point.y);
+                        active.extend_to(&mut editor.write(), hit);
+
+                        let (_, top, height) = viewport_rect();
+                        let y = point.y - top;
+                        let line_height = METRICS.read().line_height;
+                        drag_scroll.set(if y < line_height {
+                            -1
+                        } else if y > height - line_height {
+                            1
+                        } else {
+                            0
+                        });
+                    },
+
+                    onmouseup: move |_| {
+                        row_drag.set(None);
+                        thumb_drag.set(None);
+                        drag.set(None);
+                        drag_scroll.set(0);
+                    },
+
+                    // Dragging out past the top or bottom keeps scrolling
+                    onmouseleave: move |evt| {
+                        hover_generation += 1;
+                        if hover.peek().is_some() {
+                            hover.set(None);
+                        }
+                        if drag().is_some() {
+                            let point = evt.client_coordinates();
+                            pointer.set((point.x, point.y));
+                            let (_, top, height) = viewport_rect();
+                            drag_scroll.set(if point.y < top {
+                                -1
+                            } else if point.y > top + height {
+                                1
+                            } else {
+                                0
+                            });
+                        }
+                    },
+
                     {visible_lines_rsx}
 
+                    // Rulers, over the lines but under the selection
+                    for column in rulers {
+                        div {
+                            key: "ruler_{column}",
+                            style: {
+                                let colors = use_theme().colors();
+                                let left = gutter_width + column as f64 * char_width - scroll_left;
+                                format!("position: absolute; top: 0; bottom: 0; left: {left}px; width: 1px; background-color: {}; opacity: 0.6; pointer-events: none; z-index: 998; display: {};", colors.border_primary, if left < gutter_width { "none" } else { "block" })
+                            },
+                        }
+                    }
+
+                    // Selection overlay
+                    for (index, (top, left, width)) in selection_rects.into_iter().enumerate() {
+                        div {
+                            key: "selection_{index}",
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("position: absolute; top: {top}px; left: {left}px; width: {width}px; height: {line_height}px; background-color: {}; opacity: 0.35; pointer-events: none; z-index: 999;", colors.accent)
+                            },
+                        }
+                    }
+
+                    // Squiggles under reported problems; hints are only dotted
+                    for (index, (top, left, width, severity)) in diagnostic_rects.into_iter().enumerate() {
+                        div {
+                            key: "diagnostic_{index}",
+                            style: {
+                                let colors = use_theme().colors();
+                                let color = severity_color(severity, &colors).replace('#', "%23");
+                                let underline = if severity == Severity::Hint {
+                                    format!("border-bottom: 2px dotted {};", severity_color(severity, &colors))
+                                } else {
+                                    format!("background: url(\"data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' width='6' height='3'%3E%3Cpath d='M0 2.5 L1.5 0.5 L3 2.5 L4.5 0.5 L6 2.5' fill='none' stroke='{color}' stroke-width='1'/%3E%3C/svg%3E\") repeat-x left bottom;")
+                                };
+                                format!("position: absolute; top: {}px; left: {left}px; width: {width}px; height: 3px; {underline} pointer-events: none; z-index: 999;", top + line_height - 3.0)
+                            },
+                        }
+                    }
+
+                    // Horizontal scrollbar under the text, when some of it is out of view
+                    if max_scroll > 0.0 {
+                        {
+                            let (thumb_left, thumb_width) = scroll::thumb(scroll_left, max_scroll, text_width, text_width);
+                            rsx! {
+                                div {
+                                    key: "hscroll_{component_id()}",
+                                    style: "position: absolute; left: {gutter_width}px; width: {text_width}px; bottom: 0; height: 10px; z-index: 1001; cursor: default;",
+                                    // Clicking the track pages towards the click
+                                    onmousedown: move |evt| {
+                                        evt.stop_propagation();
+                                        evt.prevent_default();
+                                        let page = if evt.element_coordinates().x < thumb_left { -text_width } else { text_width };
+                                        scroll_x.set((scroll_left + page).clamp(0.0, max_scroll));
+                                    },
+                                    div {
+                                        style: {
+                                            let colors = use_theme().colors();
+                                            format!("position: absolute; left: {thumb_left}px; width: {thumb_width}px; top: 2px; bottom: 2px; border-radius: 3px; background-color: {}; opacity: 0.5;", colors.text_muted)
+                                        },
+                                        onmousedown: move |evt| {
+                                            evt.stop_propagation();
+                                            evt.prevent_default();
+                                            thumb_drag.set(Some((evt.client_coordinates().x, scroll_left)));
+                                        },
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // Clicking a pinned header jumps to it
+                    if sticky_height > 0.0 {
+                        div {
+                            key: "sticky_{component_id()}",
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("position: absolute; top: 0; left: 0; right: {right_inset}px; height: {sticky_height}px; z-index: 1000; background-color: {}; box-shadow: 0 2px 4px rgba(0, 0, 0, 0.35);", colors.editor_bg)
+                            },
+                            for (visual, content, content_start, columns, text_left, highlights, layout, decorations) in sticky_rows {
+                                div {
+                                    key: "sticky_{visual.line}",
+                                    style: "position: relative; height: {line_height}px; cursor: pointer;",
+                                    onmousedown: move |evt| {
+                                        evt.stop_propagation();
+                                        evt.prevent_default();
+                                        {
+                                            let mut editor_write = editor.write();
+                                            editor_write.set_selection_anchor(None);
+                                            editor_write.set_cursor(visual.line, 0);
+                                        }
+                                        first_visible_line.set(display_map().row_of(visual.line, 0));
+                                        document::eval(&format!("document.getElementById('editor_{}')?.focus()", component_id()));
+                                    },
+                                    OptimizedLineComponent {
+                                        line_idx: visual.line,
+                                        wrap_start: visual.start,
+                                        wrap_end: visual.end,
+                                        top_val: 0.0,
+                                        number_width: number_width,
+                                        line_content: content,
+                                        content_start: content_start,
+                                        draw_start: columns.start,
+                                        draw_end: columns.end,
+                                        highlights: highlights,
+                                        text_left: text_left,
+                                        layout: layout,
+                                        decorations: decorations,
+                                        bg_color: use_theme().colors().editor_bg,
+                                        is_cursor_line: false,
+                                        fold: None,
+                                        whitespace: whitespace,
+                                        indent_guides: Vec::new(),
+                                        bookmarked: false,
+                                        diagnostic: None,
+                                        on_toggle_fold: move |_| {},
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // Vertical scrollbar, marking git changes, search matches, problems and the cursor
+                    div {
+                        key: "vscroll_{component_id()}",
+                        style: "position: absolute; top: 0; right: 0; width: {SCROLLBAR_WIDTH}px; height: 100%; z-index: 1001; cursor: default;",
+                        // Clicking the track pages towards the click
+                        onmousedown: move |evt| {
+                            evt.stop_propagation();
+                            evt.prevent_default();
+                            let page = viewport_rows().saturating_sub(1).max(1);
+                            let first = first_visible_line();
+                            let max_first = display_map().row_count().saturating_sub(viewport_rows());
+                            if evt.element_coordinates().y < scrollbar_thumb.0 {
+                                first_visible_line.set(first.min(max_first).saturating_sub(page));
+                            } else {
+                                first_visible_line.set((first + page).min(max_first));
+                            }
+                        },
+                        div {
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("position: absolute; left: 2px; right: 2px; top: {}px; height: {}px; border-radius: 3px; background-color: {}; opacity: 0.35;", scrollbar_thumb.0, scrollbar_thumb.1, colors.text_muted)
+                            },
+                            onmousedown: move |evt| {
+                                evt.stop_propagation();
+                                evt.prevent_default();
+                                row_drag.set(Some((evt.client_coordinates().y, first_visible_line(), row_scale)));
+                            },
+                        }
+                        for (kind, top, height) in overview_marks {
+                            div {
+                                key: "mark_{kind:?}_{top}",
+                                style: {
+                                    let colors = use_theme().colors();
+                                    let (left, width) = kind.lane();
+                                    let color = match kind {
+                                        MarkKind::Added => colors.success,
+                                        MarkKind::Modified => colors.accent,
+                                        MarkKind::Deleted | MarkKind::Error => colors.error,
+                                        MarkKind::SearchMatch => colors.syntax_function,
+                                        MarkKind::Information => colors.text_secondary,
+                                        MarkKind::Warning => colors.warning,
+                                    };
+                                    format!("position: absolute; left: {left}px; width: {width}px; top: {top}px; height: {height}px; background-color: {color}; pointer-events: none;")
+                                },
+                            }
+                        }
+                        div {
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("position: absolute; left: 0; right: 0; top: {cursor_mark}px; height: 2px; background-color: {}; pointer-events: none;", colors.editor_cursor)
+                            },
+                        }
+                    }
+
+                    // Minimap: click to jump there, drag to scroll
+                    if minimap_enabled {
+                        div {
+                            key: "minimap_{component_id()}",
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("position: absolute; top: 0; right: {SCROLLBAR_WIDTH}px; width: {MINIMAP_WIDTH}px; height: 100%; z-index: 1001; cursor: default; background-color: {};", colors.editor_bg)
+                            },
+                            onmousedown: move |evt| {
+                                evt.stop_propagation();
+                                evt.prevent_default();
+                                let y = evt.element_coordinates().y;
+                                let mut first = first_visible_line();
+                                let on_slider = (minimap.slider_top..minimap.slider_top + minimap.slider_height).contains(&y);
+                                if !on_slider {
+                                    let max_first = display_map().row_count().saturating_sub(viewport_rows());
+                                    first = minimap.row_at(y).saturating_sub(viewport_rows() / 2).min(max_first);
+                                    first_visible_line.set(first);
+                                }
+                                row_drag.set(Some((evt.client_coordinates().y, first, minimap.rows_per_pixel)));
+                            },
+                            canvas {
+                                id: "minimap_{component_id()}",
+                                style: "position: absolute; top: 0; left: 0; width: {MINIMAP_WIDTH}px; height: 100%; pointer-events: none;",
+                            }
+                            div {
+                                style: {
+                                    let colors = use_theme().colors();
+                                    format!("position: absolute; left: 0; right: 0; top: {}px; height: {}px; background-color: {}; opacity: 0.15; pointer-events: none;", minimap.slider_top, minimap.slider_height, colors.text_primary)
+                                },
+                            }
+                        }
+                    }
+
+                    // The other cursors of a column selection
+                    if is_focused() && blink_visible() {
+                        for (top, left) in block_cursors.into_iter().filter(|&(_, left)| left >= gutter_width - 0.5) {
+                            div {
+                                key: "block_cursor_{top}",
+                                style: {
+                                    let colors = use_theme().colors();
+                                    format!("position: absolute; top: {top}px; left: {left}px; width: {cursor_width}px; height: {line_height}px; background-color: {}; z-index: 1000; pointer-events: none;", colors.editor_cursor)
+                                },
+                            }
+                        }
+                    }
+
+                    // Signature help over the caret line, or under it near the top
+                    if let Some((before, active, after, documentation, count)) = signature_box.filter(|_| cursor_top >= 0.0) {
+                        {
+                            let position = if cursor_top > 3.0 * line_height {
+                                format!("bottom: {}px;", viewport_rect().2 - cursor_top)
+                            } else {
+                                format!("top: {}px;", cursor_top + line_height)
+                            };
+                            let left = cursor_left.max(gutter_width);
+                            let colors = use_theme().colors();
+                            rsx! {
+                                div {
+                                    key: "signature_{component_id()}",
+                                    style: "position: absolute; {position} left: {left}px; z-index: 1002; max-width: 560px; max-height: 240px; overflow: auto; padding: 4px 8px; background-color: {colors.bg_secondary}; border: 1px solid {colors.border_primary}; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.4); cursor: default;",
+                                    onmousedown: move |evt| {
+                                        evt.stop_propagation();
+                                        evt.prevent_default();
+                                    },
+                                    onwheel: move |evt| evt.stop_propagation(),
+                                    div {
+                                        style: "display: flex; align-items: baseline; gap: 8px; {font_css} white-space: pre-wrap; color: {colors.text_primary};",
+                                        if let Some(count) = count {
+                                            span {
+                                                style: "flex-shrink: 0; color: {colors.text_muted}; font-size: 0.85em; cursor: pointer; user-select: none;",
+                                                title: "Next signature",
+                                                onmousedown: move |evt| {
+                                                    evt.stop_propagation();
+                                                    evt.prevent_default();
+                                                    if let Some(help) = signature_help.write().as_mut() {
+                                                        help.cycle(true);
+                                                    }
+                                                },
+                                                "{count}"
+                                            }
+                                        }
+                                        span {
+                                            "{before}"
+                                            span { style: "color: {colors.accent}; font-weight: 600; text-decoration: underline;", "{active}" }
+                                            "{after}"
+                                        }
+                                    }
+                                    if !documentation.trim().is_empty() {
+                                        div {
+                                            style: "margin-top: 4px; padding-top: 4px; border-top: 1px solid {colors.border_primary};",
+                                            MarkdownView { text: documentation, code_font: font_css.clone() }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // Suggestions under the word at the caret, or over it near the bottom,
+                    // with the selected one's documentation beside them
+                    if let Some((rows, scroll, details, query_len)) = suggestions.filter(|_| is_focused() && cursor_top >= 0.0) {
+                        {
+                            let height = rows.len() as f64 * line_height + 2.0;
+                            let viewport = viewport_rect().2;
+                            let above = cursor_top + line_height + height > viewport && cursor_top >= height;
+                            let top = if above { cursor_top - height } else { cursor_top + line_height };
+                            let left = (cursor_left - query_len as f64 * char_width - SUGGEST_ICON_WIDTH).max(gutter_width);
+                            let align = if above { "flex-end" } else { "flex-start" };
+                            let colors = use_theme().colors();
+                            rsx! {
+                                div {
+                                    key: "suggest_{component_id()}",
+                                    style: "position: absolute; top: {top}px; left: {left}px; z-index: 1002; display: flex; gap: 2px; align-items: {align}; {font_css}",
+                                    onmousedown: move |evt| {
+                                        evt.stop_propagation();
+                                        evt.prevent_default();
+                                    },
+                                    div {
+                                        style: "width: 380px; background-color: {colors.bg_secondary}; border: 1px solid {colors.border_primary}; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.4); overflow: hidden;",
+                                        onwheel: move |evt| {
+                                            evt.stop_propagation();
+                                            let delta = evt.delta().strip_units().y;
+                                            if let Some(list) = completion.write().as_mut() {
+                                                list.scroll_by(delta.signum() as isize * 3);
+                                            }
+                                        },
+                                        for (offset, (label, kind, detail, selected)) in rows.into_iter().enumerate() {
+                                            {
+                                                let background = if selected { colors.bg_accent } else { "transparent" };
+                                                rsx! {
+                                                    div {
+                                                        key: "{scroll + offset}",
+                                                        style: "height: {line_height}px; display: flex; align-items: center; gap: 6px; padding-right: 8px; cursor: pointer; white-space: pre; background-color: {background};",
+                                                        title: "{kind.label()}",
+                                                        onmousedown: move |evt| {
+                                                            evt.stop_propagation();
+                                                            evt.prevent_default();
+                                                            if let Some(list) = completion.write().as_mut() {
+                                                                list.selected = scroll + offset;
+                                                            }
+                                                            accept_completion(None);
+                                                        },
+                                                        span {
+                                                            style: "width: {SUGGEST_ICON_WIDTH}px; text-align: center; flex-shrink: 0; color: {completion_kind_color(kind, &colors)};",
+                                                            "{kind.icon()}"
+                                                        }
+                                                        span {
+                                                            style: "color: {colors.text_primary}; overflow: hidden; text-overflow: ellipsis; flex-shrink: 1;",
+                                                            "{label}"
+                                                        }
+                                                        span {
+                                                            style: "flex: 1; min-width: 0; text-align: right; color: {colors.text_muted}; overflow: hidden; text-overflow: ellipsis; font-size: 0.85em;",
+                                                            "{detail}"
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                    if let Some((detail, documentation)) = details {
+                                        div {
+                                            style: "width: 320px; max-height: {height.max(8.0 * line_height)}px; overflow-y: auto; padding: 6px 8px; background-color: {colors.bg_secondary}; border: 1px solid {colors.border_primary}; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.4); color: {colors.text_secondary}; white-space: pre-wrap; font-size: 0.9em;",
+                                            if !detail.is_empty() {
+                                                div { style: "color: {colors.text_primary}; margin-bottom: 4px;", "{detail}" }
+                                            }
+                                            "{documentation}"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
+                    // Hover text above its symbol, or below it near the top
+                    if let Some((top, left, markdown)) = hover_box {
+                        {
+                            let viewport = viewport_rect().2;
+                            let position = if top > viewport / 3.0 {
+                                format!("bottom: {}px;", viewport - top)
+                            } else {
+                                format!("top: {}px;", top + line_height)
+                            };
+                            let colors = use_theme().colors();
+                            rsx! {
+                                div {
+                                    key: "hover_{component_id()}",
+                                    style: "position: absolute; {position} left: {left}px; z-index: 1003; max-width: 520px; max-height: 300px; overflow: auto; padding: 6px 10px; background-color: {colors.bg_secondary}; border: 1px solid {colors.border_primary}; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.4); cursor: default; user-select: text;",
+                                    onmousedown: move |evt| evt.stop_propagation(),
+                                    onmousemove: move |evt| evt.stop_propagation(),
+                                    onwheel: move |evt| evt.stop_propagation(),
+                                    MarkdownView { text: markdown, code_font: font_css.clone() }
+                                }
+                            }
+                        }
+                    }
+
+                    // Lightbulb in the gutter of the cursor line, listing its code actions
+                    if let Some((top, has_fix)) = lightbulb {
+                        {
+                            let opacity = if has_fix { 1.0 } else { 0.6 };
+                            rsx! {
+                                div {
+                                    key: "lightbulb_{component_id()}",
+                                    style: "position: absolute; top: {top}px; left: 0; width: 18px; height: {line_height}px; display: flex; align-items: center; justify-content: center; font-size: 11px; opacity: {opacity}; cursor: pointer; user-select: none; z-index: 1001;",
+                                    title: "Show code actions",
+                                    onmousedown: move |evt| {
+                                        evt.stop_propagation();
+                                        evt.prevent_default();
+                                        run_editor_command("editor.quickFix");
+                                    },
+                                    "\u{1F4A1}"
+                                }
+                            }
+                        }
+                    }
+
+                    // The peeked definition, over the lines under the one it was asked from
+                    if let Some((top, peeked)) = peek_box {
+                        {
+                            let colors = use_theme().colors();
+                            let height = PEEK_LINES as f64 * line_height + 26.0;
+                            let target = peeked.target.clone();
+                            let name = target.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                            let folder = target.path.parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_default();
+                            let count = if peeked.definitions > 1 { format!("1 of {}", peeked.definitions) } else { String::new() };
+                            let opened = target.clone();
+                            rsx! {
+                                div {
+                                    key: "peek_{component_id()}",
+                                    style: "position: absolute; top: {top}px; left: {gutter_width}px; right: {right_inset}px; height: {height}px; z-index: 1002; display: flex; flex-direction: column; background-color: {colors.bg_secondary}; border-top: 2px solid {colors.accent}; border-bottom: 2px solid {colors.accent}; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.4); cursor: default;",
+                                    onmousedown: move |evt| evt.stop_propagation(),
+                                    onmousemove: move |evt| evt.stop_propagation(),
+                                    onwheel: move |evt| evt.stop_propagation(),
+                                    div {
+                                        style: "height: 24px; flex-shrink: 0; display: flex; align-items: center; gap: 8px; padding: 0 10px; font-size: 0.8rem; border-bottom: 1px solid {colors.border_primary}; white-space: nowrap; overflow: hidden;",
+                                        span {
+                                            style: "color: {colors.text_primary}; cursor: pointer;",
+                                            title: "Open {name}",
+                                            onclick: move |_| {
+                                                peek.set(None);
+                                                navigation::jump(Some(Location::of(&editor.read())), opened.clone());
+                                            },
+                                            "{name}:{target.line + 1}"
+                                        }
+                                        span { style: "color: {colors.text_muted}; overflow: hidden; text-overflow: ellipsis;", "{folder}" }
+                                        span { style: "color: {colors.text_muted};", "{count}" }
+                                        span {
+                                            style: "margin-left: auto; color: {colors.text_muted}; cursor: pointer;",
+                                            title: "Close",
+                                            onclick: move |_| peek.set(None),
+                                            "✕"
+                                        }
+                                    }
+                                    div {
+                                        style: "flex: 1; overflow: hidden; {font_css}",
+                                        for (offset, text) in peeked.lines.into_iter().enumerate() {
+                                            {
+                                                let line = peeked.first_line + offset;
+                                                let is_target = line == target.line;
+                                                let background = if is_target { colors.editor_selection } else { "transparent" };
+                                                let destination = Location {
+                                                    path: target.path.clone(),
+                                                    line,
+                                                    column: if is_target { target.column } else { 0 },
+                                                };
+                                                rsx! {
+                                                    div {
+                                                        key: "{line}",
+                                                        style: "display: flex; height: {line_height}px; white-space: pre; background-color: {background};",
+                                                        // Double-clicking a line opens the file there
+                                                        ondoubleclick: move |_| {
+                                                            peek.set(None);
+                                                            navigation::jump(Some(Location::of(&editor.read())), destination.clone());
+                                                        },
+                                                        span {
+                                                            style: "width: 48px; flex-shrink: 0; text-align: right; padding-right: 12px; color: {colors.editor_line_number};",
+                                                            "{line + 1}"
+                                                        }
+                                                        span { style: "color: {colors.text_primary}; overflow: hidden;", "{text}" }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+
                     // Optimized cursor with GPU acceleration and unique identity
-                    if is_focused() && blink_visible() && cursor_top >= 0.0 {
+                    if is_focused() && blink_visible() && cursor_top >= 0.0 && cursor_left >= gutter_width - 0.5 {
                         div {
                             key: "cursor_{component_id()}",
                             style: {
                                 let colors = use_theme().colors();
-                                format!("position: absolute; top: {cursor_top}px; left: {cursor_left}px; width: 1px; height: {LINE_HEIGHT}px; background-color: {}; z-index: 1000; pointer-events: none; will-change: transform; transform: translateZ(0);", colors.editor_cursor)
+                                format!("position: absolute; top: {cursor_top}px; left: {cursor_left}px; width: {cursor_width}px; height: {line_height}px; background-color: {}; opacity: {}; z-index: 1000; pointer-events: none; will-change: transform; transform: translateZ(0);", colors.editor_cursor, if cursor_width > 1.0 { 0.6 } else { 1.0 })
                             },
                         }
                     }
@@ -516,62 +3249,405 @@ pub fn VirtualEditorView(editor: Signal<RopeEditor>, on_save: EventHandler<()>)
                     let colors = use_theme().colors();
                     format!("height: 24px; background-color: {}; color: {}; display: flex; align-items: center; padding: 0 15px; font-size: 0.75rem; font-family: 'Consolas', monospace; justify-content: space-between; flex-shrink: 0; border-top: 1px solid {};", colors.accent, colors.bg_primary, colors.border_primary)
                 },
-                span {
-                    style: "font-weight: 500;",
-                    "Ln {cursor_line}, Col {cursor_col}"
+                div {
+                    style: "display: flex; align-items: center; gap: 10px;",
+                    // Vim mode indicator
+                    if vim_enabled {
+                        span {
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("font-weight: 600; padding: 0 6px; border-radius: 2px; background-color: {}; color: {};", colors.bg_primary, colors.accent)
+                            },
+                            "-- {vim_mode.label()} --"
+                        }
+                        span {
+                            style: "white-space: pre;",
+                            "{vim_status}"
+                        }
+                    }
+                    // Emacs echo area
+                    if emacs_enabled {
+                        span {
+                            style: {
+                                let colors = use_theme().colors();
+                                format!("font-weight: 600; padding: 0 6px; border-radius: 2px; background-color: {}; color: {};", colors.bg_primary, colors.accent)
+                            },
+                            "EMACS"
+                        }
+                        span {
+                            style: "white-space: pre;",
+                            "{emacs_status}"
+                        }
+                    }
+                    if macro_recording {
+                        span {
+                            style: "font-weight: 600;",
+                            title: "Recording macro",
+                            "● REC"
+                        }
+                    }
+                    span {
+                        style: "font-weight: 500;",
+                        "Ln {cursor_line}, Col {cursor_col}"
+                    }
+                    if error_count + warning_count > 0 {
+                        span {
+                            title: "Errors and warnings in this file",
+                            "⊗ {error_count}  ⚠ {warning_count}"
+                        }
+                    }
+                    if let Some((headline, details)) = report {
+                        span {
+                            style: "cursor: pointer; white-space: nowrap; overflow: hidden; text-overflow: ellipsis;",
+                            title: "{details}\nClick to dismiss",
+                            onclick: move |evt| {
+                                evt.stop_propagation();
+                                *REPORT.write() = None;
+                            },
+                            "{headline}"
+                        }
+                    }
                 }
-                span {
-                    style: "font-size: 0.7rem; opacity: 0.9;",
-                    "Ctrl+S: Save • PgUp/PgDn: Scroll • Smooth Virtual Scrolling Active"
+                div {
+                    style: "display: flex; align-items: center; gap: 10px;",
+                    span {
+                        style: "font-size: 0.7rem; opacity: 0.9;",
+                        "{keymap_hint}"
+                    }
+                    if !keymap_problems.is_empty() {
+                        span {
+                            style: "font-size: 0.7rem; font-weight: 600;",
+                            title: "{keymap_problems_text}",
+                            "⚠ Keybindings ({keymap_problems.len()})"
+                        }
+                    }
+                    // The language server of the file; clicking restarts it
+                    if let Some((command, status)) = server {
+                        span {
+                            style: "font-size: 0.7rem; cursor: pointer; user-select: none;",
+                            title: match &status {
+                                ServerStatus::Failed(error) => format!("{command} failed: {error}\nClick to restart"),
+                                _ => format!("{command}\nClick to restart"),
+                            },
+                            onclick: move |evt| {
+                                evt.stop_propagation();
+                                run_editor_command("lsp.restartServer");
+                            },
+                            match status {
+                                ServerStatus::Starting => "{command} …",
+                                ServerStatus::Running => "{command}",
+                                ServerStatus::Restarting => "{command} ↻",
+                                ServerStatus::Failed(_) => "⚠ {command}",
+                            }
+                        }
+                    }
+                    span {
+                        style: "font-size: 0.7rem; cursor: pointer; user-select: none;",
+                        title: "Select language mode",
+                        onclick: move |evt| {
+                            evt.stop_propagation();
+                            run_editor_command("editor.changeLanguage");
+                        },
+                        "{language_name}"
+                    }
+                    span {
+                        style: "font-size: 0.7rem; cursor: pointer; user-select: none; font-weight: 600;",
+                        title: "Toggle Vim mode",
+                        onclick: move |evt| {
+                            evt.stop_propagation();
+                            run_editor_command("editor.toggleVim");
+                        },
+                        if vim_enabled { "VIM: ON" } else { "VIM: OFF" }
+                    }
+                    span {
+                        style: "font-size: 0.7rem; cursor: pointer; user-select: none; font-weight: 600;",
+                        title: "Toggle Emacs keybindings",
+                        onclick: move |evt| {
+                            evt.stop_propagation();
+                            run_editor_command("editor.toggleEmacs");
+                        },
+                        if emacs_enabled { "EMACS: ON" } else { "EMACS: OFF" }
+                    }
                 }
             }
         }
     }
 }
 
+// Buffer columns drawn of the long lines among `rows`, when the screen shows
+// `screen_columns`; empty when there are none
+fn long_line_window(
+    editor: &RopeEditor,
+    display: &DisplayMap,
+    rows: Range<usize>,
+    screen_columns: Range<usize>,
+) -> Range<usize> {
+    rows.map(|row| display.row(row))
+        .filter(|visual| editor.line_len(visual.line) > scroll::LONG_LINE)
+        .map(|visual| {
+            scroll::row_columns(
+                &visual,
+                &display.layout(visual.line),
+                screen_columns.clone(),
+                editor.line_len(visual.line),
+            )
+        })
+        .reduce(|window, columns| window.start.min(columns.start)..window.end.max(columns.end))
+        .unwrap_or(0..0)
+}
+
+// The code actions the server offers for chars `range` of `editor`'s file;
+// None without a running server to ask
+async fn code_actions_at(
+    editor: Signal<RopeEditor>,
+    range: Range<usize>,
+    only: Option<&'static str>,
+    invoked: bool,
+) -> Option<Vec<CodeAction>> {
+    let (path, params) = {
+        let editor_read = editor.peek();
+        lsp::flush_changes(&editor_read);
+        (
+            editor_read.file_path().clone(),
+            lsp::code_action_params(&editor_read, range, only, invoked),
+        )
+    };
+    let response = lsp::request(&path, "textDocument/codeAction", params)
+        .await?
+        .ok()?;
+    Some(code_actions::from_response(&response))
+}
+
+// Text of a row around the viewport: the whole line, or only the columns drawn
+// for long lines. Returns the text, the column it starts at and the columns to draw
+fn row_content(
+    editor: &RopeEditor,
+    display: &DisplayMap,
+    visual: &VisualRow,
+    screen_columns: Range<usize>,
+) -> (String, usize, Range<usize>) {
+    let line_len = editor.line_len(visual.line);
+    let columns = scroll::row_columns(
+        visual,
+        &display.layout(visual.line),
+        screen_columns,
+        line_len,
+    );
+    if line_len > scroll::LONG_LINE {
+        let line_start = editor.line_to_char(visual.line);
+        let content = editor.slice_text(line_start + columns.start, line_start + columns.end);
+        (content, columns.start, columns)
+    } else {
+        (editor.get_line(visual.line).unwrap_or_default(), 0, columns)
+    }
+}
+
+// Decorations of a row that fall among its drawn columns
+fn row_decorations(
+    display: &DisplayMap,
+    visual: &VisualRow,
+    columns: &Range<usize>,
+) -> Vec<InlineDecoration> {
+    display
+        .decorations()
+        .on_row(visual)
+        .iter()
+        .filter(|decoration| {
+            columns.contains(&decoration.column) || decoration.column == columns.end
+        })
+        .cloned()
+        .collect()
+}
+
+// Splits a char range into `(line, start_col, end_col)` spans clipped to the visible lines
+fn range_spans(
+    editor: &RopeEditor,
+    start: usize,
+    end: usize,
+    first_line: usize,
+    last_line: usize,
+) -> Vec<(usize, usize, usize)> {
+    let start_line = editor.char_to_line(start);
+    let end_line = editor.char_to_line(end);
+    (start_line.max(first_line)..=end_line.min(last_line))
+        .map(|line| {
+            let line_start = editor.line_to_char(line);
+            let len = editor.line_len(line);
+            let from = start.saturating_sub(line_start).min(len);
+            // Selected line breaks show as one extra column
+            let to = if line < end_line {
+                len + 1
+            } else {
+                (end - line_start).min(len)
+            };
+            (line, from, to.max(from))
+        })
+        .collect()
+}
+
 // Optimized line component with reduced re-renders
 #[component]
 fn OptimizedLineComponent(
     line_idx: usize,
+    /// Columns of the line shown on this row; the whole line unless soft wrapped
+    wrap_start: usize,
+    wrap_end: Option<usize>,
     top_val: f64,
+    /// Width of the line number column, from the font metrics
+    number_width: f64,
     line_content: String,
+    /// Column of the line `line_content` starts at; only long lines are fetched in part
+    content_start: usize,
+    /// Columns drawn: this row's part of the line around the viewport
+    draw_start: usize,
+    draw_end: usize,
+    /// Syntax highlights of the whole line
+    highlights: LineHighlights,
+    /// Where `draw_start` goes, from the left of the text area after scrolling
+    text_left: f64,
+    /// Where the line's tabs reach to
+    layout: LineLayout,
+    /// Drawn among the text without being part of it, in column order
+    decorations: Vec<InlineDecoration>,
     bg_color: &'static str,
     is_cursor_line: bool,
+    /// Some(collapsed) when a fold region starts on this line
+    fold: Option<bool>,
+    whitespace: WhitespaceMode,
+    /// Indent guides as (left, active), from the left of the text area after scrolling
+    indent_guides: Vec<(f64, bool)>,
+    bookmarked: bool,
+    /// The worst problem starting on this line, with the messages of all of them
+    diagnostic: Option<(Severity, String)>,
+    on_toggle_fold: EventHandler<usize>,
 ) -> Element {
-    const LINE_HEIGHT: f64 = 20.0;
+    let metrics = *METRICS.read();
+    let line_height = metrics.line_height;
+    let font = typography::font_css(&SETTINGS.read(), &metrics);
 
     rsx! {
         div {
-            style: "position: absolute; top: {top_val}px; left: 0; right: 0; height: {LINE_HEIGHT}px; display: flex; align-items: center; padding: 0 8px; font-family: 'Consolas', monospace; font-size: 14px; color: #d4d4d4; white-space: pre; background-color: {bg_color}; will-change: transform; transform: translateZ(0); contain: layout style paint;",
+            style: "position: absolute; top: {top_val}px; left: 0; right: 0; height: {line_height}px; display: flex; align-items: center; padding: 0 8px; {font} color: #d4d4d4; white-space: pre; background-color: {bg_color}; will-change: transform; transform: translateZ(0); contain: layout style paint;",
+
+            // Bookmark marker, at the left edge of the gutter
+            if bookmarked {
+                span {
+                    style: {
+                        let colors = use_theme().colors();
+                        format!("position: absolute; left: 2px; top: 50%; width: 6px; height: 6px; margin-top: -3px; border-radius: 1px; background-color: {}; pointer-events: none;", colors.accent)
+                    },
+                }
+            }
+
+            // Problem marker, hovered for the messages
+            if let Some((severity, messages)) = diagnostic {
+                span {
+                    title: "{messages}",
+                    style: {
+                        let colors = use_theme().colors();
+                        format!("position: absolute; left: 10px; top: 0; bottom: 0; display: flex; align-items: center; font-size: 9px; user-select: none; cursor: default; color: {};", severity_color(severity, &colors))
+                    },
+                    match severity {
+                        Severity::Error => "\u{25CF}",
+                        Severity::Warning => "\u{25B2}",
+                        Severity::Information | Severity::Hint => "\u{25C6}",
+                    }
+                }
+            }
 
             // Line number
             span {
                 style: {
                     let colors = use_theme().colors();
-                    format!("color: {}; margin-right: 14px; width: 32px; text-align: right; font-size: 14px; user-select: none; flex-shrink: 0; font-weight: 400; font-family: 'Consolas', 'Monaco', 'Courier New', monospace; line-height: {LINE_HEIGHT}px; display: flex; align-items: center; justify-content: flex-end;", colors.editor_line_number)
+                    format!("color: {}; width: {number_width}px; text-align: right; user-select: none; flex-shrink: 0; font-weight: 400; {font} display: flex; align-items: center; justify-content: flex-end;", colors.editor_line_number)
+                },
+                // Continuation rows of a wrapped line have no number
+                if wrap_start == 0 {
+                    "{line_idx + 1}"
+                }
+            }
+
+            // Fold marker, in the gap between the line number and the text
+            span {
+                style: {
+                    let colors = use_theme().colors();
+                    format!("width: {FOLD_MARKER_WIDTH}px; flex-shrink: 0; font-size: 10px; text-align: center; user-select: none; color: {}; cursor: {};", colors.editor_line_number, if fold.is_some() { "pointer" } else { "default" })
+                },
+                onclick: move |evt| {
+                    if fold.is_some() && wrap_start == 0 {
+                        evt.stop_propagation();
+                        on_toggle_fold.call(line_idx);
+                    }
                 },
-                "{line_idx + 1}"
+                match fold.filter(|_| wrap_start == 0) {
+                    Some(true) => "\u{25B8}",
+                    Some(false) => "\u{25BE}",
+                    None => "",
+                }
             }
 
-            // Line content with optimized rendering
-            // Render tokens with basic syntax highlighting
+            // Line content, coloured by its syntax highlights
             {
                 let colors = use_theme().colors();
-                let tokens = tokenize_line(&line_content);
+                let tokens = clip_tokens(
+                    syntax::line_tokens(&line_content, content_start, &highlights),
+                    draw_start - content_start,
+                    Some(draw_end - content_start),
+                );
+                let tokens = if whitespace == WhitespaceMode::None {
+                    tokens
+                } else {
+                    let trailing = content_start + line_content.trim_end_matches([' ', '\t']).chars().count();
+                    guides::show_whitespace(tokens, whitespace, trailing.saturating_sub(draw_start), TokenClass::Whitespace)
+                };
+                // Tabs are drawn as spaces, as the row may start between tab stops
+                let pieces = decorations::expand_tabs(decorations::interleave(tokens, draw_start, &decorations), draw_start, &layout);
                 rsx! {
                     span {
-                        style: "font-family: 'Consolas', 'Monaco', 'Courier New', monospace; white-space: pre; user-select: text; letter-spacing: 0; font-size: 14px; line-height: {LINE_HEIGHT}px; contain: layout style; flex: 1; display: flex; align-items: center;",
-                        for (text, class_) in tokens {
+                        style: "position: relative; flex: 1; align-self: stretch; overflow: hidden;",
+                        for (left, active) in indent_guides {
                             span {
-                                style: match class_ {
-                                    TokenClass::Keyword => format!("color: {};", colors.syntax_keyword),
-                                    TokenClass::String => format!("color: {};", colors.syntax_string),
-                                    TokenClass::Comment => format!("color: {}; font-style: italic;", colors.syntax_comment),
-                                    TokenClass::Number => format!("color: {};", colors.syntax_number),
-                                    TokenClass::Function => format!("color: {};", colors.syntax_function),
-                                    TokenClass::Plain => format!("color: {};", colors.text_primary),
-                                },
-                                "{text}"
+                                style: format!("position: absolute; left: {left}px; top: 0; bottom: 0; width: 1px; background-color: {}; opacity: {}; pointer-events: none;", if active { colors.text_muted } else { colors.border_primary }, if active { 0.8 } else { 0.5 }),
+                            }
+                        }
+                        span {
+                            style: "position: absolute; left: {text_left}px; top: 0; bottom: 0; {font} white-space: pre; user-select: text; letter-spacing: 0; contain: layout style; display: flex; align-items: center;",
+                            for piece in pieces {
+                                match piece {
+                                    Piece::Text(text, class_) => rsx! {
+                                        span {
+                                            style: match class_ {
+                                                TokenClass::Whitespace => format!("color: {}; opacity: 0.5;", token_color(class_, &colors)),
+                                                _ => format!("color: {}; {}", token_color(class_, &colors), token_font_style(class_, &colors).css()),
+                                            },
+                                            "{text}"
+                                        }
+                                    },
+                                    // Exactly as wide as the columns it takes, in a smaller font
+                                    Piece::Decoration(decoration) => {
+                                        let width = decoration.text.chars().count() as f64 * metrics.char_width;
+                                        let margin_left = if decoration.padding_left { metrics.char_width } else { 0.0 };
+                                        let margin_right = if decoration.padding_right { metrics.char_width } else { 0.0 };
+                                        let title = decoration.tooltip.clone().unwrap_or_else(|| match decoration.kind {
+                                            DecorationKind::Type => "Inferred type".to_string(),
+                                            DecorationKind::Parameter => "Parameter name".to_string(),
+                                            DecorationKind::Other => String::new(),
+                                        });
+                                        rsx! {
+                                            span {
+                                                title: "{title}",
+                                                style: "display: inline-block; flex-shrink: 0; width: {width}px; margin-left: {margin_left}px; margin-right: {margin_right}px; overflow: hidden; text-align: center; font-size: 0.85em; line-height: 1.3; border-radius: 3px; color: {colors.text_muted}; background-color: {colors.bg_secondary}; user-select: none; cursor: default;",
+                                                "{decoration.text}"
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            if fold == Some(true) && wrap_end.is_none() {
+                                span {
+                                    style: format!("margin-left: 6px; padding: 0 4px; border-radius: 3px; font-size: 11px; line-height: 14px; color: {}; background-color: {};", colors.text_muted, colors.bg_secondary),
+                                    "\u{22EF}"
+                                }
                             }
                         }
                     }
@@ -581,217 +3657,87 @@ fn OptimizedLineComponent(
     }
 }
 
-#[derive(Clone, Copy)]
-enum TokenClass {
-    Keyword,
-    String,
-    Comment,
-    Number,
-    Function,
-    Plain,
+fn token_color(class_: TokenClass, colors: &ThemeColors) -> &'static str {
+    match class_ {
+        TokenClass::Keyword => colors.syntax_keyword,
+        TokenClass::Type => colors.syntax_type,
+        TokenClass::Function => colors.syntax_function,
+        TokenClass::Macro => colors.syntax_macro,
+        TokenClass::String => colors.syntax_string,
+        TokenClass::Escape => colors.syntax_escape,
+        TokenClass::Number => colors.syntax_number,
+        TokenClass::Constant => colors.syntax_constant,
+        TokenClass::Comment => colors.syntax_comment,
+        TokenClass::DocComment => colors.syntax_doc_comment,
+        TokenClass::Lifetime => colors.syntax_lifetime,
+        TokenClass::Attribute => colors.syntax_attribute,
+        TokenClass::Operator => colors.syntax_operator,
+        TokenClass::Punctuation => colors.syntax_punctuation,
+        TokenClass::Parameter => colors.syntax_parameter,
+        TokenClass::Property => colors.syntax_property,
+        TokenClass::Plain => colors.text_primary,
+        TokenClass::Whitespace => colors.editor_line_number,
+    }
 }
 
-fn tokenize_line(line: &str) -> Vec<(String, TokenClass)> {
-    // Very simple, non-stateful tokenizer for common patterns
-    let mut out = Vec::new();
-    let mut chars = line.chars().peekable();
-
-    // Helpers
-    let is_ident_start = |c: char| c.is_ascii_alphabetic() || c == '_' || c == '$';
-    let is_ident_part = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
-
-    // Keyword sets combined for Rust/JS/TS/General
-    let keywords = [
-        // Rust
-        "fn",
-        "let",
-        "mut",
-        "struct",
-        "enum",
-        "impl",
-        "trait",
-        "pub",
-        "use",
-        "mod",
-        "match",
-        "if",
-        "else",
-        "while",
-        "for",
-        "in",
-        "loop",
-        "return",
-        "break",
-        "continue",
-        "const",
-        "static",
-        "crate",
-        "super",
-        "self",
-        "Self",
-        "as",
-        "where",
-        "type",
-        "move",
-        "ref",
-        "async",
-        "await",
-        "dyn",
-        "unsafe",
-        // JS/TS
-        "function",
-        "var",
-        "const",
-        "let",
-        "class",
-        "interface",
-        "extends",
-        "implements",
-        "import",
-        "from",
-        "export",
-        "return",
-        "if",
-        "else",
-        "for",
-        "while",
-        "do",
-        "switch",
-        "case",
-        "break",
-        "continue",
-        "new",
-        "this",
-        "super",
-        "try",
-        "catch",
-        "finally",
-        "throw",
-        "await",
-        "async",
-        "yield",
-        "typeof",
-        "instanceof",
-        "in",
-        "of",
-        "void",
-        "delete",
-        // General
-        "true",
-        "false",
-        "null",
-        "undefined",
-    ];
-
-    while let Some(&c) = chars.peek() {
-        // Comments
-        if c == '/' {
-            let mut it = chars.clone();
-            it.next();
-            if let Some('/') = it.next() {
-                // // comment
-                let mut text = String::new();
-                while let Some(ch) = chars.next() {
-                    text.push(ch);
-                }
-                out.push((text, TokenClass::Comment));
-                break;
-            }
-        }
-        if c == '#' {
-            // shell/py comment style
-            let mut text = String::new();
-            while let Some(ch) = chars.next() {
-                text.push(ch);
-            }
-            out.push((text, TokenClass::Comment));
-            break;
-        }
-
-        // Strings
-        if c == '"' || c == '\'' || c == '`' {
-            let quote = c;
-            let mut text = String::new();
-            text.push(chars.next().unwrap());
-            let mut escaped = false;
-            while let Some(ch) = chars.next() {
-                text.push(ch);
-                if escaped {
-                    escaped = false;
-                    continue;
-                }
-                if ch == '\\' {
-                    escaped = true;
-                    continue;
-                }
-                if ch == quote {
-                    break;
-                }
-            }
-            out.push((text, TokenClass::String));
-            continue;
-        }
+fn token_font_style(class_: TokenClass, colors: &ThemeColors) -> FontStyle {
+    match class_ {
+        TokenClass::Keyword => colors.syntax_keyword_style,
+        TokenClass::Type => colors.syntax_type_style,
+        TokenClass::Comment => colors.syntax_comment_style,
+        TokenClass::DocComment => colors.syntax_doc_comment_style,
+        TokenClass::Lifetime => colors.syntax_lifetime_style,
+        TokenClass::Parameter => colors.syntax_parameter_style,
+        _ => FontStyle::NORMAL,
+    }
+}
 
-        // Numbers
-        if c.is_ascii_digit() {
-            let mut text = String::new();
-            while let Some(&ch) = chars.peek() {
-                if ch.is_ascii_hexdigit()
-                    || ch == 'x'
-                    || ch == 'b'
-                    || ch == 'o'
-                    || ch == '_'
-                    || ch == '.'
-                {
-                    text.push(ch);
-                    chars.next();
-                } else {
-                    break;
-                }
-            }
-            out.push((text, TokenClass::Number));
-            continue;
+// Keeps the parts of `tokens` that fall within columns `start..end`
+fn clip_tokens(
+    tokens: Vec<(String, TokenClass)>,
+    start: usize,
+    end: Option<usize>,
+) -> Vec<(String, TokenClass)> {
+    let end = end.unwrap_or(usize::MAX);
+    let mut column = 0;
+    let mut clipped = Vec::new();
+    for (text, class_) in tokens {
+        let len = text.chars().count();
+        let (from, to) = (start.max(column), end.min(column + len));
+        if from < to {
+            let part: String = text.chars().skip(from - column).take(to - from).collect();
+            clipped.push((part, class_));
         }
+        column += len;
+    }
+    clipped
+}
 
-        // Identifiers / keywords / functions
-        if is_ident_start(c) {
-            let mut ident = String::new();
-            ident.push(chars.next().unwrap());
-            while let Some(&ch) = chars.peek() {
-                if is_ident_part(ch) {
-                    ident.push(ch);
-                    chars.next();
-                } else {
-                    break;
-                }
-            }
-
-            // Function heuristic: followed by '(' with no space (or with spaces)
-            let mut look = chars.clone();
-            let mut saw_ws = false;
-            while let Some(&ch) = look.peek() {
-                if ch.is_whitespace() {
-                    saw_ws = true;
-                    look.next();
-                } else {
-                    break;
-                }
-            }
-            let is_func = matches!(look.peek(), Some('('));
-
-            if keywords.contains(&ident.as_str()) {
-                out.push((ident, TokenClass::Keyword));
-            } else if is_func {
-                out.push((ident, TokenClass::Function));
-            } else {
-                out.push((ident, TokenClass::Plain));
-            }
-            continue;
+fn severity_color(severity: Severity, colors: &ThemeColors) -> &'static str {
+    match severity {
+        Severity::Error => colors.error,
+        Severity::Warning => colors.warning,
+        Severity::Information => colors.accent,
+        Severity::Hint => colors.text_muted,
+    }
+}
+fn completion_kind_color(kind: CompletionKind, colors: &ThemeColors) -> &'static str {
+    match kind {
+        CompletionKind::Method | CompletionKind::Function | CompletionKind::Constructor => {
+            colors.syntax_function
         }
-
-        // Single char fallback
-        out.push((chars.next().unwrap().to_string(), TokenClass::Plain));
+        CompletionKind::Class
+        | CompletionKind::Struct
+        | CompletionKind::Enum
+        | CompletionKind::Interface
+        | CompletionKind::TypeParameter => colors.syntax_type,
+        CompletionKind::Keyword | CompletionKind::Module => colors.syntax_keyword,
+        CompletionKind::Constant
+        | CompletionKind::EnumMember
+        | CompletionKind::Value
+        | CompletionKind::Unit => colors.syntax_constant,
+        CompletionKind::Field | CompletionKind::Property => colors.syntax_property,
+        CompletionKind::Variable | CompletionKind::Reference => colors.syntax_parameter,
+        _ => colors.text_secondary,
     }
-
-    out
 }
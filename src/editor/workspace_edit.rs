@@ -0,0 +1,731 @@
+use super::navigation::Location;
+use super::references;
+use super::rope_engine::RopeEditor;
+use crate::lsp;
+use crate::lsp::protocol::{self, Position};
+use dioxus::prelude::*;
+use ropey::Rope;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Edits a language server makes across files, for renames, code actions and
+// organize imports. Files open in an editor are edited in their buffers, each
+// as one undo step and left unsaved; the rest are rewritten on disk through a
+// temporary file, so that a failed write leaves them as they were. What was
+// changed is reported in the status bar through REPORT.
+
+const REPORT_TIME: Duration = Duration::from_secs(8);
+
+/// Replaces `start..end` with `text`, in the protocol's positions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    pub start: Position,
+    pub end: Position,
+    pub text: String,
+}
+
+/// The edits of one file, made together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileEdit {
+    pub path: PathBuf,
+    /// The version of the document the edits are for, when the server said
+    pub version: Option<i64>,
+    pub edits: Vec<TextEdit>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkspaceEdit {
+    pub files: Vec<FileEdit>,
+    /// Files to create, rename or delete, which aren't supported, such as
+    /// "create new.rs"
+    pub skipped: Vec<String>,
+}
+
+impl WorkspaceEdit {
+    /// Reads a WorkspaceEdit: its `documentChanges` in order, or else its
+    /// `changes` by file.
+    pub fn from_lsp(value: &Value) -> Self {
+        let mut edit = Self::default();
+        if let Some(changes) = value.get("documentChanges").and_then(Value::as_array) {
+            for change in changes {
+                let document = change.get("textDocument");
+                let path = document
+                    .and_then(|document| document["uri"].as_str())
+                    .and_then(protocol::uri_to_path);
+                match path {
+                    Some(path) => edit.files.push(FileEdit {
+                        path,
+                        version: document.and_then(|document| document["version"].as_i64()),
+                        edits: text_edits(&change["edits"]),
+                    }),
+                    None => edit.skipped.push(file_operation(change)),
+                }
+            }
+        } else if let Some(changes) = value.get("changes").and_then(Value::as_object) {
+            for (uri, edits) in changes {
+                if let Some(path) = protocol::uri_to_path(uri) {
+                    edit.files.push(FileEdit {
+                        path,
+                        version: None,
+                        edits: text_edits(edits),
+                    });
+                }
+            }
+            edit.files.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        edit.files.retain(|file| !file.edits.is_empty());
+        edit
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn edit_count(&self) -> usize {
+        self.files.iter().map(|file| file.edits.len()).sum()
+    }
+}
+
+// A CreateFile, RenameFile or DeleteFile, as the report names it
+fn file_operation(change: &Value) -> String {
+    let kind = change["kind"].as_str().unwrap_or("change");
+    let uri = change["uri"].as_str().or(change["oldUri"].as_str());
+    let name = uri
+        .and_then(protocol::uri_to_path)
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    format!("{kind} {name}").trim_end().to_string()
+}
+
+/// TextEdits from a list of them, such as a formatting answer.
+pub fn text_edits(value: &Value) -> Vec<TextEdit> {
+    let Some(edits) = value.as_array() else {
+        return Vec::new();
+    };
+    edits
+        .iter()
+        .filter_map(|edit| {
+            let range = edit.get("range")?;
+            Some(TextEdit {
+                start: Position::from_json(range.get("start")?)?,
+                end: Position::from_json(range.get("end")?)?,
+                text: edit.get("newText")?.as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The edits as char ranges of `text`, last first, so that making each leaves
+/// the ranges of those after it in place. Edits at the same place keep their
+/// order. Overlapping edits are refused.
+pub fn resolve(text: &Rope, edits: &[TextEdit]) -> Result<Vec<(Range<usize>, String)>, String> {
+    let mut resolved: Vec<(usize, Range<usize>, &str)> = edits
+        .iter()
+        .enumerate()
+        .map(|(index, edit)| {
+            let start = edit.start.to_char(text);
+            (
+                index,
+                start..edit.end.to_char(text).max(start),
+                edit.text.as_str(),
+            )
+        })
+        .collect();
+    resolved.sort_by_key(|(index, range, _)| (range.start, range.end, *index));
+    if resolved
+        .windows(2)
+        .any(|pair| pair[0].1.end > pair[1].1.start)
+    {
+        return Err("the edits overlap".to_string());
+    }
+    Ok(resolved
+        .into_iter()
+        .rev()
+        .map(|(_, range, text)| (range, text.to_string()))
+        .collect())
+}
+
+/// Where char `offset` is once `edits` from `resolve` are made. Inside a
+/// replaced range it stays as far into the new text as it can.
+pub fn moved(offset: usize, edits: &[(Range<usize>, String)]) -> usize {
+    let mut shift: isize = 0;
+    for (range, text) in edits.iter().rev() {
+        let inserted = text.chars().count();
+        if range.end <= offset && !(range.is_empty() && range.start == offset) {
+            shift += inserted as isize - range.len() as isize;
+        } else if range.start < offset {
+            return range.start.saturating_add_signed(shift) + (offset - range.start).min(inserted);
+        }
+    }
+    offset.saturating_add_signed(shift)
+}
+
+/// Makes `edits` in `text`.
+pub fn apply_to_text(text: &mut Rope, edits: &[TextEdit]) -> Result<(), String> {
+    for (range, new_text) in resolve(text, edits)? {
+        text.remove(range.clone());
+        text.insert(range.start, &new_text);
+    }
+    Ok(())
+}
+
+/// Makes edits from `resolve` in an open buffer as a single undo step,
+/// keeping its cursor on the text it was on.
+pub fn apply_to_buffer(editor: &mut RopeEditor, resolved: Vec<(Range<usize>, String)>) {
+    let cursor = moved(editor.get_cursor().byte_offset, &resolved);
+    editor.begin_undo_group();
+    editor.set_selection_anchor(None);
+    editor.set_block_selection(None);
+    for (range, text) in resolved {
+        if !range.is_empty() {
+            editor.delete_range(range.start, range.end);
+        }
+        editor.set_cursor_offset(range.start);
+        if !text.is_empty() {
+            editor.insert_text(&text);
+        }
+    }
+    editor.end_undo_group();
+    editor.set_cursor_offset(cursor);
+}
+
+/// Writes `text` over `path` by way of a temporary file beside it, so that
+/// the file is either left alone or replaced whole.
+pub fn write_atomically(path: &Path, text: &Rope) -> std::io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    let written = (|| {
+        let mut file = std::fs::File::create(&temporary)?;
+        text.write_to(&mut file)?;
+        file.sync_all()?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            std::fs::set_permissions(&temporary, metadata.permissions())?;
+        }
+        std::fs::rename(&temporary, path)
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    written
+}
+
+/// The buffers of an editor pane by file.
+pub type Buffers = Signal<HashMap<PathBuf, Signal<RopeEditor>>>;
+
+// Every pane's buffers. Edits go to every buffer of a file, as split panes
+// each have their own
+static BUFFERS: GlobalSignal<Vec<Buffers>> = Signal::global(Vec::new);
+
+/// Lets edits reach the buffers of an editor pane while it lives.
+pub fn register_buffers(buffers: Buffers) {
+    BUFFERS.write().push(buffers);
+}
+
+pub fn unregister_buffers(buffers: Buffers) {
+    BUFFERS.write().retain(|registered| *registered != buffers);
+}
+
+/// The buffers of `path` in every pane.
+pub fn open_buffers(path: &Path) -> Vec<Signal<RopeEditor>> {
+    BUFFERS
+        .peek()
+        .iter()
+        .filter_map(|buffers| buffers.peek().get(path).copied())
+        .collect()
+}
+
+/// What became of the edits of one file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// Made in the open buffers, which are left unsaved
+    Buffer,
+    /// Written to the file on disk
+    Written,
+    Failed(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileOutcome {
+    pub path: PathBuf,
+    pub edits: usize,
+    pub outcome: Outcome,
+}
+
+/// What applying a WorkspaceEdit changed, for telling the user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// What the edit was for, such as "Rename foo to bar"
+    pub title: String,
+    pub files: Vec<FileOutcome>,
+    /// The file operations left out
+    pub skipped: Vec<String>,
+}
+
+impl Summary {
+    /// One line, such as "Rename foo to bar: 7 edits in 3 files", naming the
+    /// files that failed and the file operations skipped.
+    pub fn headline(&self) -> String {
+        let (failed, changed): (Vec<&FileOutcome>, Vec<&FileOutcome>) = self
+            .files
+            .iter()
+            .partition(|file| matches!(file.outcome, Outcome::Failed(_)));
+        let edits: usize = changed.iter().map(|file| file.edits).sum();
+        let mut headline = match (edits, changed.len()) {
+            (0, _) => format!("{}: no changes", self.title),
+            (1, _) => format!("{}: 1 edit", self.title),
+            (edits, 1) => format!("{}: {edits} edits in 1 file", self.title),
+            (edits, files) => format!("{}: {edits} edits in {files} files", self.title),
+        };
+        if !failed.is_empty() {
+            let names: Vec<String> = failed
+                .iter()
+                .map(|file| {
+                    let name = file.path.file_name().unwrap_or(file.path.as_os_str());
+                    name.to_string_lossy().into_owned()
+                })
+                .collect();
+            headline.push_str(&format!(", failed: {}", names.join(", ")));
+        }
+        if !self.skipped.is_empty() {
+            headline.push_str(&format!(", skipped: {}", self.skipped.join(", ")));
+        }
+        headline
+    }
+
+    /// A line for each file, saying what was done to it.
+    pub fn details(&self) -> String {
+        let lines: Vec<String> = self
+            .files
+            .iter()
+            .map(|file| {
+                let edits = if file.edits == 1 {
+                    "1 edit".to_string()
+                } else {
+                    format!("{} edits", file.edits)
+                };
+                match &file.outcome {
+                    Outcome::Buffer => format!("{}: {edits}, unsaved", file.path.display()),
+                    Outcome::Written => format!("{}: {edits}, saved", file.path.display()),
+                    Outcome::Failed(error) => {
+                        format!("{}: not changed, {error}", file.path.display())
+                    }
+                }
+            })
+            .chain(self.skipped.iter().map(|operation| {
+                format!("{operation}: skipped, as files can't be created, renamed or deleted")
+            }))
+            .collect();
+        lines.join("\n")
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+            && self
+                .files
+                .iter()
+                .all(|file| !matches!(file.outcome, Outcome::Failed(_)))
+    }
+}
+
+/// Makes `edit` in the open buffers and on disk, file by file. Files whose
+/// text has moved on from the version the edit is for are left alone.
+pub fn apply(title: &str, edit: &WorkspaceEdit) -> Summary {
+    let files = edit
+        .files
+        .iter()
+        .map(|file| {
+            let outcome = match apply_file(file) {
+                Ok(outcome) => outcome,
+                Err(error) => Outcome::Failed(error),
+            };
+            FileOutcome {
+                path: file.path.clone(),
+                edits: file.edits.len(),
+                outcome,
+            }
+        })
+        .collect();
+    Summary {
+        title: title.to_string(),
+        files,
+        skipped: edit.skipped.clone(),
+    }
+}
+
+fn apply_file(file: &FileEdit) -> Result<Outcome, String> {
+    if let (Some(version), Some(current)) = (file.version, lsp::document_version(&file.path)) {
+        if version != current {
+            return Err("it changed since the edit was made".to_string());
+        }
+    }
+    let buffers = open_buffers(&file.path);
+    if !buffers.is_empty() {
+        // Every buffer takes the edits or none does, so that they stay alike
+        let resolved = buffers
+            .iter()
+            .map(|buffer| resolve(buffer.peek().rope(), &file.edits))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (mut buffer, resolved) in buffers.into_iter().zip(resolved) {
+            apply_to_buffer(&mut buffer.write(), resolved);
+        }
+        return Ok(Outcome::Buffer);
+    }
+    let mut text = std::fs::read_to_string(&file.path)
+        .map(|text| Rope::from_str(&text))
+        .map_err(|e| e.to_string())?;
+    apply_to_text(&mut text, &file.edits)?;
+    write_atomically(&file.path, &text).map_err(|e| e.to_string())?;
+    Ok(Outcome::Written)
+}
+
+/// The summary of the last edit, shown in the status bar for a while.
+pub static REPORT: GlobalSignal<Option<Summary>> = Signal::global(|| None);
+
+/// Shows `summary` in the status bar until it times out or another replaces it.
+/// One that left something undone stays until it is dismissed.
+pub fn report(summary: Summary) {
+    *REPORT.write() = Some(summary.clone());
+    if !summary.is_complete() {
+        return;
+    }
+    spawn(async move {
+        async_std::task::sleep(REPORT_TIME).await;
+        if REPORT.peek().as_ref() == Some(&summary) {
+            *REPORT.write() = None;
+        }
+    });
+}
+
+/// Applies `edit` and reports what it changed.
+pub fn apply_and_report(title: &str, edit: &WorkspaceEdit) -> Summary {
+    let summary = apply(title, edit);
+    report(summary.clone());
+    summary
+}
+
+/// One edit as the preview lists it: its line, with the replaced text and
+/// what replaces it. Line breaks in either show as ↵.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreviewRow {
+    pub location: Location,
+    pub before: String,
+    pub removed: String,
+    pub inserted: String,
+    pub after: String,
+}
+
+/// An edit waiting to be applied from the preview panel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub title: String,
+    /// Where it was asked for
+    pub origin: Location,
+    pub edit: WorkspaceEdit,
+    pub rows: Vec<PreviewRow>,
+}
+
+impl Preview {
+    pub fn new(title: String, origin: Location, edit: WorkspaceEdit) -> Self {
+        let mut rows = Vec::new();
+        for file in &edit.files {
+            let Some(text) = references::text_of(&file.path) else {
+                continue;
+            };
+            let Ok(resolved) = resolve(&text, &file.edits) else {
+                continue;
+            };
+            rows.extend(
+                resolved.iter().rev().map(|(range, inserted)| {
+                    preview_row(&text, &file.path, range.clone(), inserted)
+                }),
+            );
+        }
+        Self {
+            title,
+            origin,
+            edit,
+            rows,
+        }
+    }
+}
+
+fn preview_row(text: &Rope, path: &Path, range: Range<usize>, inserted: &str) -> PreviewRow {
+    let line = text.char_to_line(range.start);
+    let line_start = text.line_to_char(line);
+    let content = text.line(line).to_string();
+    let content = content.trim_end_matches(['\n', '\r']);
+    let indent = content.chars().take_while(|c| c.is_whitespace()).count();
+    let column = range.start - line_start;
+    let line_end = line_start + content.chars().count();
+    let shown = |text: &str| {
+        text.trim_end_matches(['\n', '\r'])
+            .replace("\r\n", "↵")
+            .replace('\n', "↵")
+    };
+    PreviewRow {
+        location: Location {
+            path: path.to_path_buf(),
+            line,
+            column,
+        },
+        before: content
+            .chars()
+            .skip(indent)
+            .take(column.saturating_sub(indent))
+            .collect(),
+        removed: shown(&text.slice(range.clone()).to_string()),
+        inserted: shown(inserted),
+        after: text.slice(range.end.min(line_end)..line_end).to_string(),
+    }
+}
+
+/// The edit waiting in the preview panel.
+pub static PREVIEW: GlobalSignal<Option<Preview>> = Signal::global(|| None);
+
+/// Shows `edit` in the preview panel, to be applied or discarded there.
+pub fn preview(title: String, origin: Location, edit: WorkspaceEdit) {
+    *PREVIEW.write() = Some(Preview::new(title, origin, edit));
+}
+
+/// Applies the edit in the preview panel and closes it.
+pub fn apply_preview() {
+    let Some(preview) = PREVIEW.write().take() else {
+        return;
+    };
+    apply_and_report(&preview.title, &preview.edit);
+}
+
+pub fn discard_preview() {
+    *PREVIEW.write() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn edit(start: (usize, usize), end: (usize, usize), text: &str) -> TextEdit {
+        TextEdit {
+            start: Position {
+                line: start.0,
+                character: start.1,
+            },
+            end: Position {
+                line: end.0,
+                character: end.1,
+            },
+            text: text.to_string(),
+        }
+    }
+
+    fn range(start: u64, end: u64) -> Value {
+        json!({ "start": { "line": 0, "character": start },
+                "end": { "line": 0, "character": end } })
+    }
+
+    // A folder of its own for each test, removed after it
+    fn folder() -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "workspace_edit_test_{}_{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn edits_resolve_last_first() {
+        let mut text = Rope::from_str("let foo = 1;\nfoo + foo\n");
+        let edits = [
+            edit((1, 6), (1, 9), "bar"),
+            edit((0, 4), (0, 7), "bar"),
+            edit((1, 0), (1, 3), "bar"),
+        ];
+        let resolved = resolve(&text, &edits).unwrap();
+        let ranges: Vec<Range<usize>> = resolved.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, [19..22, 13..16, 4..7]);
+        apply_to_text(&mut text, &edits).unwrap();
+        assert_eq!(text, "let bar = 1;\nbar + bar\n");
+
+        assert_eq!(
+            resolve(&text, &[edit((0, 0), (0, 5), ""), edit((0, 3), (0, 6), "")]),
+            Err("the edits overlap".to_string())
+        );
+        // Inserts at the same place keep their order
+        let mut text = Rope::from_str("x");
+        apply_to_text(
+            &mut text,
+            &[edit((0, 0), (0, 0), "a"), edit((0, 0), (0, 0), "b")],
+        )
+        .unwrap();
+        assert_eq!(text, "abx");
+    }
+
+    #[test]
+    fn offsets_move_with_the_edits() {
+        let text = Rope::from_str("let bar = 1;\n");
+        let resolved = resolve(&text, &[edit((0, 4), (0, 7), "longer")]).unwrap();
+        assert_eq!(moved(2, &resolved), 2);
+        assert_eq!(moved(10, &resolved), 13);
+        // Inside the replaced text, as far in as the new text goes
+        assert_eq!(moved(6, &resolved), 6);
+        let resolved = resolve(&text, &[edit((0, 4), (0, 7), "b")]).unwrap();
+        assert_eq!(moved(6, &resolved), 5);
+    }
+
+    #[test]
+    fn buffers_take_the_edits_as_one_undo_step() {
+        let mut editor = RopeEditor::new();
+        editor.insert_text("let foo = 1;\nfoo + foo\n");
+        editor.set_cursor_offset(21);
+        let edits = [
+            edit((0, 4), (0, 7), "renamed"),
+            edit((1, 6), (1, 9), "renamed"),
+        ];
+        let resolved = resolve(editor.rope(), &edits).unwrap();
+        apply_to_buffer(&mut editor, resolved);
+        assert_eq!(editor.get_content(), "let renamed = 1;\nfoo + renamed\n");
+        assert_eq!(editor.get_cursor().byte_offset, 25);
+        editor.undo();
+        assert_eq!(editor.get_content(), "let foo = 1;\nfoo + foo\n");
+    }
+
+    #[test]
+    fn workspace_edits_read_changes_and_document_changes() {
+        let changes = WorkspaceEdit::from_lsp(&json!({ "changes": {
+            "file:///tmp/b.rs": [{ "range": range(7, 10), "newText": "bar" }],
+            "file:///tmp/a.rs": [{ "range": range(3, 6), "newText": "bar" }],
+            "file:///tmp/c.rs": [],
+        }}));
+        let paths: Vec<&Path> = changes
+            .files
+            .iter()
+            .map(|file| file.path.as_path())
+            .collect();
+        assert_eq!(paths, [Path::new("/tmp/a.rs"), Path::new("/tmp/b.rs")]);
+        assert_eq!(changes.edit_count(), 2);
+
+        let documents = WorkspaceEdit::from_lsp(&json!({ "documentChanges": [
+            { "textDocument": { "uri": "file:///tmp/b.rs", "version": 4 },
+              "edits": [{ "range": range(7, 10), "newText": "bar" }] },
+            { "kind": "create", "uri": "file:///tmp/new.rs" },
+            { "kind": "rename", "oldUri": "file:///tmp/old.rs", "newUri": "file:///tmp/b.rs" },
+        ]}));
+        assert_eq!(documents.files[0].version, Some(4));
+        assert_eq!(documents.skipped, ["create new.rs", "rename old.rs"]);
+    }
+
+    #[test]
+    fn files_are_written_and_failures_named() {
+        let dir = folder();
+        let (a, b) = (dir.join("a.rs"), dir.join("b.rs"));
+        std::fs::write(&a, "fn foo() {}\n").unwrap();
+        std::fs::write(&b, "use a::foo;\n").unwrap();
+        let rename = WorkspaceEdit {
+            files: vec![
+                FileEdit {
+                    path: a.clone(),
+                    version: None,
+                    edits: vec![edit((0, 3), (0, 6), "bar")],
+                },
+                FileEdit {
+                    path: b.clone(),
+                    version: None,
+                    edits: vec![edit((0, 7), (0, 10), "bar")],
+                },
+            ],
+            skipped: Vec::new(),
+        };
+        let broken = WorkspaceEdit {
+            files: vec![FileEdit {
+                path: dir.join("missing/none.rs"),
+                version: None,
+                edits: vec![edit((0, 0), (0, 0), "x")],
+            }],
+            skipped: vec!["create new.rs".to_string()],
+        };
+
+        VirtualDom::new(|| rsx! {}).in_runtime(|| {
+            let summary = apply("Rename foo to bar", &rename);
+            assert!(summary.is_complete());
+            assert_eq!(summary.headline(), "Rename foo to bar: 2 edits in 2 files");
+
+            let summary = apply("Fix", &broken);
+            assert!(!summary.is_complete());
+            assert_eq!(
+                summary.headline(),
+                "Fix: no changes, failed: none.rs, skipped: create new.rs"
+            );
+            assert!(summary.details().ends_with(
+                "create new.rs: skipped, as files can't be created, renamed or deleted"
+            ));
+        });
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "fn bar() {}\n");
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "use a::bar;\n");
+        // No temporary files left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn every_buffer_of_a_file_takes_the_edits_or_none_does() {
+        let dir = folder();
+        let path = dir.join("a.rs");
+        std::fs::write(&path, "fn foo() {}\n").unwrap();
+        let dom = VirtualDom::new(|| rsx! {});
+        dom.in_runtime(|| {
+            ScopeId::ROOT.in_runtime(|| {
+                // Two panes with the file, one with an edit the other lacks
+                let buffers: Vec<Signal<RopeEditor>> = (0..2)
+                    .map(|_| {
+                        let mut editor = RopeEditor::new();
+                        editor.load_file(&path).unwrap();
+                        let editor = Signal::new(editor);
+                        register_buffers(Signal::new(HashMap::from([(path.clone(), editor)])));
+                        editor
+                    })
+                    .collect();
+                let mut edited = buffers[0];
+                edited.write().insert_text("\n\n");
+
+                // These overlap in the unedited text only
+                let clash = WorkspaceEdit {
+                    files: vec![FileEdit {
+                        path: path.clone(),
+                        version: None,
+                        edits: vec![edit((0, 0), (1, 0), "a"), edit((0, 5), (0, 6), "b")],
+                    }],
+                    skipped: Vec::new(),
+                };
+                let summary = apply("Clash", &clash);
+                assert_eq!(
+                    summary.files[0].outcome,
+                    Outcome::Failed("the edits overlap".to_string())
+                );
+                assert_eq!(edited.peek().get_content(), "\n\nfn foo() {}\n");
+                assert_eq!(buffers[1].peek().get_content(), "fn foo() {}\n");
+
+                let rename = WorkspaceEdit {
+                    files: vec![FileEdit {
+                        path: path.clone(),
+                        version: None,
+                        edits: vec![edit((0, 3), (0, 6), "bar")],
+                    }],
+                    skipped: Vec::new(),
+                };
+                assert_eq!(apply("Rename", &rename).files[0].outcome, Outcome::Buffer);
+                assert_eq!(edited.peek().get_content(), "bar\n\nfn foo() {}\n");
+                assert_eq!(buffers[1].peek().get_content(), "fn bar() {}\n");
+            })
+        });
+        // Left unsaved
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn foo() {}\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
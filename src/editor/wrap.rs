@@ -0,0 +1,570 @@
+use super::decorations::LineDecorations;
+use super::folding::FoldMap;
+use super::rope_engine::RopeEditor;
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+// Soft wrap. Long lines are split into visual rows; together with folding this
+// gives the rows the view actually draws and scrolls through. Every char takes
+// one screen column, except tabs, which reach to the next tab stop.
+
+// Narrower wrap widths make every line a tower of rows
+const MIN_WRAP_WIDTH: usize = 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrapMode {
+    #[default]
+    Off,
+    /// At the right edge of the editor
+    Viewport,
+    /// At `wrap_column`
+    Column,
+    /// At the first ruler, or `wrap_column` without rulers
+    Ruler,
+}
+
+impl WrapMode {
+    pub const ALL: [WrapMode; 4] = [
+        WrapMode::Off,
+        WrapMode::Viewport,
+        WrapMode::Column,
+        WrapMode::Ruler,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WrapMode::Off => "off",
+            WrapMode::Viewport => "viewport",
+            WrapMode::Column => "column",
+            WrapMode::Ruler => "ruler",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// One visual row: columns `start..end` of a buffer line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VisualRow {
+    pub line: usize,
+    pub start: usize,
+    /// None on the last row of a line
+    pub end: Option<usize>,
+    /// Columns of indentation drawn before a continuation row
+    pub indent: usize,
+}
+
+impl VisualRow {
+    fn whole_line(line: usize) -> Self {
+        Self {
+            line,
+            start: 0,
+            end: None,
+            indent: 0,
+        }
+    }
+
+    /// Screen column of `column`, counted from the start of the text area.
+    pub fn x_of(&self, layout: &LineLayout, column: usize) -> usize {
+        self.indent + layout.x_of(column) - layout.x_of(self.start.min(column))
+    }
+
+    /// Buffer column shown at screen column `x`, kept on this row.
+    pub fn column_at(&self, layout: &LineLayout, x: usize) -> usize {
+        let column = layout
+            .column_at(layout.x_of(self.start) + x.saturating_sub(self.indent))
+            .max(self.start);
+        match self.end {
+            // The end column itself is drawn at the start of the next row
+            Some(end) => column.min(end - 1),
+            None => column,
+        }
+    }
+}
+
+// Screen column after `c` when it starts at `x`
+fn advance(x: usize, c: char, tab_width: usize) -> usize {
+    match c {
+        '\t' => x + tab_width - x % tab_width,
+        _ => x + 1,
+    }
+}
+
+/// Where the chars of one line go on screen, from the columns of its tabs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineLayout {
+    tabs: Rc<[usize]>,
+    tab_width: usize,
+}
+
+impl LineLayout {
+    pub fn new(text: impl Iterator<Item = char>, tab_width: usize) -> Self {
+        let tabs = text
+            .take_while(|&c| c != '\n' && c != '\r')
+            .enumerate()
+            .filter(|&(_, c)| c == '\t')
+            .map(|(column, _)| column)
+            .collect();
+        Self {
+            tabs,
+            tab_width: tab_width.max(1),
+        }
+    }
+
+    // Screen column of each tab before `column`, with the tab's column
+    fn tabs_before(&self, column: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut x = 0;
+        let mut previous = 0;
+        self.tabs
+            .iter()
+            .take_while(move |&&tab| tab < column)
+            .map(move |&tab| {
+                let tab_x = x + tab - previous;
+                x = advance(tab_x, '\t', self.tab_width);
+                previous = tab + 1;
+                (tab, tab_x)
+            })
+    }
+
+    pub fn is_tab(&self, column: usize) -> bool {
+        self.tabs.binary_search(&column).is_ok()
+    }
+
+    /// Screen column `column` starts at, counted from the start of the line.
+    pub fn x_of(&self, column: usize) -> usize {
+        match self.tabs_before(column).last() {
+            Some((tab, x)) => advance(x, '\t', self.tab_width) + column - tab - 1,
+            None => column,
+        }
+    }
+
+    /// The column whose start is nearest screen column `x`; the middle of a
+    /// tab belongs to the column before it. Columns past the text are one wide.
+    pub fn column_at(&self, x: usize) -> usize {
+        let column = self.column_under(x);
+        let (left, right) = (self.x_of(column), self.x_of(column + 1));
+        if (x - left) * 2 > right - left {
+            column + 1
+        } else {
+            column
+        }
+    }
+
+    // The column drawn over screen column `x`
+    fn column_under(&self, x: usize) -> usize {
+        // Column and screen column just after the last tab passed
+        let mut after = (0, 0);
+        for (tab, tab_x) in self.tabs_before(usize::MAX) {
+            if tab_x > x {
+                break;
+            }
+            let end = advance(tab_x, '\t', self.tab_width);
+            if x < end {
+                return tab;
+            }
+            after = (tab + 1, end);
+        }
+        after.0 + x - after.1
+    }
+
+    /// Columns drawn at least partly within the screen columns `screen`.
+    pub fn columns_in(&self, screen: Range<usize>) -> Range<usize> {
+        if screen.is_empty() {
+            let column = self.column_under(screen.start);
+            return column..column;
+        }
+        self.column_under(screen.start)..self.column_under(screen.end - 1) + 1
+    }
+
+    /// Pads the tabs in `text`, drawn from `column` on, with spaces out to the
+    /// next tab stop, so they take the columns given here whatever the webview
+    /// makes of a tab. A tab shown as a glyph keeps it in front.
+    pub fn expand_tabs(&self, text: &str, column: usize) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        for (column, c) in (column..).zip(text.chars()) {
+            if self.is_tab(column) {
+                let width = self.x_of(column + 1) - self.x_of(column);
+                expanded.push(if c == '\t' { ' ' } else { c });
+                expanded.extend(std::iter::repeat_n(' ', width - 1));
+            } else {
+                expanded.push(c);
+            }
+        }
+        expanded
+    }
+}
+
+// Whether `chars`, up to the line ending, take at most `width` screen columns
+fn fits(chars: impl Iterator<Item = char>, width: usize, tab_width: usize) -> bool {
+    let mut x = 0;
+    for c in chars.take_while(|&c| c != '\n' && c != '\r') {
+        x = advance(x, c, tab_width);
+        if x > width {
+            return false;
+        }
+    }
+    true
+}
+
+/// Splits `text` into rows at most `width` screen columns wide, returning the
+/// first column of every row and the indentation of continuation rows. Breaks
+/// after a space where one is available.
+pub fn wrap_line(text: &str, width: usize, tab_width: usize) -> (Vec<usize>, usize) {
+    let chars: Vec<char> = text.trim_end_matches(['\n', '\r']).chars().collect();
+    let width = width.max(MIN_WRAP_WIDTH);
+    // Screen column each char starts at, then where the line ends
+    let mut xs = Vec::with_capacity(chars.len() + 1);
+    let mut x = 0;
+    for &c in &chars {
+        xs.push(x);
+        x = advance(x, c, tab_width.max(1));
+    }
+    xs.push(x);
+    if x <= width {
+        return (vec![0], 0);
+    }
+
+    // Continuation rows keep the line's indentation unless that leaves too little room
+    let indent = xs[chars.iter().take_while(|c| c.is_whitespace()).count()];
+    let indent = if indent <= width / 2 { indent } else { 0 };
+
+    let mut starts = vec![0];
+    let mut start = 0;
+    let mut available = width;
+    while xs[chars.len()] - xs[start] > available {
+        // The last column the row can end before, always past its first char
+        let limit = (xs.partition_point(|&x| x - xs[start] <= available) - 1).max(start + 1);
+        let break_at = (start + 1..=limit)
+            .rev()
+            .find(|&i| chars[i - 1] == ' ' && chars[i] != ' ')
+            .filter(|&i| xs[i] - xs[start] > available / 4)
+            .unwrap_or(limit);
+        starts.push(break_at);
+        start = break_at;
+        available = width - indent;
+    }
+    (starts, indent)
+}
+
+/// Maps between buffer positions and the rows drawn on screen, after folding,
+/// wrapping and inline decorations.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayMap {
+    folds: FoldMap,
+    /// Every row when wrapping; otherwise each visible line is one row
+    rows: Option<Vec<VisualRow>>,
+    /// Drawn among the text without wrapping it
+    decorations: Rc<LineDecorations>,
+    /// The text as the map was built, for where its tabs are
+    text: Rope,
+    tab_width: usize,
+    /// Layouts of the lines measured so far
+    layouts: RefCell<HashMap<usize, LineLayout>>,
+    /// What the map was built from, to tell when it is stale
+    revision: u64,
+    wrap_width: Option<usize>,
+}
+
+impl DisplayMap {
+    pub fn new(editor: &RopeEditor, folds: FoldMap, wrap_width: Option<usize>) -> Self {
+        let tab_width = editor.tab_width();
+        let rows = wrap_width.map(|width| {
+            let width = width.max(MIN_WRAP_WIDTH);
+            let mut rows = Vec::with_capacity(folds.visible_count());
+            for visible in 0..folds.visible_count() {
+                let line = folds.to_line(visible);
+                // Only lines that can wrap are worth fetching
+                let fits = editor.line_len(line) <= width / tab_width
+                    || fits(editor.line_chars(line), width, tab_width);
+                if fits {
+                    rows.push(VisualRow::whole_line(line));
+                    continue;
+                }
+                let text = editor.get_line(line).unwrap_or_default();
+                let (starts, indent) = wrap_line(&text, width, tab_width);
+                for (i, &start) in starts.iter().enumerate() {
+                    rows.push(VisualRow {
+                        line,
+                        start,
+                        end: starts.get(i + 1).copied(),
+                        indent: if i == 0 { 0 } else { indent },
+                    });
+                }
+            }
+            rows
+        });
+        Self {
+            folds,
+            rows,
+            decorations: Rc::default(),
+            text: editor.rope().clone(),
+            tab_width,
+            layouts: RefCell::default(),
+            revision: editor.revision(),
+            wrap_width,
+        }
+    }
+
+    pub fn with_decorations(self, decorations: Rc<LineDecorations>) -> Self {
+        Self {
+            decorations,
+            ..self
+        }
+    }
+
+    pub fn decorations(&self) -> &Rc<LineDecorations> {
+        &self.decorations
+    }
+
+    /// Where the chars of `line` go on screen.
+    pub fn layout(&self, line: usize) -> LineLayout {
+        let mut layouts = self.layouts.borrow_mut();
+        let layout = layouts.entry(line).or_insert_with(|| {
+            let text = (line < self.text.len_lines()).then(|| self.text.line(line).chars());
+            LineLayout::new(text.into_iter().flatten(), self.tab_width)
+        });
+        layout.clone()
+    }
+
+    /// Screen column of `column` on `row`, after the decorations before it.
+    pub fn x_of(&self, row: &VisualRow, column: usize) -> usize {
+        self.decorations.x_of(row, &self.layout(row.line), column)
+    }
+
+    /// Buffer column shown at screen column `x` of `row`.
+    pub fn column_at(&self, row: &VisualRow, x: usize) -> usize {
+        self.decorations.column_at(row, &self.layout(row.line), x)
+    }
+
+    /// Whether this map still describes `editor` with these folds and wrap width.
+    pub fn is_current(
+        &self,
+        editor: &RopeEditor,
+        folds: &FoldMap,
+        wrap_width: Option<usize>,
+    ) -> bool {
+        self.revision == editor.revision()
+            && self.tab_width == editor.tab_width()
+            && self.wrap_width == wrap_width
+            && &self.folds == folds
+    }
+
+    pub fn row_count(&self) -> usize {
+        match &self.rows {
+            Some(rows) => rows.len().max(1),
+            None => self.folds.visible_count(),
+        }
+    }
+
+    pub fn row(&self, row: usize) -> VisualRow {
+        match &self.rows {
+            Some(rows) => rows
+                .get(row)
+                .or(rows.last())
+                .copied()
+                .unwrap_or(VisualRow::whole_line(0)),
+            None => VisualRow::whole_line(self.folds.to_line(row)),
+        }
+    }
+
+    /// Row that shows `column` of `line`; hidden lines map to their fold's row.
+    pub fn row_of(&self, line: usize, column: usize) -> usize {
+        match &self.rows {
+            Some(rows) => rows
+                .partition_point(|row| (row.line, row.start) <= (line, column))
+                .saturating_sub(1),
+            None => self.folds.to_row(line),
+        }
+    }
+
+    /// Rows drawn for `line`; empty when the line is folded away.
+    pub fn rows_of_line(&self, line: usize) -> std::ops::Range<usize> {
+        match &self.rows {
+            Some(rows) => {
+                let first = rows.partition_point(|row| row.line < line);
+                let end = rows.partition_point(|row| row.line <= line);
+                first..end
+            }
+            None if self.folds.is_hidden(line) => 0..0,
+            None => {
+                let row = self.folds.to_row(line);
+                row..row + 1
+            }
+        }
+    }
+
+    /// Moves the cursor one row up or down, keeping its screen column. Returns
+    /// the cursor's new row.
+    pub fn move_vertically(&self, editor: &mut RopeEditor, down: bool) -> usize {
+        let cursor = *editor.get_cursor();
+        let row = self.row_of(cursor.line, cursor.column);
+        let target_row = if down {
+            (row + 1).min(self.row_count() - 1)
+        } else {
+            row.saturating_sub(1)
+        };
+        if target_row != row {
+            let x = self.x_of(&self.row(row), cursor.column);
+            let target = self.row(target_row);
+            editor.set_cursor(target.line, self.column_at(&target, x));
+        }
+        target_row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::folding::{self, FoldState};
+    #[test]
+    fn wrap_line_breaks_after_spaces_and_keeps_the_indent() {
+        let (starts, indent) = wrap_line("    aaaa bbbb cccc dddd eeee ffff gggg hhhh\n", 20, 4);
+        assert_eq!(indent, 4);
+        assert_eq!(starts, vec![0, 19, 34]);
+        assert_eq!(wrap_line(&"x".repeat(50), 20, 4), (vec![0, 20, 40], 0));
+        assert_eq!(wrap_line("short", 20, 4), (vec![0], 0));
+        // Widths below the minimum are raised to it
+        assert_eq!(wrap_line(&"x".repeat(30), 5, 4).0, vec![0, 20]);
+    }
+
+    #[test]
+    fn rows_of_wrapped_lines() {
+        // "word " ten times is 50 columns: three rows at width 20
+        let editor = RopeEditor::from_text(&format!("a\n{}\nb\n", "word ".repeat(10)));
+        let folds = FoldState::default().fold_map(editor.line_count());
+        let display = DisplayMap::new(&editor, folds, Some(20));
+        assert_eq!(display.row_count(), 6);
+        assert_eq!(display.rows_of_line(1), 1..4);
+        assert_eq!(display.row(2).start, 20);
+        assert_eq!(display.row(2).end, Some(40));
+        assert_eq!(display.row_of(1, 19), 1);
+        assert_eq!(display.row_of(1, 20), 2);
+        assert_eq!(display.row_of(1, 25), 2);
+        assert_eq!(display.row_of(2, 0), 4);
+    }
+
+    #[test]
+    fn rows_skip_folded_lines_when_wrapping() {
+        let long = "x".repeat(30);
+        let editor = RopeEditor::from_text(&format!("a:\n    {long}\n    b\nc {long}\n"));
+        let mut state = FoldState::default();
+        state.collapse(folding::region_at(&editor, 0).unwrap());
+        let folds = state.fold_map(editor.line_count());
+        let display = DisplayMap::new(&editor, folds.clone(), Some(20));
+        // a:, two rows of c, the empty last line
+        assert_eq!(display.row_count(), 4);
+        assert_eq!(display.rows_of_line(1), 1..1);
+        assert_eq!(display.rows_of_line(3), 1..3);
+        assert_eq!(display.row(1).line, 3);
+        assert_eq!(display.row_of(3, 25), 2);
+
+        let plain = DisplayMap::new(&editor, folds, None);
+        assert_eq!(plain.row_count(), 3);
+        assert_eq!(plain.rows_of_line(2), 0..0);
+        assert_eq!(plain.row_of(3, 25), 1);
+    }
+
+    #[test]
+    fn move_vertically_steps_through_wrapped_rows() {
+        let mut editor = RopeEditor::from_text(&format!("a\n{}\nb\n", "word ".repeat(10)));
+        let folds = FoldState::default().fold_map(editor.line_count());
+        let display = DisplayMap::new(&editor, folds.clone(), Some(20));
+        editor.set_cursor(0, 0);
+        assert_eq!(display.move_vertically(&mut editor, true), 1);
+        assert_eq!(display.move_vertically(&mut editor, true), 2);
+        assert_eq!(
+            (editor.get_cursor().line, editor.get_cursor().column),
+            (1, 20)
+        );
+        assert_eq!(display.move_vertically(&mut editor, true), 3);
+        assert_eq!(display.move_vertically(&mut editor, true), 4);
+        assert_eq!(editor.get_cursor().line, 2);
+        assert_eq!(display.move_vertically(&mut editor, false), 3);
+
+        assert!(display.is_current(&editor, &folds, Some(20)));
+        assert!(!display.is_current(&editor, &folds, Some(30)));
+        editor.insert_text("x");
+        assert!(!display.is_current(&editor, &folds, Some(20)));
+        let plain = DisplayMap::new(&editor, folds.clone(), None);
+        assert_eq!(plain.row_count(), 4);
+        editor.set_tab_width(8);
+        assert!(!plain.is_current(&editor, &folds, None));
+    }
+
+    #[test]
+    fn tabs_reach_to_the_next_tab_stop() {
+        let layout = LineLayout::new("a\tbc\t\td\n".chars(), 4);
+        let xs: Vec<usize> = (0..=8).map(|column| layout.x_of(column)).collect();
+        assert_eq!(xs, vec![0, 1, 4, 5, 6, 8, 12, 13, 14]);
+        // A click in the first half of a tab lands before it
+        assert_eq!(layout.column_at(0), 0);
+        assert_eq!(layout.column_at(2), 1);
+        assert_eq!(layout.column_at(3), 2);
+        assert_eq!(layout.column_at(4), 2);
+        assert_eq!(layout.column_at(9), 5);
+        assert_eq!(layout.column_at(11), 6);
+        assert_eq!(layout.column_at(20), 14);
+        // Tabs partly in view are drawn
+        assert_eq!(layout.columns_in(2..3), 1..2);
+        assert_eq!(layout.columns_in(7..10), 4..6);
+        assert_eq!(layout.columns_in(13..16), 7..10);
+        assert!(layout.is_tab(4) && layout.is_tab(5) && !layout.is_tab(6));
+
+        let plain = LineLayout::new("abc".chars(), 4);
+        assert_eq!((plain.x_of(2), plain.column_at(2)), (2, 2));
+        assert_eq!(plain.columns_in(1..5), 1..5);
+    }
+
+    #[test]
+    fn expand_tabs_pads_to_the_tab_stops_of_the_line() {
+        let layout = LineLayout::new("a\tb\t\tc".chars(), 4);
+        assert_eq!(layout.expand_tabs("a\tb", 0), "a   b");
+        assert_eq!(layout.expand_tabs("\t\tc", 3), "       c");
+        assert_eq!(layout.expand_tabs("c", 5), "c");
+        // A tab shown as a glyph keeps it at the start of the tab's columns
+        assert_eq!(layout.expand_tabs("a\u{2192}b", 0), "a\u{2192}  b");
+    }
+
+    #[test]
+    fn display_columns_follow_the_tab_width() {
+        let mut editor = RopeEditor::from_text("\tlet x;\n");
+        let folds = FoldState::default().fold_map(editor.line_count());
+        let display = DisplayMap::new(&editor, folds.clone(), None);
+        let row = display.row(0);
+        assert_eq!((display.x_of(&row, 1), display.x_of(&row, 2)), (4, 5));
+        assert_eq!(display.column_at(&row, 5), 2);
+
+        editor.set_tab_width(8);
+        let display = DisplayMap::new(&editor, folds, None);
+        let row = display.row(0);
+        assert_eq!(display.x_of(&row, 1), 8);
+        assert_eq!(display.column_at(&row, 3), 0);
+        assert_eq!(display.column_at(&row, 5), 1);
+    }
+
+    #[test]
+    fn wrap_line_measures_tabs() {
+        // Two tabs take 8 columns, so 13 letters fit on the first row of 20
+        let text = format!("\t\t{}", "x".repeat(30));
+        let (starts, indent) = wrap_line(&text, 20, 4);
+        assert_eq!(indent, 8);
+        assert_eq!(starts, vec![0, 14, 26]);
+
+        let editor = RopeEditor::from_text(&format!("{text}\n\tshort\n"));
+        let folds = FoldState::default().fold_map(editor.line_count());
+        let display = DisplayMap::new(&editor, folds, Some(20));
+        assert_eq!(display.rows_of_line(0), 0..3);
+        assert_eq!(display.rows_of_line(1), 3..4);
+        let second = display.row(1);
+        assert_eq!((second.start, second.indent), (14, 8));
+        assert_eq!(display.x_of(&second, 14), 8);
+        assert_eq!(display.x_of(&second, 20), 14);
+    }
+}
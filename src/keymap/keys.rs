@@ -0,0 +1,301 @@
+use dioxus::prelude::*;
+use std::fmt;
+
+// Context keys a `when` clause may refer to
+pub const CONTEXT_KEYS: &[&str] = &[
+    "editorFocus",
+    "terminalFocus",
+    "textInputFocus",
+    "editorHasSelection",
+    "editorHasBlockSelection",
+    "vimMode",
+    "emacsMode",
+];
+
+/// A single key press with its modifiers, e.g. `ctrl+shift+z`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyStroke {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub meta: bool,
+    pub key: String,
+}
+
+impl KeyStroke {
+    /// Returns `None` for presses of a bare modifier key.
+    pub fn from_event(evt: &KeyboardEvent) -> Option<Self> {
+        let key = match evt.key() {
+            Key::Character(s) if s == " " => "space".to_string(),
+            Key::Character(s) => s.to_lowercase(),
+            Key::Control
+            | Key::Shift
+            | Key::Alt
+            | Key::AltGraph
+            | Key::Meta
+            | Key::Super
+            | Key::Hyper
+            | Key::CapsLock
+            | Key::Unidentified => return None,
+            other => normalize_key_name(&other.to_string().to_lowercase()),
+        };
+        let modifiers = evt.modifiers();
+        Some(Self {
+            ctrl: modifiers.ctrl(),
+            shift: modifiers.shift(),
+            alt: modifiers.alt(),
+            meta: modifiers.meta(),
+            key,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase();
+        // A trailing "+" is the plus key itself, as in "ctrl++"
+        let (modifiers, key) = match text.strip_suffix("++") {
+            Some(rest) => (rest, "+".to_string()),
+            None => match text.rsplit_once('+') {
+                Some((rest, key)) => (rest, key.to_string()),
+                None => ("", text.clone()),
+            },
+        };
+        if key.is_empty() {
+            return Err(format!("missing key in `{}`", text));
+        }
+
+        let mut stroke = Self {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            meta: false,
+            key: normalize_key_name(&key),
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier {
+                "ctrl" | "control" => stroke.ctrl = true,
+                "shift" => stroke.shift = true,
+                "alt" | "option" => stroke.alt = true,
+                "meta" | "cmd" | "super" | "win" => stroke.meta = true,
+                other => return Err(format!("unknown modifier `{}` in `{}`", other, text)),
+            }
+        }
+        Ok(stroke)
+    }
+
+    /// Parses a space separated chord such as `ctrl+k ctrl+c`.
+    pub fn parse_sequence(text: &str) -> Result<Vec<Self>, String> {
+        let strokes = text
+            .split_whitespace()
+            .map(Self::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if strokes.is_empty() {
+            return Err("empty key binding".to_string());
+        }
+        Ok(strokes)
+    }
+}
+
+impl fmt::Display for KeyStroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "shift+")?;
+        }
+        if self.alt {
+            write!(f, "alt+")?;
+        }
+        if self.meta {
+            write!(f, "meta+")?;
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+pub fn format_sequence(strokes: &[KeyStroke]) -> String {
+    strokes
+        .iter()
+        .map(|stroke| stroke.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn normalize_key_name(name: &str) -> String {
+    let name = name.strip_prefix("arrow").unwrap_or(name);
+    match name {
+        "esc" => "escape",
+        "del" => "delete",
+        "return" => "enter",
+        "pgup" => "pageup",
+        "pgdn" => "pagedown",
+        "ins" => "insert",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Active context keys, checked against each binding's `when` clause.
+#[derive(Clone, Debug, Default)]
+pub struct KeyContext {
+    pub editor_focus: bool,
+    pub terminal_focus: bool,
+    pub has_selection: bool,
+    pub has_block_selection: bool,
+    pub vim_mode: bool,
+    pub emacs_mode: bool,
+    pub suggest_visible: bool,
+    pub in_snippet: bool,
+    pub peek_visible: bool,
+    pub parameter_hints_visible: bool,
+}
+
+impl KeyContext {
+    pub fn is_set(&self, name: &str) -> bool {
+        match name {
+            "editorFocus" => self.editor_focus,
+            "terminalFocus" => self.terminal_focus,
+            "textInputFocus" => self.editor_focus || self.terminal_focus,
+            "editorHasSelection" => self.has_selection,
+            "editorHasBlockSelection" => self.has_block_selection,
+            "vimMode" => self.vim_mode,
+            "emacsMode" => self.emacs_mode,
+            "suggestWidgetVisible" => self.suggest_visible,
+            "inSnippetMode" => self.in_snippet,
+            "peekVisible" => self.peek_visible,
+            "parameterHintsVisible" => self.parameter_hints_visible,
+            _ => false,
+        }
+    }
+}
+
+/// Parsed `when` clause: context keys combined with `!`, `&&`, `||` and parentheses.
+#[derive(Clone, Debug, PartialEq)]
+pub enum When {
+    Key(String),
+    Not(Box<When>),
+    And(Box<When>, Box<When>),
+    Or(Box<When>, Box<When>),
+}
+
+impl When {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut pos = 0;
+        let when = parse_or(&tokens, &mut pos)?;
+        if pos < tokens.len() {
+            return Err(format!("unexpected `{}` in `{}`", tokens[pos], text));
+        }
+        Ok(when)
+    }
+
+    pub fn matches(&self, context: &KeyContext) -> bool {
+        match self {
+            When::Key(name) => context.is_set(name),
+            When::Not(inner) => !inner.matches(context),
+            When::And(a, b) => a.matches(context) && b.matches(context),
+            When::Or(a, b) => a.matches(context) || b.matches(context),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '!' | '(' | ')' => tokens.push(c.to_string()),
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    return Err(format!("expected `{}{}` in `{}`", c, c, text));
+                }
+                tokens.push(format!("{}{}", c, c));
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '.' => {
+                let mut name = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || next == '.') {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                if !CONTEXT_KEYS.contains(&name.as_str()) {
+                    return Err(format!("unknown context key `{}`", name));
+                }
+                tokens.push(name);
+            }
+            other => return Err(format!("unexpected `{}` in `{}`", other, text)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> Result<When, String> {
+    let mut left = parse_and(tokens, pos)?;
+    while tokens.get(*pos).map(String::as_str) == Some("||") {
+        *pos += 1;
+        let right = parse_and(tokens, pos)?;
+        left = When::Or(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> Result<When, String> {
+    let mut left = parse_unary(tokens, pos)?;
+    while tokens.get(*pos).map(String::as_str) == Some("&&") {
+        *pos += 1;
+        let right = parse_unary(tokens, pos)?;
+        left = When::And(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> Result<When, String> {
+    let token = tokens.get(*pos).ok_or("unexpected end of when clause")?;
+    *pos += 1;
+    match token.as_str() {
+        "!" => Ok(When::Not(Box::new(parse_unary(tokens, pos)?))),
+        "(" => {
+            let inner = parse_or(tokens, pos)?;
+            if tokens.get(*pos).map(String::as_str) != Some(")") {
+                return Err("missing `)` in when clause".to_string());
+            }
+            *pos += 1;
+            Ok(inner)
+        }
+        "&&" | "||" | ")" => Err(format!("unexpected `{}` in when clause", token)),
+        name => Ok(When::Key(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_strokes() {
+        let stroke = |text| KeyStroke::parse(text).unwrap();
+        assert_eq!(stroke("Ctrl+Shift+Z").to_string(), "ctrl+shift+z");
+        assert_eq!(stroke("ctrl++").key, "+");
+        assert_eq!(stroke("esc").key, "escape");
+        assert!(KeyStroke::parse("hyper+x").is_err());
+    }
+
+    #[test]
+    fn when_clauses() {
+        let context = KeyContext {
+            editor_focus: true,
+            ..Default::default()
+        };
+        let when = When::parse("editorFocus && !(vimMode || emacsMode)").unwrap();
+        assert!(when.matches(&context));
+        assert!(!when.matches(&KeyContext {
+            vim_mode: true,
+            ..context
+        }));
+        assert!(When::parse("foo").is_err());
+        assert!(When::parse("editorFocus &&").is_err());
+    }
+}
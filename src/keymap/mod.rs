@@ -0,0 +1,684 @@
+mod keys;
+
+pub use keys::{format_sequence, KeyContext, KeyStroke, When};
+
+use crate::commands;
+use crate::editor::emacs::EMACS;
+use crate::editor::vim::VIM;
+use crate::utils::file_watcher::watch_file;
+use crate::utils::{config_dir, log};
+use dioxus::prelude::*;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+// Built-in bindings as (keys, command, when). User bindings from keybindings.toml
+// are appended after these, and later bindings win.
+const DEFAULT_BINDINGS: &[(&str, &str, Option<&str>)] = &[
+    // Editor
+    ("ctrl+s", "editor.save", Some("editorFocus")),
+    ("ctrl+z", "editor.undo", Some("editorFocus")),
+    ("ctrl+shift+z", "editor.redo", Some("editorFocus")),
+    ("ctrl+y", "editor.redo", Some("editorFocus")),
+    ("ctrl+x", "editor.cut", Some("editorFocus")),
+    ("ctrl+c", "editor.copy", Some("editorFocus")),
+    ("ctrl+v", "editor.paste", Some("editorFocus")),
+    ("ctrl+a", "selection.selectAll", Some("editorFocus")),
+    ("alt+shift+up", "selection.columnUp", Some("editorFocus")),
+    (
+        "alt+shift+down",
+        "selection.columnDown",
+        Some("editorFocus"),
+    ),
+    // Left and right grow a column selection once there is one, and the
+    // selection along the syntax tree otherwise
+    (
+        "alt+shift+left",
+        "selection.columnLeft",
+        Some("editorFocus && editorHasBlockSelection"),
+    ),
+    (
+        "alt+shift+right",
+        "selection.columnRight",
+        Some("editorFocus && editorHasBlockSelection"),
+    ),
+    (
+        "alt+shift+left",
+        "selection.shrink",
+        Some("editorFocus && !editorHasBlockSelection"),
+    ),
+    (
+        "alt+shift+right",
+        "selection.expand",
+        Some("editorFocus && !editorHasBlockSelection"),
+    ),
+    ("ctrl+alt+r", "macro.toggleRecording", Some("editorFocus")),
+    ("ctrl+alt+p", "macro.play", Some("editorFocus")),
+    ("ctrl+k ctrl+[", "editor.fold", Some("editorFocus")),
+    ("ctrl+k ctrl+]", "editor.unfold", Some("editorFocus")),
+    ("ctrl+k ctrl+l", "editor.toggleFold", Some("editorFocus")),
+    ("ctrl+k ctrl+0", "editor.foldAll", Some("editorFocus")),
+    ("ctrl+k ctrl+j", "editor.unfoldAll", Some("editorFocus")),
+    ("ctrl+k ctrl+1", "editor.foldLevel:1", Some("editorFocus")),
+    ("ctrl+k ctrl+2", "editor.foldLevel:2", Some("editorFocus")),
+    ("ctrl+k ctrl+3", "editor.foldLevel:3", Some("editorFocus")),
+    ("alt+z", "editor.toggleWordWrap", Some("editorFocus")),
+    ("ctrl+g", "editor.goToLine", Some("editorFocus")),
+    ("ctrl+shift+o", "editor.goToSymbol", Some("editorFocus")),
+    ("ctrl+/", "editor.toggleLineComment", Some("editorFocus")),
+    ("ctrl+k m", "editor.changeLanguage", Some("editorFocus")),
+    ("f12", "editor.goToDefinition", Some("editorFocus")),
+    ("alt+f12", "editor.peekDefinition", Some("editorFocus")),
+    ("ctrl+f12", "editor.goToImplementation", Some("editorFocus")),
+    ("shift+f12", "editor.findAllReferences", Some("editorFocus")),
+    ("ctrl+k ctrl+i", "editor.showHover", Some("editorFocus")),
+    ("f2", "editor.rename", Some("editorFocus")),
+    ("ctrl+.", "editor.quickFix", Some("editorFocus")),
+    ("shift+alt+o", "editor.organizeImports", Some("editorFocus")),
+    (
+        "escape",
+        "editor.closePeek",
+        Some("editorFocus && peekVisible"),
+    ),
+    ("alt+left", "navigation.back", Some("editorFocus")),
+    ("alt+right", "navigation.forward", Some("editorFocus")),
+    ("ctrl+alt+k", "editor.toggleBookmark", Some("editorFocus")),
+    ("ctrl+alt+l", "editor.nextBookmark", Some("editorFocus")),
+    ("ctrl+alt+j", "editor.previousBookmark", Some("editorFocus")),
+    ("up", "cursor.up", Some("editorFocus")),
+    ("down", "cursor.down", Some("editorFocus")),
+    ("left", "cursor.left", Some("editorFocus")),
+    ("right", "cursor.right", Some("editorFocus")),
+    ("home", "cursor.lineStart", Some("editorFocus")),
+    ("end", "cursor.lineEnd", Some("editorFocus")),
+    ("pageup", "editor.pageUp", Some("editorFocus")),
+    ("pagedown", "editor.pageDown", Some("editorFocus")),
+    ("backspace", "editor.deleteLeft", Some("editorFocus")),
+    ("delete", "editor.deleteRight", Some("editorFocus")),
+    ("enter", "editor.newline", Some("editorFocus")),
+    ("tab", "editor.tab", Some("editorFocus")),
+    // Completion and snippets, over the keys above while they're showing
+    ("ctrl+space", "editor.triggerSuggest", Some("editorFocus")),
+    (
+        "ctrl+shift+space",
+        "editor.triggerParameterHints",
+        Some("editorFocus"),
+    ),
+    (
+        "alt+down",
+        "parameterHints.next",
+        Some("editorFocus && parameterHintsVisible"),
+    ),
+    (
+        "alt+up",
+        "parameterHints.previous",
+        Some("editorFocus && parameterHintsVisible"),
+    ),
+    (
+        "escape",
+        "parameterHints.hide",
+        Some("editorFocus && parameterHintsVisible"),
+    ),
+    (
+        "down",
+        "suggest.next",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "up",
+        "suggest.previous",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "pagedown",
+        "suggest.nextPage",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "pageup",
+        "suggest.previousPage",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "enter",
+        "suggest.accept",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "tab",
+        "suggest.accept",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "escape",
+        "suggest.hide",
+        Some("editorFocus && suggestWidgetVisible"),
+    ),
+    (
+        "tab",
+        "snippet.next",
+        Some("editorFocus && inSnippetMode && !suggestWidgetVisible"),
+    ),
+    (
+        "shift+tab",
+        "snippet.previous",
+        Some("editorFocus && inSnippetMode && !suggestWidgetVisible"),
+    ),
+    (
+        "escape",
+        "snippet.exit",
+        Some("editorFocus && inSnippetMode && !suggestWidgetVisible"),
+    ),
+    // Terminal
+    ("ctrl+l", "terminal.clear", Some("terminalFocus")),
+    // Workbench
+    ("ctrl+shift+p", "workbench.showCommands", None),
+    ("f1", "workbench.showCommands", None),
+    ("ctrl+`", "workbench.toggleTerminal", None),
+    ("ctrl+b", "workbench.toggleSidebar", None),
+    ("ctrl+alt+b", "workbench.toggleRightSidebar", None),
+    ("ctrl+\\", "workbench.splitRight", None),
+    ("ctrl+pagedown", "workbench.nextEditor", None),
+    ("ctrl+pageup", "workbench.previousEditor", None),
+    ("ctrl+k ctrl+s", "workbench.openKeybindings", None),
+    ("ctrl+=", "editor.fontZoomIn", None),
+    ("ctrl+shift++", "editor.fontZoomIn", None),
+    ("ctrl+-", "editor.fontZoomOut", None),
+    ("ctrl+0", "editor.fontZoomReset", None),
+];
+
+const KEYBINDINGS_TEMPLATE: &str = r#"# Key bindings, applied on top of the built-in defaults.
+#
+# [[bindings]]
+# key = "ctrl+k ctrl+t"             # chords are space separated
+# command = "workbench.toggleTerminal"
+# when = "editorFocus && !vimMode"  # optional: editorFocus, terminalFocus,
+#                                   # textInputFocus, editorHasSelection,
+#                                   # editorHasBlockSelection, vimMode, emacsMode,
+#                                   # suggestWidgetVisible, inSnippetMode, peekVisible,
+#                                   # parameterHintsVisible
+#
+# A command prefixed with "-" removes a default binding:
+#
+# [[bindings]]
+# key = "ctrl+b"
+# command = "-workbench.toggleSidebar"
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyFocus {
+    None,
+    Editor,
+    Terminal,
+}
+
+pub static FOCUS: GlobalSignal<KeyFocus> = Signal::global(|| KeyFocus::None);
+
+pub static KEYMAP: GlobalSignal<KeymapState> = Signal::global(KeymapState::load);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Source {
+    Default,
+    User,
+}
+
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub keys: Vec<KeyStroke>,
+    pub command: String,
+    pub when: Option<When>,
+    when_text: Option<String>,
+    source: Source,
+}
+
+impl Binding {
+    fn parse(
+        keys: &str,
+        command: &str,
+        when: Option<&str>,
+        source: Source,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            keys: KeyStroke::parse_sequence(keys)?,
+            command: command.to_string(),
+            when: when.map(When::parse).transpose()?,
+            when_text: when.map(|w| w.split_whitespace().collect::<String>()),
+            source,
+        })
+    }
+
+    fn matches(&self, context: &KeyContext) -> bool {
+        self.when.as_ref().is_none_or(|when| when.matches(context))
+    }
+
+    // Identical or missing `when` clauses can both be active at once
+    fn overlaps(&self, other: &Binding) -> bool {
+        self.when_text.is_none() || other.when_text.is_none() || self.when_text == other.when_text
+    }
+}
+
+#[derive(Deserialize)]
+struct KeybindingsFile {
+    #[serde(default)]
+    bindings: Vec<BindingEntry>,
+}
+
+#[derive(Deserialize)]
+struct BindingEntry {
+    key: String,
+    command: String,
+    when: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyResolution {
+    Command(String),
+    /// The stroke started (or continued) a chord
+    Pending,
+    /// A chord was started but the stroke completed no binding
+    ChordAborted,
+    Unbound,
+}
+
+#[derive(Debug)]
+pub struct KeymapState {
+    path: PathBuf,
+    bindings: Vec<Binding>,
+    pending: Vec<KeyStroke>,
+    pub errors: Vec<String>,
+    pub conflicts: Vec<String>,
+}
+
+impl KeymapState {
+    pub fn load() -> Self {
+        let mut state = Self {
+            path: keybindings_path(),
+            bindings: Vec::new(),
+            pending: Vec::new(),
+            errors: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        state.reload();
+        state
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn reload(&mut self) {
+        self.pending.clear();
+        self.errors.clear();
+        self.bindings = DEFAULT_BINDINGS
+            .iter()
+            .filter_map(|(keys, command, when)| {
+                Binding::parse(keys, command, *when, Source::Default).ok()
+            })
+            .collect();
+
+        match std::fs::read_to_string(&self.path) {
+            Ok(text) => self.apply_user_bindings(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => self.errors.push(format!("{}: {}", self.path.display(), e)),
+        }
+
+        self.conflicts = find_conflicts(&self.bindings);
+        for problem in self.errors.iter().chain(&self.conflicts) {
+            log::write("Keybindings", problem);
+        }
+    }
+
+    fn apply_user_bindings(&mut self, text: &str) {
+        let file: KeybindingsFile = match toml::from_str(text) {
+            Ok(file) => file,
+            Err(e) => {
+                self.errors
+                    .push(format!("{}: {}", self.path.display(), e.message()));
+                return;
+            }
+        };
+
+        for entry in file.bindings {
+            if let Some(command) = entry.command.strip_prefix('-') {
+                // Removal entries match on command and keys, ignoring the when clause
+                match KeyStroke::parse_sequence(&entry.key) {
+                    Ok(keys) => self
+                        .bindings
+                        .retain(|b| !(b.command == command && b.keys == keys)),
+                    Err(e) => self.errors.push(format!("`{}`: {}", entry.key, e)),
+                }
+                continue;
+            }
+            if commands::find(&entry.command).is_none() {
+                self.errors.push(format!(
+                    "`{}`: unknown command {}",
+                    entry.key, entry.command
+                ));
+                continue;
+            }
+            match Binding::parse(
+                &entry.key,
+                &entry.command,
+                entry.when.as_deref(),
+                Source::User,
+            ) {
+                Ok(binding) => self.bindings.push(binding),
+                Err(e) => self
+                    .errors
+                    .push(format!("`{}` -> {}: {}", entry.key, entry.command, e)),
+            }
+        }
+    }
+
+    pub fn resolve(&mut self, stroke: KeyStroke, context: &KeyContext) -> KeyResolution {
+        let chord_started = !self.pending.is_empty();
+        let mut sequence = std::mem::take(&mut self.pending);
+        sequence.push(stroke);
+
+        let active = || self.bindings.iter().filter(|b| b.matches(context));
+        // A longer chord sharing this prefix takes precedence over an exact match
+        if active().any(|b| b.keys.len() > sequence.len() && b.keys.starts_with(&sequence)) {
+            self.pending = sequence;
+            return KeyResolution::Pending;
+        }
+        match active().rev().find(|b| b.keys == sequence) {
+            Some(binding) => KeyResolution::Command(binding.command.clone()),
+            None if chord_started => KeyResolution::ChordAborted,
+            None => KeyResolution::Unbound,
+        }
+    }
+
+    /// Status text while waiting for the rest of a chord.
+    pub fn pending_text(&self) -> Option<String> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "({}) waiting for next key...",
+                format_sequence(&self.pending)
+            ))
+        }
+    }
+
+    /// Key sequence currently bound to `command`, for display next to it.
+    pub fn keys_for(&self, command: &str) -> Option<String> {
+        self.bindings
+            .iter()
+            .rev()
+            .find(|b| b.command == command)
+            .map(|b| format_sequence(&b.keys))
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        self.errors.iter().chain(&self.conflicts).cloned().collect()
+    }
+
+    /// Creates the user keybindings file from a commented template if it doesn't exist yet.
+    pub fn ensure_user_file(&self) -> std::io::Result<()> {
+        if !self.path.exists() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&self.path, KEYBINDINGS_TEMPLATE)?;
+        }
+        Ok(())
+    }
+}
+
+fn find_conflicts(bindings: &[Binding]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for (i, a) in bindings.iter().enumerate() {
+        for b in &bindings[i + 1..] {
+            if !a.overlaps(b) || a.command == b.command {
+                continue;
+            }
+            if a.keys == b.keys {
+                // A user binding replacing a default is an intentional override
+                if a.source == b.source {
+                    conflicts.push(format!(
+                        "`{}` is bound to both {} and {}",
+                        format_sequence(&a.keys),
+                        a.command,
+                        b.command
+                    ));
+                }
+            } else if b.keys.starts_with(&a.keys) || a.keys.starts_with(&b.keys) {
+                let (short, long) = if a.keys.len() < b.keys.len() {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                conflicts.push(format!(
+                    "`{}` ({}) is shadowed by the chord `{}` ({})",
+                    format_sequence(&short.keys),
+                    short.command,
+                    format_sequence(&long.keys),
+                    long.command
+                ));
+            }
+        }
+    }
+    conflicts
+}
+
+pub fn keybindings_path() -> PathBuf {
+    config_dir().join("keybindings.toml")
+}
+
+/// Resolves a key event against the keymap. Events that resolve to a command or
+/// take part in a chord are consumed so they don't reach default handling.
+pub fn resolve_key_event(evt: &KeyboardEvent, context: &KeyContext) -> Option<String> {
+    let stroke = KeyStroke::from_event(evt)?;
+    let resolution = KEYMAP.write().resolve(stroke, context);
+    if resolution == KeyResolution::Unbound {
+        return None;
+    }
+    evt.prevent_default();
+    evt.stop_propagation();
+    match resolution {
+        KeyResolution::Command(command) => Some(command),
+        _ => None,
+    }
+}
+
+impl KeyContext {
+    /// Context from the current focus and keybinding profiles.
+    pub fn current() -> Self {
+        let focus = *FOCUS.read();
+        Self {
+            editor_focus: focus == KeyFocus::Editor,
+            terminal_focus: focus == KeyFocus::Terminal,
+            has_selection: false,
+            has_block_selection: false,
+            vim_mode: VIM.read().enabled,
+            emacs_mode: EMACS.read().enabled,
+            suggest_visible: false,
+            in_snippet: false,
+            peek_visible: false,
+            parameter_hints_visible: false,
+        }
+    }
+}
+
+/// Reloads the keymap whenever keybindings.toml changes on disk.
+pub fn use_keymap_watcher() {
+    use_hook(|| {
+        spawn(async move {
+            let path = KEYMAP.read().path().to_path_buf();
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            let _watcher = match watch_file(&path, move || {
+                let _ = tx.send(());
+            }) {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    log::write(
+                        "Keybindings",
+                        format!("Cannot watch {}: {}", path.display(), e),
+                    );
+                    return;
+                }
+            };
+            while rx.recv().await.is_some() {
+                // Editors write files in several steps; let them settle
+                async_std::task::sleep(std::time::Duration::from_millis(100)).await;
+                while rx.try_recv().is_ok() {}
+                KEYMAP.write().reload();
+            }
+        });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> KeyContext {
+        KeyContext {
+            editor_focus: true,
+            ..Default::default()
+        }
+    }
+
+    fn stroke(text: &str) -> KeyStroke {
+        KeyStroke::parse(text).unwrap()
+    }
+
+    fn command(name: &str) -> KeyResolution {
+        KeyResolution::Command(name.to_string())
+    }
+
+    // The defaults with `user` laid over them, as if read from the file
+    fn state(user: &str) -> KeymapState {
+        let mut state = KeymapState {
+            path: PathBuf::from("/nonexistent/keybindings.toml"),
+            bindings: Vec::new(),
+            pending: Vec::new(),
+            errors: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        state.reload();
+        state.apply_user_bindings(user);
+        state.conflicts = find_conflicts(&state.bindings);
+        state
+    }
+
+    #[test]
+    fn chords() {
+        let mut state = state("");
+        assert_eq!(
+            state.resolve(stroke("ctrl+k"), &context()),
+            KeyResolution::Pending
+        );
+        assert!(state.pending_text().is_some());
+        assert_eq!(
+            state.resolve(stroke("ctrl+s"), &context()),
+            command("workbench.openKeybindings")
+        );
+        assert_eq!(
+            state.resolve(stroke("ctrl+k"), &context()),
+            KeyResolution::Pending
+        );
+        assert_eq!(
+            state.resolve(stroke("x"), &context()),
+            KeyResolution::ChordAborted
+        );
+        assert_eq!(
+            state.resolve(stroke("ctrl+s"), &context()),
+            command("editor.save")
+        );
+        assert_eq!(
+            state.resolve(stroke("ctrl+s"), &KeyContext::default()),
+            KeyResolution::Unbound
+        );
+        assert!(state.conflicts.is_empty(), "{:?}", state.conflicts);
+    }
+
+    #[test]
+    fn alt_shift_arrows_grow_a_column_selection_once_there_is_one() {
+        let mut state = state("");
+        assert_eq!(
+            state.resolve(stroke("alt+shift+down"), &context()),
+            command("selection.columnDown")
+        );
+        assert_eq!(
+            state.resolve(stroke("alt+shift+right"), &context()),
+            command("selection.expand")
+        );
+        let block = KeyContext {
+            has_block_selection: true,
+            ..context()
+        };
+        assert_eq!(
+            state.resolve(stroke("alt+shift+right"), &block),
+            command("selection.columnRight")
+        );
+        assert_eq!(
+            state.resolve(stroke("alt+shift+left"), &block),
+            command("selection.columnLeft")
+        );
+    }
+
+    #[test]
+    fn user_bindings_override_and_conflict() {
+        let mut state = state(
+            r#"
+[[bindings]]
+key = "ctrl+s"
+command = "workbench.toggleTerminal"
+when = "editorFocus"
+
+[[bindings]]
+key = "ctrl+b"
+command = "-workbench.toggleSidebar"
+
+[[bindings]]
+key = "ctrl+k"
+command = "workbench.toggleSidebar"
+
+[[bindings]]
+key = "ctrl+j"
+command = "editor.undo"
+
+[[bindings]]
+key = "ctrl+j"
+command = "editor.redo"
+
+[[bindings]]
+key = "ctrl+q"
+command = "editor.copy"
+when = "bogus"
+"#,
+        );
+        assert_eq!(
+            state.resolve(stroke("ctrl+s"), &context()),
+            command("workbench.toggleTerminal")
+        );
+        assert_eq!(
+            state.resolve(stroke("ctrl+b"), &context()),
+            KeyResolution::Unbound
+        );
+        assert!(
+            state.conflicts.iter().any(|c| c.contains("shadowed")),
+            "{:?}",
+            state.conflicts
+        );
+        assert_eq!(
+            state
+                .conflicts
+                .iter()
+                .filter(|c| c.contains("both"))
+                .count(),
+            1
+        );
+        assert_eq!(state.errors.len(), 1, "{:?}", state.errors);
+    }
+
+    #[test]
+    fn a_broken_file_keeps_the_defaults() {
+        let mut state = state("not toml [");
+        assert_eq!(state.errors.len(), 1);
+        assert_eq!(
+            state.resolve(stroke("ctrl+z"), &context()),
+            command("editor.undo")
+        );
+    }
+}
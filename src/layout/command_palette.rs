@@ -0,0 +1,215 @@
+use crate::commands::{self, fuzzy_score, PaletteMode, COMMANDS, PALETTE, RECENT_COMMANDS};
+use crate::keymap::KEYMAP;
+use crate::settings::SETTINGS;
+use crate::theme::use_theme;
+use dioxus::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+struct PaletteItem {
+    /// Command to run, possibly with an argument
+    id: String,
+    label: String,
+    keys: Option<String>,
+    recent: bool,
+}
+
+// Registry commands plus one entry per saved macro
+fn command_items() -> Vec<PaletteItem> {
+    let keymap = KEYMAP.read();
+    let registered = COMMANDS
+        .iter()
+        .filter(|command| command.in_palette)
+        .map(|command| PaletteItem {
+            id: command.id.to_string(),
+            label: command.label(),
+            keys: keymap.keys_for(command.id),
+            recent: false,
+        });
+    let saved_macros = SETTINGS
+        .read()
+        .macros
+        .keys()
+        .map(|name| PaletteItem {
+            id: format!("macro.run:{}", name),
+            label: format!("Macro: Run \"{}\"", name),
+            keys: None,
+            recent: false,
+        })
+        .collect::<Vec<_>>();
+    registered.chain(saved_macros).collect()
+}
+
+fn rank(items: Vec<PaletteItem>, query: &str) -> Vec<PaletteItem> {
+    let mut scored: Vec<(i32, PaletteItem)> = items
+        .into_iter()
+        .filter_map(|item| fuzzy_score(query, &item.label).map(|score| (score, item)))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+#[component]
+pub fn CommandPalette() -> Element {
+    let mut query = use_signal(String::new);
+    let mut selected = use_signal(|| 0usize);
+    let colors = use_theme().colors();
+    let Some(mode) = PALETTE.read().clone() else {
+        return rsx! {};
+    };
+
+    let items: Vec<PaletteItem> = match &mode {
+        PaletteMode::Commands => {
+            let mut items = command_items();
+            if query().trim().is_empty() {
+                // Recently used commands lead the list until something is typed
+                let recent = RECENT_COMMANDS.read();
+                let mut leading: Vec<PaletteItem> = recent
+                    .iter()
+                    .filter_map(|id| items.iter().find(|item| &item.id == id).cloned())
+                    .map(|item| PaletteItem {
+                        recent: true,
+                        ..item
+                    })
+                    .collect();
+                items.retain(|item| !recent.contains(&item.id));
+                leading.extend(items);
+                leading
+            } else {
+                rank(items, &query())
+            }
+        }
+        PaletteMode::Input {
+            command,
+            suggestions,
+            ..
+        } => {
+            let items = suggestions
+                .iter()
+                .map(|suggestion| PaletteItem {
+                    id: format!("{}:{}", command, suggestion),
+                    label: suggestion.clone(),
+                    keys: None,
+                    recent: false,
+                })
+                .collect();
+            rank(items, &query())
+        }
+    };
+    let selected_index = selected().min(items.len().saturating_sub(1));
+    let placeholder = match &mode {
+        PaletteMode::Commands => "Type a command...".to_string(),
+        PaletteMode::Input { prompt, .. } => prompt.clone(),
+    };
+
+    let mut close = move || {
+        *PALETTE.write() = None;
+        query.set(String::new());
+        selected.set(0);
+    };
+    let mut run = move |id: String| {
+        close();
+        if !id.contains(':') || id.starts_with("macro.run:") {
+            commands::record_recent(&id);
+        }
+        commands::execute(&id);
+    };
+
+    let key_items = items.clone();
+    let key_mode = mode.clone();
+
+    rsx! {
+        // Backdrop closes the palette on click
+        div {
+            style: "position: fixed; inset: 0; z-index: 2000; display: flex; justify-content: center; align-items: flex-start; padding-top: 60px; background-color: rgba(0,0,0,0.2);",
+            onclick: move |_| close(),
+
+            div {
+                style: "width: 560px; max-width: 90vw; background-color: {colors.bg_secondary}; border: 1px solid {colors.border_primary}; border-radius: 6px; box-shadow: 0 8px 24px rgba(0,0,0,0.4); overflow: hidden;",
+                onclick: move |evt| evt.stop_propagation(),
+
+                input {
+                    style: "width: 100%; padding: 8px 10px; background-color: {colors.bg_primary}; color: {colors.text_primary}; border: none; border-bottom: 1px solid {colors.border_primary}; outline: none; font-size: 0.85rem;",
+                    r#type: "text",
+                    value: query(),
+                    placeholder: "{placeholder}",
+                    onmounted: move |evt| async move {
+                        let _ = evt.set_focus(true).await;
+                    },
+                    oninput: move |evt| {
+                        query.set(evt.value());
+                        selected.set(0);
+                    },
+                    onkeydown: move |evt| {
+                        let count = key_items.len();
+                        match evt.key() {
+                            Key::ArrowDown if count > 0 => {
+                                evt.prevent_default();
+                                evt.stop_propagation();
+                                selected.set((selected_index + 1) % count);
+                            }
+                            Key::ArrowUp if count > 0 => {
+                                evt.prevent_default();
+                                evt.stop_propagation();
+                                selected.set((selected_index + count - 1) % count);
+                            }
+                            Key::Enter => {
+                                evt.prevent_default();
+                                evt.stop_propagation();
+                                if let Some(item) = key_items.get(selected_index) {
+                                    run(item.id.clone());
+                                } else if let PaletteMode::Input { command, .. } = &key_mode {
+                                    // Free-form input when nothing is suggested
+                                    let value = query();
+                                    if !value.trim().is_empty() {
+                                        run(format!("{}:{}", command, value.trim()));
+                                    }
+                                }
+                            }
+                            Key::Escape => {
+                                evt.prevent_default();
+                                evt.stop_propagation();
+                                close();
+                            }
+                            _ => {}
+                        }
+                    },
+                }
+
+                div {
+                    style: "max-height: 400px; overflow-y: auto; padding: 4px 0;",
+                    if items.is_empty() && mode == PaletteMode::Commands {
+                        div {
+                            style: "padding: 6px 12px; font-size: 0.8rem; color: {colors.text_muted};",
+                            "No matching commands"
+                        }
+                    }
+                    for (index, item) in items.into_iter().enumerate() {
+                        div {
+                            key: "{item.id}",
+                            style: format!(
+                                "padding: 5px 12px; font-size: 0.8rem; color: {}; cursor: pointer; display: flex; align-items: center; justify-content: space-between; gap: 12px; background-color: {};",
+                                colors.text_primary,
+                                if index == selected_index { colors.bg_accent } else { "transparent" }
+                            ),
+                            onmouseenter: move |_| selected.set(index),
+                            onclick: {
+                                let id = item.id.clone();
+                                move |_| run(id.clone())
+                            },
+                            span { "{item.label}" }
+                            span {
+                                style: "display: flex; gap: 8px; font-size: 0.7rem; color: {colors.text_muted};",
+                                if item.recent && index == 0 {
+                                    span { "recently used" }
+                                }
+                                if let Some(keys) = &item.keys {
+                                    span { "{keys}" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -106,9 +106,21 @@ fn IconButton(
 ) -> Element {
     let is_active = active_panel().map_or(false, |p| p == panel_type);
     let colors = use_theme().colors();
-    let bg_color = if is_active { colors.bg_primary } else { "transparent" };
-    let border = if is_active { format!("2px solid {}", colors.accent) } else { "2px solid transparent".to_string() };
-    let color = if is_active { colors.text_primary } else { colors.text_muted };
+    let bg_color = if is_active {
+        colors.bg_primary
+    } else {
+        "transparent"
+    };
+    let border = if is_active {
+        format!("2px solid {}", colors.accent)
+    } else {
+        "2px solid transparent".to_string()
+    };
+    let color = if is_active {
+        colors.text_primary
+    } else {
+        colors.text_muted
+    };
 
     rsx! {
         div {
@@ -1,7 +1,11 @@
+use crate::editor::language::LANGUAGE_OVERRIDES;
+use crate::editor::workspace_edit;
 use crate::editor::{RopeEditor, VirtualEditorView};
 use crate::layout::tab_bar::TabBar;
 use crate::layout::OpenFile;
+use crate::lsp;
 use crate::theme::use_theme;
+use crate::utils::log;
 use dioxus::prelude::*;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -18,6 +22,10 @@ pub fn MainContent(
 ) -> Element {
     let mut editors = use_signal(|| HashMap::<PathBuf, Signal<RopeEditor>>::new());
 
+    // Edits from language servers, such as renames, reach open files through their buffers
+    use_hook(move || workspace_edit::register_buffers(editors));
+    use_drop(move || workspace_edit::unregister_buffers(editors));
+
     // Clean up editors for closed files
     use_effect(move || {
         let files = open_files();
@@ -26,7 +34,29 @@ pub fn MainContent(
         // Remove editors for files that are no longer open
         let open_paths: std::collections::HashSet<PathBuf> =
             files.iter().map(|f| f.path.clone()).collect();
-        editors_map.retain(|path, _| open_paths.contains(path));
+        let mut closed = Vec::new();
+        editors_map.retain(|path, _| {
+            let open = open_paths.contains(path);
+            if !open {
+                closed.push(path.clone());
+            }
+            open
+        });
+        drop(editors_map);
+        // The server keeps a file while a buffer in the other pane has it
+        for path in closed {
+            if workspace_edit::open_buffers(&path).is_empty() {
+                lsp::close_document(&path);
+            }
+        }
+    });
+
+    // Keeps the language servers up to date with every buffer
+    use_effect(move || {
+        let workspace = PathBuf::from(workspace_path());
+        for editor in editors.read().values() {
+            lsp::sync_document(&editor.read(), &workspace);
+        }
     });
 
     // Load editor for new files
@@ -39,8 +69,11 @@ pub fn MainContent(
                 if !editors.peek().contains_key(&path) {
                     let mut editor = RopeEditor::new();
                     if let Err(e) = editor.load_file(&path) {
-                        eprintln!("Failed to load file {}: {}", path.display(), e);
+                        log::write("Files", format!("Failed to load {}: {}", path.display(), e));
                     } else {
+                        if let Some(language) = LANGUAGE_OVERRIDES.peek().get(&path) {
+                            editor.set_language(language);
+                        }
                         let editor_signal = Signal::new(editor);
                         editors.write().insert(path, editor_signal);
                     }
@@ -53,11 +86,58 @@ pub fn MainContent(
         if let Some(mut editor_signal) = editors.read().get(&path).cloned() {
             let mut editor = editor_signal.write();
             if let Err(e) = editor.save_file() {
-                eprintln!("Failed to save file: {}", e);
+                log::write("Files", format!("Failed to save {}: {}", path.display(), e));
+            } else {
+                lsp::did_save(&path);
             }
         }
     };
 
+    let mut close_file = move |path: PathBuf| {
+        let mut files = open_files.write();
+        if let Some(index) = files.iter().position(|f| f.path == path) {
+            files.remove(index);
+
+            // Update active index
+            if files.is_empty() {
+                active_file_index.set(None);
+            } else if let Some(active_idx) = active_file_index() {
+                if index < active_idx {
+                    // The active file moved one tab to the left
+                    active_file_index.set(Some(active_idx - 1));
+                } else if active_idx >= files.len() {
+                    active_file_index.set(Some(files.len() - 1));
+                } else if active_idx == index && index > 0 {
+                    active_file_index.set(Some(index - 1));
+                }
+            }
+        }
+    };
+
+    // The buffer moves to the file it was saved as, and its tab with it. A tab
+    // already open on that file shows the buffer in place of its own.
+    let mut handle_save_as = move |path: PathBuf, new_path: PathBuf| {
+        let Some(mut editor_signal) = editors.read().get(&path).cloned() else {
+            return;
+        };
+        if let Err(e) = editor_signal.write().save_as(new_path.clone()) {
+            log::write(
+                "Files",
+                format!("Failed to save {}: {}", new_path.display(), e),
+            );
+            return;
+        }
+        let mut editors_map = editors.write();
+        editors_map.remove(&path);
+        editors_map.insert(new_path.clone(), editor_signal);
+        drop(editors_map);
+        if open_files.read().iter().any(|file| file.path == new_path) {
+            close_file(path);
+        } else if let Some(file) = open_files.write().iter_mut().find(|file| file.path == path) {
+            file.path = new_path;
+        }
+    };
+
     // If no files are open, show welcome screen with TabBar
     if open_files.read().is_empty() {
         return rsx! {
@@ -187,11 +267,15 @@ pub fn MainContent(
 
         let editor_content = if let Some(editor_sig) = editor_signal {
             let path_clone = file.path.clone();
+            let close_path = file.path.clone();
+            let save_as_path = file.path.clone();
             rsx! {
                 VirtualEditorView {
                     key: "{file.path.to_string_lossy()}",
                     editor: editor_sig,
                     on_save: move |_| handle_save(path_clone.clone()),
+                    on_save_as: move |new_path| handle_save_as(save_as_path.clone(), new_path),
+                    on_close: move |_| close_file(close_path.clone()),
                 }
             }
         } else {
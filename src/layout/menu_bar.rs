@@ -1,3 +1,5 @@
+use crate::commands::{self, Menu};
+use crate::keymap::KEYMAP;
 use crate::theme::{use_theme, IconTheme, Theme};
 use dioxus::desktop::use_window;
 use dioxus::prelude::*;
@@ -10,11 +12,6 @@ pub fn MenuBar(
     on_toggle_terminal: EventHandler<()>,
     right_sidebar_visible: Signal<bool>,
     on_toggle_right_sidebar: EventHandler<()>,
-    on_open_file: EventHandler<String>,
-    on_open_folder: EventHandler<String>,
-    on_new_file: EventHandler<()>,
-    on_save_file: EventHandler<()>,
-    on_save_as: EventHandler<()>,
 ) -> Element {
     let mut theme_dropdown_visible = use_signal(|| false);
     let mut icon_dropdown_visible = use_signal(|| false);
@@ -42,45 +39,8 @@ pub fn MenuBar(
                 // Non-draggable menu items
                 div {
                     style: "display: flex; align-items: center; gap: 15px;",
-                    MenuBarItem {
-                        label: "File".to_string(),
-                        on_open_file: on_open_file,
-                        on_open_folder: on_open_folder,
-                        on_new_file: on_new_file,
-                        on_save_file: on_save_file,
-                        on_save_as: on_save_as,
-                    }
-                    MenuBarItem {
-                        label: "Edit".to_string(),
-                        on_open_file: on_open_file,
-                        on_open_folder: on_open_folder,
-                        on_new_file: on_new_file,
-                        on_save_file: on_save_file,
-                        on_save_as: on_save_as,
-                    }
-                    MenuBarItem {
-                        label: "View".to_string(),
-                        on_open_file: on_open_file,
-                        on_open_folder: on_open_folder,
-                        on_new_file: on_new_file,
-                        on_save_file: on_save_file,
-                        on_save_as: on_save_as,
-                    }
-                    MenuBarItem {
-                        label: "Selection".to_string(),
-                        on_open_file: on_open_file,
-                        on_open_folder: on_open_folder,
-                        on_new_file: on_new_file,
-                        on_save_file: on_save_file,
-                        on_save_as: on_save_as,
-                    }
-                    MenuBarItem {
-                        label: "Help".to_string(),
-                        on_open_file: on_open_file,
-                        on_open_folder: on_open_folder,
-                        on_new_file: on_new_file,
-                        on_save_file: on_save_file,
-                        on_save_as: on_save_as,
+                    for menu in Menu::ALL {
+                        MenuBarItem { key: "{menu.label()}", menu: menu }
                     }
                 }
 
@@ -242,36 +202,23 @@ pub fn MenuBar(
     }
 }
 
-// In menu_bar.rs, update the MenuBarItem component:
-
-// In menu_bar.rs, update the MenuBarItem component to avoid capturing window in the outer closure:
-
+// Menu items come from the command registry
 #[component]
-fn MenuBarItem(
-    label: String,
-    on_open_file: EventHandler<String>,
-    on_open_folder: EventHandler<String>,
-    on_new_file: EventHandler<()>,
-    on_save_file: EventHandler<()>,
-    on_save_as: EventHandler<()>,
-) -> Element {
+fn MenuBarItem(menu: Menu) -> Element {
     let mut is_hovered = use_signal(|| false);
     let mut is_dropdown_open = use_signal(|| false);
     let colors = use_theme().colors();
-    // Remove window from here and get it inside the specific closure where needed
-
-    // Define dropdown items (for "File" only)
-    let dropdown_items = match label.as_str() {
-        "File" => Some(vec![
-            "New File",
-            "Open File",
-            "Open Folder",
-            "Save",
-            "Save As...",
-            "Exit",
-        ]),
-        _ => None,
-    };
+    let label = menu.label();
+
+    // Each item with whether a separator goes above it
+    let mut previous_group = None;
+    let items: Vec<_> = commands::menu_commands(menu)
+        .map(|command| {
+            let separated = previous_group.is_some_and(|group| group != command.group);
+            previous_group = Some(command.group);
+            (command, separated)
+        })
+        .collect();
 
     rsx! {
         div {
@@ -289,71 +236,46 @@ fn MenuBarItem(
                 onmouseleave: move |_| is_hovered.set(false),
                 onclick: move |evt| {
                     evt.stop_propagation();
-                    if dropdown_items.is_some() {
-                        is_dropdown_open.set(!is_dropdown_open());
-                    }
+                    is_dropdown_open.set(!is_dropdown_open());
                 },
                 "{label}"
             }
 
-            // Dropdown menu (for File)
             if is_dropdown_open() {
                 div {
                     style: format!(
                         "position: absolute; top: 100%; left: 0; background-color: {}; \
-                         border: 1px solid {}; border-radius: 4px; min-width: 140px; \
-                         z-index: 1000; box-shadow: 0 4px 8px rgba(0,0,0,0.3);",
+                         border: 1px solid {}; border-radius: 4px; min-width: 220px; \
+                         z-index: 1000; box-shadow: 0 4px 8px rgba(0,0,0,0.3); padding: 4px 0;",
                         colors.bg_secondary, colors.border_primary
                     ),
-                    for item in dropdown_items.clone().unwrap_or_default() {
+                    for (command, separated) in items {
+                        if separated {
+                            div {
+                                key: "{command.id}_separator",
+                                style: "height: 1px; margin: 4px 0; background-color: {colors.border_primary};",
+                            }
+                        }
                         div {
-                            key: "{item}",
+                            key: "{command.id}",
                             style: format!(
                                 "padding: 6px 12px; font-size: 0.8rem; color: {}; \
-                                 cursor: pointer; user-select: none; \
-                                 &:hover {{ background-color: {}; }}",
-                                colors.text_primary,
-                                colors.bg_accent
+                                 cursor: pointer; user-select: none; display: flex; \
+                                 justify-content: space-between; gap: 24px;",
+                                colors.text_primary
                             ),
-                            onmouseenter: move |_| {},
                             onclick: move |evt| {
                                 evt.stop_propagation();
                                 is_dropdown_open.set(false);
-
-                                // Handle each menu action
-                                match item {
-                                    "New File" => {
-                                        on_new_file.call(());
-                                    }
-                                    "Open File" => {
-                                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                            if let Some(path_str) = path.to_str() {
-                                                on_open_file.call(path_str.to_string());
-                                            }
-                                        }
-                                    }
-                                    "Open Folder" => {
-                                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                                            if let Some(path_str) = path.to_str() {
-                                                on_open_folder.call(path_str.to_string());
-                                            }
-                                        }
-                                    }
-                                    "Save" => {
-                                        on_save_file.call(());
-                                    }
-                                    "Save As..." => {
-                                        on_save_as.call(());
-                                    }
-                                    "Exit" => {
-                                        // FIX: Get window inside this specific closure
-                                        let window = use_window();
-                                        window.close();
-                                    }
-                                    _ => {}
-                                }
+                                commands::execute(command.id);
                             },
-                            "{item}"
+                            span { "{command.title}" }
+                            if let Some(keys) = KEYMAP.read().keys_for(command.id) {
+                                span {
+                                    style: "font-size: 0.7rem; color: {colors.text_muted};",
+                                    "{keys}"
+                                }
+                            }
                         }
                     }
                 }
@@ -412,4 +334,4 @@ fn WindowControls() -> Element {
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,24 @@
-use crate::theme::use_theme;
+use crate::commands::{self, Target, PALETTE};
+use crate::components::edit_preview_panel::EditPreviewPanel;
+use crate::components::outline_panel::OutlinePanel;
+use crate::components::references_panel::ReferencesPanel;
+use crate::editor::navigation::{self, PENDING_JUMP};
+use crate::editor::references::REFERENCES;
+use crate::editor::typography;
+use crate::editor::workspace_edit::PREVIEW;
+use crate::keymap::{self, resolve_key_event, KeyContext, KeyFocus, FOCUS, KEYMAP};
+use crate::lsp;
+use crate::theme::{use_theme, IconTheme, Theme};
+use crate::utils::log;
 use dioxus::prelude::*;
+mod command_palette;
 mod icon_strip;
 mod main_content;
 mod menu_bar;
 mod sidebar;
 mod tab_bar;
 
+use command_palette::CommandPalette;
 use icon_strip::{IconStrip, PanelType};
 use main_content::MainContent;
 use menu_bar::MenuBar;
@@ -34,7 +47,8 @@ pub fn Layout() -> Element {
     // SEPARATE state for the right split pane
     let mut right_pane_file_index = use_signal(|| None::<usize>);
 
-    let colors = use_theme().colors();
+    let mut theme_context = use_theme();
+    let colors = theme_context.colors();
 
     let mut workspace_path = use_signal(|| {
         std::env::current_dir()
@@ -44,7 +58,7 @@ pub fn Layout() -> Element {
     });
 
     // File operation handlers
-    let on_open_file = move |path: String| {
+    let mut on_open_file = move |path: String| {
         let path_buf = PathBuf::from(path);
 
         let mut files = open_files.write();
@@ -56,29 +70,163 @@ pub fn Layout() -> Element {
         }
     };
 
-    let on_open_folder = move |path: String| {
-        println!("Setting workspace to: {}", path);
+    let mut on_open_folder = move |path: String| {
+        log::write("Workspace", format!("Opening {path}"));
+        lsp::shutdown_all();
         workspace_path.set(path);
         open_files.write().clear();
         active_file_index.set(None);
         right_pane_file_index.set(None);
     };
 
-    let on_new_file = move |_: ()| {
-        println!("Creating new file");
+    // Asks where the file goes, creates it empty and opens it. Picking a file
+    // that exists opens it as it is.
+    let mut on_new_file = move || {
+        let mut dialog = rfd::FileDialog::new();
+        if !workspace_path.read().is_empty() {
+            dialog = dialog.set_directory(workspace_path());
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        let created = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path);
+        match created {
+            Ok(_) => on_open_file(path.to_string_lossy().to_string()),
+            Err(e) => log::write(
+                "Files",
+                format!("Failed to create {}: {}", path.display(), e),
+            ),
+        }
     };
 
-    let on_save_file = move |_: ()| {
-        println!("Saving current file");
-    };
+    keymap::use_keymap_watcher();
+    lsp::use_language_servers();
+    typography::use_font_metrics();
 
-    let on_save_as = move |_: ()| {
-        println!("Save as dialog");
+    // Handlers for the registry's workbench commands, those that need the
+    // layout's own state
+    let mut run_command = move |command: &str| match command {
+        "file.newFile" => on_new_file(),
+        "file.openFile" => {
+            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                if let Some(path_str) = path.to_str() {
+                    on_open_file(path_str.to_string());
+                }
+            }
+        }
+        "file.openFolder" => {
+            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                if let Some(path_str) = path.to_str() {
+                    on_open_folder(path_str.to_string());
+                }
+            }
+        }
+        "app.exit" => {
+            let window = dioxus::desktop::use_window();
+            lsp::shutdown_all();
+            window.close();
+        }
+        "workbench.toggleActivityBar" => {
+            strip_visible.set(!strip_visible());
+            if !strip_visible() {
+                active_panel.set(None);
+            } else {
+                active_panel.set(Some(PanelType::Files));
+            }
+        }
+        "workbench.toggleTerminal" => terminal_visible.set(!terminal_visible()),
+        "workbench.toggleSidebar" => {
+            if active_panel().is_some() {
+                active_panel.set(None);
+            } else {
+                active_panel.set(Some(PanelType::Files));
+            }
+        }
+        "workbench.toggleRightSidebar" => right_sidebar_visible.set(!right_sidebar_visible()),
+        "workbench.splitRight" => {
+            is_split_horizontal.set(true);
+            right_pane_file_index.set(active_file_index());
+        }
+        "workbench.closeSplit" => is_split_horizontal.set(false),
+        "workbench.nextEditor" | "workbench.previousEditor" => {
+            let count = open_files.read().len();
+            if let (Some(index), true) = (active_file_index(), count > 0) {
+                let next = if command == "workbench.nextEditor" {
+                    (index + 1) % count
+                } else {
+                    (index + count - 1) % count
+                };
+                active_file_index.set(Some(next));
+            }
+        }
+        "workbench.openKeybindings" => {
+            let (path, created) = {
+                let keymap = KEYMAP.read();
+                (keymap.path().to_path_buf(), keymap.ensure_user_file())
+            };
+            match created {
+                Ok(()) => on_open_file(path.to_string_lossy().to_string()),
+                Err(e) => log::write(
+                    "Keybindings",
+                    format!("Failed to create {}: {}", path.display(), e),
+                ),
+            }
+        }
+        "theme.vscode" => theme_context.current_theme.set(Theme::VSCode),
+        "theme.gruvbox" => theme_context.current_theme.set(Theme::Gruvbox),
+        "theme.atom" => theme_context.current_theme.set(Theme::Atom),
+        "theme.monokai" => theme_context.current_theme.set(Theme::Monokai),
+        "iconTheme.vscode" => theme_context.current_icon_theme.set(IconTheme::VSCode),
+        "iconTheme.material" => theme_context.current_icon_theme.set(IconTheme::Material),
+        "iconTheme.gruvbox" => theme_context.current_icon_theme.set(IconTheme::Gruvbox),
+        "iconTheme.atom" => theme_context.current_icon_theme.set(IconTheme::Atom),
+        // With a file open, its editor takes the bookmark so the jump can
+        // be undone with Go Back
+        "navigation.listBookmarks" => {
+            let target = if open_files.read().is_empty() {
+                "navigation.openBookmark"
+            } else {
+                "editor.openBookmark"
+            };
+            commands::prompt("Bookmark to go to", target, navigation::bookmark_list());
+        }
+        other => log::write(
+            "Commands",
+            format!("The workbench has no handler for {other}"),
+        ),
     };
 
+    use_effect(move || {
+        for command in commands::take_queued(Target::Workbench) {
+            run_command(&command);
+        }
+    });
+
+    // Jumps to another file open it; its editor then moves the cursor
+    use_effect(move || {
+        let Some(path) = PENDING_JUMP.read().as_ref().map(|jump| jump.path.clone()) else {
+            return;
+        };
+        let active = active_file_index()
+            .and_then(|index| open_files.peek().get(index).map(|file| file.path.clone()));
+        if active.as_ref() != Some(&path) {
+            on_open_file(path.to_string_lossy().to_string());
+        }
+    });
+
     rsx! {
         div {
-            style: "height: 100vh; width: 100vw; display: flex; flex-direction: column; overflow: hidden; background-color: {colors.bg_primary};",
+            style: "height: 100vh; width: 100vw; display: flex; flex-direction: column; overflow: hidden; outline: none; background-color: {colors.bg_primary};",
+            // Focusable so global shortcuts work when nothing else has focus
+            tabindex: -1,
+            onkeydown: move |evt| {
+                if let Some(command) = resolve_key_event(&evt, &KeyContext::current()) {
+                    commands::execute(&command);
+                }
+            },
             onmousemove: move |evt| {
                 if is_resizing() {
                     let new_width = evt.client_coordinates().x - 40.0;
@@ -108,11 +256,9 @@ pub fn Layout() -> Element {
                 on_toggle_right_sidebar: move |_| {
                     right_sidebar_visible.set(!right_sidebar_visible());
                 },
-                on_open_file: EventHandler::new(on_open_file),
-                on_open_folder: EventHandler::new(on_open_folder),
-                on_new_file: EventHandler::new(on_new_file),
-                on_save_file: EventHandler::new(on_save_file),
-                on_save_as: EventHandler::new(on_save_as),
+            }
+            if PALETTE.read().is_some() {
+                CommandPalette {}
             }
             div {
                 style: "flex: 1; display: flex; flex-direction: row; position: relative; overflow: visible; min-height: 0; height: calc(100vh - 30px);",
@@ -158,7 +304,7 @@ pub fn Layout() -> Element {
                     // Main content area
                     if !is_split_horizontal() {
                         div {
-                            style: if terminal_visible() { "flex: 1; display: flex; flex-direction: column; min-height: 0;" } else { "flex: 1; display: flex; flex-direction: column; height: 100%;" },
+                            style: if terminal_visible() || REFERENCES.read().is_some() || PREVIEW.read().is_some() { "flex: 1; display: flex; flex-direction: column; min-height: 0;" } else { "flex: 1; display: flex; flex-direction: column; height: 100%;" },
                             MainContent {
                                 open_files: open_files,
                                 active_file_index: active_file_index,
@@ -215,6 +361,16 @@ pub fn Layout() -> Element {
                         }
                     }
 
+                    // Results of the last references search, under the editors
+                    if REFERENCES.read().is_some() {
+                        ReferencesPanel { workspace_path: workspace_path }
+                    }
+
+                    // An edit across files, such as a rename, waiting to be applied
+                    if PREVIEW.read().is_some() {
+                        EditPreviewPanel { workspace_path: workspace_path }
+                    }
+
                     // Terminal panel at bottom
                     if terminal_visible() {
                         Terminal {}
@@ -224,12 +380,8 @@ pub fn Layout() -> Element {
                 // Right sidebar
                 if right_sidebar_visible() {
                     div {
-                        style: "width: 300px; background-color: {colors.bg_tertiary}; border-left: 1px solid {colors.border_primary}; display: flex; flex-direction: column;",
-                        div {
-                            style: "padding: 15px; color: {colors.text_primary};",
-                            h3 { style: "font-size: 0.85rem; margin-bottom: 10px;", "Right Panel" }
-                            "Right sidebar functionality coming soon..."
-                        }
+                        style: "width: 300px; background-color: {colors.bg_tertiary}; border-left: 1px solid {colors.border_primary}; display: flex; flex-direction: column; min-height: 0;",
+                        OutlinePanel {}
                     }
                 }
             }
@@ -252,6 +404,14 @@ fn Terminal() -> Element {
             .push(format!("Terminal started in: {}", cwd));
     });
 
+    use_effect(move || {
+        for command in commands::take_queued(Target::Terminal) {
+            if command == "terminal.clear" {
+                terminal_output.write().clear();
+            }
+        }
+    });
+
     let execute_command = move |cmd: String| {
         spawn(async move {
             let mut output = terminal_output.write();
@@ -352,6 +512,20 @@ fn Terminal() -> Element {
                         value: terminal_input(),
                         placeholder: "Enter command...",
                         oninput: move |evt| terminal_input.set(evt.value()),
+                        onfocus: move |_| *FOCUS.write() = KeyFocus::Terminal,
+                        onblur: move |_| {
+                            if *FOCUS.read() == KeyFocus::Terminal {
+                                *FOCUS.write() = KeyFocus::None;
+                            }
+                        },
+                        onkeydown: move |evt| {
+                            if let Some(command) = resolve_key_event(&evt, &KeyContext::current()) {
+                                match command.as_str() {
+                                    "terminal.clear" => terminal_output.write().clear(),
+                                    other => commands::execute(other),
+                                }
+                            }
+                        },
                         onkeypress: move |evt| {
                             if evt.key() == Key::Enter {
                                 let input = terminal_input();
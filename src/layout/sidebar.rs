@@ -44,4 +44,4 @@ pub fn Sidebar(
             }
         }
     }
-}
\ No newline at end of file
+}
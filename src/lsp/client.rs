@@ -0,0 +1,279 @@
+use super::protocol::{self, Message, ResponseError};
+use super::ServerCommand;
+use crate::utils::log;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot};
+
+// One language server process, spoken to over its stdin and stdout. Responses
+// are matched to their requests here; everything else the server sends, and its
+// exit, goes to the owner's callback.
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+type Pending = Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Value, ResponseError>>>>>;
+
+#[derive(Debug)]
+pub enum ClientEvent {
+    /// A request or notification from the server
+    Message(Message),
+    /// The process is gone, with how it ended
+    Exited(String),
+}
+
+#[derive(Debug)]
+pub struct Client {
+    outgoing: mpsc::UnboundedSender<Value>,
+    pending: Pending,
+    next_id: AtomicI64,
+    kill: Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl Client {
+    /// Starts `command` in `root`. Must be called within the tokio runtime.
+    pub fn spawn(
+        command: &ServerCommand,
+        root: &Path,
+        on_event: impl Fn(ClientEvent) + Send + Sync + 'static,
+    ) -> std::io::Result<Self> {
+        let mut child = Command::new(&command.command)
+            .args(&command.args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (Some(mut stdin), Some(stdout), Some(stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(std::io::Error::other("no stdio"));
+        };
+        let on_event = Arc::new(on_event);
+        let pending: Pending = Arc::default();
+
+        let (outgoing, mut queue) = mpsc::unbounded_channel::<Value>();
+        tokio::spawn(async move {
+            while let Some(message) = queue.recv().await {
+                if stdin.write_all(&protocol::frame(&message)).await.is_err()
+                    || stdin.flush().await.is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let reader_pending = pending.clone();
+        let reader_events = on_event.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(value)) = protocol::read_message(&mut reader).await {
+                match protocol::classify(value) {
+                    Some(Message::Response { id, result }) => {
+                        let waiting = id
+                            .as_i64()
+                            .and_then(|id| reader_pending.lock().unwrap().remove(&id));
+                        if let Some(waiting) = waiting {
+                            let _ = waiting.send(result);
+                        }
+                    }
+                    Some(message) => reader_events(ClientEvent::Message(message)),
+                    None => {}
+                }
+            }
+            // Requests still waiting fail rather than time out
+            reader_pending.lock().unwrap().clear();
+        });
+
+        let name = command.command.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log::write(&name, line);
+            }
+        });
+
+        let (kill, killed) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status.map(|status| status.to_string()).unwrap_or_else(|e| e.to_string()),
+                _ = killed => {
+                    let _ = child.kill().await;
+                    "killed".to_string()
+                }
+            };
+            on_event(ClientEvent::Exited(status));
+        });
+
+        Ok(Self {
+            outgoing,
+            pending,
+            next_id: AtomicI64::new(1),
+            kill: Mutex::new(Some(kill)),
+        })
+    }
+
+    /// Sends a request and waits for its result.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, sender);
+        if self
+            .outgoing
+            .send(protocol::request(id, method, params))
+            .is_err()
+        {
+            self.pending.lock().unwrap().remove(&id);
+            return Err(closed());
+        }
+        match tokio::time::timeout(REQUEST_TIMEOUT, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(closed()),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                self.notify("$/cancelRequest", json!({ "id": id }));
+                Err(ResponseError {
+                    code: -32800,
+                    message: format!("{method} timed out"),
+                })
+            }
+        }
+    }
+
+    pub fn notify(&self, method: &str, params: Value) {
+        let _ = self.outgoing.send(protocol::notification(method, params));
+    }
+
+    /// Answers a request from the server.
+    pub fn respond(&self, id: Value, result: Value) {
+        let _ = self.outgoing.send(protocol::response(id, result));
+    }
+
+    pub fn respond_error(&self, id: Value, code: i64, message: &str) {
+        let _ = self.outgoing.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }));
+    }
+
+    /// Asks the server to shut down and exit, killing it if it takes too long.
+    pub async fn shutdown(&self) {
+        let _ = tokio::time::timeout(
+            Duration::from_secs(2),
+            self.request("shutdown", Value::Null),
+        )
+        .await;
+        self.notify("exit", Value::Null);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        self.kill();
+    }
+
+    pub fn kill(&self) {
+        if let Some(kill) = self.kill.lock().unwrap().take() {
+            let _ = kill.send(());
+        }
+    }
+}
+
+fn closed() -> ResponseError {
+    ResponseError {
+        code: -32099,
+        message: "the language server has exited".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::tests::stub_server;
+
+    // The client, and what its callback was told
+    fn spawn() -> (Client, Arc<Mutex<Vec<String>>>) {
+        let events: Arc<Mutex<Vec<String>>> = Arc::default();
+        let seen = events.clone();
+        let client = Client::spawn(&stub_server(&[]), &std::env::temp_dir(), move |event| {
+            let event = match event {
+                ClientEvent::Message(Message::Request { method, .. }) => {
+                    format!("request {method}")
+                }
+                ClientEvent::Message(message) => format!("{message:?}"),
+                ClientEvent::Exited(status) => format!("exited {status}"),
+            };
+            seen.lock().unwrap().push(event);
+        })
+        .unwrap();
+        (client, events)
+    }
+
+    async fn exited(events: &Mutex<Vec<String>>) -> String {
+        for _ in 0..100 {
+            if let Some(exit) = events
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|event| event.starts_with("exited"))
+            {
+                return exit.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the server is still running: {:?}", events.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn requests_get_their_responses() {
+        let (client, events) = spawn();
+        let result = client.request("initialize", json!({})).await.unwrap();
+        assert_eq!(result["capabilities"]["textDocumentSync"], 2);
+        // The server asked for its settings before it answered
+        assert_eq!(
+            events.lock().unwrap().as_slice(),
+            ["request workspace/configuration"]
+        );
+        assert_eq!(
+            client.request("stub/unknown", Value::Null).await,
+            Err(ResponseError {
+                code: -32601,
+                message: "stub/unknown".to_string(),
+            })
+        );
+
+        client.shutdown().await;
+        assert_eq!(exited(&events).await, "exited exit status: 0");
+    }
+
+    #[tokio::test]
+    async fn a_crash_fails_the_waiting_requests() {
+        let (client, events) = spawn();
+        client.notify("stub/crash", Value::Null);
+        assert_eq!(
+            client.request("stub/state", Value::Null).await,
+            Err(closed())
+        );
+        assert_eq!(exited(&events).await, "exited exit status: 3");
+    }
+
+    #[tokio::test]
+    async fn kill_stops_the_process() {
+        let (client, events) = spawn();
+        client.kill();
+        assert_eq!(exited(&events).await, "exited killed");
+    }
+
+    #[tokio::test]
+    async fn a_missing_command_fails_to_spawn() {
+        let command = ServerCommand {
+            command: "no-such-language-server".to_string(),
+            args: Vec::new(),
+        };
+        assert!(Client::spawn(&command, &std::env::temp_dir(), |_| {}).is_err());
+    }
+}
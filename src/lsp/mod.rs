@@ -0,0 +1,1215 @@
+pub mod client;
+pub mod protocol;
+
+use crate::editor::decorations::{self, Source};
+use crate::editor::diagnostics::{Diagnostic, Severity, DIAGNOSTICS};
+use crate::editor::inlay_hints;
+use crate::editor::workspace_edit::{self, WorkspaceEdit};
+use crate::editor::RopeEditor;
+use crate::settings::SETTINGS;
+use crate::utils::log;
+use client::{Client, ClientEvent};
+use dioxus::prelude::*;
+use protocol::{ContentChange, Message, Position, ResponseError};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+// Language servers for the open files, one per workspace and language, started
+// by the first file that needs one. Each open file is a document the server
+// follows: opened with its text, then sent the edits of its buffer as they
+// happen. Servers that crash are restarted, unless they keep crashing.
+//
+// Server messages arrive on tokio tasks and are handled by a task on the UI
+// thread (see `use_language_servers`), as only it may write the signals.
+
+const MAX_CRASHES: usize = 5;
+const CRASH_WINDOW: Duration = Duration::from_secs(180);
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// How to start the server for a language.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The servers used out of the box, by language id.
+pub fn default_servers() -> BTreeMap<String, ServerCommand> {
+    let server = |command: &str, args: &[&str]| ServerCommand {
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+    };
+    let typescript = server("typescript-language-server", &["--stdio"]);
+    BTreeMap::from([
+        ("rust".to_string(), server("rust-analyzer", &[])),
+        ("python".to_string(), server("pylsp", &[])),
+        ("javascript".to_string(), typescript.clone()),
+        ("typescript".to_string(), typescript.clone()),
+        ("typescriptreact".to_string(), typescript),
+        (
+            "shellscript".to_string(),
+            server("bash-language-server", &["start"]),
+        ),
+    ])
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerKey {
+    pub root: PathBuf,
+    pub language: &'static str,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerStatus {
+    Starting,
+    Running,
+    /// Down after a crash, to be started again shortly
+    Restarting,
+    /// Couldn't start, or crashed too often
+    Failed(String),
+}
+
+#[derive(Debug)]
+struct Server {
+    client: Arc<Client>,
+    command: String,
+    // Tells events of this process from those of one it replaced
+    generation: u64,
+    status: ServerStatus,
+    capabilities: Value,
+    crashes: Vec<Instant>,
+}
+
+#[derive(Debug)]
+struct Document {
+    key: ServerKey,
+    uri: String,
+    version: i64,
+    // The text as the server last heard of it
+    text: Rope,
+    // The buffer whose edits it heard last; edits from another are sent whole
+    buffer: u64,
+}
+
+#[derive(Debug)]
+enum Event {
+    Client {
+        key: ServerKey,
+        generation: u64,
+        event: ClientEvent,
+    },
+    Initialized {
+        key: ServerKey,
+        generation: u64,
+        result: Result<Value, ResponseError>,
+    },
+    Restart {
+        key: ServerKey,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct LanguageServers {
+    servers: HashMap<ServerKey, Server>,
+    documents: HashMap<PathBuf, Document>,
+    // Servers that couldn't be started: their command and why
+    failures: HashMap<ServerKey, (String, String)>,
+    events: Option<mpsc::UnboundedSender<Event>>,
+    next_generation: u64,
+}
+
+pub static LANGUAGE_SERVERS: GlobalSignal<LanguageServers> =
+    Signal::global(LanguageServers::default);
+
+/// Handles server messages for as long as the calling component lives, and
+/// shuts the servers down after it.
+pub fn use_language_servers() {
+    use_hook(|| {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        LANGUAGE_SERVERS.write().events = Some(sender);
+        spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                handle_event(event);
+            }
+        });
+    });
+    use_drop(shutdown_all);
+}
+
+/// Opens `editor`'s file with its language server, or sends the server the
+/// edits made since the last call. Files in `workspace` share its server; others
+/// get one for their folder.
+pub fn sync_document(editor: &RopeEditor, workspace: &Path) {
+    let path = editor.file_path();
+    if path.as_os_str().is_empty() {
+        return;
+    }
+    let language = editor.language().id;
+    // Checked without writing, as this runs after every edit of every buffer
+    let current = LANGUAGE_SERVERS
+        .peek()
+        .documents
+        .get(path)
+        .map(|document| document.key.language);
+    match current {
+        // Opened by another buffer of the file; this one's edits count too
+        Some(id) if id == language => {
+            if !editor.is_followed_by_lsp() {
+                editor.follow_lsp_changes(true);
+            }
+            flush_changes(editor)
+        }
+        Some(_) => {
+            let mut servers = LANGUAGE_SERVERS.write();
+            servers.close(path);
+            servers.open(editor, workspace);
+        }
+        None if SETTINGS.peek().language_servers.contains_key(language) => {
+            LANGUAGE_SERVERS.write().open(editor, workspace)
+        }
+        None => {}
+    }
+}
+
+/// Sends the server the edits made in `editor` since the last call, so that a
+/// request sees the text as it is now.
+pub fn flush_changes(editor: &RopeEditor) {
+    let changes = editor.take_lsp_changes();
+    if !changes.is_empty() {
+        LANGUAGE_SERVERS
+            .write()
+            .send_changes(editor.file_path(), editor, changes);
+    }
+}
+
+/// Sends a request about `path` to its server. None while no running server
+/// has the file open.
+pub async fn request(
+    path: &Path,
+    method: &str,
+    params: Value,
+) -> Option<Result<Value, ResponseError>> {
+    let client = LANGUAGE_SERVERS
+        .peek()
+        .document_server(path)?
+        .1
+        .client
+        .clone();
+    Some(client.request(method, params).await)
+}
+
+/// What the server of `path` said it can do, once it runs.
+pub fn capabilities(path: &Path) -> Option<Value> {
+    Some(
+        LANGUAGE_SERVERS
+            .peek()
+            .document_server(path)?
+            .1
+            .capabilities
+            .clone(),
+    )
+}
+
+/// Whether the server of `path` offers a feature, such as "hoverProvider".
+pub fn provides(path: &Path, capability: &str) -> bool {
+    capabilities(path).is_some_and(|capabilities| match &capabilities[capability] {
+        Value::Null => false,
+        Value::Bool(provided) => *provided,
+        _ => true,
+    })
+}
+
+/// The text of `path` as its server last heard of it, while it's open.
+pub fn document_text(path: &Path) -> Option<Rope> {
+    Some(LANGUAGE_SERVERS.peek().documents.get(path)?.text.clone())
+}
+
+/// The version of `path` the server last heard of, while it's open.
+pub fn document_version(path: &Path) -> Option<i64> {
+    Some(LANGUAGE_SERVERS.peek().documents.get(path)?.version)
+}
+
+/// The document and cursor position of `editor`, as most requests take them.
+pub fn position_params(editor: &RopeEditor) -> Value {
+    position_params_at(editor, editor.get_cursor().byte_offset)
+}
+
+/// The document of `editor` and char offset `offset` in it.
+pub fn position_params_at(editor: &RopeEditor, offset: usize) -> Value {
+    let position = Position::of(editor.rope(), offset);
+    json!({
+        "textDocument": { "uri": protocol::path_to_uri(editor.file_path()) },
+        "position": position.to_json(),
+    })
+}
+
+/// Params for completions at `editor`'s cursor, asked for by typing `trigger`,
+/// or again as the word grows when `retrigger`.
+pub fn completion_params(editor: &RopeEditor, trigger: Option<char>, retrigger: bool) -> Value {
+    let mut params = position_params(editor);
+    params["context"] = match trigger {
+        Some(c) => json!({ "triggerKind": 2, "triggerCharacter": c.to_string() }),
+        None if retrigger => json!({ "triggerKind": 3 }),
+        None => json!({ "triggerKind": 1 }),
+    };
+    params
+}
+
+/// Params for signature help at `editor`'s cursor, asked for by typing
+/// `trigger`, or again as the text changes while `showing` is up.
+pub fn signature_help_params(
+    editor: &RopeEditor,
+    trigger: Option<char>,
+    showing: Option<&Value>,
+) -> Value {
+    let mut params = position_params(editor);
+    let mut context = match trigger {
+        Some(c) => json!({ "triggerKind": 2, "triggerCharacter": c.to_string() }),
+        None if showing.is_some() => json!({ "triggerKind": 3 }),
+        None => json!({ "triggerKind": 1 }),
+    };
+    context["isRetrigger"] = showing.is_some().into();
+    if let Some(help) = showing {
+        context["activeSignatureHelp"] = help.clone();
+    }
+    params["context"] = context;
+    params
+}
+
+/// Params for the inlay hints of the whole of `editor`'s text.
+pub fn inlay_hint_params(editor: &RopeEditor) -> Value {
+    let rope = editor.rope();
+    let end = Position::of(rope, rope.len_chars());
+    json!({
+        "textDocument": { "uri": protocol::path_to_uri(editor.file_path()) },
+        "range": protocol::range_json(Position::default(), end),
+    })
+}
+
+/// Params for the code actions over chars `range` of `editor`, with the
+/// problems reported there. `only` narrows them to a kind, such as
+/// "source.organizeImports"; `invoked` says the user asked for them.
+pub fn code_action_params(
+    editor: &RopeEditor,
+    range: Range<usize>,
+    only: Option<&str>,
+    invoked: bool,
+) -> Value {
+    let rope = editor.rope();
+    let (start, end) = (
+        Position::of(rope, range.start),
+        Position::of(rope, range.end),
+    );
+    let at = |offset: usize| {
+        let line = rope.char_to_line(offset.min(rope.len_chars()));
+        (line, offset - rope.line_to_char(line))
+    };
+    let (from, to) = (at(range.start), at(range.end));
+    let diagnostics: Vec<Value> = DIAGNOSTICS
+        .peek()
+        .get(editor.file_path())
+        .map(|diagnostics| {
+            diagnostics
+                .iter()
+                .filter(|diagnostic| {
+                    (diagnostic.line, diagnostic.column) <= to
+                        && (diagnostic.end_line, diagnostic.end_column) >= from
+                })
+                .filter_map(|diagnostic| diagnostic.lsp.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut context =
+        json!({ "diagnostics": diagnostics, "triggerKind": if invoked { 1 } else { 2 } });
+    if let Some(kind) = only {
+        context["only"] = json!([kind]);
+    }
+    json!({
+        "textDocument": { "uri": protocol::path_to_uri(editor.file_path()) },
+        "range": protocol::range_json(start, end),
+        "context": context,
+    })
+}
+
+/// Whether code actions come without their edits, to be filled in with
+/// `codeAction/resolve`.
+pub fn resolves_code_actions(path: &Path) -> bool {
+    capabilities(path)
+        .is_some_and(|capabilities| capabilities["codeActionProvider"]["resolveProvider"] == true)
+}
+
+/// Whether the server says what a rename at a position would rename first.
+pub fn prepares_rename(path: &Path) -> bool {
+    capabilities(path)
+        .is_some_and(|capabilities| capabilities["renameProvider"]["prepareProvider"] == true)
+}
+
+/// Characters the server of `path` wants to complete after, such as `.`.
+pub fn completion_triggers(path: &Path) -> Vec<char> {
+    completion_characters(path, "triggerCharacters")
+}
+
+/// Characters that accept any suggestion and are typed after it.
+pub fn commit_characters(path: &Path) -> Vec<char> {
+    completion_characters(path, "allCommitCharacters")
+}
+
+/// Whether suggestions are filled in later with `completionItem/resolve`.
+pub fn resolves_completions(path: &Path) -> bool {
+    capabilities(path)
+        .is_some_and(|capabilities| capabilities["completionProvider"]["resolveProvider"] == true)
+}
+
+/// Characters that bring up signature help, such as `(` and `,`.
+pub fn signature_help_triggers(path: &Path) -> Vec<char> {
+    let Some(capabilities) = capabilities(path) else {
+        return Vec::new();
+    };
+    let provider = &capabilities["signatureHelpProvider"];
+    ["triggerCharacters", "retriggerCharacters"]
+        .iter()
+        .flat_map(|key| provider[key].as_array().into_iter().flatten())
+        .filter_map(Value::as_str)
+        .filter_map(|c| c.chars().next())
+        .collect()
+}
+
+fn completion_characters(path: &Path, key: &str) -> Vec<char> {
+    let Some(capabilities) = capabilities(path) else {
+        return Vec::new();
+    };
+    capabilities["completionProvider"][key]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(Value::as_str)
+                .filter_map(|c| c.chars().next())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Tells the server `path` is saved.
+pub fn did_save(path: &Path) {
+    let servers = LANGUAGE_SERVERS.peek();
+    if let Some((document, server)) = servers.document_server(path) {
+        server.client.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": document.uri } }),
+        );
+    }
+}
+
+/// Closes `path` with its server, as when its last editor closes.
+pub fn close_document(path: &Path) {
+    LANGUAGE_SERVERS.write().close(path);
+}
+
+/// Closes every document and stops every server, as when the workspace changes.
+pub fn shutdown_all() {
+    let mut servers = LANGUAGE_SERVERS.write();
+    let paths: Vec<PathBuf> = servers.documents.keys().cloned().collect();
+    for path in paths {
+        servers.close(&path);
+    }
+    for (_, server) in servers.servers.drain() {
+        tokio::spawn(async move { server.client.shutdown().await });
+    }
+}
+
+/// Stops the server of `path`, if it runs, and starts it afresh.
+pub fn restart(path: &Path) {
+    let mut servers = LANGUAGE_SERVERS.write();
+    let Some(key) = servers
+        .documents
+        .get(path)
+        .map(|document| document.key.clone())
+    else {
+        return;
+    };
+    servers.failures.remove(&key);
+    if let Some(server) = servers.servers.remove(&key) {
+        tokio::spawn(async move { server.client.shutdown().await });
+    }
+    for document in servers
+        .documents
+        .values_mut()
+        .filter(|document| document.key == key)
+    {
+        document.version += 1;
+    }
+    servers.start(key);
+}
+
+/// The state of the server for `path`, with the server's name.
+pub fn status_of(path: &Path) -> Option<(String, ServerStatus)> {
+    let servers = LANGUAGE_SERVERS.read();
+    let key = &servers.documents.get(path)?.key;
+    if let Some((command, error)) = servers.failures.get(key) {
+        return Some((command.clone(), ServerStatus::Failed(error.clone())));
+    }
+    let server = servers.servers.get(key)?;
+    Some((server.command.clone(), server.status.clone()))
+}
+
+impl LanguageServers {
+    fn document_server(&self, path: &Path) -> Option<(&Document, &Server)> {
+        let document = self.documents.get(path)?;
+        let server = self.servers.get(&document.key)?;
+        (server.status == ServerStatus::Running).then_some((document, server))
+    }
+
+    fn open(&mut self, editor: &RopeEditor, workspace: &Path) {
+        let path = editor.file_path();
+        let language = editor.language().id;
+        if !SETTINGS.peek().language_servers.contains_key(language) {
+            return;
+        }
+        let root = if path.starts_with(workspace) {
+            workspace.to_path_buf()
+        } else {
+            path.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+        let key = ServerKey { root, language };
+        editor.follow_lsp_changes(true);
+        let document = Document {
+            key: key.clone(),
+            uri: protocol::path_to_uri(path),
+            version: 1,
+            text: editor.rope().clone(),
+            buffer: editor.id(),
+        };
+        match self.servers.get(&key) {
+            Some(server) if server.status == ServerStatus::Running => {
+                did_open(&server.client, &document, language)
+            }
+            // Opened once the server is up
+            Some(_) => {}
+            None if self.failures.contains_key(&key) => {}
+            None => self.start(key),
+        }
+        self.documents.insert(path.clone(), document);
+    }
+
+    fn send_changes(&mut self, path: &Path, editor: &RopeEditor, changes: Vec<ContentChange>) {
+        let Some(document) = self.documents.get_mut(path) else {
+            return;
+        };
+        let server = self
+            .servers
+            .get(&document.key)
+            .filter(|server| server.status == ServerStatus::Running);
+        let sync = server.map_or(1, |server| sync_kind(&server.capabilities));
+        // Servers that don't follow edits keep the text they were opened with
+        if sync == 0 {
+            return;
+        }
+        document.version += 1;
+        document.text = editor.rope().clone();
+        // Edits from another buffer of the file start from a text the server never saw
+        let incremental = sync == 2 && document.buffer == editor.id();
+        document.buffer = editor.id();
+        let Some(server) = server else {
+            return;
+        };
+        // Otherwise the server gets the text as it is now
+        let content_changes: Vec<Value> = if incremental {
+            changes.iter().map(|change| change.to_json()).collect()
+        } else {
+            vec![json!({ "text": document.text.to_string() })]
+        };
+        server.client.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": document.uri, "version": document.version },
+                "contentChanges": content_changes,
+            }),
+        );
+    }
+
+    fn close(&mut self, path: &Path) {
+        let Some(document) = self.documents.remove(path) else {
+            return;
+        };
+        DIAGNOSTICS.write().remove(path);
+        decorations::clear_layer(path, Source::InlayHints);
+        if let Some(server) = self
+            .servers
+            .get(&document.key)
+            .filter(|server| server.status == ServerStatus::Running)
+        {
+            server.client.notify(
+                "textDocument/didClose",
+                json!({ "textDocument": { "uri": document.uri } }),
+            );
+        }
+        // A server with nothing left open is stopped
+        if !self
+            .documents
+            .values()
+            .any(|other| other.key == document.key)
+        {
+            self.failures.remove(&document.key);
+            if let Some(server) = self.servers.remove(&document.key) {
+                tokio::spawn(async move { server.client.shutdown().await });
+            }
+        }
+    }
+
+    fn start(&mut self, key: ServerKey) {
+        let Some(command) = SETTINGS.peek().language_servers.get(key.language).cloned() else {
+            return;
+        };
+        let Some(events) = self.events.clone() else {
+            return;
+        };
+        self.next_generation += 1;
+        let generation = self.next_generation;
+        let crashes = self
+            .servers
+            .remove(&key)
+            .map(|server| server.crashes)
+            .unwrap_or_default();
+
+        let client_events = events.clone();
+        let client_key = key.clone();
+        let client = Client::spawn(&command, &key.root, move |event| {
+            let key = client_key.clone();
+            let _ = client_events.send(Event::Client {
+                key,
+                generation,
+                event,
+            });
+        });
+        let client = match client {
+            Ok(client) => Arc::new(client),
+            Err(e) => {
+                log::write(
+                    "Language servers",
+                    format!("Failed to start {}: {}", command.command, e),
+                );
+                self.failures.insert(key, (command.command, e.to_string()));
+                return;
+            }
+        };
+
+        let handshake = client.clone();
+        let root = key.root.clone();
+        let handshake_key = key.clone();
+        tokio::spawn(async move {
+            let result = handshake
+                .request("initialize", initialize_params(&root))
+                .await;
+            if result.is_ok() {
+                handshake.notify("initialized", json!({}));
+            }
+            let key = handshake_key;
+            let _ = events.send(Event::Initialized {
+                key,
+                generation,
+                result,
+            });
+        });
+
+        self.servers.insert(
+            key,
+            Server {
+                client,
+                command: command.command,
+                generation,
+                status: ServerStatus::Starting,
+                capabilities: Value::Null,
+                crashes,
+            },
+        );
+    }
+}
+
+fn handle_event(event: Event) {
+    let mut guard = LANGUAGE_SERVERS.write();
+    let servers = &mut *guard;
+    match event {
+        Event::Initialized {
+            key,
+            generation,
+            result,
+        } => {
+            let Some(server) = servers
+                .servers
+                .get_mut(&key)
+                .filter(|server| server.generation == generation)
+            else {
+                return;
+            };
+            match result {
+                Ok(result) => {
+                    server.status = ServerStatus::Running;
+                    server.capabilities = result.get("capabilities").cloned().unwrap_or_default();
+                    for document in servers
+                        .documents
+                        .values()
+                        .filter(|document| document.key == key)
+                    {
+                        did_open(&server.client, document, key.language);
+                    }
+                }
+                Err(e) => {
+                    log::write(
+                        "Language servers",
+                        format!("{} failed to initialize: {}", server.command, e),
+                    );
+                    server.status = ServerStatus::Failed(e.to_string());
+                    server.client.kill();
+                }
+            }
+        }
+        Event::Client {
+            key,
+            generation,
+            event,
+        } => {
+            let Some(server) = servers
+                .servers
+                .get_mut(&key)
+                .filter(|server| server.generation == generation)
+            else {
+                return;
+            };
+            match event {
+                ClientEvent::Message(Message::Notification { method, params }) => {
+                    drop(guard);
+                    handle_notification(&method, params);
+                }
+                ClientEvent::Message(Message::Request { id, method, params }) => {
+                    let client = server.client.clone();
+                    let root = key.root.clone();
+                    drop(guard);
+                    handle_request(&client, &root, id, &method, params);
+                }
+                ClientEvent::Message(Message::Response { .. }) => {}
+                ClientEvent::Exited(status) => {
+                    if matches!(server.status, ServerStatus::Failed(_)) {
+                        return;
+                    }
+                    log::write(
+                        "Language servers",
+                        format!("{} exited unexpectedly: {}", server.command, status),
+                    );
+                    let now = Instant::now();
+                    server
+                        .crashes
+                        .retain(|crash| now.duration_since(*crash) < CRASH_WINDOW);
+                    server.crashes.push(now);
+                    if server.crashes.len() >= MAX_CRASHES {
+                        server.status =
+                            ServerStatus::Failed(format!("crashed {} times", server.crashes.len()));
+                    } else {
+                        server.status = ServerStatus::Restarting;
+                        if let Some(events) = servers.events.clone() {
+                            tokio::spawn(async move {
+                                tokio::time::sleep(RESTART_DELAY).await;
+                                let _ = events.send(Event::Restart { key });
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Event::Restart { key } => {
+            let restarting = servers
+                .servers
+                .get(&key)
+                .is_some_and(|server| server.status == ServerStatus::Restarting);
+            if restarting {
+                // The new process gets each document's text as it is now
+                for document in servers
+                    .documents
+                    .values_mut()
+                    .filter(|document| document.key == key)
+                {
+                    document.version += 1;
+                }
+                servers.start(key);
+            }
+        }
+    }
+}
+
+fn handle_notification(method: &str, params: Value) {
+    match method {
+        "textDocument/publishDiagnostics" => {
+            let Some(path) = params
+                .get("uri")
+                .and_then(Value::as_str)
+                .and_then(protocol::uri_to_path)
+            else {
+                return;
+            };
+            let servers = LANGUAGE_SERVERS.peek();
+            let Some(document) = servers.documents.get(&path) else {
+                return;
+            };
+            let diagnostics = params
+                .get("diagnostics")
+                .and_then(Value::as_array)
+                .map(|list| {
+                    list.iter()
+                        .filter_map(|item| diagnostic(&document.text, item))
+                        .collect()
+                })
+                .unwrap_or_default();
+            drop(servers);
+            DIAGNOSTICS.write().insert(path, diagnostics);
+        }
+        "window/showMessage" | "window/logMessage" => {
+            if let Some(message) = params.get("message").and_then(Value::as_str) {
+                log::write("Language servers", message);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Requests the client is expected to answer
+fn handle_request(client: &Client, root: &Path, id: Value, method: &str, params: Value) {
+    match method {
+        // No settings of our own; servers use their defaults
+        "workspace/configuration" => {
+            let count = params
+                .get("items")
+                .and_then(Value::as_array)
+                .map_or(0, Vec::len);
+            client.respond(id, Value::Array(vec![Value::Null; count]));
+        }
+        "workspace/workspaceFolders" => client.respond(id, json!([workspace_folder(root)])),
+        // Edits a command run with workspace/executeCommand makes
+        "workspace/applyEdit" => {
+            let edit = WorkspaceEdit::from_lsp(&params["edit"]);
+            let title = params["label"].as_str().unwrap_or("Code action");
+            let summary = workspace_edit::apply_and_report(title, &edit);
+            if summary.is_complete() {
+                client.respond(id, json!({ "applied": true }));
+            } else {
+                client.respond(
+                    id,
+                    json!({ "applied": false, "failureReason": summary.details() }),
+                );
+            }
+        }
+        "workspace/inlayHint/refresh" => {
+            inlay_hints::refresh();
+            client.respond(id, Value::Null);
+        }
+        "window/workDoneProgress/create"
+        | "client/registerCapability"
+        | "client/unregisterCapability" => client.respond(id, Value::Null),
+        _ => client.respond_error(id, -32601, &format!("{method} is not supported")),
+    }
+}
+
+fn did_open(client: &Client, document: &Document, language: &str) {
+    client.notify(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": {
+                "uri": document.uri,
+                "languageId": language,
+                "version": document.version,
+                "text": document.text.to_string(),
+            }
+        }),
+    );
+}
+
+// 0 none, 1 whole documents, 2 incremental
+fn sync_kind(capabilities: &Value) -> u64 {
+    match capabilities.get("textDocumentSync") {
+        Some(Value::Number(kind)) => kind.as_u64().unwrap_or(1),
+        // Options that leave out `change` ask for no edits
+        Some(sync) => sync.get("change").and_then(Value::as_u64).unwrap_or(0),
+        None => 1,
+    }
+}
+
+fn diagnostic(text: &Rope, item: &Value) -> Option<Diagnostic> {
+    let range = item.get("range")?;
+    let start = Position::from_json(range.get("start")?)?;
+    let end = Position::from_json(range.get("end")?)?;
+    let column = |position: Position| {
+        let offset = position.to_char(text);
+        offset - text.line_to_char(text.char_to_line(offset))
+    };
+    let severity = match item.get("severity").and_then(Value::as_u64) {
+        Some(2) => Severity::Warning,
+        Some(3) => Severity::Information,
+        Some(4) => Severity::Hint,
+        _ => Severity::Error,
+    };
+    Some(Diagnostic {
+        line: start.line,
+        column: column(start),
+        end_line: end.line.max(start.line),
+        end_column: column(end),
+        severity,
+        message: item.get("message")?.as_str()?.to_string(),
+        source: item
+            .get("source")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        lsp: Some(item.clone()),
+    })
+}
+
+fn workspace_folder(root: &Path) -> Value {
+    let name = root.file_name().unwrap_or_default().to_string_lossy();
+    json!({ "uri": protocol::path_to_uri(root), "name": name })
+}
+
+fn initialize_params(root: &Path) -> Value {
+    json!({
+        "processId": std::process::id(),
+        "clientInfo": { "name": "code_editor", "version": env!("CARGO_PKG_VERSION") },
+        "rootUri": protocol::path_to_uri(root),
+        "rootPath": root.to_string_lossy(),
+        "workspaceFolders": [workspace_folder(root)],
+        "capabilities": {
+            "general": { "positionEncodings": ["utf-16"] },
+            "textDocument": {
+                "synchronization": { "didSave": true, "dynamicRegistration": false },
+                "publishDiagnostics": { "relatedInformation": false, "versionSupport": false },
+                "completion": {
+                    "completionItem": {
+                        "snippetSupport": true,
+                        "commitCharactersSupport": true,
+                        "documentationFormat": ["markdown", "plaintext"],
+                        "preselectSupport": true,
+                        "insertReplaceSupport": true,
+                        "resolveSupport": { "properties": ["documentation", "detail", "additionalTextEdits"] },
+                    },
+                    "completionItemKind": { "valueSet": (1..=25).collect::<Vec<u32>>() },
+                    "contextSupport": true,
+                },
+                "hover": { "contentFormat": ["markdown", "plaintext"] },
+                "signatureHelp": {
+                    "signatureInformation": {
+                        "documentationFormat": ["markdown", "plaintext"],
+                        "parameterInformation": { "labelOffsetSupport": true },
+                        "activeParameterSupport": true,
+                    },
+                    "contextSupport": true,
+                },
+                "inlayHint": {},
+                "definition": { "linkSupport": true },
+                "typeDefinition": { "linkSupport": true },
+                "implementation": { "linkSupport": true },
+                "references": {},
+                "rename": { "prepareSupport": true },
+                "codeAction": {
+                    "codeActionLiteralSupport": {
+                        "codeActionKind": {
+                            "valueSet": ["quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite", "source", "source.organizeImports"],
+                        },
+                    },
+                    "isPreferredSupport": true,
+                    "disabledSupport": true,
+                    "resolveSupport": { "properties": ["edit"] },
+                },
+                "documentSymbol": {
+                    "hierarchicalDocumentSymbolSupport": true,
+                    "symbolKind": { "valueSet": (1..=26).collect::<Vec<u32>>() },
+                },
+            },
+            "workspace": {
+                "workspaceFolders": true,
+                "configuration": true,
+                "applyEdit": true,
+                "workspaceEdit": { "documentChanges": true, "resourceOperations": [] },
+                "executeCommand": {},
+                "inlayHint": { "refreshSupport": true },
+            },
+            "window": { "workDoneProgress": true },
+        },
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// The stub server from `examples/`, which cargo builds beside the tests.
+    pub(crate) fn stub_server(args: &[&str]) -> ServerCommand {
+        let deps = std::env::current_exe().unwrap();
+        let path = deps
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("examples")
+            .join(format!(
+                "stub_language_server{}",
+                std::env::consts::EXE_SUFFIX
+            ));
+        assert!(
+            path.exists(),
+            "build the examples to get {}",
+            path.display()
+        );
+        ServerCommand {
+            command: path.to_string_lossy().into_owned(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    // Language servers for files in a folder of their own, with the stub for Rust
+    struct Harness {
+        dom: VirtualDom,
+        events: mpsc::UnboundedReceiver<Event>,
+        root: PathBuf,
+    }
+
+    impl Harness {
+        fn new(args: &[&str]) -> Self {
+            static NEXT: AtomicU64 = AtomicU64::new(0);
+            let root = std::env::temp_dir().join(format!(
+                "lsp_test_{}_{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            std::fs::create_dir_all(&root).unwrap();
+            let dom = VirtualDom::new(|| rsx! {});
+            let (sender, events) = mpsc::unbounded_channel();
+            dom.in_runtime(|| {
+                SETTINGS
+                    .write()
+                    .language_servers
+                    .insert("rust".to_string(), stub_server(args));
+                LANGUAGE_SERVERS.write().events = Some(sender);
+            });
+            Self { dom, events, root }
+        }
+
+        fn editor(&self, text: &str) -> RopeEditor {
+            RopeEditor::from_named_text(&self.root.join("main.rs").to_string_lossy(), text)
+        }
+
+        fn sync(&self, editor: &RopeEditor) {
+            self.dom.in_runtime(|| sync_document(editor, &self.root));
+        }
+
+        /// Handles server events until `done` holds.
+        async fn until(&mut self, done: impl Fn(&LanguageServers) -> bool) {
+            let wait = async {
+                while !self.dom.in_runtime(|| done(&LANGUAGE_SERVERS.peek())) {
+                    let event = self.events.recv().await.unwrap();
+                    self.dom.in_runtime(|| handle_event(event));
+                }
+            };
+            tokio::time::timeout(Duration::from_secs(10), wait)
+                .await
+                .expect("the servers never got there");
+        }
+
+        async fn running(&mut self) {
+            let path = self.root.join("main.rs");
+            self.until(|servers| servers.document_server(&path).is_some())
+                .await;
+        }
+
+        fn client(&self) -> Arc<Client> {
+            self.dom.in_runtime(|| {
+                let servers = LANGUAGE_SERVERS.peek();
+                servers.servers.values().next().unwrap().client.clone()
+            })
+        }
+
+        /// What the stub holds for main.rs, and how many changes it was sent.
+        async fn served(&self) -> (Value, u64) {
+            let state = self
+                .client()
+                .request("stub/state", Value::Null)
+                .await
+                .unwrap();
+            let uri = protocol::path_to_uri(&self.root.join("main.rs"));
+            (
+                state["documents"][&uri].clone(),
+                state["changes"].as_u64().unwrap(),
+            )
+        }
+    }
+
+    impl Drop for Harness {
+        fn drop(&mut self) {
+            self.dom.in_runtime(shutdown_all);
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn diagnostics_take_char_columns() {
+        let text = Rope::from_str("let \u{1f600} = x;\n");
+        let item = json!({
+            "range": protocol::range_json(
+                Position { line: 0, character: 4 },
+                Position { line: 0, character: 6 },
+            ),
+            "severity": 2,
+            "message": "unused",
+            "source": "rustc",
+        });
+        assert_eq!(
+            diagnostic(&text, &item),
+            Some(Diagnostic {
+                line: 0,
+                column: 4,
+                end_line: 0,
+                end_column: 5,
+                severity: Severity::Warning,
+                message: "unused".to_string(),
+                source: Some("rustc".to_string()),
+                lsp: Some(item.clone()),
+            })
+        );
+        assert_eq!(diagnostic(&text, &json!({ "message": "where?" })), None);
+    }
+
+    #[test]
+    fn sync_kinds_read_both_forms() {
+        assert_eq!(sync_kind(&json!({ "textDocumentSync": 0 })), 0);
+        assert_eq!(
+            sync_kind(&json!({ "textDocumentSync": { "change": 2 } })),
+            2
+        );
+        assert_eq!(
+            sync_kind(&json!({ "textDocumentSync": { "openClose": true } })),
+            0
+        );
+        assert_eq!(sync_kind(&json!({})), 1);
+    }
+
+    #[tokio::test]
+    async fn opens_documents_after_the_handshake() {
+        let mut harness = Harness::new(&[]);
+        let editor = harness.editor("fn main() {}\n");
+        harness.sync(&editor);
+        harness.running().await;
+        assert!(editor.is_followed_by_lsp());
+
+        let state = harness
+            .client()
+            .request("stub/state", Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(state["initialized"], true);
+        // Answered before the server was told it runs
+        assert_eq!(state["configuration"], json!([null]));
+        let (document, changes) = harness.served().await;
+        assert_eq!(document, json!({ "version": 1, "text": "fn main() {}\n" }));
+        assert_eq!(changes, 0);
+    }
+
+    #[tokio::test]
+    async fn edits_reach_the_server_at_utf16_positions() {
+        let mut harness = Harness::new(&[]);
+        let mut editor = harness.editor("fn main() {\n    let \u{e9} = \"\u{1f600}\";\n}\n");
+        harness.sync(&editor);
+        harness.running().await;
+
+        editor.set_cursor(1, 14);
+        editor.insert_text("\u{fc}\n\u{1f600}x");
+        harness.sync(&editor);
+        editor.set_cursor(2, 1);
+        editor.backspace();
+        editor.delete_range(editor.line_to_char(1) + 8, editor.line_to_char(1) + 10);
+        editor.undo();
+        harness.sync(&editor);
+
+        let (document, changes) = harness.served().await;
+        assert_eq!(document["text"], editor.get_content());
+        assert_eq!(document["version"], 3);
+        assert_eq!(changes, 2);
+    }
+
+    #[tokio::test]
+    async fn servers_that_do_not_sync_are_sent_no_changes() {
+        let mut harness = Harness::new(&["--sync", "0"]);
+        let mut editor = harness.editor("fn main() {}\n");
+        harness.sync(&editor);
+        harness.running().await;
+
+        editor.insert_text("// ");
+        harness.sync(&editor);
+        let (document, changes) = harness.served().await;
+        assert_eq!(document, json!({ "version": 1, "text": "fn main() {}\n" }));
+        assert_eq!(changes, 0);
+    }
+
+    #[tokio::test]
+    async fn edits_from_every_buffer_of_a_file_reach_the_server() {
+        let mut harness = Harness::new(&[]);
+        let mut first = harness.editor("fn a() {}\n");
+        let mut second = harness.editor("fn a() {}\n");
+        harness.sync(&first);
+        harness.sync(&second);
+        harness.running().await;
+        assert!(second.is_followed_by_lsp());
+
+        first.insert_text("// first\n");
+        harness.sync(&first);
+        assert_eq!(harness.served().await.0["text"], first.get_content());
+        // Made to a text the server no longer has, so it gets this one whole
+        second.set_cursor(1, 0);
+        second.insert_text("fn b() {}\n");
+        harness.sync(&second);
+        assert_eq!(harness.served().await.0["text"], second.get_content());
+        second.insert_text("fn c() {}\n");
+        harness.sync(&second);
+
+        let (document, changes) = harness.served().await;
+        assert_eq!(document["text"], "fn a() {}\nfn b() {}\nfn c() {}\n");
+        assert_eq!(document["version"], 4);
+        assert_eq!(changes, 3);
+    }
+
+    #[tokio::test]
+    async fn crashed_servers_restart_with_the_current_text() {
+        let mut harness = Harness::new(&[]);
+        let mut editor = harness.editor("fn main() {}\n");
+        harness.sync(&editor);
+        harness.running().await;
+
+        harness.client().notify("stub/crash", Value::Null);
+        harness
+            .until(|servers| {
+                servers
+                    .servers
+                    .values()
+                    .any(|server| server.status == ServerStatus::Restarting)
+            })
+            .await;
+        // Made while it was down
+        editor.insert_text("// ");
+        harness.sync(&editor);
+        harness.running().await;
+
+        // A version on from the edit, as the restart counts as a change
+        let (document, changes) = harness.served().await;
+        assert_eq!(
+            document,
+            json!({ "version": 3, "text": "// fn main() {}\n" })
+        );
+        assert_eq!(changes, 0);
+        let crashes = harness.dom.in_runtime(|| {
+            let servers = LANGUAGE_SERVERS.peek();
+            servers.servers.values().next().unwrap().crashes.len()
+        });
+        assert_eq!(crashes, 1);
+    }
+}
@@ -0,0 +1,365 @@
+use ropey::Rope;
+use serde_json::{json, Value};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+
+// The wire format of the Language Server Protocol: JSON-RPC messages framed by a
+// Content-Length header, and positions counted in UTF-16 code units per line.
+
+/// A message from the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Request {
+        id: Value,
+        method: String,
+        params: Value,
+    },
+    Response {
+        id: Value,
+        result: Result<Value, ResponseError>,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl std::fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// A message body with its header, ready to write.
+pub fn frame(body: &Value) -> Vec<u8> {
+    let body = body.to_string();
+    let mut bytes = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    bytes.extend_from_slice(body.as_bytes());
+    bytes
+}
+
+pub fn request(id: i64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+pub fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+/// Reads the next framed message. None at the end of the stream.
+pub async fn read_message(
+    reader: &mut (impl AsyncBufRead + Unpin),
+) -> std::io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body).await?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Sorts a decoded message into a request, response or notification.
+pub fn classify(value: Value) -> Option<Message> {
+    let method = value
+        .get("method")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let params = value.get("params").cloned().unwrap_or(Value::Null);
+    match (value.get("id").cloned(), method) {
+        (Some(id), Some(method)) => Some(Message::Request { id, method, params }),
+        (None, Some(method)) => Some(Message::Notification { method, params }),
+        (Some(id), None) => {
+            let result = match value.get("error") {
+                Some(error) => Err(ResponseError {
+                    code: error
+                        .get("code")
+                        .and_then(Value::as_i64)
+                        .unwrap_or_default(),
+                    message: error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_string(),
+                }),
+                None => Ok(value.get("result").cloned().unwrap_or(Value::Null)),
+            };
+            Some(Message::Response { id, result })
+        }
+        (None, None) => None,
+    }
+}
+
+/// A position as the protocol counts it: line, and UTF-16 units into the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    /// The position of char offset `char_idx` in `rope`.
+    pub fn of(rope: &Rope, char_idx: usize) -> Self {
+        let char_idx = char_idx.min(rope.len_chars());
+        let line = rope.char_to_line(char_idx);
+        let line_start = rope.line_to_char(line);
+        let character = rope.char_to_utf16_cu(char_idx) - rope.char_to_utf16_cu(line_start);
+        Self { line, character }
+    }
+
+    /// The char offset in `rope`, clamped to the line and the text.
+    pub fn to_char(self, rope: &Rope) -> usize {
+        if self.line >= rope.len_lines() {
+            return rope.len_chars();
+        }
+        let line_start = rope.line_to_char(self.line);
+        let line = rope.line(self.line);
+        let content = line.len_chars() - line_ending_len(&line);
+        let units = line.slice(..content).len_utf16_cu();
+        line_start + line.utf16_cu_to_char(self.character.min(units))
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({ "line": self.line, "character": self.character })
+    }
+
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            line: value.get("line")?.as_u64()? as usize,
+            character: value.get("character")?.as_u64()? as usize,
+        })
+    }
+}
+
+fn line_ending_len(line: &ropey::RopeSlice) -> usize {
+    let len = line.len_chars();
+    match (
+        len.checked_sub(2).map(|i| line.char(i)),
+        len.checked_sub(1).map(|i| line.char(i)),
+    ) {
+        (Some('\r'), Some('\n')) => 2,
+        (_, Some('\n' | '\r')) => 1,
+        _ => 0,
+    }
+}
+
+/// A protocol range as char offsets into `rope`.
+pub fn range_to_chars(rope: &Rope, range: &Value) -> Option<Range<usize>> {
+    let start = Position::from_json(range.get("start")?)?.to_char(rope);
+    let end = Position::from_json(range.get("end")?)?.to_char(rope);
+    Some(start..end.max(start))
+}
+
+pub fn range_json(start: Position, end: Position) -> Value {
+    json!({ "start": start.to_json(), "end": end.to_json() })
+}
+
+/// One edit for `textDocument/didChange`; no range replaces the whole text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentChange {
+    pub range: Option<(Position, Position)>,
+    pub text: String,
+}
+
+impl ContentChange {
+    pub fn to_json(&self) -> Value {
+        match self.range {
+            Some((start, end)) => json!({ "range": range_json(start, end), "text": self.text }),
+            None => json!({ "text": self.text }),
+        }
+    }
+}
+
+/// The change for inserting `text` at char `position`, before it is made.
+pub fn insert_change(rope: &Rope, position: usize, text: &str) -> ContentChange {
+    let at = Position::of(rope, position);
+    ContentChange {
+        range: Some((at, at)),
+        text: text.to_string(),
+    }
+}
+
+/// The change for removing chars `range`, before it is made.
+pub fn remove_change(rope: &Rope, range: Range<usize>) -> ContentChange {
+    ContentChange {
+        range: Some((
+            Position::of(rope, range.start),
+            Position::of(rope, range.end),
+        )),
+        text: String::new(),
+    }
+}
+
+/// The `file://` URI of `path`.
+pub fn path_to_uri(path: &Path) -> String {
+    url::Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("file://{}", path.display()))
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    url::Url::parse(uri).ok()?.to_file_path().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::RopeEditor;
+
+    #[tokio::test]
+    async fn framed_messages_read_back() {
+        // Content-Length counts bytes, not chars
+        let first = request(1, "initialize", json!({ "name": "\u{e9}\u{1f600}" }));
+        let second = notification("exit", Value::Null);
+        let mut bytes = frame(&first);
+        bytes.extend_from_slice(b"Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n");
+        bytes.extend(frame(&second));
+        let mut reader = tokio::io::BufReader::new(&bytes[..]);
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some(first));
+        assert_eq!(read_message(&mut reader).await.unwrap(), Some(second));
+        assert_eq!(read_message(&mut reader).await.unwrap(), None);
+
+        let mut reader = tokio::io::BufReader::new(&b"Content-Length: 2\r\n\r\n{]"[..]);
+        assert!(read_message(&mut reader).await.is_err());
+    }
+
+    #[test]
+    fn messages_classify_by_id_and_method() {
+        assert!(matches!(
+            classify(json!({ "id": 1, "method": "m" })),
+            Some(Message::Request { .. })
+        ));
+        assert!(matches!(
+            classify(json!({ "method": "m" })),
+            Some(Message::Notification { .. })
+        ));
+        assert_eq!(
+            classify(json!({ "id": 2, "error": { "code": -1, "message": "no" } })),
+            Some(Message::Response {
+                id: json!(2),
+                result: Err(ResponseError {
+                    code: -1,
+                    message: "no".to_string(),
+                }),
+            })
+        );
+        assert_eq!(
+            classify(json!({ "id": 3, "result": 5 })),
+            Some(Message::Response {
+                id: json!(3),
+                result: Ok(json!(5)),
+            })
+        );
+        assert_eq!(classify(json!({})), None);
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let rope = Rope::from_str("a\u{1f600}b\r\nxyz\n");
+        assert_eq!(
+            Position::of(&rope, 2),
+            Position {
+                line: 0,
+                character: 3
+            }
+        );
+        assert_eq!(
+            Position::of(&rope, 5),
+            Position {
+                line: 1,
+                character: 0
+            }
+        );
+        assert_eq!(
+            Position {
+                line: 0,
+                character: 3
+            }
+            .to_char(&rope),
+            2
+        );
+        // Past the end of a line stops before its line ending
+        assert_eq!(
+            Position {
+                line: 0,
+                character: 99
+            }
+            .to_char(&rope),
+            3
+        );
+        assert_eq!(
+            Position {
+                line: 9,
+                character: 0
+            }
+            .to_char(&rope),
+            rope.len_chars()
+        );
+    }
+
+    #[test]
+    fn recorded_changes_replay_to_the_same_text() {
+        let mut editor = RopeEditor::from_text("fn main() {\n    let \u{e9} = 1;\n}\n");
+
+        editor.follow_lsp_changes(true);
+        let mut mirror = editor.rope().clone();
+        editor.set_cursor_offset(16);
+        editor.insert_text("\u{1f600}x\nyy");
+        editor.delete_range(3, 20);
+        editor.backspace();
+        editor.replace_range(0, 2, "pub fn");
+        editor.undo();
+        for change in editor.take_lsp_changes() {
+            match change.range {
+                Some((start, end)) => {
+                    let (start, end) = (start.to_char(&mirror), end.to_char(&mirror));
+                    mirror.remove(start..end);
+                    mirror.insert(start, &change.text);
+                }
+                None => mirror = Rope::from_str(&change.text),
+            }
+        }
+        assert_eq!(mirror, *editor.rope());
+        assert!(editor.take_lsp_changes().is_empty());
+
+        editor.follow_lsp_changes(false);
+        editor.insert_text("z");
+        assert!(editor.take_lsp_changes().is_empty());
+    }
+
+    #[test]
+    fn uris_escape_paths() {
+        let path = Path::new("/tmp/a b/c#.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///tmp/a%20b/c%23.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+    }
+}
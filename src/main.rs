@@ -1,9 +1,13 @@
 use dioxus::desktop::{Config, WindowBuilder};
 use dioxus::prelude::*;
 
+mod commands;
 mod components;
 mod editor;
+mod keymap;
 mod layout;
+mod lsp;
+mod settings;
 mod theme;
 mod utils;
 
@@ -0,0 +1,118 @@
+use crate::editor::guides::WhitespaceMode;
+use crate::editor::macros::MacroStep;
+use crate::editor::rope_engine::DEFAULT_TAB_WIDTH;
+use crate::editor::wrap::WrapMode;
+use crate::lsp::{self, ServerCommand};
+use crate::utils::{config_dir, log};
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// User settings persisted as settings.toml next to keybindings.toml
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// CSS font family of the editor text
+    pub font_family: String,
+    /// Editor font size in pixels, before zooming
+    pub font_size: f64,
+    /// Height of an editor row in pixels, before zooming
+    pub line_height: f64,
+    /// Whether the font may join characters such as `=>` into ligatures
+    pub font_ligatures: bool,
+    /// Columns from one tab stop to the next
+    pub tab_size: usize,
+    /// Soft wrap: "off", "viewport", "column" or "ruler"
+    pub word_wrap: WrapMode,
+    /// Column used by `word_wrap = "column"`
+    pub wrap_column: usize,
+    /// Columns of the vertical rulers
+    pub rulers: Vec<usize>,
+    /// Whitespace drawn as glyphs: "none", "trailing" or "all"
+    pub render_whitespace: WhitespaceMode,
+    /// Whether vertical guides mark each level of indentation
+    pub indent_guides: bool,
+    /// Whether a minimap of the document is shown beside the scrollbar
+    pub minimap: bool,
+    /// Whether the first lines of enclosing scopes stay pinned at the top
+    pub sticky_scroll: bool,
+    /// Most lines sticky scroll pins at once
+    pub sticky_scroll_max_lines: usize,
+    /// Whether the types and parameter names a language server hints at are shown
+    pub inlay_hints: bool,
+    /// Saved keyboard macros by name
+    pub macros: BTreeMap<String, Vec<MacroStep>>,
+    /// Language server to start for each language id, such as "rust"
+    pub language_servers: BTreeMap<String, ServerCommand>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            font_family: "'Consolas', 'Monaco', 'Courier New', monospace".to_string(),
+            font_size: 14.0,
+            line_height: 20.0,
+            font_ligatures: false,
+            tab_size: DEFAULT_TAB_WIDTH,
+            word_wrap: WrapMode::Off,
+            wrap_column: 80,
+            rulers: Vec::new(),
+            render_whitespace: WhitespaceMode::None,
+            indent_guides: true,
+            minimap: false,
+            sticky_scroll: true,
+            sticky_scroll_max_lines: 5,
+            inlay_hints: true,
+            macros: BTreeMap::new(),
+            language_servers: lsp::default_servers(),
+        }
+    }
+}
+
+pub static SETTINGS: GlobalSignal<Settings> = Signal::global(Settings::load);
+
+impl Settings {
+    /// Column to wrap at for the fixed-width modes.
+    pub fn wrap_column(&self) -> Option<usize> {
+        match self.word_wrap {
+            WrapMode::Off | WrapMode::Viewport => None,
+            WrapMode::Column => Some(self.wrap_column),
+            WrapMode::Ruler => Some(self.rulers.first().copied().unwrap_or(self.wrap_column)),
+        }
+    }
+
+    pub fn path() -> PathBuf {
+        config_dir().join("settings.toml")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).unwrap_or_else(|e| {
+                log::write("Settings", format!("{}: {}", path.display(), e));
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = Self::path();
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, text).map_err(|e| e.to_string())
+    }
+}
+
+/// Applies `change` to the settings and writes them to disk.
+pub fn update_settings(change: impl FnOnce(&mut Settings)) {
+    let mut settings = SETTINGS.write();
+    change(&mut settings);
+    if let Err(e) = settings.save() {
+        log::write("Settings", format!("Failed to save: {}", e));
+    }
+}
@@ -16,6 +16,47 @@ pub enum IconTheme {
     Atom,
 }
 
+/// Font modifiers for a kind of token.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl FontStyle {
+    pub const NORMAL: FontStyle = FontStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+    };
+    pub const BOLD: FontStyle = FontStyle {
+        bold: true,
+        italic: false,
+        underline: false,
+    };
+    pub const ITALIC: FontStyle = FontStyle {
+        bold: false,
+        italic: true,
+        underline: false,
+    };
+
+    /// CSS declarations for the modifiers, empty for none.
+    pub fn css(self) -> String {
+        let mut css = String::new();
+        if self.bold {
+            css.push_str("font-weight: 600; ");
+        }
+        if self.italic {
+            css.push_str("font-style: italic; ");
+        }
+        if self.underline {
+            css.push_str("text-decoration: underline; ");
+        }
+        css
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ThemeColors {
     // Background colors
@@ -54,6 +95,25 @@ pub struct ThemeColors {
     pub syntax_comment: &'static str,
     pub syntax_number: &'static str,
     pub syntax_function: &'static str,
+    pub syntax_type: &'static str,
+    pub syntax_macro: &'static str,
+    pub syntax_lifetime: &'static str,
+    pub syntax_attribute: &'static str,
+    pub syntax_constant: &'static str,
+    pub syntax_operator: &'static str,
+    pub syntax_punctuation: &'static str,
+    pub syntax_parameter: &'static str,
+    pub syntax_property: &'static str,
+    pub syntax_escape: &'static str,
+    pub syntax_doc_comment: &'static str,
+
+    // Syntax font modifiers
+    pub syntax_keyword_style: FontStyle,
+    pub syntax_type_style: FontStyle,
+    pub syntax_comment_style: FontStyle,
+    pub syntax_doc_comment_style: FontStyle,
+    pub syntax_lifetime_style: FontStyle,
+    pub syntax_parameter_style: FontStyle,
 }
 
 impl Theme {
@@ -83,6 +143,23 @@ impl Theme {
                 syntax_comment: "#6a9955",
                 syntax_number: "#b5cea8",
                 syntax_function: "#dcdcaa",
+                syntax_type: "#4ec9b0",
+                syntax_macro: "#569cd6",
+                syntax_lifetime: "#569cd6",
+                syntax_attribute: "#d7ba7d",
+                syntax_constant: "#4fc1ff",
+                syntax_operator: "#d4d4d4",
+                syntax_punctuation: "#b4b4b4",
+                syntax_parameter: "#9cdcfe",
+                syntax_property: "#9cdcfe",
+                syntax_escape: "#d7ba7d",
+                syntax_doc_comment: "#608b4e",
+                syntax_keyword_style: FontStyle::NORMAL,
+                syntax_type_style: FontStyle::NORMAL,
+                syntax_comment_style: FontStyle::ITALIC,
+                syntax_doc_comment_style: FontStyle::ITALIC,
+                syntax_lifetime_style: FontStyle::ITALIC,
+                syntax_parameter_style: FontStyle::NORMAL,
             },
             Theme::Gruvbox => ThemeColors {
                 bg_primary: "#282828",
@@ -108,6 +185,23 @@ impl Theme {
                 syntax_comment: "#928374",
                 syntax_number: "#d3869b",
                 syntax_function: "#fabd2f",
+                syntax_type: "#8ec07c",
+                syntax_macro: "#83a598",
+                syntax_lifetime: "#fe8019",
+                syntax_attribute: "#689d6a",
+                syntax_constant: "#d3869b",
+                syntax_operator: "#fe8019",
+                syntax_punctuation: "#a89984",
+                syntax_parameter: "#83a598",
+                syntax_property: "#ebdbb2",
+                syntax_escape: "#fe8019",
+                syntax_doc_comment: "#a89984",
+                syntax_keyword_style: FontStyle::BOLD,
+                syntax_type_style: FontStyle::NORMAL,
+                syntax_comment_style: FontStyle::ITALIC,
+                syntax_doc_comment_style: FontStyle::ITALIC,
+                syntax_lifetime_style: FontStyle::ITALIC,
+                syntax_parameter_style: FontStyle::NORMAL,
             },
             Theme::Atom => ThemeColors {
                 bg_primary: "#21252b",
@@ -133,6 +227,23 @@ impl Theme {
                 syntax_comment: "#5c6370",
                 syntax_number: "#d19a66",
                 syntax_function: "#61afef",
+                syntax_type: "#e5c07b",
+                syntax_macro: "#56b6c2",
+                syntax_lifetime: "#e06c75",
+                syntax_attribute: "#d19a66",
+                syntax_constant: "#d19a66",
+                syntax_operator: "#56b6c2",
+                syntax_punctuation: "#abb2bf",
+                syntax_parameter: "#e06c75",
+                syntax_property: "#e06c75",
+                syntax_escape: "#56b6c2",
+                syntax_doc_comment: "#7f848e",
+                syntax_keyword_style: FontStyle::NORMAL,
+                syntax_type_style: FontStyle::NORMAL,
+                syntax_comment_style: FontStyle::ITALIC,
+                syntax_doc_comment_style: FontStyle::ITALIC,
+                syntax_lifetime_style: FontStyle::ITALIC,
+                syntax_parameter_style: FontStyle::ITALIC,
             },
             Theme::Monokai => ThemeColors {
                 bg_primary: "#272822",
@@ -158,6 +269,23 @@ impl Theme {
                 syntax_comment: "#75715e",
                 syntax_number: "#ae81ff",
                 syntax_function: "#a6e22e",
+                syntax_type: "#66d9ef",
+                syntax_macro: "#66d9ef",
+                syntax_lifetime: "#f92672",
+                syntax_attribute: "#a6e22e",
+                syntax_constant: "#ae81ff",
+                syntax_operator: "#f92672",
+                syntax_punctuation: "#f8f8f2",
+                syntax_parameter: "#fd971f",
+                syntax_property: "#f8f8f2",
+                syntax_escape: "#ae81ff",
+                syntax_doc_comment: "#8f8a73",
+                syntax_keyword_style: FontStyle::NORMAL,
+                syntax_type_style: FontStyle::ITALIC,
+                syntax_comment_style: FontStyle::ITALIC,
+                syntax_doc_comment_style: FontStyle::ITALIC,
+                syntax_lifetime_style: FontStyle::ITALIC,
+                syntax_parameter_style: FontStyle::ITALIC,
             },
         }
     }
@@ -294,3 +422,23 @@ pub fn use_theme() -> ThemeContext {
 pub fn provide_theme_context() {
     // Already initialized by the global factory
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_style_css_lists_each_modifier() {
+        assert_eq!(FontStyle::NORMAL.css(), "");
+        assert_eq!(FontStyle::BOLD.css(), "font-weight: 600; ");
+        let all = FontStyle {
+            bold: true,
+            italic: true,
+            underline: true,
+        };
+        assert_eq!(
+            all.css(),
+            "font-weight: 600; font-style: italic; text-decoration: underline; "
+        );
+    }
+}
@@ -1 +1,30 @@
 #![allow(dead_code)]
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::Path;
+
+/// Calls `on_change` whenever `path` is created, modified or removed. The parent
+/// directory is watched so the file may appear after the watcher starts.
+/// The watcher stops when the returned value is dropped.
+pub fn watch_file<F>(path: &Path, on_change: F) -> notify::Result<RecommendedWatcher>
+where
+    F: Fn() + Send + 'static,
+{
+    let file_name = path.file_name().map(|name| name.to_os_string());
+    let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+    std::fs::create_dir_all(&dir)?;
+
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        if let Ok(event) = result {
+            let touches_file = event
+                .paths
+                .iter()
+                .any(|p| p.file_name().map(|name| name.to_os_string()) == file_name);
+            if touches_file && !event.kind.is_access() {
+                on_change();
+            }
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+    Ok(watcher)
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Contents of `path` as committed at HEAD. None outside a repository, for
+/// files git doesn't track, or when git isn't installed.
+pub fn head_text(path: &Path) -> Option<String> {
+    let dir = path.parent()?;
+    let name = path.file_name()?.to_str()?;
+    // `./name` is resolved against the working directory rather than the repository root
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["show", &format!("HEAD:./{}", name)])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    #[test]
+    fn head_text_reads_the_committed_file() {
+        let dir = std::env::temp_dir().join(format!("git_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        if !git(&dir, &["init", "-q"]) {
+            // Nothing to test against without git
+            return;
+        }
+        let file = dir.join("src").join("main.rs");
+        std::fs::write(&file, "fn main() {}\n").unwrap();
+        assert!(git(&dir, &["add", "."]));
+        assert!(git(&dir, &["commit", "-q", "-m", "first"]));
+        std::fs::write(&file, "fn main() { edited(); }\n").unwrap();
+        std::fs::write(dir.join("src").join("new.rs"), "").unwrap();
+
+        assert_eq!(head_text(&file).as_deref(), Some("fn main() {}\n"));
+        assert_eq!(head_text(&dir.join("src").join("new.rs")), None);
+        assert_eq!(head_text(&dir.join("missing.rs")), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,36 @@
+use super::config_dir;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+// Messages with no better place to go: what language servers print, files
+// that could not be read or written, commands nothing handled. Each run starts
+// the log afresh in the config directory, beside the settings.
+
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn path() -> PathBuf {
+    // Tests leave the user's log alone
+    let dir = if cfg!(test) {
+        std::env::temp_dir()
+    } else {
+        config_dir()
+    };
+    dir.join("editor.log")
+}
+
+/// Appends `message` to the log, under `source`. Callable from any thread.
+pub fn write(source: &str, message: impl std::fmt::Display) {
+    let mut log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if log.is_none() {
+        let path = path();
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        *log = File::create(path).ok();
+    }
+    if let Some(file) = log.as_mut() {
+        let _ = writeln!(file, "[{source}] {message}");
+    }
+}
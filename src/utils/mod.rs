@@ -0,0 +1,12 @@
+pub mod file_watcher;
+pub mod git;
+pub mod log;
+
+use std::path::PathBuf;
+
+/// Directory for user settings and key bindings.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join("rust-code-editor"))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default())
+}